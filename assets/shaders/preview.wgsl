@@ -0,0 +1,30 @@
+// Box-downsamples the colorized trail map into the picture-in-picture preview, see preview.rs.
+// The names in braces after a `#` are substituted with the values in `ShaderConstants` in lib.rs.
+
+@group(0) @binding(0)
+var display: texture_2d<f32>;
+
+@group(0) @binding(1)
+var preview: texture_storage_2d<rgba8unorm, write>;
+
+@compute @workgroup_size(#{TEXTURE_WORKGROUP_SIZE}, #{TEXTURE_WORKGROUP_SIZE}, 1)
+fn downsample(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let position = vec2<i32>(invocation_id.xy);
+    let preview_size = vec2<i32>(textureDimensions(preview));
+    if (position.x >= preview_size.x || position.y >= preview_size.y) {
+        return;
+    }
+
+    // the display texels covered by this preview texel, at least one when the map is smaller
+    let display_size = vec2<i32>(textureDimensions(display));
+    let start = position * display_size / preview_size;
+    let end = max((position + 1) * display_size / preview_size, start + 1);
+    var sum = vec4<f32>(0.0);
+    for (var y = start.y; y < end.y; y = y + 1) {
+        for (var x = start.x; x < end.x; x = x + 1) {
+            sum = sum + textureLoad(display, vec2<i32>(x, y), 0);
+        }
+    }
+    let count = f32((end.x - start.x) * (end.y - start.y));
+    textureStore(preview, position, sum / count);
+}
@@ -1,16 +1,1074 @@
-struct Slime {
-  value: f32,
+// The names in braces after a `#` are substituted with the values in `ShaderConstants` in lib.rs.
+
+struct SpeciesSettings {
+  color: vec4<f32>,
+  interaction: vec4<f32>,
+  move_speed: f32,
+  sensor_angle: f32,
   _padding0: f32,
   _padding1: f32,
-  _padding2: f32,
+}
+
+struct SimulationSettings {
+  agent_count: u32,
+  move_speed: f32,
+  turn_speed: f32,
+  sensor_spread: f32,
+  sensor_distance: f32,
+  decay_rate: f32,
+  diffuse_rate: f32,
+  species_count: u32,
+  // Already divided by `substeps`, each update pass moving agents by one substep.
+  time_scale: f32,
+  seed: u32,
+  boundary_mode: u32,
+  // One of the `COLOR_*` constants.
+  color_mode: u32,
+  max_trail: f32,
+  arena_radius: f32,
+  food_attraction: f32,
+  food_consumption: f32,
+  sense_weight: f32,
+  deposit_amount: f32,
+  memory_penalty: f32,
+  memory_length: u32,
+  sensor_count: u32,
+  antialiased_deposit: u32,
+  substeps: u32,
+  sharpen_amount: f32,
+  kill_respawn: u32,
+  spawn_pattern: u32,
+  // 0 to keep the heading of the spawn pattern, otherwise one of the `HEADING_*` constants.
+  initial_heading: u32,
+  heading_angle: f32,
+  blur_radius: u32,
+  gaussian_blur: u32,
+  crowd_avoidance: f32,
+  noise_strength: f32,
+  noise_scale: f32,
+  // One of the `DEPOSIT_*` constants, with the radius of `DEPOSIT_DISK`.
+  deposit_shape: u32,
+  deposit_radius: f32,
+  exploration: f32,
+  exploration_threshold: f32,
+  // 0 for agents that never age out.
+  max_age: u32,
+  age_deposit_falloff: f32,
+  // Decayed trail below it is zeroed, 0 to keep it.
+  evaporation_floor: f32,
+  // Whether the noise field repeats across the trail map, the boundary is already wrapping.
+  seamless: u32,
+  _padding1: u32,
+  _padding2: u32,
+  // Shown where there is no trail, the lookup texture of the other palettes fades into it too.
+  background: vec4<f32>,
+  // Row `i` is the fraction species `i` deposits on each trail channel.
+  deposit_matrix: array<vec4<f32>, #{MAX_SPECIES}>,
+  species: array<SpeciesSettings, #{MAX_SPECIES}>,
+}
+
+struct Agent {
+  position: vec2<f32>,
+  angle: f32,
+  species: u32,
+  speed: f32,
+  // Index into `recent` the next position is written to.
+  memory_cursor: u32,
+  recent: array<vec2<f32>, #{MAX_MEMORY}>,
+  // 0 once killed at the edge without respawning.
+  alive: u32,
+  // Steps lived since spawning or respawning.
+  age: u32,
 }
 
 @group(0) @binding(0)
-var<storage, read_write> slime: Slime;
+var<uniform> settings: SimulationSettings;
+
+// The agents as of the previous step, `update` writes the new state into `agents_out`.
+@group(0) @binding(1)
+var<storage, read> agents_in: array<Agent>;
+
+@group(0) @binding(2)
+var trail_map: texture_storage_2d<#{TRAIL_FORMAT}, read_write>;
+
+// Written by the diffuse pass, becomes `trail_map` on the next frame.
+@group(0) @binding(3)
+var next_trail_map: texture_storage_2d<#{TRAIL_FORMAT}, read_write>;
+
+@group(0) @binding(4)
+var display: texture_storage_2d<rgba8unorm, write>;
+
+struct TrailInjection {
+  position: vec2<u32>,
+  strength: f32,
+  // `INJECT_TRAIL` or `INJECT_FOOD`.
+  channel: u32,
+}
+
+@group(0) @binding(5)
+var<storage, read> injections: array<TrailInjection>;
+
+struct Frame {
+  index: u32,
+  // Duration of the frame relative to a 60 fps frame.
+  time_step: f32,
+  // Non-zero while the sensor points are drawn.
+  sensor_debug: u32,
+  // Agents the update pass moves, the rest of the agent buffers is room for bursts.
+  active_count: u32,
+  // Where this frame's burst spawns `burst_count` agents, after the active ones.
+  burst_position: vec2<f32>,
+  burst_count: u32,
+  // Non-zero while the agent density heatmap is drawn.
+  density_overlay: u32,
+  // Columns and rows of cells of `agent_grid`, 0 without one.
+  grid_size: vec2<u32>,
+  grid_cell_size: u32,
+  _padding0: u32,
+}
+
+@group(0) @binding(6)
+var<uniform> frame: Frame;
+
+// 256 entry gradient indexed by trail intensity when `settings.color_mode` is `COLOR_INTENSITY`.
+@group(0) @binding(7)
+var palette: texture_2d<f32>;
+
+// Markers at the texels sensed this frame, cleared by `colorize` once drawn.
+@group(0) @binding(8)
+var sensor_overlay: texture_storage_2d<#{TRAIL_FORMAT}, read_write>;
+
+// 1 where the trail map is walled off by the obstacle mask, 0 elsewhere.
+@group(0) @binding(9)
+var obstacles: texture_2d<f32>;
+
+@group(0) @binding(10)
+var<storage, read_write> agents_out: array<Agent>;
+
+// Must match `GpuTrailStats` in stats.rs.
+struct TrailStats {
+  min: f32,
+  max: f32,
+  sum: f32,
+  _padding0: f32,
+}
+
+// One entry per workgroup of the `reduce` pass, in row-major order.
+@group(0) @binding(11)
+var<storage, read_write> trail_stats: array<TrailStats>;
+
+// Food left on each texel of the trail map, in row-major order.
+@group(0) @binding(12)
+var<storage, read_write> food: array<f32>;
+
+// Anti-aliased deposits of this step, one fixed point sum per channel of each trail texel in
+// row-major order, added to the trail map and zeroed by the `resolve` pass.
+@group(0) @binding(13)
+var<storage, read_write> deposits: array<atomic<u32>>;
+
+// Agents on each trail texel during this step, summed over its substeps, in row-major order.
+// Counted by `update` and rotated by `rotate_density` only while `counts_density()`.
+@group(0) @binding(14)
+var<storage, read_write> agent_density: array<atomic<u32>>;
+
+// `agent_density` as of the previous step, sensed by agents avoiding crowds.
+@group(0) @binding(15)
+var<storage, read_write> previous_density: array<u32>;
+
+// Random values from -1 to 1 on a lattice, interpolated by `heading_noise`.
+@group(0) @binding(16)
+var noise: texture_2d<f32>;
+
+// The agents sorted by the cell of the grid they are in: where every cell ends, in row-major
+// order, followed by the index of every agent, see `grid_cell_range`. Rebuilt at the start of
+// every frame by `clear_grid`, `count_grid`, `scan_grid` and `scatter_grid`.
+@group(0) @binding(17)
+var<storage, read_write> agent_grid: array<atomic<u32>>;
+
+// Distance in texels from a remembered position within which sensors are penalized.
+let MEMORY_RADIUS: f32 = 2.0;
+
+// Colors of the forward and side sensor markers.
+let SENSOR_MARKER_FORWARD: vec4<f32> = vec4<f32>(1.0, 1.0, 0.0, 1.0);
+let SENSOR_MARKER_SIDE: vec4<f32> = vec4<f32>(1.0, 0.0, 1.0, 1.0);
+
+let BOUNDARY_WRAP: u32 = #{BOUNDARY_WRAP}u;
+let BOUNDARY_BOUNCE: u32 = #{BOUNDARY_BOUNCE}u;
+let BOUNDARY_KILL: u32 = #{BOUNDARY_KILL}u;
+let BOUNDARY_CIRCLE: u32 = #{BOUNDARY_CIRCLE}u;
+
+let SPAWN_CENTER_POINT: u32 = #{SPAWN_CENTER_POINT}u;
+let SPAWN_RANDOM_UNIFORM: u32 = #{SPAWN_RANDOM_UNIFORM}u;
+let SPAWN_CIRCLE_INWARD: u32 = #{SPAWN_CIRCLE_INWARD}u;
+let SPAWN_CIRCLE_OUTWARD: u32 = #{SPAWN_CIRCLE_OUTWARD}u;
+let SPAWN_RING_RANDOM: u32 = #{SPAWN_RING_RANDOM}u;
+// Must match `SPAWN_RADIUS`, `RING_INNER_RADIUS` and `SEAMLESS_TILES` in lib.rs.
+let SPAWN_RADIUS: f32 = 0.4;
+let RING_INNER_RADIUS: f32 = 0.8;
+let SEAMLESS_TILES: u32 = 2u;
+
+let HEADING_PATTERN: u32 = 0u;
+let HEADING_RANDOM: u32 = #{HEADING_RANDOM}u;
+let HEADING_FACING_CENTER: u32 = #{HEADING_FACING_CENTER}u;
+let HEADING_AWAY_FROM_CENTER: u32 = #{HEADING_AWAY_FROM_CENTER}u;
+let HEADING_TANGENTIAL: u32 = #{HEADING_TANGENTIAL}u;
+let HEADING_FIXED: u32 = #{HEADING_FIXED}u;
+
+// Where killed agents that don't respawn are parked, off the map.
+let PARKED_POSITION: vec2<f32> = vec2<f32>(-1.0, -1.0);
+
+let COLOR_SPECIES: u32 = #{COLOR_SPECIES}u;
+let COLOR_INTENSITY: u32 = #{COLOR_INTENSITY}u;
+let COLOR_DIRECTION: u32 = #{COLOR_DIRECTION}u;
+
+let INJECT_TRAIL: u32 = #{INJECT_TRAIL}u;
+let INJECT_FOOD: u32 = #{INJECT_FOOD}u;
+
+// Fixed point steps per unit of trail in `deposits`.
+let DEPOSIT_SCALE: f32 = #{DEPOSIT_SCALE}.0;
+
+let DEPOSIT_POINT: u32 = #{DEPOSIT_POINT}u;
+let DEPOSIT_SEGMENT: u32 = #{DEPOSIT_SEGMENT}u;
+let DEPOSIT_DISK: u32 = #{DEPOSIT_DISK}u;
+let MAX_DEPOSIT_RADIUS: u32 = #{MAX_DEPOSIT_RADIUS}u;
+let MAX_SEGMENT_LENGTH: u32 = #{MAX_SEGMENT_LENGTH}u;
+
+// Invocations of the `scan_grid` workgroup.
+let GRID_SCAN_THREADS: u32 = #{GRID_SCAN_THREADS}u;
+
+// Color food is drawn with, on top of the trail.
+let FOOD_COLOR: vec3<f32> = vec3<f32>(0.3, 0.8, 0.2);
+
+// Integer hash from https://www.cs.ubc.ca/~rbridson/docs/schechter-sca08-turbulence.pdf
+fn hash(value: u32) -> u32 {
+    var state = value;
+    state = state ^ 2747636419u;
+    state = state * 2654435769u;
+    state = state ^ (state >> 16u);
+    state = state * 2654435769u;
+    state = state ^ (state >> 16u);
+    state = state * 2654435769u;
+    return state;
+}
+
+fn random_float(value: u32) -> f32 {
+    return f32(hash(value)) / 4294967295.0;
+}
+
+// The trail map is sized by `sim_width` and `sim_height`, independent of the window.
+fn in_bounds(position: vec2<i32>) -> bool {
+    let size = vec2<i32>(textureDimensions(trail_map));
+    return position.x >= 0 && position.y >= 0 && position.x < size.x && position.y < size.y;
+}
+
+fn is_obstacle(position: vec2<i32>) -> bool {
+    return textureLoad(obstacles, position, 0).r > 0.5;
+}
+
+// Whether `position` is beyond the edge of the circular arena, always false in other modes.
+fn outside_arena(position: vec2<f32>) -> bool {
+    if (settings.boundary_mode != BOUNDARY_CIRCLE) {
+        return false;
+    }
+    let center = vec2<f32>(textureDimensions(trail_map)) / 2.0;
+    return distance(position, center) > settings.arena_radius;
+}
+
+// Index of a texel in the row-major buffers sized like the trail map.
+fn texel_index(position: vec2<i32>) -> u32 {
+    return u32(position.y) * textureDimensions(trail_map).x + u32(position.x);
+}
+
+// Wraps a texel position around the edges of the trail map.
+fn wrap(position: vec2<i32>) -> vec2<i32> {
+    let size = vec2<i32>(textureDimensions(trail_map));
+    return (position % size + size) % size;
+}
+
+// The texel `offset` away from `position` sampled by the blurs. Wrapping keeps trails continuous
+// across the edges of a torus, clamping to the edge everywhere else so the border isn't darkened
+// by sampling nothing.
+fn neighbour(position: vec2<i32>, offset: vec2<i32>) -> vec2<i32> {
+    let sample = position + offset;
+    if (settings.boundary_mode == BOUNDARY_WRAP) {
+        return wrap(sample);
+    }
+    return clamp(sample, vec2<i32>(0), vec2<i32>(textureDimensions(trail_map)) - 1);
+}
+
+// The texel at `sensor_distance` from the agent, rotated by `angle_offset` from its heading.
+fn sensor_position(agent: Agent, angle_offset: f32) -> vec2<i32> {
+    let angle = agent.angle + angle_offset;
+    let direction = vec2<f32>(cos(angle), sin(angle));
+    let position = vec2<i32>(floor(agent.position + direction * settings.sensor_distance));
+    if (settings.boundary_mode == BOUNDARY_WRAP) {
+        return wrap(position);
+    }
+    return position;
+}
+
+// Samples the trail map at the sensor rotated by `angle_offset` from the agent's heading.
+// Each species' trail is weighted by how much the agent's species is attracted to it, the food
+// by `food_attraction`. Trail above `exploration_threshold` reads weaker by `exploration`.
+fn sense(agent: Agent, angle_offset: f32) -> f32 {
+    let position = sensor_position(agent, angle_offset);
+    if (!in_bounds(position)) {
+        return 0.0;
+    }
+    var trail = dot(textureLoad(trail_map, position), settings.species[agent.species].interaction);
+    trail = trail - settings.exploration * max(trail - settings.exploration_threshold, 0.0);
+    var value = trail + settings.food_attraction * food[texel_index(position)];
+    if (settings.crowd_avoidance != 0.0) {
+        // the average count over the substeps of the previous step
+        let crowd = f32(previous_density[texel_index(position)]) / f32(max(settings.substeps, 1u));
+        value = value - settings.crowd_avoidance * crowd;
+    }
+    // steer away from where the agent has just been, copied to a var to be indexed dynamically
+    var recent = agent.recent;
+    for (var i = 0u; i < settings.memory_length; i = i + 1u) {
+        if (distance(vec2<f32>(position), recent[i]) < MEMORY_RADIUS) {
+            value = value - settings.memory_penalty;
+            break;
+        }
+    }
+    return value;
+}
+
+fn mark_sensor(agent: Agent, angle_offset: f32, marker: vec4<f32>) {
+    let position = sensor_position(agent, angle_offset);
+    if (in_bounds(position)) {
+        textureStore(sensor_overlay, position, marker);
+    }
+}
+
+// Places a killed agent again like `build_agents` places agents at startup, following
+// `spawn_pattern` and `initial_heading`, with draws hashed from `random`. When seamless the
+// pattern is laid out on a random one of the tiles.
+fn respawn(agent: Agent, random: u32) -> Agent {
+    let first = hash(random);
+    let second = hash(first);
+    let third = hash(second);
+    let random_angle = random_float(first) * 2.0 * 3.1415927;
+    var size = vec2<f32>(textureDimensions(trail_map));
+    var origin = vec2<f32>(0.0);
+    if (settings.seamless != 0u) {
+        size = size / f32(SEAMLESS_TILES);
+        let tile = hash(hash(third)) % (SEAMLESS_TILES * SEAMLESS_TILES);
+        origin = vec2<f32>(f32(tile % SEAMLESS_TILES), f32(tile / SEAMLESS_TILES)) * size;
+    }
+    let center = origin + size / 2.0;
+    let radius = min(size.x, size.y) * SPAWN_RADIUS;
+
+    var position = center;
+    var angle = random_angle;
+    if (settings.spawn_pattern == SPAWN_RANDOM_UNIFORM) {
+        position = origin + vec2<f32>(random_float(second), random_float(third)) * size;
+    } else if (settings.spawn_pattern == SPAWN_CIRCLE_INWARD || settings.spawn_pattern == SPAWN_CIRCLE_OUTWARD) {
+        // the square root spreads agents evenly over the area of the circle
+        let distance = radius * sqrt(random_float(second));
+        position = center + vec2<f32>(cos(random_angle), sin(random_angle)) * distance;
+        if (settings.spawn_pattern == SPAWN_CIRCLE_INWARD) {
+            angle = random_angle + 3.1415927;
+        }
+    } else if (settings.spawn_pattern == SPAWN_RING_RANDOM) {
+        let inner = radius * RING_INNER_RADIUS;
+        let distance = sqrt(inner * inner + random_float(second) * (radius * radius - inner * inner));
+        position = center + vec2<f32>(cos(random_angle), sin(random_angle)) * distance;
+        angle = random_float(third) * 2.0 * 3.1415927;
+    }
+
+    // agents right on the center have no direction to it, so they face a random one
+    let offset = position - center;
+    let outward = atan2(offset.y, offset.x);
+    let heading = settings.initial_heading;
+    if (heading == HEADING_FIXED) {
+        angle = settings.heading_angle;
+    } else if (heading == HEADING_RANDOM || (heading != HEADING_PATTERN && all(offset == vec2<f32>(0.0)))) {
+        angle = random_float(hash(third)) * 2.0 * 3.1415927;
+    } else if (heading == HEADING_FACING_CENTER) {
+        angle = outward + 3.1415927;
+    } else if (heading == HEADING_AWAY_FROM_CENTER) {
+        angle = outward;
+    } else if (heading == HEADING_TANGENTIAL) {
+        angle = outward + 3.1415927 / 2.0;
+    }
+
+    var respawned = agent;
+    respawned.position = position;
+    respawned.angle = angle;
+    respawned.memory_cursor = 0u;
+    respawned.age = 0u;
+    for (var i = 0u; i < #{MAX_MEMORY}u; i = i + 1u) {
+        respawned.recent[i] = position;
+    }
+    return respawned;
+}
+
+// The fraction of a deposit of `agent` left on each trail channel, its row of the deposit
+// matrix. Coloring by direction keeps the heading in the last two channels instead, as
+// `(1 + cos) / 2` and `(1 + sin) / 2` of what goes to the first two so it stays positive.
+fn deposit_fractions(agent: Agent) -> vec4<f32> {
+    let fractions = settings.deposit_matrix[agent.species];
+    if (settings.color_mode != COLOR_DIRECTION) {
+        return fractions;
+    }
+    let total = fractions.x + fractions.y;
+    let heading = 0.5 + 0.5 * vec2<f32>(cos(agent.angle), sin(agent.angle));
+    return vec4<f32>(fractions.xy, total * heading);
+}
+
+// Adds `amount` of a deposit to the texel at `position`, split between the trail channels by
+// `fractions`, skipping walls and texels off the map.
+fn deposit_texel(fractions: vec4<f32>, position: vec2<i32>, amount: f32) {
+    var texel = position;
+    if (settings.boundary_mode == BOUNDARY_WRAP) {
+        texel = wrap(texel);
+    }
+    if (!in_bounds(texel) || is_obstacle(texel)) {
+        return;
+    }
+    if (settings.antialiased_deposit != 0u) {
+        for (var channel = 0u; channel < 4u; channel = channel + 1u) {
+            if (fractions[channel] > 0.0) {
+                let steps = u32(amount * fractions[channel] * DEPOSIT_SCALE + 0.5);
+                atomicAdd(&deposits[texel_index(texel) * 4u + channel], steps);
+            }
+        }
+    } else {
+        let trail = textureLoad(trail_map, texel) + fractions * amount;
+        textureStore(trail_map, texel, min(trail, vec4<f32>(settings.max_trail)));
+    }
+}
+
+// Deposits `amount` at `position`, on the texel it is on, or with `antialiased_deposit` split
+// between the four texels whose centers surround it, weighted by how close it is to each.
+fn deposit_point(fractions: vec4<f32>, position: vec2<f32>, amount: f32) {
+    if (settings.antialiased_deposit == 0u) {
+        deposit_texel(fractions, vec2<i32>(position), amount);
+        return;
+    }
+    let corner = position - 0.5;
+    let base = vec2<i32>(floor(corner));
+    let fraction = corner - floor(corner);
+    for (var i = 0; i < 4; i = i + 1) {
+        let offset = vec2<i32>(i % 2, i / 2);
+        let weights = mix(1.0 - fraction, fraction, vec2<f32>(offset));
+        deposit_texel(fractions, base + offset, weights.x * weights.y * amount);
+    }
+}
+
+// The fraction of `deposit_amount` an agent of its age deposits, shrinking by
+// `age_deposit_falloff` over its life to `max_age`.
+fn age_deposit_factor(agent: Agent) -> f32 {
+    if (settings.max_age == 0u) {
+        return 1.0;
+    }
+    let life = f32(agent.age) / f32(settings.max_age);
+    return max(1.0 - settings.age_deposit_falloff * life, 0.0);
+}
+
+// Spreads the agent's deposit over the texels of the `deposit_shape`, `previous` being where it
+// started this step.
+fn deposit(agent: Agent, previous: vec2<f32>) {
+    let amount = settings.deposit_amount * age_deposit_factor(agent);
+    let fractions = deposit_fractions(agent);
+    let moved = distance(previous, agent.position);
+    if (settings.deposit_shape == DEPOSIT_SEGMENT && moved <= f32(MAX_SEGMENT_LENGTH)) {
+        // a sample per texel moved, the start was covered by the end of the last step
+        let samples = max(u32(ceil(moved)), 1u);
+        for (var i = 1u; i <= samples; i = i + 1u) {
+            let position = mix(previous, agent.position, f32(i) / f32(samples));
+            deposit_point(fractions, position, amount / f32(samples));
+        }
+    } else if (settings.deposit_shape == DEPOSIT_DISK) {
+        let radius = min(settings.deposit_radius, f32(MAX_DEPOSIT_RADIUS));
+        let first = vec2<i32>(floor(agent.position - radius));
+        let last = vec2<i32>(floor(agent.position + radius));
+        // the area of the disk, the texels covered at the smallest radii
+        let share = amount / max(3.1415927 * radius * radius, 1.0);
+        for (var y = first.y; y <= last.y; y = y + 1) {
+            for (var x = first.x; x <= last.x; x = x + 1) {
+                let center = vec2<f32>(f32(x), f32(y)) + 0.5;
+                if (distance(center, agent.position) <= radius) {
+                    deposit_texel(fractions, vec2<i32>(x, y), share);
+                }
+            }
+        }
+    } else {
+        deposit_point(fractions, agent.position, amount);
+    }
+}
+
+// Zeroes both trail maps and the sensor overlay, on the first frame and on resets.
+@compute @workgroup_size(#{TEXTURE_WORKGROUP_SIZE}, #{TEXTURE_WORKGROUP_SIZE}, 1)
+fn clear(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let position = vec2<i32>(invocation_id.xy);
+    if (!in_bounds(position)) {
+        return;
+    }
+
+    textureStore(trail_map, position, vec4<f32>(0.0));
+    textureStore(next_trail_map, position, vec4<f32>(0.0));
+    textureStore(sensor_overlay, position, vec4<f32>(0.0));
+}
+
+// Value noise from -1 to 1 at `position`, smoothly interpolating the lattice point every
+// `noise_scale` texels and wrapping around it. When seamless it wraps at the edges of the trail
+// map instead, the scale rounded so a whole number of lattice points, at most the lattice's
+// size, fit across it.
+fn heading_noise(position: vec2<f32>) -> f32 {
+    var size = vec2<i32>(textureDimensions(noise));
+    var scale = vec2<f32>(settings.noise_scale);
+    if (settings.seamless != 0u) {
+        let map_size = vec2<f32>(textureDimensions(trail_map));
+        size = clamp(vec2<i32>(round(map_size / settings.noise_scale)), vec2<i32>(1), size);
+        scale = map_size / vec2<f32>(size);
+    }
+    let scaled = position / scale;
+    let cell = vec2<i32>(floor(scaled));
+    let t = fract(scaled);
+    let smooth_t = t * t * (3.0 - 2.0 * t);
+    let corner = vec2<i32>(1, 0);
+    let a = textureLoad(noise, (cell % size + size) % size, 0).r;
+    let b = textureLoad(noise, ((cell + corner.xy) % size + size) % size, 0).r;
+    let c = textureLoad(noise, ((cell + corner.yx) % size + size) % size, 0).r;
+    let d = textureLoad(noise, ((cell + corner.xx) % size + size) % size, 0).r;
+    return mix(mix(a, b, smooth_t.x), mix(c, d, smooth_t.x), smooth_t.y);
+}
+
+// Whether the agents are counted into `agent_density` this step.
+fn counts_density() -> bool {
+    return frame.density_overlay != 0u || settings.crowd_avoidance != 0.0;
+}
+
+// Keeps the agent density of the last step for sensing and zeroes it before the update passes
+// of this step count the agents again.
+@compute @workgroup_size(#{TEXTURE_WORKGROUP_SIZE}, #{TEXTURE_WORKGROUP_SIZE}, 1)
+fn rotate_density(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let position = vec2<i32>(invocation_id.xy);
+    if (!in_bounds(position)) {
+        return;
+    }
+
+    let texel = texel_index(position);
+    previous_density[texel] = atomicExchange(&agent_density[texel], 0u);
+}
+
+// Adds a small gaussian splat of trail or food around every queued injection.
+@compute @workgroup_size(#{TEXTURE_WORKGROUP_SIZE}, #{TEXTURE_WORKGROUP_SIZE}, 1)
+fn inject(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let position = vec2<i32>(invocation_id.xy);
+    if (!in_bounds(position)) {
+        return;
+    }
+
+    var value = textureLoad(trail_map, position);
+    var food_value = food[texel_index(position)];
+    for (var i = 0u; i < arrayLength(&injections); i = i + 1u) {
+        let injection = injections[i];
+        let offset = vec2<f32>(position) - vec2<f32>(injection.position);
+        // gaussian with a standard deviation of 6 pixels
+        let splat = injection.strength * exp(-dot(offset, offset) / 72.0);
+        if (injection.channel == INJECT_FOOD) {
+            food_value = food_value + splat;
+        } else {
+            value = value + splat;
+        }
+    }
+    textureStore(trail_map, position, max(value, vec4<f32>(0.0)));
+    food[texel_index(position)] = max(food_value, 0.0);
+}
+
+fn grid_cell_count() -> u32 {
+    return frame.grid_size.x * frame.grid_size.y;
+}
+
+// The cell of the grid `position` is in, or `grid_cell_count()` outside the map.
+fn grid_cell(position: vec2<f32>) -> u32 {
+    if (!in_bounds(vec2<i32>(floor(position)))) {
+        return grid_cell_count();
+    }
+    let cell = vec2<u32>(position) / frame.grid_cell_size;
+    let clamped = min(cell, frame.grid_size - 1u);
+    return clamped.y * frame.grid_size.x + clamped.x;
+}
+
+// The slots of `grid_agent` holding the agents of `cell` this frame, from `x` up to `y`.
+fn grid_cell_range(cell: u32) -> vec2<u32> {
+    var start = 0u;
+    if (cell > 0u) {
+        start = atomicLoad(&agent_grid[cell - 1u]);
+    }
+    return vec2<u32>(start, atomicLoad(&agent_grid[cell]));
+}
+
+// The index of the agent sorted into `slot` of the grid.
+fn grid_agent(slot: u32) -> u32 {
+    return atomicLoad(&agent_grid[grid_cell_count() + slot]);
+}
+
+// The cell of the grid agent `index` is sorted into, `grid_cell_count()` for dead agents.
+fn grid_cell_of(index: u32) -> u32 {
+    let agent = agents_in[index];
+    if (agent.alive == 0u) {
+        return grid_cell_count();
+    }
+    return grid_cell(agent.position);
+}
+
+// Zeroes the count of every cell of the grid.
+@compute @workgroup_size(#{AGENT_WORKGROUP_SIZE}, 1, 1)
+fn clear_grid(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    if (invocation_id.x < grid_cell_count()) {
+        atomicStore(&agent_grid[invocation_id.x], 0u);
+    }
+}
+
+// Counts every living agent in the entry after the one of its cell, so summing the entries up
+// to a cell gives where it starts.
+@compute @workgroup_size(#{AGENT_WORKGROUP_SIZE}, 1, 1)
+fn count_grid(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    if (invocation_id.x >= frame.active_count) {
+        return;
+    }
+    let cell = grid_cell_of(invocation_id.x);
+    // the count of the last cell isn't needed to know where any cell starts
+    if (cell + 1u < grid_cell_count()) {
+        atomicAdd(&agent_grid[cell + 1u], 1u);
+    }
+}
+
+var<workgroup> scan_totals: array<u32, #{GRID_SCAN_THREADS}>;
+
+// Sums the counts up in place, every invocation summing a contiguous run of cells and adding
+// the totals of the runs before it.
+@compute @workgroup_size(#{GRID_SCAN_THREADS}, 1, 1)
+fn scan_grid(@builtin(local_invocation_index) thread: u32) {
+    let cell_count = grid_cell_count();
+    let run = (cell_count + GRID_SCAN_THREADS - 1u) / GRID_SCAN_THREADS;
+    let first = min(thread * run, cell_count);
+    let last = min(first + run, cell_count);
+
+    var total = 0u;
+    for (var cell = first; cell < last; cell = cell + 1u) {
+        total = total + atomicLoad(&agent_grid[cell]);
+    }
+    scan_totals[thread] = total;
+    workgroupBarrier();
+
+    var sum = 0u;
+    for (var i = 0u; i < thread; i = i + 1u) {
+        sum = sum + scan_totals[i];
+    }
+    for (var cell = first; cell < last; cell = cell + 1u) {
+        sum = sum + atomicLoad(&agent_grid[cell]);
+        atomicStore(&agent_grid[cell], sum);
+    }
+}
 
-@compute @workgroup_size(1, 1, 1)
+// Writes every living agent into the next free slot of its cell, moving the start of the cell
+// on, so once all are written it holds where the next cell starts.
+@compute @workgroup_size(#{AGENT_WORKGROUP_SIZE}, 1, 1)
+fn scatter_grid(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    if (invocation_id.x >= frame.active_count) {
+        return;
+    }
+    let cell = grid_cell_of(invocation_id.x);
+    if (cell < grid_cell_count()) {
+        let slot = atomicAdd(&agent_grid[cell], 1u);
+        atomicStore(&agent_grid[grid_cell_count() + slot], invocation_id.x);
+    }
+}
+
+@compute @workgroup_size(#{AGENT_WORKGROUP_SIZE}, 1, 1)
 fn update(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
-    slime.value = slime.value + 1.0;
+    let index = invocation_id.x;
+    if (index < frame.active_count && agents_in[index].alive == 0u) {
+        // dead agents stay parked, copied along so both buffers agree
+        agents_out[index] = agents_in[index];
+    } else if (index < frame.active_count) {
+        var agent = agents_in[index];
+        let species = settings.species[agent.species];
+        let sensor_spread = settings.sensor_spread * species.sensor_angle;
+        // hash(agent_index ^ frame ^ seed), with each input hashed first so they don't cancel out
+        let random = hash(index ^ hash(frame.index ^ hash(settings.seed)));
+
+        // speeds are given per 60 fps frame
+        let time_step = frame.time_step * settings.time_scale;
+        let turn = settings.turn_speed * time_step;
+
+        // the strongest reading on each side, and how far out towards `sensor_spread` it is
+        let forward = sense(agent, 0.0);
+        var left = -3.4e38;
+        var right = -3.4e38;
+        var left_fraction = 0.0;
+        var right_fraction = 0.0;
+        let side_count = settings.sensor_count / 2u;
+        for (var i = 1u; i <= side_count; i = i + 1u) {
+            let fraction = f32(i) / f32(side_count);
+            let left_reading = sense(agent, sensor_spread * fraction);
+            if (left_reading > left) {
+                left = left_reading;
+                left_fraction = fraction;
+            }
+            let right_reading = sense(agent, -sensor_spread * fraction);
+            if (right_reading > right) {
+                right = right_reading;
+                right_fraction = fraction;
+            }
+            if (frame.sensor_debug != 0u) {
+                mark_sensor(agent, sensor_spread * fraction, SENSOR_MARKER_SIDE);
+                mark_sensor(agent, -sensor_spread * fraction, SENSOR_MARKER_SIDE);
+            }
+        }
+        if (frame.sensor_debug != 0u) {
+            mark_sensor(agent, 0.0, SENSOR_MARKER_FORWARD);
+        }
+
+        // the stronger the pull of one side, the sharper the turn, up to `turn`
+        let steer = turn * min(settings.sense_weight * abs(left - right), 1.0);
+        if (forward >= left && forward >= right) {
+            // Keep going straight, also when there are no side sensors.
+        } else if (left > right) {
+            agent.angle = agent.angle + steer * left_fraction;
+        } else if (right > left) {
+            agent.angle = agent.angle - steer * right_fraction;
+        } else if (random_float(random) < 0.5) {
+            agent.angle = agent.angle + turn;
+        } else {
+            agent.angle = agent.angle - turn;
+        }
+
+        // skipped entirely when off, so the heading is exactly the same as without noise
+        if (settings.noise_strength != 0.0) {
+            let noise_turn = settings.noise_strength * time_step;
+            agent.angle = agent.angle + noise_turn * heading_noise(agent.position);
+        }
+
+        let direction = vec2<f32>(cos(agent.angle), sin(agent.angle));
+        let speed = settings.move_speed * species.move_speed * agent.speed * time_step;
+        let new_position = agent.position + direction * speed;
+        let size = vec2<f32>(textureDimensions(trail_map));
+        let target = vec2<i32>(floor(new_position));
+        let outward = new_position - size / 2.0;
+        if (outside_arena(new_position) && dot(direction, outward) > 0.0) {
+            // Stay in place and reflect the heading off the edge of the arena. Agents spawned
+            // outside of it are let through while heading inwards.
+            let normal = normalize(outward);
+            let reflected = direction - 2.0 * dot(direction, normal) * normal;
+            agent.angle = atan2(reflected.y, reflected.x);
+        } else if (in_bounds(target) && !is_obstacle(target)) {
+            agent.position = new_position;
+        } else if (in_bounds(target)) {
+            // Stay in place and reflect the heading off the obstacle, checking each axis on its own.
+            let from = vec2<i32>(floor(agent.position));
+            let blocked_x = is_obstacle(vec2<i32>(target.x, from.y));
+            let blocked_y = is_obstacle(vec2<i32>(from.x, target.y));
+            if (blocked_x) {
+                agent.angle = 3.1415927 - agent.angle;
+            }
+            if (blocked_y) {
+                agent.angle = -agent.angle;
+            }
+            if (!blocked_x && !blocked_y) {
+                // only the diagonal is blocked, a corner
+                agent.angle = agent.angle + 3.1415927;
+            }
+        } else if (settings.boundary_mode == BOUNDARY_WRAP) {
+            let wrapped = new_position - size * floor(new_position / size);
+            if (is_obstacle(wrap(vec2<i32>(floor(wrapped))))) {
+                agent.angle = agent.angle + 3.1415927;
+            } else {
+                agent.position = wrapped;
+            }
+        } else if (settings.boundary_mode == BOUNDARY_BOUNCE || settings.boundary_mode == BOUNDARY_CIRCLE) {
+            // Stay in place and reflect the heading off the walls that were hit.
+            if (new_position.x < 0.0 || new_position.x >= size.x) {
+                agent.angle = 3.1415927 - agent.angle;
+            }
+            if (new_position.y < 0.0 || new_position.y >= size.y) {
+                agent.angle = -agent.angle;
+            }
+        } else if (settings.kill_respawn != 0u) {
+            // BOUNDARY_KILL: start over like at startup, which keeps the population constant.
+            agent = respawn(agent, random);
+        } else {
+            agent.alive = 0u;
+            agent.position = PARKED_POSITION;
+        }
+        // agents that lived out `max_age` start over like killed ones, as a new generation
+        agent.age = agent.age + 1u;
+        if (settings.max_age != 0u && agent.age >= settings.max_age && agent.alive != 0u) {
+            agent = respawn(agent, hash(random));
+        }
+        if (settings.memory_length > 0u) {
+            agent.recent[agent.memory_cursor % settings.memory_length] = agent.position;
+            agent.memory_cursor = (agent.memory_cursor + 1u) % settings.memory_length;
+        }
+        agents_out[index] = agent;
+
+        // agents spawned inside a wall are stuck there, without leaving any trail
+        let deposit_position = vec2<i32>(agent.position);
+        // just killed agents leave nothing behind
+        if (agent.alive != 0u && !outside_arena(agent.position)) {
+            deposit(agent, agents_in[index].position);
+        }
+        // agents on the same texel race to eat, so each step removes at least one bite
+        if (settings.food_consumption > 0.0 && agent.alive != 0u && in_bounds(deposit_position)) {
+            let texel = texel_index(deposit_position);
+            food[texel] = max(food[texel] - settings.food_consumption * time_step, 0.0);
+        }
+        if (counts_density() && agent.alive != 0u && in_bounds(deposit_position)) {
+            atomicAdd(&agent_density[texel_index(deposit_position)], 1u);
+        }
+    }
 
     storageBarrier();
 }
+
+// Adds the agents of this frame's burst with random headings, written after the active agents
+// into the buffer the next update pass reads.
+@compute @workgroup_size(#{AGENT_WORKGROUP_SIZE}, 1, 1)
+fn spawn(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    if (invocation_id.x >= frame.burst_count) {
+        return;
+    }
+    let index = frame.active_count + invocation_id.x;
+    let random = hash(index ^ hash(frame.index ^ hash(settings.seed)));
+
+    var agent: Agent;
+    agent.position = frame.burst_position;
+    agent.angle = random_float(random) * 2.0 * 3.1415927;
+    agent.species = index % max(settings.species_count, 1u);
+    agent.speed = 1.0;
+    agent.memory_cursor = 0u;
+    for (var i = 0u; i < #{MAX_MEMORY}u; i = i + 1u) {
+        agent.recent[i] = agent.position;
+    }
+    agent.alive = 1u;
+    agent.age = 0u;
+    agents_out[index] = agent;
+}
+
+// Adds the anti-aliased deposits of this step to the trail map and zeroes them for the next.
+@compute @workgroup_size(#{TEXTURE_WORKGROUP_SIZE}, #{TEXTURE_WORKGROUP_SIZE}, 1)
+fn resolve(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let position = vec2<i32>(invocation_id.xy);
+    if (!in_bounds(position)) {
+        return;
+    }
+
+    let first = texel_index(position) * 4u;
+    var deposit = vec4<f32>(0.0);
+    for (var channel = 0u; channel < 4u; channel = channel + 1u) {
+        deposit[channel] = f32(atomicLoad(&deposits[first + channel])) / DEPOSIT_SCALE;
+        atomicStore(&deposits[first + channel], 0u);
+    }
+    let trail = textureLoad(trail_map, position) + deposit;
+    textureStore(trail_map, position, min(trail, vec4<f32>(settings.max_trail)));
+}
+
+// Zeroes the channels of decayed trail that fell below the evaporation floor.
+fn evaporate(trail: vec4<f32>) -> vec4<f32> {
+    return select(trail, vec4<f32>(0.0), trail < vec4<f32>(settings.evaporation_floor));
+}
+
+@compute @workgroup_size(#{TEXTURE_WORKGROUP_SIZE}, #{TEXTURE_WORKGROUP_SIZE}, 1)
+fn diffuse(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let position = vec2<i32>(invocation_id.xy);
+    if (!in_bounds(position)) {
+        return;
+    }
+
+    // walls never hold trail, so sensing them finds nothing
+    if (is_obstacle(position)) {
+        textureStore(next_trail_map, position, vec4<f32>(0.0));
+        return;
+    }
+
+    let original = textureLoad(trail_map, position);
+    // diffusion turned off, only decaying
+    if (settings.diffuse_rate == 0.0) {
+        textureStore(next_trail_map, position, evaporate(original * settings.decay_rate));
+        return;
+    }
+
+    // (2r + 1)^2 samples, so the radius is capped to keep a frame bounded
+    let radius = i32(min(settings.blur_radius, #{MAX_BLUR_RADIUS}u));
+    // a gaussian drops to about 14% at the edge of the neighbourhood
+    let sigma = max(f32(radius) * 0.5, 0.5);
+    var sum = vec4<f32>(0.0);
+    var weight_sum = 0.0;
+    for (var dy = -radius; dy <= radius; dy = dy + 1) {
+        for (var dx = -radius; dx <= radius; dx = dx + 1) {
+            var weight = 1.0;
+            if (settings.gaussian_blur != 0u) {
+                weight = exp(-f32(dx * dx + dy * dy) / (2.0 * sigma * sigma));
+            }
+            sum = sum + weight * textureLoad(trail_map, neighbour(position, vec2<i32>(dx, dy)));
+            weight_sum = weight_sum + weight;
+        }
+    }
+
+    let blurred = mix(original, sum / weight_sum, settings.diffuse_rate);
+    textureStore(next_trail_map, position, evaporate(blurred * settings.decay_rate));
+}
+
+// Unsharp masks the diffused trail back into the trail map the step started from, which then
+// holds the output of the step. Only dispatched when `sharpen_amount` is above 0.
+@compute @workgroup_size(#{TEXTURE_WORKGROUP_SIZE}, #{TEXTURE_WORKGROUP_SIZE}, 1)
+fn sharpen(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let position = vec2<i32>(invocation_id.xy);
+    if (!in_bounds(position)) {
+        return;
+    }
+
+    if (is_obstacle(position)) {
+        textureStore(trail_map, position, vec4<f32>(0.0));
+        return;
+    }
+
+    var sum = vec4<f32>(0.0);
+    for (var dy = -1; dy <= 1; dy = dy + 1) {
+        for (var dx = -1; dx <= 1; dx = dx + 1) {
+            sum = sum + textureLoad(next_trail_map, neighbour(position, vec2<i32>(dx, dy)));
+        }
+    }
+
+    let center = textureLoad(next_trail_map, position);
+    let sharpened = center + settings.sharpen_amount * (center - sum / 9.0);
+    let max_trail = vec4<f32>(settings.max_trail);
+    textureStore(trail_map, position, clamp(sharpened, vec4<f32>(0.0), max_trail));
+}
+
+// Maps a trail intensity to a glowing color, going from black through `color` to white.
+// Must match `color_ramp` in screenshot.rs.
+fn color_ramp(intensity: f32, color: vec3<f32>) -> vec3<f32> {
+    let t = clamp(intensity, 0.0, 1.0);
+    let glow = mix(vec3<f32>(0.0), color, smoothstep(0.0, 0.5, t));
+    return mix(glow, vec3<f32>(1.0), smoothstep(0.5, 1.0, t));
+}
+
+// The total trail on a texel, leaving out the heading of `COLOR_DIRECTION`.
+fn trail_intensity(trail: vec4<f32>) -> f32 {
+    if (settings.color_mode == COLOR_DIRECTION) {
+        return trail.r + trail.g;
+    }
+    return trail.r + trail.g + trail.b + trail.a;
+}
+
+// The hue from 0 to 1 of the average heading of the agents that left a trail, from the sums of
+// the sines and cosines of their headings. Must match `heading_hue` in palette.rs.
+fn heading_hue(sum_sin: f32, sum_cos: f32) -> f32 {
+    return fract(atan2(sum_sin, sum_cos) / (2.0 * 3.1415927) + 1.0);
+}
+
+// The fully saturated color of a hue from 0 to 1. Must match `hue_color` in palette.rs.
+fn hue_color(hue: f32) -> vec3<f32> {
+    let offsets = vec3<f32>(0.0, 2.0 / 3.0, 1.0 / 3.0);
+    return clamp(abs(fract(hue + offsets) * 6.0 - 3.0) - 1.0, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+// Agents on a texel shown at full heat by the density overlay.
+let DENSITY_SATURATION: f32 = 32.0;
+
+// Maps a density from 0 to 1 to the heat palette, black through red and yellow to white.
+fn heat_ramp(t: f32) -> vec3<f32> {
+    return clamp(vec3<f32>(3.0 * t, 3.0 * t - 1.0, 3.0 * t - 2.0), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+@compute @workgroup_size(#{TEXTURE_WORKGROUP_SIZE}, #{TEXTURE_WORKGROUP_SIZE}, 1)
+fn colorize(@builtin(global_invocation_id) invocation_id: vec3<u32>) {
+    let position = vec2<i32>(invocation_id.xy);
+    if (!in_bounds(position)) {
+        return;
+    }
+
+    let trail = textureLoad(next_trail_map, position);
+    var color = vec3<f32>(0.0);
+    if (settings.color_mode == COLOR_SPECIES) {
+        color = settings.background.rgb;
+        for (var i = 0u; i < min(settings.species_count, #{MAX_SPECIES}u); i = i + 1u) {
+            color = color + color_ramp(trail[i], settings.species[i].color.rgb);
+        }
+    } else if (settings.color_mode == COLOR_DIRECTION) {
+        // undo the shift of `deposit_fractions`
+        let intensity = trail_intensity(trail);
+        let hue = heading_hue(2.0 * trail.a - intensity, 2.0 * trail.b - intensity);
+        color = settings.background.rgb + color_ramp(intensity, hue_color(hue));
+    } else {
+        let intensity = clamp(trail_intensity(trail), 0.0, 1.0);
+        let size = i32(textureDimensions(palette).x);
+        color = textureLoad(palette, vec2<i32>(i32(intensity * f32(size - 1)), 0), 0).rgb;
+    }
+
+    color = color + FOOD_COLOR * min(food[texel_index(position)], 1.0);
+
+    if (frame.density_overlay != 0u) {
+        // the average over the substeps, on a log scale so single agents still show
+        let count = f32(atomicLoad(&agent_density[texel_index(position)]));
+        let agents = count / f32(max(settings.substeps, 1u));
+        let heat = clamp(log2(1.0 + agents) / log2(1.0 + DENSITY_SATURATION), 0.0, 1.0);
+        color = mix(color, heat_ramp(heat), min(heat * 4.0, 1.0));
+    }
+
+    // drawn once, the next update pass marks the sensors again
+    let marker = textureLoad(sensor_overlay, position);
+    if (marker.a > 0.0) {
+        color = mix(color, marker.rgb, marker.a);
+        textureStore(sensor_overlay, position, vec4<f32>(0.0));
+    }
+    textureStore(display, position, vec4<f32>(min(color, vec3<f32>(1.0)), 1.0));
+}
+
+let WORKGROUP_TEXELS: u32 = #{WORKGROUP_TEXELS}u;
+
+var<workgroup> reduce_min: array<f32, #{WORKGROUP_TEXELS}>;
+var<workgroup> reduce_max: array<f32, #{WORKGROUP_TEXELS}>;
+var<workgroup> reduce_sum: array<f32, #{WORKGROUP_TEXELS}>;
+
+// Reduces the intensity of the latest trail map to its min, max and sum over each workgroup.
+@compute @workgroup_size(#{TEXTURE_WORKGROUP_SIZE}, #{TEXTURE_WORKGROUP_SIZE}, 1)
+fn reduce(
+    @builtin(global_invocation_id) invocation_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    @builtin(workgroup_id) workgroup_id: vec3<u32>,
+    @builtin(num_workgroups) num_workgroups: vec3<u32>,
+) {
+    // every invocation has to reach the barriers, so the ones outside the map can't return early
+    let position = vec2<i32>(invocation_id.xy);
+    if (in_bounds(position)) {
+        let trail = textureLoad(next_trail_map, position);
+        let intensity = trail_intensity(trail);
+        reduce_min[local_index] = intensity;
+        reduce_max[local_index] = intensity;
+        reduce_sum[local_index] = intensity;
+    } else {
+        reduce_min[local_index] = 3.4e38;
+        reduce_max[local_index] = -3.4e38;
+        reduce_sum[local_index] = 0.0;
+    }
+    workgroupBarrier();
+
+    // halve the active entries until one is left, the workgroup size needn't be a power of two
+    var active = WORKGROUP_TEXELS;
+    loop {
+        if (active <= 1u) {
+            break;
+        }
+        let half = (active + 1u) / 2u;
+        let other = local_index + half;
+        if (other < active) {
+            reduce_min[local_index] = min(reduce_min[local_index], reduce_min[other]);
+            reduce_max[local_index] = max(reduce_max[local_index], reduce_max[other]);
+            reduce_sum[local_index] = reduce_sum[local_index] + reduce_sum[other];
+        }
+        workgroupBarrier();
+        active = half;
+    }
+
+    if (local_index == 0u) {
+        let index = workgroup_id.y * num_workgroups.x + workgroup_id.x;
+        trail_stats[index] = TrailStats(reduce_min[0], reduce_max[0], reduce_sum[0], 0.0);
+    }
+}
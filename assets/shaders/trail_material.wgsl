@@ -0,0 +1,29 @@
+// Shows the colorized trail map on the full-window quad, see `TrailMaterial` in display.rs.
+
+#import bevy_sprite::mesh2d_types
+#import bevy_sprite::mesh2d_view_bindings
+
+struct TrailMaterial {
+  gamma: f32,
+  brightness: f32,
+}
+
+@group(1) @binding(0)
+var<uniform> material: TrailMaterial;
+
+@group(1) @binding(1)
+var display_texture: texture_2d<f32>;
+
+@group(1) @binding(2)
+var display_sampler: sampler;
+
+struct FragmentInput {
+  #import bevy_sprite::mesh2d_vertex_output
+}
+
+@fragment
+fn fragment(in: FragmentInput) -> @location(0) vec4<f32> {
+    let color = textureSample(display_texture, display_sampler, in.uv).rgb * material.brightness;
+    let corrected = pow(max(color, vec3<f32>(0.0)), vec3<f32>(1.0 / max(material.gamma, 0.01)));
+    return vec4<f32>(corrected, 1.0);
+}
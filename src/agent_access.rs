@@ -0,0 +1,245 @@
+//! Reading and writing single agents from the main world through
+//! [`SlimeSimulation::get_agent`](crate::SlimeSimulation::get_agent) and
+//! [`SlimeSimulation::set_agent`](crate::SlimeSimulation::set_agent), without uploading or
+//! reading back the whole agent buffer.
+//!
+//! The requests of a frame are extracted as [`AgentRequests`]. Writes go into both agent
+//! buffers at the offset of the agent with `RenderQueue::write_buffer`, after a reset of the same
+//! frame so it doesn't overwrite them. Reads are copied out of the latest agent buffer by
+//! [`DisplayNode`](crate::nodes::DisplayNode) once the frame's steps have run and sent back
+//! through the [`AgentReadsChannel`]. One readback is in flight at a time, reads requested in the
+//! meantime wait for the next one.
+
+use std::sync::{Arc, Mutex};
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::ExtractResource,
+        render_resource::CommandEncoder,
+        renderer::{RenderDevice, RenderQueue},
+    },
+};
+
+use crate::{
+    burst::AgentBursts,
+    readback::{ReadbackStatus, StagingBuffers},
+    Agent, AgentBuffer, SimulationConfig,
+};
+
+const AGENT_SIZE: u64 = std::mem::size_of::<Agent>() as u64;
+
+/// Where agent `index` starts in an agent buffer, in bytes.
+pub(crate) fn agent_offset(index: u32) -> u64 {
+    index as u64 * AGENT_SIZE
+}
+
+/// Agents to write and to read back, queued through the [`SlimeSimulation`] this frame.
+///
+/// [`SlimeSimulation`]: crate::SlimeSimulation
+#[derive(Debug, Clone, Default, Resource, ExtractResource)]
+pub(crate) struct AgentRequests {
+    pub(crate) writes: Vec<(u32, Agent)>,
+    pub(crate) reads: Vec<u32>,
+}
+
+impl AgentRequests {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.writes.is_empty() && self.reads.is_empty()
+    }
+
+    /// The writes and reads of agents below `active_count`, warning about the others.
+    fn in_range(&self, active_count: u32) -> (Vec<(u32, Agent)>, Vec<u32>) {
+        let writes = self
+            .writes
+            .iter()
+            .copied()
+            .filter(|&(index, _)| {
+                let in_range = index < active_count;
+                if !in_range {
+                    warn!("Can't set agent {index}, there are only {active_count} agents");
+                }
+                in_range
+            })
+            .collect();
+        let reads = self
+            .reads
+            .iter()
+            .copied()
+            .filter(|&index| {
+                let in_range = index < active_count;
+                if !in_range {
+                    warn!("Can't read agent {index}, there are only {active_count} agents");
+                }
+                in_range
+            })
+            .collect();
+        (writes, reads)
+    }
+}
+
+/// Agents read back by the render world along with their indices, inserted into both worlds.
+#[derive(Debug, Clone, Default, Resource)]
+pub(crate) struct AgentReadsChannel(pub(crate) Arc<Mutex<Vec<(u32, Agent)>>>);
+
+/// Reads waiting for a readback, and the readback in flight.
+#[derive(Default, Resource)]
+pub(crate) struct AgentReads {
+    pending: Vec<u32>,
+    pub(crate) readback: Option<AgentReadback>,
+}
+
+/// Staging buffer the agents at `indices` are copied into, one after the other.
+pub(crate) struct AgentReadback {
+    indices: Vec<u32>,
+    pub(crate) staging: StagingBuffers,
+}
+
+impl AgentReadback {
+    /// Records the copies of the agents out of the buffer written by the last step.
+    pub(crate) fn copy(&self, encoder: &mut CommandEncoder, agents: &AgentBuffer) {
+        for (slot, &index) in self.indices.iter().enumerate() {
+            encoder.copy_buffer_to_buffer(
+                agents.latest(),
+                agent_offset(index),
+                self.staging.buffer(0),
+                agent_offset(slot as u32),
+                AGENT_SIZE,
+            );
+        }
+    }
+}
+
+/// Writes the agents set this frame and starts a readback of the ones asked for, skipping the
+/// indices past the active agents with a warning.
+pub(crate) fn apply_agent_requests(
+    requests: Res<AgentRequests>,
+    agent_buffer: Option<Res<AgentBuffer>>,
+    config: Res<SimulationConfig>,
+    bursts: Option<Res<AgentBursts>>,
+    mut reads: ResMut<AgentReads>,
+    render_queue: Res<RenderQueue>,
+    render_device: Res<RenderDevice>,
+) {
+    let Some(agent_buffer) = agent_buffer else {
+        return;
+    };
+    // the requests are only extracted on the frames they change
+    if requests.is_changed() {
+        let active_count = bursts.map_or(config.agent_count, |bursts| bursts.active_count);
+        let (writes, read_indices) = requests.in_range(active_count);
+        for (index, agent) in writes {
+            agent_buffer.write_from(&render_queue, index, &[agent]);
+        }
+        for index in read_indices {
+            if !reads.pending.contains(&index) {
+                reads.pending.push(index);
+            }
+        }
+    }
+
+    if reads.readback.is_none() && !reads.pending.is_empty() {
+        let indices = std::mem::take(&mut reads.pending);
+        let staging = StagingBuffers::new(
+            &render_device,
+            &[("agent_reads", indices.len() as u64 * AGENT_SIZE)],
+        );
+        reads.readback = Some(AgentReadback { indices, staging });
+    }
+}
+
+/// Sends the agents back to the main world once their readback is readable.
+pub(crate) fn map_agent_reads(mut reads: ResMut<AgentReads>, channel: Res<AgentReadsChannel>) {
+    let Some(readback) = &mut reads.readback else {
+        return;
+    };
+    match readback.staging.poll() {
+        ReadbackStatus::Pending => return,
+        ReadbackStatus::Failed => error!("Failed to read the agents back from the GPU"),
+        ReadbackStatus::Ready => {
+            let agents = readback
+                .staging
+                .read(0)
+                .chunks_exact(AGENT_SIZE as usize)
+                .map(bytemuck::pod_read_unaligned)
+                .collect::<Vec<Agent>>();
+            channel
+                .0
+                .lock()
+                .unwrap()
+                .extend(readback.indices.iter().copied().zip(agents));
+        }
+    }
+    reads.readback = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{simulation::sync_simulation, stats::TrailStats, SimState, SlimeSimulation};
+
+    #[test]
+    fn agents_past_the_active_ones_are_skipped() {
+        let agent = Agent::new(Vec2::ZERO, 0.);
+        let requests = AgentRequests {
+            writes: vec![(0, agent), (9, agent), (10, agent)],
+            reads: vec![3, 12, 9],
+        };
+        let (writes, reads) = requests.in_range(10);
+        let written: Vec<_> = writes.iter().map(|&(index, _)| index).collect();
+        assert_eq!(written, [0, 9]);
+        assert_eq!(reads, [3, 9]);
+    }
+
+    #[test]
+    fn agents_are_laid_out_one_after_the_other() {
+        assert_eq!(agent_offset(0), 0);
+        assert_eq!(agent_offset(5), 5 * std::mem::size_of::<Agent>() as u64);
+        assert_eq!(AGENT_SIZE as usize, std::mem::size_of::<Agent>());
+    }
+
+    #[test]
+    fn a_set_agent_reads_back_as_set() {
+        let mut world = World::new();
+        world.init_resource::<SlimeSimulation>();
+        world.init_resource::<SimState>();
+        world.init_resource::<TrailStats>();
+        world.init_resource::<AgentRequests>();
+        world.init_resource::<AgentReadsChannel>();
+        let mut sync = SystemStage::single(sync_simulation);
+
+        let agent = Agent::new(Vec2::new(12.5, 40.25), 1.5);
+        {
+            let mut simulation = world.resource_mut::<SlimeSimulation>();
+            simulation.set_agent(5, agent);
+            assert!(simulation.get_agent(5).is_none());
+        }
+        sync.run(&mut world);
+
+        // what the render world does with the requests, on the bytes of an agent buffer
+        let (writes, reads) = world.resource::<AgentRequests>().in_range(10);
+        let mut buffer = vec![0; 10 * AGENT_SIZE as usize];
+        let range = |index| agent_offset(index) as usize..agent_offset(index + 1) as usize;
+        for (index, agent) in writes {
+            buffer[range(index)].copy_from_slice(bytemuck::bytes_of(&agent));
+        }
+        let read_back: Vec<(u32, Agent)> = reads
+            .into_iter()
+            .map(|index| (index, bytemuck::pod_read_unaligned(&buffer[range(index)])))
+            .collect();
+        world
+            .resource::<AgentReadsChannel>()
+            .0
+            .lock()
+            .unwrap()
+            .extend(read_back);
+        sync.run(&mut world);
+
+        let read = world
+            .resource_mut::<SlimeSimulation>()
+            .get_agent(5)
+            .unwrap();
+        assert!((read.position - agent.position).length() < f32::EPSILON);
+        assert_eq!(read.angle, agent.angle);
+    }
+}
@@ -0,0 +1,161 @@
+//! Seeding the agents from a hand-written list, see
+//! [`SimulationSettings::agents_file`](crate::SimulationSettings::agents_file), and writing the
+//! agents out in the same format with I.
+//!
+//! A `.csv` file has an `x, y, angle, species` row per agent, with `#` starting a comment line.
+//! Any other file is read as RON, a list of `(x, y, angle, species)` tuples:
+//!
+//! ```text
+//! # x, y, angle, species
+//! 640, 360, 0, 0
+//! 660, 360, 3.1416, 1
+//! ```
+//!
+//! The list is read when the plugin is built, before anything is spawned, so it is loaded
+//! straight from the asset folder rather than through an asset loader.
+
+use std::{fmt::Write, path::Path, sync::Arc};
+
+use bevy::{asset::AssetServer, prelude::*, tasks::futures_lite::future};
+use serde::{Deserialize, Serialize};
+
+use crate::{Agent, AgentInit, MAX_AGENT_COUNT};
+
+/// File the agents are written to with I, loadable as an `agents_file`.
+pub(crate) const AGENT_DUMP_FILE: &str = "agents.csv";
+
+/// One agent of an agents file.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct AgentRow(f32, f32, f32, u32);
+
+impl AgentRow {
+    fn agent(self) -> Agent {
+        let AgentRow(x, y, angle, species) = self;
+        Agent {
+            species,
+            ..Agent::new(Vec2::new(x, y), angle)
+        }
+    }
+}
+
+/// Reads the agents file at `path`, relative to the asset folder.
+pub(crate) fn load_agents(asset_server: &AssetServer, path: &str) -> Result<Vec<AgentRow>, String> {
+    let bytes = future::block_on(asset_server.asset_io().load_path(Path::new(path)))
+        .map_err(|error| error.to_string())?;
+    let text = String::from_utf8(bytes).map_err(|_| "isn't UTF-8".to_string())?;
+    let rows = if path.ends_with(".csv") {
+        parse_csv(&text)?
+    } else {
+        ron::from_str(&text).map_err(|error| error.to_string())?
+    };
+    if rows.is_empty() {
+        return Err("has no agents".to_string());
+    }
+    if rows.len() > MAX_AGENT_COUNT as usize {
+        return Err(format!(
+            "has {} agents, at most {MAX_AGENT_COUNT} are supported",
+            rows.len()
+        ));
+    }
+    Ok(rows)
+}
+
+fn parse_csv(text: &str) -> Result<Vec<AgentRow>, String> {
+    let mut rows = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let error = |message: &str| format!("line {}: {message}, in {line:?}", index + 1);
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let &[x, y, angle, species] = fields.as_slice() else {
+            return Err(error("expected 4 fields, x, y, angle and species"));
+        };
+        let float = |field: &str, name: &str| {
+            field
+                .parse::<f32>()
+                .map_err(|_| error(&format!("invalid {name} {field:?}")))
+        };
+        let species = species
+            .parse()
+            .map_err(|_| error(&format!("invalid species {species:?}")))?;
+        rows.push(AgentRow(
+            float(x, "x")?,
+            float(y, "y")?,
+            float(angle, "angle")?,
+            species,
+        ));
+    }
+    Ok(rows)
+}
+
+/// Places agent `i` as row `i`. Agents added later by raising the agent count repeat the list
+/// from the start.
+pub(crate) fn agent_init(rows: Vec<AgentRow>) -> AgentInit {
+    Arc::new(move |i, _| rows[i as usize % rows.len()].agent())
+}
+
+/// The living agents as a `.csv` agents file.
+pub(crate) fn to_csv(agents: &[Agent]) -> String {
+    let mut csv = String::from("# x, y, angle, species\n");
+    for agent in agents.iter().filter(|agent| agent.alive != 0) {
+        let _ = writeln!(
+            csv,
+            "{}, {}, {}, {}",
+            agent.position.x, agent.position.y, agent.angle, agent.species
+        );
+    }
+    csv
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn agents_round_trip_through_csv() {
+        let agents = [
+            Agent::new(Vec2::new(640., 360.), 0.),
+            Agent {
+                species: 1,
+                ..Agent::new(Vec2::new(12.25, 700.5), 3.1416)
+            },
+            Agent {
+                species: 3,
+                ..Agent::new(Vec2::new(0.1, 1e-3), -1.7)
+            },
+        ];
+        let rows = parse_csv(&to_csv(&agents)).unwrap();
+        let expected: Vec<_> = agents
+            .iter()
+            .map(|agent| {
+                let position = agent.position;
+                AgentRow(position.x, position.y, agent.angle, agent.species)
+            })
+            .collect();
+        assert_eq!(rows, expected);
+    }
+
+    #[test]
+    fn dead_agents_are_not_written() {
+        let agents = [
+            Agent::new(Vec2::ONE, 0.),
+            Agent {
+                alive: 0,
+                ..Agent::new(Vec2::ZERO, 0.)
+            },
+        ];
+        assert_eq!(parse_csv(&to_csv(&agents)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn malformed_rows_name_their_line() {
+        let error = parse_csv("# x, y, angle, species\n1, 2, x, 0\n").unwrap_err();
+        assert!(error.starts_with("line 2: invalid angle"), "{error}");
+        let error = parse_csv("1, 2, 0, 0\n\n1, 2, 0\n").unwrap_err();
+        assert!(error.starts_with("line 3: expected 4 fields"), "{error}");
+        let error = parse_csv("1, 2, 0, -1\n").unwrap_err();
+        assert!(error.starts_with("line 1: invalid species"), "{error}");
+    }
+}
@@ -0,0 +1,214 @@
+//! Animating settings over time, like oscillating `sensor_angle` or ramping up `decay_rate`, set
+//! up with the `animations` of the [`SimulationSettings`].
+//!
+//! Every frame the simulation advances, [`animate_settings`] evaluates the [`ParamAnimation`]
+//! entries at the time simulated so far and writes them into the settings asset, which uploads
+//! them to the uniform buffer like any other edit. Paused, the values hold.
+//!
+//! An animated field belongs to its animation: edits to it, from the egui panel or the `.slime`
+//! file, last until the next frame that advances. Fields without an animation keep their edits.
+
+use std::f32::consts::TAU;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{SimClock, SimState, SimulationSettings, Slime, SlimeHandle};
+
+/// Shape of an [`Animation`] over one period, from its `min` at the start.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Waveform {
+    /// Eases from `min` up to `max` half way through the period and back down.
+    Sine,
+    /// Goes from `min` up to `max` half way through the period and back down at a constant rate.
+    Triangle,
+    /// Goes from `min` to `max` over the first period and stays at `max` after that.
+    Linear,
+}
+
+impl Waveform {
+    /// Where between `min` and `max` the animation is `periods` periods in, from 0 to 1.
+    fn evaluate(self, periods: f32) -> f32 {
+        match self {
+            Self::Sine => 0.5 - 0.5 * (periods * TAU).cos(),
+            Self::Triangle => 1. - (2. * periods.fract() - 1.).abs(),
+            Self::Linear => periods.clamp(0., 1.),
+        }
+    }
+}
+
+/// A setting an [`Animation`] can drive.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AnimatedField {
+    MoveSpeed,
+    TurnSpeed,
+    SensorAngle,
+    SensorDistance,
+    SenseWeight,
+    CrowdAvoidance,
+    NoiseStrength,
+    NoiseScale,
+    DepositAmount,
+    MemoryPenalty,
+    DecayRate,
+    DiffuseRate,
+    SharpenAmount,
+    TimeScale,
+    FoodAttraction,
+    Gamma,
+    Brightness,
+}
+
+impl AnimatedField {
+    fn value_mut(self, settings: &mut SimulationSettings) -> &mut f32 {
+        match self {
+            Self::MoveSpeed => &mut settings.move_speed,
+            Self::TurnSpeed => &mut settings.turn_speed,
+            Self::SensorAngle => &mut settings.sensor_angle,
+            Self::SensorDistance => &mut settings.sensor_distance,
+            Self::SenseWeight => &mut settings.sense_weight,
+            Self::CrowdAvoidance => &mut settings.crowd_avoidance,
+            Self::NoiseStrength => &mut settings.noise_strength,
+            Self::NoiseScale => &mut settings.noise_scale,
+            Self::DepositAmount => &mut settings.deposit_amount,
+            Self::MemoryPenalty => &mut settings.memory_penalty,
+            Self::DecayRate => &mut settings.decay_rate,
+            Self::DiffuseRate => &mut settings.diffuse_rate,
+            Self::SharpenAmount => &mut settings.sharpen_amount,
+            Self::TimeScale => &mut settings.time_scale,
+            Self::FoodAttraction => &mut settings.food_attraction,
+            Self::Gamma => &mut settings.gamma,
+            Self::Brightness => &mut settings.brightness,
+        }
+    }
+}
+
+/// Drives `field` between `min` and `max` following `waveform`, repeating every `period`
+/// seconds of simulated time.
+///
+/// ```ron
+/// animations: [(field: SensorAngle, waveform: Sine, min: 0.3, max: 1.2, period: 20.)],
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Animation {
+    pub field: AnimatedField,
+    pub waveform: Waveform,
+    pub min: f32,
+    pub max: f32,
+    /// Seconds, above 0.
+    pub period: f32,
+}
+
+impl Animation {
+    /// The value of the field `elapsed` seconds in.
+    pub fn value(&self, elapsed: f32) -> f32 {
+        let t = self.waveform.evaluate(elapsed / self.period);
+        self.min + (self.max - self.min) * t
+    }
+}
+
+/// The animations applied to the settings every frame, taken from
+/// [`SimulationSettings::animations`] at startup and when the `.slime` file changes.
+///
+/// Later entries win when several animate the same field.
+#[derive(Debug, Clone, Default, Resource)]
+pub struct ParamAnimation(pub Vec<Animation>);
+
+/// Advances the animation time while the simulation runs and writes the animated values into
+/// the settings.
+pub(crate) fn animate_settings(
+    animation: Res<ParamAnimation>,
+    state: Res<SimState>,
+    clock: Res<SimClock>,
+    mut elapsed: Local<f32>,
+    slime: Option<Res<SlimeHandle>>,
+    mut slimes: ResMut<Assets<Slime>>,
+) {
+    if animation.0.is_empty() || !state.advances() {
+        return;
+    }
+    *elapsed += clock.elapsed();
+    let Some(slime) = slime else {
+        return;
+    };
+    let Some(settings) = slimes.get(&slime.0) else {
+        return;
+    };
+    let mut animated = settings.0.clone();
+    let mut changed = false;
+    for entry in &animation.0 {
+        let value = entry.value(*elapsed);
+        let field = entry.field.value_mut(&mut animated);
+        changed |= *field != value;
+        *field = value;
+    }
+
+    // only touch the asset when a value changes, so it isn't re-extracted for nothing
+    if changed {
+        if let Some(settings) = slimes.get_mut(&slime.0) {
+            settings.0 = animated;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{actual} isn't {expected}"
+        );
+    }
+
+    #[test]
+    fn sine_eases_up_and_down() {
+        for (periods, expected) in [
+            (0., 0.),
+            (0.25, 0.5),
+            (0.5, 1.),
+            (0.75, 0.5),
+            (1., 0.),
+            (1.5, 1.),
+        ] {
+            assert_near(Waveform::Sine.evaluate(periods), expected);
+        }
+    }
+
+    #[test]
+    fn triangle_ramps_up_and_down() {
+        for (periods, expected) in [
+            (0., 0.),
+            (0.25, 0.5),
+            (0.5, 1.),
+            (0.75, 0.5),
+            (1., 0.),
+            (2.5, 1.),
+        ] {
+            assert_near(Waveform::Triangle.evaluate(periods), expected);
+        }
+    }
+
+    #[test]
+    fn linear_ramps_once_and_holds() {
+        for (periods, expected) in [(-1., 0.), (0., 0.), (0.25, 0.25), (1., 1.), (3., 1.)] {
+            assert_near(Waveform::Linear.evaluate(periods), expected);
+        }
+    }
+
+    #[test]
+    fn animations_scale_to_their_range() {
+        let animation = Animation {
+            field: AnimatedField::SensorAngle,
+            waveform: Waveform::Triangle,
+            min: 2.,
+            max: 4.,
+            period: 10.,
+        };
+        assert_near(animation.value(0.), 2.);
+        assert_near(animation.value(2.5), 3.);
+        assert_near(animation.value(5.), 4.);
+        assert_near(animation.value(10.), 2.);
+    }
+}
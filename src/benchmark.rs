@@ -0,0 +1,156 @@
+//! Measures how long the compute passes take on the GPU, enabled by the `benchmark` feature.
+//!
+//! Timestamps are written before the passes of [`UpdateNode`](crate::nodes::UpdateNode) and
+//! after those of [`DisplayNode`](crate::nodes::DisplayNode), read back one frame at a time,
+//! logging the average every [`SAMPLES_PER_REPORT`] samples. Run with different
+//! `workgroup_size` and `agent_workgroup_size` settings to compare them.
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{Buffer, BufferDescriptor, BufferUsages, CommandEncoder},
+        renderer::{RenderDevice, RenderQueue},
+        RenderApp, RenderStage,
+    },
+};
+
+use crate::{
+    readback::{ReadbackStatus, StagingBuffers},
+    SimulationConfig,
+};
+
+const SAMPLES_PER_REPORT: u32 = 120;
+
+/// Size of the two resolved `u64` timestamps.
+const TIMESTAMPS_SIZE: u64 = 2 * std::mem::size_of::<u64>() as u64;
+
+pub(crate) struct BenchmarkPlugin;
+
+impl Plugin for BenchmarkPlugin {
+    fn build(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        let features = render_app.world.resource::<RenderDevice>().features();
+        if !features.contains(wgpu::Features::TIMESTAMP_QUERY) {
+            warn!("This GPU doesn't support timestamp queries, dispatch times won't be measured");
+            return;
+        }
+        render_app
+            .init_resource::<DispatchTimer>()
+            .add_system_to_stage(RenderStage::Prepare, prepare_dispatch_timer)
+            .add_system_to_stage(RenderStage::Cleanup, map_dispatch_timer);
+    }
+}
+
+#[derive(Resource)]
+pub(crate) struct DispatchTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    /// Readback of the timestamps of a single frame.
+    pub(crate) staging: Option<StagingBuffers>,
+    total_nanoseconds: f64,
+    samples: u32,
+}
+
+impl FromWorld for DispatchTimer {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let query_set = render_device
+            .wgpu_device()
+            .create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("dispatch_timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: 2,
+            });
+        let resolve_buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("dispatch_timestamps_resolve"),
+            size: TIMESTAMPS_SIZE,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        Self {
+            query_set,
+            resolve_buffer,
+            period: world.resource::<RenderQueue>().get_timestamp_period(),
+            staging: None,
+            total_nanoseconds: 0.,
+            samples: 0,
+        }
+    }
+}
+
+impl DispatchTimer {
+    /// Whether the node should time its compute pass this frame.
+    pub(crate) fn timing(&self) -> bool {
+        self.staging
+            .as_ref()
+            .map_or(false, |staging| staging.copy_pending())
+    }
+
+    /// Records the timestamp before the compute pass.
+    pub(crate) fn begin(&self, encoder: &mut CommandEncoder) {
+        encoder.write_timestamp(&self.query_set, 0);
+    }
+
+    /// Records the timestamp after the compute pass and copies both into the staging buffer.
+    pub(crate) fn end(&self, encoder: &mut CommandEncoder) {
+        let Some(staging) = &self.staging else {
+            return;
+        };
+        encoder.write_timestamp(&self.query_set, 1);
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            staging.buffer(0),
+            0,
+            TIMESTAMPS_SIZE,
+        );
+    }
+}
+
+/// Starts timing the next frame once the previous measurement has been read.
+fn prepare_dispatch_timer(mut timer: ResMut<DispatchTimer>, render_device: Res<RenderDevice>) {
+    if timer.staging.is_none() {
+        timer.staging = Some(StagingBuffers::new(
+            &render_device,
+            &[("dispatch_timestamps_staging", TIMESTAMPS_SIZE)],
+        ));
+    }
+}
+
+fn map_dispatch_timer(mut timer: ResMut<DispatchTimer>, config: Res<SimulationConfig>) {
+    let Some(staging) = &mut timer.staging else {
+        return;
+    };
+    let timestamps = match staging.poll() {
+        ReadbackStatus::Pending => return,
+        ReadbackStatus::Failed => {
+            timer.staging = None;
+            return;
+        }
+        ReadbackStatus::Ready => staging.read(0),
+    };
+    timer.staging = None;
+
+    let start = u64::from_le_bytes(timestamps[..8].try_into().unwrap());
+    let end = u64::from_le_bytes(timestamps[8..].try_into().unwrap());
+    // frames where the node didn't get to run leave both timestamps at zero
+    if end <= start {
+        return;
+    }
+    timer.total_nanoseconds += (end - start) as f64 * timer.period as f64;
+    timer.samples += 1;
+
+    if timer.samples == SAMPLES_PER_REPORT {
+        info!(
+            "Workgroup sizes {0}x{0} and {1}: compute passes took {2:.3} ms on average",
+            config.texture_workgroup_size,
+            config.agent_workgroup_size,
+            timer.total_nanoseconds / timer.samples as f64 / 1_000_000.
+        );
+        timer.total_nanoseconds = 0.;
+        timer.samples = 0;
+    }
+}
@@ -0,0 +1,89 @@
+//! Spawn bursts, shift-clicking to add live agents at the cursor with random headings.
+//!
+//! The agent buffers have room for [`SimulationSettings::burst_headroom`] agents on top of
+//! `agent_count`, which the update pass skips until a burst fills them. Only the first
+//! [`AgentBursts::active_count`] agents are moved, and a burst's agents are written after those
+//! by the `spawn` pass of simple.wgsl, so nothing has to be copied back to the CPU. Resets and
+//! changes to the agent count drop every agent added by bursts, and snapshots leave them out.
+//!
+//! [`SimulationSettings::burst_headroom`]: crate::SimulationSettings::burst_headroom
+
+use bevy::{prelude::*, render::extract_resource::ExtractResource};
+
+use crate::{camera::CameraView, cursor_on_trail, SimState, SimulationConfig};
+
+/// Agents added by a single click.
+const BURST_SIZE: u32 = 500;
+
+/// Agents the update pass moves this frame, and the burst spawned after them.
+#[derive(Debug, Clone, Default, Resource, ExtractResource)]
+pub(crate) struct AgentBursts {
+    /// Between `agent_count` and [`SimulationConfig::agent_capacity`].
+    pub(crate) active_count: u32,
+    /// Spawned at the end of this frame, counted as active from the next one.
+    pub(crate) burst: Option<Burst>,
+    /// Position on the trail map of a burst asked for this frame.
+    requested: Option<Vec2>,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct Burst {
+    /// Position on the trail map every agent of the burst starts at.
+    pub(crate) position: Vec2,
+    pub(crate) count: u32,
+}
+
+/// Asks for a burst under the cursor when the left mouse button is clicked with Shift held.
+pub(crate) fn burst_controls(
+    windows: Res<Windows>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    config: Res<SimulationConfig>,
+    cameras: CameraView,
+    mut bursts: ResMut<AgentBursts>,
+) {
+    if !keys.any_pressed([KeyCode::LShift, KeyCode::RShift])
+        || !mouse_buttons.just_pressed(MouseButton::Left)
+    {
+        return;
+    }
+    if let Some(position) = windows
+        .get_primary()
+        .and_then(|window| cursor_on_trail(window, &cameras, &config))
+    {
+        bursts.requested = Some(position);
+    }
+}
+
+/// Counts last frame's burst as active, goes back to `agent_count` agents on resets and
+/// resizes, and turns a requested burst into one that fits in the agent buffers.
+///
+/// Runs in `PostUpdate`, once every system that can request a reset has run.
+pub(crate) fn apply_bursts(
+    mut bursts: ResMut<AgentBursts>,
+    config: Res<SimulationConfig>,
+    state: Res<SimState>,
+) {
+    // only touch the resource when something changes, so it isn't re-extracted every frame
+    if let Some(burst) = bursts.burst {
+        bursts.active_count += burst.count;
+        bursts.burst = None;
+    }
+    if (state.reset || config.is_changed()) && bursts.active_count != config.agent_count {
+        bursts.active_count = config.agent_count;
+    }
+
+    let Some(position) = bursts.requested else {
+        return;
+    };
+    bursts.requested = None;
+    let count = BURST_SIZE.min(config.agent_capacity() - bursts.active_count);
+    if count == 0 {
+        info!(
+            "The agent buffers are full with {} agents, no more can be spawned",
+            bursts.active_count
+        );
+        return;
+    }
+    bursts.burst = Some(Burst { position, count });
+}
@@ -0,0 +1,95 @@
+//! Zooming into the trail map with the scroll wheel and panning it by dragging with the middle
+//! mouse button.
+//!
+//! Only the camera moves, so the view is kept across resets and resizes of the trail map.
+
+use bevy::{
+    input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel},
+    prelude::*,
+};
+
+use crate::instances::SimulationCamera;
+
+/// Closest the camera zooms in, as the scale of its projection.
+const MIN_SCALE: f32 = 1. / 32.;
+/// Furthest the camera zooms out, showing the whole letterboxed trail map.
+const MAX_SCALE: f32 = 1.;
+/// Factor a line of scrolling zooms in or out by.
+const ZOOM_PER_LINE: f32 = 1.1;
+/// Pixels of scrolling on a touchpad counted as one line.
+const PIXELS_PER_LINE: f32 = 32.;
+
+/// Position and zoom of the 2D camera, read by everything mapping the cursor onto the trail map.
+pub(crate) type CameraView<'w, 's> = Query<
+    'w,
+    's,
+    (&'static Transform, &'static OrthographicProjection),
+    (With<Camera2d>, Without<SimulationCamera>),
+>;
+
+/// Zooming with the scroll wheel and panning with the middle mouse button.
+pub struct CameraControllerPlugin;
+
+impl Plugin for CameraControllerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(camera_controller);
+    }
+}
+
+/// Zooms around the point under the cursor on scroll and pans while the middle button is held.
+fn camera_controller(
+    windows: Res<Windows>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    mut wheel: EventReader<MouseWheel>,
+    mut motion: EventReader<MouseMotion>,
+    mut cameras: Query<
+        (&mut Transform, &mut OrthographicProjection),
+        (With<Camera2d>, Without<SimulationCamera>),
+    >,
+) {
+    let lines: f32 = wheel
+        .iter()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / PIXELS_PER_LINE,
+        })
+        .sum();
+    let dragged: Vec2 = motion.iter().map(|event| event.delta).sum();
+    let panning = mouse_buttons.pressed(MouseButton::Middle);
+    if lines == 0. && !(panning && dragged != Vec2::ZERO) {
+        return;
+    }
+    let Some(window) = windows.get_primary() else {
+        return;
+    };
+
+    for (mut transform, mut projection) in &mut cameras {
+        if panning {
+            // motion is in window pixels with y pointing down, the world has y pointing up
+            transform.translation.x -= dragged.x * projection.scale;
+            transform.translation.y += dragged.y * projection.scale;
+        }
+        if lines != 0. {
+            let scale = (projection.scale / ZOOM_PER_LINE.powf(lines)).clamp(MIN_SCALE, MAX_SCALE);
+            // keep the point under the cursor in place
+            if let Some(cursor) = window.cursor_position() {
+                let from_center = cursor - Vec2::new(window.width(), window.height()) / 2.;
+                let shift = from_center * (projection.scale - scale);
+                transform.translation += shift.extend(0.);
+            }
+            projection.scale = scale;
+        }
+    }
+}
+
+/// Position in world space under the cursor, the trail sprite being centered on the origin.
+pub(crate) fn cursor_world_position(window: &Window, cameras: &CameraView) -> Option<Vec2> {
+    let cursor = window.cursor_position()?;
+    let from_center = cursor - Vec2::new(window.width(), window.height()) / 2.;
+    Some(match cameras.get_single() {
+        Ok((transform, projection)) => {
+            transform.translation.truncate() + from_center * projection.scale
+        }
+        Err(_) => from_center,
+    })
+}
@@ -0,0 +1,148 @@
+//! Command line arguments, parsed by hand since there are only a few.
+
+use std::path::{Path, PathBuf};
+
+use crate::{record, SimulationSettings, SlimeComputePlugin};
+
+pub(crate) const USAGE: &str = "\
+usage: slime [options]
+
+  --config <path>        load the settings from a .slime file
+  --agent-count <N>      number of agents, overriding the settings
+  --width <N>            width of the trail map, overriding the settings
+  --height <N>           height of the trail map, overriding the settings
+  --seed <N>             random seed, overriding the settings
+  --headless             run without a window and print a checksum
+  --steps <N>            steps of a headless run or check, 1000 by default
+  --check-cpu            run headless, comparing the agents with the CPU every step, needs
+                         the sim_cpu feature
+  --compare <path>       run the settings of another .slime file in a second window, with
+                         the same overrides
+  --record <dir>         write the colorized trail map to numbered PNGs in a directory
+  --record-every <N>     record every Nth step, 1 by default
+  --fps-cap <N>          start capped at N frames a second instead of vsync, F7 cycles
+                         through vsync, no vsync and the cap
+  --lockstep             run one step of 1/60 s every frame, however long frames take, for
+                         benchmarks
+  --print-settings       print the settings with the overrides applied as a .slime file
+                         and exit
+  --help                 print this message";
+
+/// Steps of a headless run or check when `--steps` isn't given.
+const DEFAULT_STEPS: u32 = 1000;
+
+#[derive(Debug, Default)]
+pub(crate) struct Args {
+    pub help: bool,
+    pub print_settings: bool,
+    pub lockstep: bool,
+    /// Steps to run if `--headless` was passed.
+    pub headless_steps: Option<u32>,
+    /// Steps to compare if `--check-cpu` was passed, only accepted with the `sim_cpu` feature.
+    pub check_cpu_steps: Option<u32>,
+    pub config: Option<PathBuf>,
+    pub agent_count: Option<u32>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub seed: Option<u64>,
+    /// Settings file to run next to these ones with `--compare`.
+    pub compare: Option<PathBuf>,
+    /// Where to write frames if `--record` was passed.
+    pub record: Option<record::Recording>,
+    /// Frame rate to start capped at if `--fps-cap` was passed.
+    pub fps_cap: Option<u32>,
+}
+
+pub(crate) fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut parsed = Args::default();
+    let mut headless = false;
+    let mut check_cpu = false;
+    let mut steps = DEFAULT_STEPS;
+    let mut record_dir = None;
+    let mut record_every = 1;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+        match arg.as_str() {
+            "--help" | "-h" => parsed.help = true,
+            "--print-settings" => parsed.print_settings = true,
+            "--lockstep" => parsed.lockstep = true,
+            "--headless" => headless = true,
+            "--check-cpu" if cfg!(feature = "sim_cpu") => check_cpu = true,
+            "--steps" => steps = parse_count(&arg, value()?)?,
+            "--config" => parsed.config = Some(value()?.into()),
+            "--compare" => parsed.compare = Some(value()?.into()),
+            "--record" => record_dir = Some(value()?.into()),
+            "--record-every" => record_every = parse_count(&arg, value()?)?,
+            "--fps-cap" => parsed.fps_cap = Some(parse_count(&arg, value()?)?),
+            "--agent-count" => parsed.agent_count = Some(parse_count(&arg, value()?)?),
+            "--width" => parsed.width = Some(parse_count(&arg, value()?)?),
+            "--height" => parsed.height = Some(parse_count(&arg, value()?)?),
+            "--seed" => {
+                let value = value()?;
+                parsed.seed = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid {arg} {value:?}"))?,
+                );
+            }
+            _ => return Err(format!("unknown argument {arg:?}")),
+        }
+    }
+    parsed.headless_steps = headless.then_some(steps);
+    parsed.check_cpu_steps = check_cpu.then_some(steps);
+    parsed.record = record_dir.map(|dir| record::Recording {
+        dir,
+        every: record_every,
+    });
+    Ok(parsed)
+}
+
+/// Parses a value that has to be at least 1.
+fn parse_count(arg: &str, value: String) -> Result<u32, String> {
+    value
+        .parse()
+        .ok()
+        .filter(|&count| count > 0)
+        .ok_or_else(|| format!("invalid {arg} {value:?}"))
+}
+
+impl Args {
+    /// The plugin simulating the `--config` file, or the default settings, with the overrides
+    /// applied.
+    pub(crate) fn plugin(&self) -> Result<SlimeComputePlugin, String> {
+        let plugin =
+            SlimeComputePlugin::new().with_settings(self.settings(self.config.as_deref())?);
+        Ok(if self.lockstep {
+            plugin.with_lockstep()
+        } else {
+            plugin
+        })
+    }
+
+    /// The settings in the file at `path`, or the default settings, with the overrides applied.
+    pub(crate) fn settings(&self, path: Option<&Path>) -> Result<SimulationSettings, String> {
+        let mut settings = match path {
+            Some(path) => {
+                let bytes = std::fs::read(path)
+                    .map_err(|error| format!("can't read {}: {error}", path.display()))?;
+                ron::de::from_bytes::<SimulationSettings>(&bytes)
+                    .map_err(|error| format!("can't parse {}: {error}", path.display()))?
+            }
+            None => SimulationSettings::default(),
+        };
+        if let Some(agent_count) = self.agent_count {
+            settings.agent_count = agent_count;
+        }
+        if let Some(width) = self.width {
+            settings.sim_width = width;
+        }
+        if let Some(height) = self.height {
+            settings.sim_height = height;
+        }
+        if let Some(seed) = self.seed {
+            settings.seed = seed;
+        }
+        Ok(settings)
+    }
+}
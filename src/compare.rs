@@ -0,0 +1,108 @@
+//! Comparing two sets of settings side by side, started with `--compare <path>`.
+//!
+//! The second set runs as a simulation added to [`SlimeSimulations`], in a window of its own
+//! placed next to the first one, both titled after their settings files. Pausing, resetting and
+//! clearing the first simulation with the keyboard does the same to the second one. Closing the
+//! first window closes the comparison too.
+
+use std::path::Path;
+
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    window::{PresentMode, WindowClosed, WindowId, WindowPosition},
+};
+
+use crate::{SimId, SimState, SimulationSettings, SlimeSimulations, HEIGHT, WIDTH};
+
+/// Half of the screen a compared window is placed on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Side {
+    Left,
+    Right,
+}
+
+impl Side {
+    /// Where the window goes, the right one just past the left one.
+    pub(crate) fn position(self) -> WindowPosition {
+        match self {
+            Self::Left => WindowPosition::At(Vec2::ZERO),
+            Self::Right => WindowPosition::At(Vec2::new(WIDTH, 0.)),
+        }
+    }
+}
+
+/// Title of a compared window, naming the settings file it runs.
+pub(crate) fn title(config: Option<&Path>) -> String {
+    let name = config
+        .and_then(Path::file_name)
+        .map_or("default settings".into(), |name| name.to_string_lossy());
+    format!("Slime Simulation: {name}")
+}
+
+/// The simulation compared against.
+#[derive(Resource)]
+struct Comparison(SimId);
+
+/// Simulates `settings`, loaded from `config`, on the right of the screen next to the
+/// simulation of `app`, which needs the [`SlimeComputePlugin`](crate::SlimeComputePlugin).
+pub(crate) fn add_comparison(
+    app: &mut App,
+    settings: SimulationSettings,
+    config: &Path,
+    present_mode: PresentMode,
+) {
+    let window = WindowDescriptor {
+        title: title(Some(config)),
+        width: WIDTH,
+        height: HEIGHT,
+        position: Side::Right.position(),
+        present_mode,
+        ..default()
+    };
+    let id = app
+        .world
+        .resource_mut::<SlimeSimulations>()
+        .add_simulation_in(settings, window);
+    app.insert_resource(Comparison(id))
+        .add_system(mirror_controls.after(crate::sim_controls))
+        .add_system(exit_with_first_window);
+}
+
+/// Pauses, resets and clears the comparison along with the first simulation.
+fn mirror_controls(
+    state: Res<SimState>,
+    comparison: Res<Comparison>,
+    mut simulations: ResMut<SlimeSimulations>,
+) {
+    if !state.is_changed() {
+        return;
+    }
+    // gone once its window is closed
+    let Some(simulation) = simulations.get_mut(comparison.0) else {
+        return;
+    };
+    if simulation.is_paused() == state.running {
+        simulation.set_paused(!state.running);
+    }
+    if state.reset {
+        simulation.full_reset();
+    }
+    if state.clear_trail {
+        simulation.clear_trail();
+    }
+}
+
+/// Exits when the first window is closed, which otherwise leaves the comparison running on its
+/// own.
+fn exit_with_first_window(
+    mut closed_events: EventReader<WindowClosed>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if closed_events
+        .iter()
+        .any(|event| event.id == WindowId::primary())
+    {
+        exit.send(AppExit);
+    }
+}
@@ -0,0 +1,262 @@
+//! Checking the CPU copy of the update pass in [`sim_cpu`](crate::sim_cpu) against the shader,
+//! enabled by the `sim_cpu` feature.
+//!
+//! Started with `--check-cpu --steps <N>`. A tiny simulation runs headless, one step a frame, and
+//! after every step the agents and the trail map are read back. From the second step on each
+//! agent is also stepped on the CPU from where the GPU had it one step earlier, sensing the trail
+//! map the GPU sensed, and agents ending up more than [`TOLERANCE`] pixels apart are reported.
+//! The process exits with an error if any were, or if the pipelines fail to compile or are
+//! still compiling after [`READY_TIMEOUT`]. `cargo test --features sim_cpu -- --ignored` runs
+//! the same check on a GPU through [`check_cpu`].
+//!
+//! The settings are the ones given otherwise, but deposits are anti-aliased so the update pass
+//! never writes the trail map it senses, which would make what an agent senses depend on the
+//! order the agents run in. Obstacles, food, crowd avoidance, noise, bursts and substeps, none
+//! of which the check gives the CPU copy, are turned off.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    render::{RenderApp, RenderStage},
+};
+
+use crate::{
+    headless::{self, HeadlessRun},
+    nodes,
+    readback::{read_agents, read_trail},
+    sim_cpu,
+    status::SlimePipelineStatus,
+    Agent, BoundaryMode, FrameUniform, SimulationConfig, SimulationSettings, SlimeComputePlugin,
+};
+
+/// Agents simulated by the check.
+const CHECK_AGENTS: u32 = 16;
+/// Edge length in pixels of the trail map of the check.
+const CHECK_SIZE: u32 = 64;
+/// Distance in pixels the CPU and GPU positions of an agent may differ by, covering the
+/// different rounding of `sin`, `cos` and fused multiply-adds on the GPU.
+const TOLERANCE: f32 = 1e-3;
+/// Mismatched agents logged per step.
+const LOGGED_MISMATCHES: u32 = 4;
+/// How long the pipelines may take to compile before the check gives up on them.
+const READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Results of the check, shared between the main and render worlds.
+#[derive(Debug, Clone, Default, Resource)]
+struct CpuCheck(Arc<CheckProgress>);
+
+#[derive(Debug, Default)]
+struct CheckProgress {
+    compared: AtomicU32,
+    mismatched: AtomicU32,
+    done: AtomicBool,
+    error: Mutex<Option<CheckError>>,
+}
+
+/// The settings the CPU steps the agents with.
+#[derive(Resource)]
+struct CheckSettings(SimulationSettings);
+
+/// The agents and trail map read back after the previous step, the input of the current one.
+#[derive(Default, Resource)]
+struct PreviousStep(Option<(Vec<Agent>, Vec<Vec4>)>);
+
+/// How a check went, see [`check_cpu`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CheckOutcome {
+    /// Steps compared, every one but the first.
+    pub compared: u32,
+    /// Agents that ended up too far from where the CPU put them, summed over the steps.
+    pub mismatched: u32,
+}
+
+/// Why a check couldn't compare any steps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckError {
+    /// A pipeline failed to compile, with the error.
+    PipelinesFailed(String),
+    /// The pipelines were still compiling a minute after the check started.
+    PipelinesNeverReady,
+}
+
+impl std::fmt::Display for CheckError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PipelinesFailed(error) => write!(f, "the pipelines failed to compile: {error}"),
+            Self::PipelinesNeverReady => write!(
+                f,
+                "the pipelines never became ready, still compiling after {} s",
+                READY_TIMEOUT.as_secs()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CheckError {}
+
+/// Runs `steps` steps of a tiny version of `plugin`, comparing every one after the first with
+/// the CPU, then exits, with an error if any agent diverged.
+pub(crate) fn run(steps: u32, plugin: SlimeComputePlugin) {
+    // an error was logged when the check gave up
+    if !matches!(check(steps, plugin), Ok(outcome) if outcome.mismatched == 0) {
+        std::process::exit(1);
+    }
+}
+
+/// Runs `steps` steps of a tiny simulation of `settings` on the GPU headless and compares every
+/// one after the first with the CPU, needing a GPU adapter.
+pub fn check_cpu(steps: u32, settings: SimulationSettings) -> Result<CheckOutcome, CheckError> {
+    check(steps, SlimeComputePlugin::new().with_settings(settings))
+}
+
+/// Runs `steps` steps of a tiny version of `plugin` and compares every one after the first with
+/// the CPU.
+fn check(steps: u32, mut plugin: SlimeComputePlugin) -> Result<CheckOutcome, CheckError> {
+    let settings = &mut plugin.settings;
+    settings.agent_count = CHECK_AGENTS;
+    settings.burst_headroom = 0;
+    settings.sim_width = CHECK_SIZE;
+    settings.sim_height = CHECK_SIZE;
+    settings.resize_follows_window = false;
+    settings.antialiased_deposit = true;
+    settings.substeps = 1;
+    settings.obstacle_mask = None;
+    settings.food_map = None;
+    settings.crowd_avoidance = 0.;
+    settings.noise_strength = 0.;
+    let settings = settings.clone().validated();
+
+    let check = CpuCheck::default();
+    let mut app = headless::headless_app(plugin);
+    app.insert_resource(check.clone()).add_system(report_check);
+    app.sub_app_mut(RenderApp)
+        .insert_resource(HeadlessRun::new(steps))
+        .insert_resource(check.clone())
+        .insert_resource(CheckSettings(settings))
+        .init_resource::<PreviousStep>()
+        .add_system_to_stage(RenderStage::Cleanup, compare_step);
+
+    info!("Checking {steps} steps of {CHECK_AGENTS} agents against the CPU");
+    app.run();
+    if let Some(error) = check.0.error.lock().unwrap().take() {
+        return Err(error);
+    }
+    Ok(CheckOutcome {
+        compared: check.0.compared.load(Ordering::Acquire),
+        mismatched: check.0.mismatched.load(Ordering::Acquire),
+    })
+}
+
+/// Reads back the agents and trail map after every step and compares the agents with the CPU
+/// stepping them from the previous readback.
+///
+/// Runs in the render world once the frame has been submitted, blocking until the GPU is done.
+fn compare_step(world: &mut World) {
+    let step = world.resource::<nodes::SlimeStep>();
+    if !step.advances() {
+        return;
+    }
+    let config = world.resource::<SimulationConfig>();
+    let agents = read_agents(world);
+    let trail = step
+        .latest_trail(world)
+        .map(|trail| {
+            read_trail(
+                world,
+                &trail.texture,
+                config.trail_extent(),
+                config.trail_precision,
+            )
+        })
+        .unwrap_or_default();
+
+    if let Some((previous_agents, previous_trail)) = &world.resource::<PreviousStep>().0 {
+        let frame = *world.resource::<FrameUniform>().0.get();
+        let settings = &world.resource::<CheckSettings>().0;
+        let size = Vec2::new(settings.sim_width as f32, settings.sim_height as f32);
+        let species = settings.sensing_species();
+        let mut mismatched = 0;
+        for (index, (before, after)) in previous_agents.iter().zip(&agents).enumerate() {
+            let interaction = species[before.species as usize].interaction;
+            let expected = sim_cpu::step_agent(before, index as u32, &frame, settings, |texel| {
+                let index = texel.y as usize * settings.sim_width as usize + texel.x as usize;
+                sim_cpu::explore(previous_trail[index].dot(interaction), settings)
+            });
+            let mut offset = after.position - expected.position;
+            // an agent right on the edge of a torus may wrap on one side and not the other
+            if settings.boundary() == BoundaryMode::Wrap {
+                offset -= size * (offset / size).round();
+            }
+            if offset.length() <= TOLERANCE {
+                continue;
+            }
+            if mismatched < LOGGED_MISMATCHES {
+                error!(
+                    "Agent #{index} moved from {} to {} on the GPU but to {} on the CPU",
+                    before.position, after.position, expected.position
+                );
+            }
+            mismatched += 1;
+        }
+
+        let check = &world.resource::<CpuCheck>().0;
+        check.compared.fetch_add(1, Ordering::AcqRel);
+        check.mismatched.fetch_add(mismatched, Ordering::AcqRel);
+    }
+
+    let done = world.resource::<HeadlessRun>().remaining() == 0;
+    world.resource_mut::<PreviousStep>().0 = Some((agents, trail));
+    if done {
+        world
+            .resource::<CpuCheck>()
+            .0
+            .done
+            .store(true, Ordering::Release);
+    }
+}
+
+/// Prints how many agents diverged once every step has been compared, then exits. Also exits
+/// once the pipelines fail or take longer than [`READY_TIMEOUT`], as no step would ever run.
+fn report_check(
+    check: Res<CpuCheck>,
+    status: Res<SlimePipelineStatus>,
+    mut started: Local<Option<Instant>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let started = *started.get_or_insert_with(Instant::now);
+    let error = match &*status {
+        SlimePipelineStatus::Failed(error) => Some(CheckError::PipelinesFailed(error.clone())),
+        SlimePipelineStatus::Loading if started.elapsed() > READY_TIMEOUT => {
+            Some(CheckError::PipelinesNeverReady)
+        }
+        _ => None,
+    };
+    if let Some(error) = error {
+        error!("{error}");
+        *check.0.error.lock().unwrap() = Some(error);
+        exit.send(AppExit);
+        return;
+    }
+    if !check.0.done.load(Ordering::Acquire) {
+        return;
+    }
+    let compared = check.0.compared.load(Ordering::Acquire);
+    let mismatched = check.0.mismatched.load(Ordering::Acquire);
+    if mismatched == 0 {
+        println!("CPU and GPU agree on {compared} steps of {CHECK_AGENTS} agents");
+    } else {
+        println!(
+            "CPU and GPU diverged {mismatched} times over {compared} steps of {CHECK_AGENTS} \
+             agents"
+        );
+    }
+    exit.send(AppExit);
+}
@@ -0,0 +1,89 @@
+//! Counting the agents on every texel, drawn as a heatmap over the trail while toggled with H
+//! and avoided by agents with
+//! [`SimulationSettings::crowd_avoidance`](crate::SimulationSettings::crowd_avoidance).
+//!
+//! The trail shows where agents have been, smeared out by diffusing and decaying. The density
+//! instead counts the agents on every texel, which shows clustering as it happens. The `update`
+//! pass `atomicAdd`s each agent into the count of the texel it ends up on, once per substep,
+//! and `colorize` averages the counts over the substeps and maps them through a heat palette of
+//! their own, black through red and yellow to white.
+//!
+//! Agents sensing crowds read the counts of the previous step instead, which no agent writes
+//! during this one, so what an agent senses doesn't depend on which agents ran before it.
+//!
+//! Counting needs atomics, which WGSL only has for `u32` and `i32` storage buffers. It has no
+//! atomic texture operations, even on `R32Uint` storage textures, and the four storage textures
+//! most GPUs allow a shader are all taken anyway. So the counts are kept in a storage buffer
+//! with one `u32` per trail texel in row-major order, laid out like an `R32Uint` texture
+//! would be, and converted to float when colorizing.
+//!
+//! The `rotate_density` pass moves the counts into the previous step's buffer and zeroes them at
+//! the start of every step they are needed for, while the overlay is shown or crowds are
+//! avoided. Otherwise neither pass runs, so turning the overlay on while paused shows the agents
+//! where they were the last time they were counted.
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::ExtractResource,
+        render_resource::{Buffer, BufferDescriptor, BufferUsages},
+        renderer::RenderDevice,
+    },
+};
+
+use crate::SimulationConfig;
+
+/// Whether the density heatmap is drawn over the trail, toggled with H.
+#[derive(Debug, Clone, Default, Resource, ExtractResource)]
+pub(crate) struct DensityOverlay(pub(crate) bool);
+
+pub(crate) fn density_controls(keys: Res<Input<KeyCode>>, mut overlay: ResMut<DensityOverlay>) {
+    if keys.just_pressed(KeyCode::H) {
+        overlay.0 = !overlay.0;
+    }
+}
+
+/// Storage buffers counting the agents on every trail texel, one `u32` per texel.
+#[derive(Resource)]
+pub(crate) struct DensityBuffer {
+    /// Counted during this step.
+    pub(crate) buffer: Buffer,
+    /// Counted during the previous step, sensed by agents avoiding crowds.
+    pub(crate) previous: Buffer,
+    texel_count: u32,
+}
+
+impl DensityBuffer {
+    pub(crate) fn new(render_device: &RenderDevice, config: &SimulationConfig) -> Self {
+        let texel_count = config.sim_width * config.sim_height;
+        let create_buffer = |label| {
+            render_device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                size: texel_count as u64 * std::mem::size_of::<u32>() as u64,
+                usage: BufferUsages::STORAGE,
+                mapped_at_creation: false,
+            })
+        };
+        Self {
+            buffer: create_buffer("agent_density"),
+            previous: create_buffer("previous_density"),
+            texel_count,
+        }
+    }
+}
+
+/// Creates the [`DensityBuffer`] on the first frame and again when the trail map is resized.
+///
+/// New buffers are zeroed by wgpu, so the first step avoids no one.
+pub(crate) fn prepare_density(
+    mut commands: Commands,
+    density: Option<Res<DensityBuffer>>,
+    config: Res<SimulationConfig>,
+    render_device: Res<RenderDevice>,
+) {
+    let texel_count = config.sim_width * config.sim_height;
+    if density.map_or(false, |density| density.texel_count == texel_count) {
+        return;
+    }
+    commands.insert_resource(DensityBuffer::new(&render_device, &config));
+}
@@ -0,0 +1,70 @@
+//! Anti-aliased deposits, enabled by
+//! [`SimulationSettings::antialiased_deposit`](crate::SimulationSettings::antialiased_deposit).
+//!
+//! Instead of adding its whole `deposit_amount` to the texel it is on, every agent splits it
+//! bilinearly between the four texels around its position. Neighboring agents splat into the same
+//! texels, and the trail map is a float texture with no atomics, so the splats are summed with
+//! `atomicAdd` into a `u32` per texel and channel in a storage buffer. The `resolve` pass then
+//! adds them to the trail map, converting back to float, and zeroes the buffer for the next step.
+//!
+//! The sums are fixed point with [`DEPOSIT_SCALE`] steps per unit of trail. A texel gets at most
+//! half a step of rounding error from each agent splatting into it, far below what `decay_rate`
+//! takes away every step, and overflows once about 65536 units of trail land on it in a single
+//! step, which no sensible `agent_count` and `deposit_amount` get close to. The trail map itself
+//! stays a float texture of the [`TrailPrecision`](crate::TrailPrecision), so diffusing, sensing
+//! and colorizing are unchanged.
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{Buffer, BufferDescriptor, BufferUsages},
+        renderer::RenderDevice,
+    },
+};
+
+use crate::SimulationConfig;
+
+/// Fixed point steps per unit of trail in the deposit buffer, substituted for
+/// `#{DEPOSIT_SCALE}` in the shader.
+pub(crate) const DEPOSIT_SCALE: u32 = 1 << 16;
+
+/// Storage buffer the update pass splats anti-aliased deposits into, one `u32` per channel of
+/// every trail texel. It is all zeros between steps.
+#[derive(Resource)]
+pub(crate) struct DepositBuffer {
+    pub(crate) buffer: Buffer,
+    texel_count: u32,
+}
+
+impl DepositBuffer {
+    pub(crate) fn new(render_device: &RenderDevice, config: &SimulationConfig) -> Self {
+        let texel_count = config.sim_width * config.sim_height;
+        let buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("deposits"),
+            size: texel_count as u64 * 4 * std::mem::size_of::<u32>() as u64,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        Self {
+            buffer,
+            texel_count,
+        }
+    }
+}
+
+/// Creates the [`DepositBuffer`] on the first frame and again when the trail map is resized.
+///
+/// New buffers are zeroed by wgpu, and the resolve pass leaves them zeroed, so it never needs
+/// clearing on resets.
+pub(crate) fn prepare_deposits(
+    mut commands: Commands,
+    deposits: Option<Res<DepositBuffer>>,
+    config: Res<SimulationConfig>,
+    render_device: Res<RenderDevice>,
+) {
+    let texel_count = config.sim_width * config.sim_height;
+    if deposits.map_or(false, |deposits| deposits.texel_count == texel_count) {
+        return;
+    }
+    commands.insert_resource(DepositBuffer::new(&render_device, &config));
+}
@@ -0,0 +1,365 @@
+//! Presenting the colorized trail map on a full-window quad.
+//!
+//! [`TrailMaterial`] samples the [`TrailDisplay`] written by the `colorize` pass and applies
+//! `gamma` and `brightness` from the settings, which can be tuned while running. The window is
+//! cleared to the `background` of the settings around the quad.
+//!
+//! With `auto_exposure` the brightness is also scaled by the [`Exposure`], which follows the
+//! trail stats so the 99th percentile intensity comes out near white.
+//!
+//! The material samples the display with the sampler of the image, which follows the
+//! [`DisplayFilter`] of the settings.
+//!
+//! With `bloom`, toggled with B, the camera renders into an HDR target with bevy's
+//! [`BloomSettings`], so what the material outputs above `bloom_threshold` glows. The material
+//! doesn't clamp its output, so a `brightness` above 1 pushes the trail past 1, which only an HDR
+//! target keeps.
+//!
+//! Every simulation added to [`SlimeSimulations`](crate::SlimeSimulations) is shown the same way
+//! in a window of its own, without bloom or exposure, see [`instances`].
+
+use bevy::{
+    core_pipeline::bloom::BloomSettings,
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        render_resource::{AsBindGroup, FilterMode, SamplerDescriptor, ShaderRef},
+        texture::ImageSampler,
+    },
+    sprite::{Material2d, Material2dPlugin, MaterialMesh2dBundle},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    instances, stats::TrailStats, Slime, SlimeHandle, SlimeStartup, TrailDisplay, TrailSprite,
+    HEIGHT, WIDTH,
+};
+
+/// Most the [`Exposure`] scales the brightness by, so an empty trail map isn't blown up to noise.
+const MAX_EXPOSURE: f32 = 16.;
+
+/// Shows the trail map on a quad letterboxed to the window.
+pub struct TrailDisplayPlugin;
+
+impl Plugin for TrailDisplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(Material2dPlugin::<TrailMaterial>::default())
+            .insert_resource(ClearColor(Color::BLACK))
+            .add_system(crate::resize_trail_sprite)
+            // after `setup` has created the display image
+            .add_startup_system_to_stage(StartupStage::PostStartup, spawn_trail_quad)
+            .init_resource::<BloomEnabled>()
+            // after `setup` has spawned the camera
+            .add_startup_system_to_stage(StartupStage::PostStartup, setup_bloom)
+            .add_system(bloom_controls)
+            .add_system(update_bloom.after(bloom_controls))
+            .init_resource::<Exposure>()
+            .add_system(update_exposure)
+            .add_system(update_trail_material.after(update_exposure))
+            .add_system(update_trail_texture)
+            // after the material points at the display image it filters
+            .add_system(update_display_filter.after(update_trail_texture))
+            .add_system(instances::show_simulations)
+            .add_system(instances::fit_simulation_quads.after(instances::show_simulations))
+            .add_system(instances::close_simulation_windows);
+    }
+}
+
+/// How the trail map is sampled between its texels when it is shown larger or smaller than it
+/// is, see [`SimulationSettings::display_filter`](crate::SimulationSettings::display_filter).
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisplayFilter {
+    /// Every texel is a crisp square when zoomed in, better for a pixel-art look.
+    Nearest,
+    /// Texels blend smoothly into their neighbors.
+    #[default]
+    Linear,
+}
+
+impl DisplayFilter {
+    #[cfg(feature = "ui")]
+    pub(crate) const ALL: [DisplayFilter; 2] = [DisplayFilter::Nearest, DisplayFilter::Linear];
+
+    fn filter_mode(self) -> FilterMode {
+        match self {
+            DisplayFilter::Nearest => FilterMode::Nearest,
+            DisplayFilter::Linear => FilterMode::Linear,
+        }
+    }
+
+    /// The sampler the [`TrailMaterial`] reads the display image with.
+    pub(crate) fn sampler(self) -> ImageSampler {
+        ImageSampler::Descriptor(SamplerDescriptor {
+            label: Some("trail_display_sampler"),
+            mag_filter: self.filter_mode(),
+            min_filter: self.filter_mode(),
+            ..default()
+        })
+    }
+}
+
+#[derive(Debug, Clone, AsBindGroup, TypeUuid)]
+#[uuid = "5d0f4c7e-2b7a-4f36-9a0e-8c3d61b2a9f4"]
+pub(crate) struct TrailMaterial {
+    /// Exponent applied to the color, above 1 to brighten the faint trails.
+    #[uniform(0)]
+    pub(crate) gamma: f32,
+    /// Multiplies the color before the gamma is applied.
+    #[uniform(0)]
+    pub(crate) brightness: f32,
+    #[texture(1)]
+    #[sampler(2)]
+    pub(crate) display: Handle<Image>,
+}
+
+impl Material2d for TrailMaterial {
+    fn fragment_shader() -> ShaderRef {
+        "shaders/trail_material.wgsl".into()
+    }
+}
+
+/// Scale of the `brightness` while `auto_exposure` is set, 1 otherwise.
+///
+/// The `colorize` pass maps intensities of 1 and above to the brightest color, so trail that is
+/// blown out can't be darkened back into detail here. The exposure only ever brightens, from 1 up
+/// to [`MAX_EXPOSURE`], and blown out trail needs a lower `deposit_amount` or `max_trail`.
+#[derive(Debug, Copy, Clone, PartialEq, Resource)]
+pub(crate) struct Exposure(pub(crate) f32);
+
+impl Default for Exposure {
+    fn default() -> Self {
+        Self(1.)
+    }
+}
+
+impl Exposure {
+    /// Moves the exposure towards `target`, keeping `smoothing` of the current one.
+    pub(crate) fn smoothed(self, target: f32, smoothing: f32) -> Self {
+        Self(self.0 * smoothing + target * (1. - smoothing))
+    }
+}
+
+/// Follows the trail stats with the [`Exposure`] every time they are read back.
+fn update_exposure(
+    stats: Res<TrailStats>,
+    slimes: Res<Assets<Slime>>,
+    slime: Res<SlimeHandle>,
+    mut exposure: ResMut<Exposure>,
+) {
+    let Some(settings) = slimes.get(&slime.0) else {
+        return;
+    };
+    let next = if !settings.auto_exposure {
+        Exposure::default()
+    } else if stats.is_changed() {
+        let target = (1. / stats.p99.max(f32::EPSILON)).clamp(1., MAX_EXPOSURE);
+        exposure.smoothed(target, settings.exposure_smoothing)
+    } else {
+        return;
+    };
+    // only touch the resource when something changes, so the material isn't rewritten every frame
+    if next != *exposure {
+        *exposure = next;
+    }
+}
+
+fn spawn_trail_quad(
+    mut commands: Commands,
+    display: Res<TrailDisplay>,
+    slimes: Res<Assets<Slime>>,
+    slime: Res<SlimeHandle>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<TrailMaterial>>,
+) {
+    let (gamma, brightness) = slimes
+        .get(&slime.0)
+        .map_or((1., 1.), |slime| (slime.gamma, slime.brightness));
+    commands.spawn((
+        MaterialMesh2dBundle {
+            // always cover the window, whatever the resolution of the simulation
+            mesh: meshes
+                .add(Mesh::from(shape::Quad::new(Vec2::new(WIDTH, HEIGHT))))
+                .into(),
+            material: materials.add(TrailMaterial {
+                gamma,
+                brightness,
+                display: display.0.clone(),
+            }),
+            ..default()
+        },
+        TrailSprite,
+    ));
+}
+
+/// Copies `gamma` and `brightness`, scaled by the [`Exposure`], into the material and
+/// `background` into the [`ClearColor`] whenever the settings or the exposure change.
+fn update_trail_material(
+    mut asset_events: EventReader<AssetEvent<Slime>>,
+    slimes: Res<Assets<Slime>>,
+    slime: Res<SlimeHandle>,
+    exposure: Res<Exposure>,
+    quads: Query<&Handle<TrailMaterial>, With<TrailSprite>>,
+    mut materials: ResMut<Assets<TrailMaterial>>,
+    mut clear_color: ResMut<ClearColor>,
+) {
+    let changed = asset_events.iter().any(|event| match event {
+        AssetEvent::Created { handle } | AssetEvent::Modified { handle } => *handle == slime.0,
+        AssetEvent::Removed { .. } => false,
+    });
+    if !changed && !exposure.is_changed() {
+        return;
+    }
+    let Some(settings) = slimes.get(&slime.0) else {
+        return;
+    };
+    for handle in &quads {
+        if let Some(material) = materials.get_mut(handle) {
+            material.gamma = settings.gamma;
+            material.brightness = settings.brightness * exposure.0;
+        }
+    }
+    let [r, g, b, a] = settings.background;
+    clear_color.0 = Color::rgba(r, g, b, a);
+}
+
+/// Points the material at the new [`TrailDisplay`] when the trail map is rebuilt at another size.
+fn update_trail_texture(
+    display: Res<TrailDisplay>,
+    quads: Query<&Handle<TrailMaterial>, With<TrailSprite>>,
+    mut materials: ResMut<Assets<TrailMaterial>>,
+) {
+    if !display.is_changed() {
+        return;
+    }
+    for handle in &quads {
+        if let Some(material) = materials.get_mut(handle) {
+            material.display = display.0.clone();
+        }
+    }
+}
+
+/// Gives the [`TrailDisplay`] the sampler of the [`DisplayFilter`] whenever the filter changes or
+/// the display is replaced.
+///
+/// The material caches the sampler in its bind group, so it is marked as changed too for bevy to
+/// rebuild it.
+fn update_display_filter(
+    display: Res<TrailDisplay>,
+    slimes: Res<Assets<Slime>>,
+    slime: Res<SlimeHandle>,
+    quads: Query<&Handle<TrailMaterial>, With<TrailSprite>>,
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<TrailMaterial>>,
+    mut applied: Local<Option<(Handle<Image>, DisplayFilter)>>,
+) {
+    let Some(settings) = slimes.get(&slime.0) else {
+        return;
+    };
+    let wanted = (display.0.clone(), settings.display_filter);
+    if applied.as_ref() == Some(&wanted) {
+        return;
+    }
+    let Some(image) = images.get_mut(&display.0) else {
+        return;
+    };
+    image.sampler_descriptor = settings.display_filter.sampler();
+    for handle in &quads {
+        materials.get_mut(handle);
+    }
+    *applied = Some(wanted);
+}
+
+/// Whether the camera renders with bloom, toggled with B.
+#[derive(Debug, Clone, Default, Resource)]
+pub(crate) struct BloomEnabled(pub(crate) bool);
+
+fn setup_bloom(startup: Res<SlimeStartup>, mut enabled: ResMut<BloomEnabled>) {
+    enabled.0 = startup.settings.bloom;
+}
+
+fn bloom_controls(keys: Res<Input<KeyCode>>, mut enabled: ResMut<BloomEnabled>) {
+    if keys.just_pressed(KeyCode::B) {
+        enabled.0 = !enabled.0;
+    }
+}
+
+/// Switches the 2D camera to an HDR target with [`BloomSettings`] while bloom is enabled, and
+/// copies `bloom_intensity` and `bloom_threshold` into them whenever the settings change.
+fn update_bloom(
+    mut commands: Commands,
+    enabled: Res<BloomEnabled>,
+    mut asset_events: EventReader<AssetEvent<Slime>>,
+    slimes: Res<Assets<Slime>>,
+    slime: Res<SlimeHandle>,
+    mut cameras: Query<
+        (Entity, &mut Camera, Option<&mut BloomSettings>),
+        (With<Camera2d>, Without<instances::SimulationCamera>),
+    >,
+) {
+    let changed = asset_events.iter().any(|event| match event {
+        AssetEvent::Created { handle } | AssetEvent::Modified { handle } => *handle == slime.0,
+        AssetEvent::Removed { .. } => false,
+    });
+    if !changed && !enabled.is_changed() {
+        return;
+    }
+    let Some(settings) = slimes.get(&slime.0) else {
+        return;
+    };
+    for (entity, mut camera, bloom) in &mut cameras {
+        if camera.hdr != enabled.0 {
+            camera.hdr = enabled.0;
+        }
+        match (enabled.0, bloom) {
+            (true, Some(mut bloom)) => {
+                bloom.intensity = settings.bloom_intensity;
+                bloom.threshold = settings.bloom_threshold;
+            }
+            (true, None) => {
+                commands.entity(entity).insert(BloomSettings {
+                    intensity: settings.bloom_intensity,
+                    threshold: settings.bloom_threshold,
+                    ..default()
+                });
+            }
+            (false, Some(_)) => {
+                commands.entity(entity).remove::<BloomSettings>();
+            }
+            (false, None) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exposure_moves_towards_the_target() {
+        assert_eq!(Exposure(1.).smoothed(3., 0.), Exposure(3.));
+        assert_eq!(Exposure(1.).smoothed(3., 1.), Exposure(1.));
+
+        // halving the distance every update
+        let mut exposure = Exposure(1.);
+        for expected in [2., 2.5, 2.75, 2.875] {
+            exposure = exposure.smoothed(3., 0.5);
+            assert_eq!(exposure, Exposure(expected));
+        }
+        // and back down again
+        assert_eq!(exposure.smoothed(1., 0.5), Exposure(1.9375));
+    }
+
+    #[test]
+    fn display_filters_pick_their_sampler() {
+        for (filter, mode) in [
+            (DisplayFilter::Nearest, FilterMode::Nearest),
+            (DisplayFilter::Linear, FilterMode::Linear),
+        ] {
+            let ImageSampler::Descriptor(descriptor) = filter.sampler() else {
+                panic!("{filter:?} keeps the default sampler");
+            };
+            assert_eq!(descriptor.mag_filter, mode, "{filter:?}");
+            assert_eq!(descriptor.min_filter, mode, "{filter:?}");
+        }
+    }
+}
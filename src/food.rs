@@ -0,0 +1,84 @@
+//! Food agents are drawn to, weighted by
+//! [`SimulationSettings::food_attraction`](crate::SimulationSettings::food_attraction).
+//!
+//! Food is a single `f32` per trail texel, kept in a storage buffer rather than a channel of the
+//! trail map since every channel of that holds a species. It is loaded from the `food_map` image
+//! or painted with Ctrl and the left mouse button, never diffuses or decays, and is only eaten
+//! when `food_consumption` is above zero.
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::ExtractResource,
+        render_resource::{Buffer, BufferInitDescriptor, BufferUsages, Extent3d},
+        renderer::{RenderDevice, RenderQueue},
+    },
+};
+
+use crate::{obstacles, SimState};
+
+/// Food at startup, one value per trail texel in row-major order. Resets bring it back.
+#[derive(Debug, Clone, Deref, Resource, ExtractResource)]
+pub(crate) struct FoodMap(pub(crate) Vec<f32>);
+
+/// Reads the food at `path` from the asset folder, a brightness of 255 being one unit of food.
+///
+/// Falls back to no food when there is no map or it can't be read.
+pub(crate) fn load_food_map(
+    asset_server: &AssetServer,
+    path: Option<&str>,
+    size: Extent3d,
+) -> FoodMap {
+    let food = path
+        .and_then(|path| obstacles::read_luminance(asset_server, path, size, "food map"))
+        .map(|texels| {
+            texels
+                .into_iter()
+                .map(|texel| texel as f32 / 255.)
+                .collect()
+        })
+        .unwrap_or_else(|| vec![0.; (size.width * size.height) as usize]);
+    FoodMap(food)
+}
+
+/// Storage buffer with the food left on the map, read and eaten by the update pass.
+#[derive(Resource)]
+pub(crate) struct FoodBuffer(pub(crate) Buffer);
+
+impl FoodBuffer {
+    pub(crate) fn new(render_device: &RenderDevice, food_map: &FoodMap) -> Self {
+        Self(
+            render_device.create_buffer_with_data(&BufferInitDescriptor {
+                label: Some("food"),
+                contents: bytemuck::cast_slice(food_map),
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_DST,
+            }),
+        )
+    }
+
+    /// Brings back the food of `food_map`, which has to be the size it was created with.
+    pub(crate) fn reset(&self, render_queue: &RenderQueue, food_map: &FoodMap) {
+        render_queue.write_buffer(&self.0, 0, bytemuck::cast_slice(food_map));
+    }
+}
+
+/// Uploads the [`FoodMap`] into a new buffer on the first frame and whenever the map is
+/// replaced, and into the same buffer on resets.
+pub(crate) fn prepare_food(
+    mut commands: Commands,
+    food_buffer: Option<Res<FoodBuffer>>,
+    food_map: Res<FoodMap>,
+    state: Res<SimState>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    match food_buffer {
+        Some(food_buffer) if !food_map.is_changed() => {
+            if state.reset {
+                food_buffer.reset(&render_queue, &food_map);
+            }
+        }
+        // the first frame, or the trail map was resized and the old buffer has the wrong size
+        _ => commands.insert_resource(FoodBuffer::new(&render_device, &food_map)),
+    }
+}
@@ -0,0 +1,115 @@
+//! Switching between vsync, no vsync and a frame rate cap at runtime with F7, to compare the
+//! simulation running flat out with running at the display's refresh rate.
+//!
+//! Vsync is switched through the present mode of the primary window. Some platforms and drivers
+//! ignore `AutoNoVsync` and keep presenting at the refresh rate, in which case no vsync runs and
+//! looks just like vsync. The cap presents without vsync and sleeps at the end of every frame
+//! until [`FramePacing::Capped`] frames a second have passed since the last one started.
+//!
+//! Only the windowed app paces its frames, headless runs and benchmarks step as fast as they can.
+
+use std::time::Duration;
+
+use bevy::{prelude::*, window::PresentMode};
+
+/// Frames a second [`FramePacing::Capped`] runs at when `--fps-cap` isn't given.
+pub(crate) const DEFAULT_FPS_CAP: u32 = 60;
+
+/// How the windowed app paces its frames, cycled through with F7.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Resource)]
+pub enum FramePacing {
+    Vsync,
+    NoVsync,
+    /// At most this many frames a second, at least 1.
+    Capped(u32),
+}
+
+impl FramePacing {
+    pub fn present_mode(self) -> PresentMode {
+        match self {
+            Self::Vsync => PresentMode::AutoVsync,
+            Self::NoVsync | Self::Capped(_) => PresentMode::AutoNoVsync,
+        }
+    }
+}
+
+/// Vsync and the frame rate cap, starting with vsync unless set up otherwise.
+pub struct FramePacingPlugin {
+    /// Pacing the app starts with.
+    pub pacing: FramePacing,
+    /// Frames a second F7 caps at.
+    pub fps_cap: u32,
+}
+
+impl Default for FramePacingPlugin {
+    fn default() -> Self {
+        Self {
+            pacing: FramePacing::Vsync,
+            fps_cap: DEFAULT_FPS_CAP,
+        }
+    }
+}
+
+/// The rate F7 caps at, kept apart from [`FramePacing`] so it comes back after cycling.
+#[derive(Debug, Resource)]
+struct FpsCap(u32);
+
+impl Plugin for FramePacingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(self.pacing)
+            .insert_resource(FpsCap(self.fps_cap.max(1)))
+            .add_system(frame_pacing_controls)
+            .add_system(apply_present_mode.after(frame_pacing_controls))
+            // last, so the sleep covers the work of the whole frame
+            .add_system_to_stage(CoreStage::Last, limit_frame_rate);
+    }
+}
+
+/// Cycles from vsync to no vsync to the cap and back with F7.
+fn frame_pacing_controls(
+    keys: Res<Input<KeyCode>>,
+    cap: Res<FpsCap>,
+    mut pacing: ResMut<FramePacing>,
+) {
+    if !keys.just_pressed(KeyCode::F7) {
+        return;
+    }
+    *pacing = match *pacing {
+        FramePacing::Vsync => FramePacing::NoVsync,
+        FramePacing::NoVsync => FramePacing::Capped(cap.0),
+        FramePacing::Capped(_) => FramePacing::Vsync,
+    };
+    info!("Frame pacing set to {:?}", *pacing);
+}
+
+/// Sets the present mode of the primary window whenever the [`FramePacing`] changes, the first
+/// frame included.
+fn apply_present_mode(pacing: Res<FramePacing>, mut windows: ResMut<Windows>) {
+    if !pacing.is_changed() {
+        return;
+    }
+    if let Some(window) = windows.get_primary_mut() {
+        let present_mode = pacing.present_mode();
+        // only touch the window when something changes, so the surface isn't reconfigured
+        if window.present_mode() != present_mode {
+            window.set_present_mode(present_mode);
+        }
+    }
+}
+
+/// Sleeps out the rest of the frame while capped.
+///
+/// Sleeping is only as precise as the OS scheduler, usually to around a millisecond, so the rate
+/// comes out a little under the cap.
+fn limit_frame_rate(pacing: Res<FramePacing>, time: Res<Time>) {
+    let FramePacing::Capped(fps) = *pacing else {
+        return;
+    };
+    let Some(frame_start) = time.last_update() else {
+        return;
+    };
+    let frame_time = Duration::from_secs_f64(1. / fps.max(1) as f64);
+    if let Some(remaining) = frame_time.checked_sub(frame_start.elapsed()) {
+        std::thread::sleep(remaining);
+    }
+}
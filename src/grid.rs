@@ -0,0 +1,241 @@
+//! A uniform grid of the agents, rebuilt every frame, for the `update` pass to find the agents
+//! near one without looking at all of them, enabled by
+//! [`SimulationSettings::grid_cell_size`](crate::SimulationSettings::grid_cell_size).
+//!
+//! The trail map is cut into square cells of `grid_cell_size` texels and the agents are
+//! counting sorted into them by four passes run before the update passes of a frame:
+//!
+//! - `clear_grid` zeroes a count per cell.
+//! - `count_grid` `atomicAdd`s each living agent into the count after the one of its cell.
+//! - `scan_grid` sums the counts up in a single workgroup, leaving where every cell starts.
+//! - `scatter_grid` `atomicAdd`s each agent into the start of its cell, writing its index into
+//!   the slot it got.
+//!
+//! Both the counts and the sorted indices are kept in one storage buffer, the counts first, as
+//! the shader already binds one more storage buffer with it than wgpu allows by default. Once
+//! scattered, the count of a cell holds where the next cell starts, so the agents of cell `c`
+//! are the slots from the count of cell `c - 1`, or 0 for the first cell, up to the count of
+//! cell `c`. `grid_cell_range` and `grid_agent` in simple.wgsl look them up.
+//!
+//! The grid holds the agents where they were at the start of the frame, before any of its
+//! substeps, and leaves out the agents of the frame's burst.
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{Buffer, BufferDescriptor, BufferUsages, ComputePass, PipelineCache},
+        renderer::RenderDevice,
+    },
+};
+
+use crate::{workgroups_for, SimulationConfig, SlimePipeline};
+
+/// Invocations of the single workgroup of the `scan_grid` pass, each summing a contiguous run of
+/// cells, substituted for `#{GRID_SCAN_THREADS}` in the shader.
+pub(crate) const SCAN_THREADS: u32 = 256;
+
+/// Columns and rows of cells covering the trail map, none while the grid is off.
+pub(crate) fn grid_size(config: &SimulationConfig) -> UVec2 {
+    if config.grid_cell_size == 0 {
+        return UVec2::ZERO;
+    }
+    UVec2::new(
+        workgroups_for(config.sim_width, config.grid_cell_size),
+        workgroups_for(config.sim_height, config.grid_cell_size),
+    )
+}
+
+/// Storage buffer holding a `u32` per cell followed by the index of every agent sorted by cell.
+#[derive(Resource)]
+pub(crate) struct AgentGrid {
+    pub(crate) buffer: Buffer,
+    cell_count: u32,
+    agent_capacity: u32,
+}
+
+impl AgentGrid {
+    /// Without a grid it only holds a placeholder, storage buffers can't be empty.
+    pub(crate) fn new(render_device: &RenderDevice, config: &SimulationConfig) -> Self {
+        let (cell_count, agent_capacity) = Self::entries(config);
+        let entries = (cell_count + agent_capacity).max(1);
+        let buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("agent_grid"),
+            size: entries as u64 * std::mem::size_of::<u32>() as u64,
+            usage: BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        Self {
+            buffer,
+            cell_count,
+            agent_capacity,
+        }
+    }
+
+    /// The cells and the agents the grid of `config` has room for.
+    fn entries(config: &SimulationConfig) -> (u32, u32) {
+        let size = grid_size(config);
+        let cell_count = size.x * size.y;
+        let agent_capacity = if cell_count == 0 {
+            0
+        } else {
+            config.agent_capacity()
+        };
+        (cell_count, agent_capacity)
+    }
+}
+
+/// Creates the [`AgentGrid`] on the first frame and again when the trail map is resized or the
+/// agent buffers grow.
+pub(crate) fn prepare_grid(
+    mut commands: Commands,
+    grid: Option<Res<AgentGrid>>,
+    config: Res<SimulationConfig>,
+    render_device: Res<RenderDevice>,
+) {
+    let entries = AgentGrid::entries(&config);
+    if grid.map_or(false, |grid| {
+        (grid.cell_count, grid.agent_capacity) == entries
+    }) {
+        return;
+    }
+    commands.insert_resource(AgentGrid::new(&render_device, &config));
+}
+
+/// Sorts the `active_count` agents read by the bind group already set into the grid of a
+/// simulation configured by `config`, called before the first update pass of a frame.
+///
+/// Nothing is dispatched while the grid is off.
+pub(crate) fn dispatch_grid<'w>(
+    pass: &mut ComputePass<'w>,
+    world: &'w World,
+    config: &SimulationConfig,
+    active_count: u32,
+) {
+    let size = grid_size(config);
+    let cell_count = size.x * size.y;
+    if cell_count == 0 {
+        return;
+    }
+    let pipeline_cache = world.resource::<PipelineCache>();
+    let pipeline = world.resource::<SlimePipeline>();
+    let agent_workgroups = workgroups_for(active_count, config.agent_workgroup_size);
+    for (pipeline, workgroups) in [
+        (
+            pipeline.clear_grid_pipeline,
+            workgroups_for(cell_count, config.agent_workgroup_size),
+        ),
+        (pipeline.count_grid_pipeline, agent_workgroups),
+        (pipeline.scan_grid_pipeline, 1),
+        (pipeline.scatter_grid_pipeline, agent_workgroups),
+    ] {
+        pass.set_pipeline(pipeline_cache.get_compute_pipeline(pipeline).unwrap());
+        pass.dispatch_workgroups(workgroups, 1, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use super::*;
+    use crate::Agent;
+
+    fn config(sim_width: u32, sim_height: u32, grid_cell_size: u32) -> SimulationConfig {
+        SimulationConfig {
+            sim_width,
+            sim_height,
+            grid_cell_size,
+            ..default()
+        }
+    }
+
+    /// `grid_cell_of` in simple.wgsl, `None` for the agents left out of the grid.
+    fn cell_of(config: &SimulationConfig, agent: &Agent) -> Option<usize> {
+        let size = grid_size(config);
+        let position = agent.position.floor();
+        let on_map = position.x >= 0.
+            && position.y >= 0.
+            && position.x < config.sim_width as f32
+            && position.y < config.sim_height as f32;
+        if agent.alive == 0 || !on_map {
+            return None;
+        }
+        let cell = (position.as_uvec2() / config.grid_cell_size).min(size - 1);
+        Some((cell.y * size.x + cell.x) as usize)
+    }
+
+    /// The buffer the four grid passes leave, scattering the agents in order.
+    fn sort_agents(config: &SimulationConfig, agents: &[Agent]) -> Vec<u32> {
+        let size = grid_size(config);
+        let cell_count = (size.x * size.y) as usize;
+        let cells: Vec<_> = agents.iter().map(|agent| cell_of(config, agent)).collect();
+        let mut grid = vec![0; cell_count + agents.len()];
+        // count_grid
+        for &cell in cells.iter().flatten() {
+            if cell + 1 < cell_count {
+                grid[cell + 1] += 1;
+            }
+        }
+        // scan_grid
+        for cell in 1..cell_count {
+            grid[cell] += grid[cell - 1];
+        }
+        // scatter_grid
+        for (index, &cell) in cells.iter().enumerate() {
+            if let Some(cell) = cell {
+                let slot = grid[cell] as usize;
+                grid[cell] += 1;
+                grid[cell_count + slot] = index as u32;
+            }
+        }
+        grid
+    }
+
+    /// `grid_cell_range` in simple.wgsl.
+    fn cell_range(grid: &[u32], cell: usize) -> Range<usize> {
+        let start = if cell == 0 { 0 } else { grid[cell - 1] };
+        start as usize..grid[cell] as usize
+    }
+
+    #[test]
+    fn agents_are_sorted_into_their_cells() {
+        let config = config(64, 32, 16);
+        let mut dead = Agent::new(Vec2::new(1., 1.), 0.);
+        dead.alive = 0;
+        let agents = [
+            Agent::new(Vec2::new(1., 1.), 0.),
+            Agent::new(Vec2::new(20., 5.), 0.),
+            Agent::new(Vec2::new(63.9, 31.9), 0.),
+            Agent::new(Vec2::new(2., 17.), 0.),
+            Agent::new(Vec2::new(5., 5.), 0.),
+            dead,
+            Agent::new(Vec2::new(-1., 3.), 0.),
+        ];
+        let grid = sort_agents(&config, &agents);
+        let cell_count = 8;
+        let cell_agents = |cell| {
+            let mut indices = grid[cell_count..][cell_range(&grid, cell)].to_vec();
+            indices.sort_unstable();
+            indices
+        };
+        assert_eq!(cell_agents(0), [0, 4]);
+        assert_eq!(cell_agents(1), [1]);
+        assert_eq!(cell_agents(4), [3]);
+        assert_eq!(cell_agents(7), [2]);
+        for cell in [2, 3, 5, 6] {
+            assert!(cell_agents(cell).is_empty(), "cell {cell}");
+        }
+    }
+
+    #[test]
+    fn no_grid_without_a_cell_size() {
+        assert_eq!(grid_size(&config(1280, 720, 0)), UVec2::ZERO);
+    }
+
+    #[test]
+    fn partial_cells_cover_the_rest_of_the_map() {
+        assert_eq!(grid_size(&config(1280, 720, 16)), UVec2::new(80, 45));
+        assert_eq!(grid_size(&config(1281, 721, 16)), UVec2::new(81, 46));
+        assert_eq!(grid_size(&config(10, 10, 32)), UVec2::ONE);
+    }
+}
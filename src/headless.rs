@@ -0,0 +1,197 @@
+//! Running a fixed number of simulation steps without a window, for benchmarking.
+//!
+//! Started with `--headless --steps <N>`. Once the steps are done the agents are read back and
+//! a checksum is printed along with the timing, so runs can be compared for determinism.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use bevy::{
+    app::AppExit,
+    log::LogPlugin,
+    prelude::*,
+    render::{
+        render_resource::CommandEncoderDescriptor,
+        renderer::{RenderDevice, RenderQueue},
+        RenderApp, RenderPlugin, RenderStage,
+    },
+};
+
+use crate::{
+    burst::AgentBursts,
+    readback::{ReadbackStatus, StagingBuffers},
+    status::SimUnsupported,
+    Agent, AgentBuffer, SimulationConfig, SlimeComputePlugin,
+};
+
+/// Progress of a headless run, shared between the main and render worlds.
+#[derive(Debug, Clone, Resource)]
+pub(crate) struct HeadlessRun(Arc<HeadlessProgress>);
+
+#[derive(Debug)]
+pub(crate) struct HeadlessProgress {
+    steps: u32,
+    remaining: AtomicU32,
+    checksum: AtomicU64,
+    done: AtomicBool,
+}
+
+impl HeadlessRun {
+    pub(crate) fn new(steps: u32) -> Self {
+        Self(Arc::new(HeadlessProgress {
+            steps,
+            remaining: AtomicU32::new(steps),
+            checksum: AtomicU64::new(0),
+            done: AtomicBool::new(false),
+        }))
+    }
+
+    /// Claims one step, called by [`advance_step`](crate::nodes::advance_step) before advancing.
+    pub(crate) fn take_step(&self) -> bool {
+        self.0
+            .remaining
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |remaining| {
+                remaining.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    fn steps(&self) -> u32 {
+        self.0.steps
+    }
+
+    pub(crate) fn remaining(&self) -> u32 {
+        self.0.remaining.load(Ordering::Acquire)
+    }
+
+    fn finish(&self, checksum: u64) {
+        self.0.checksum.store(checksum, Ordering::Release);
+        self.0.done.store(true, Ordering::Release);
+    }
+
+    fn checksum(&self) -> Option<u64> {
+        self.0
+            .done
+            .load(Ordering::Acquire)
+            .then(|| self.0.checksum.load(Ordering::Acquire))
+    }
+}
+
+/// An app simulating `plugin` with only the plugins the simulation needs, without a window.
+pub(crate) fn headless_app(plugin: SlimeComputePlugin) -> App {
+    let mut app = App::new();
+    app.add_plugins(MinimalPlugins)
+        .add_plugin(LogPlugin::default())
+        .add_plugin(AssetPlugin::default())
+        .add_plugin(WindowPlugin {
+            add_primary_window: false,
+            exit_on_all_closed: false,
+            ..default()
+        })
+        .add_plugin(RenderPlugin::default())
+        .add_plugin(ImagePlugin::default());
+    app.add_plugin(plugin.with_lockstep());
+    // the steps would never run, the reason is logged by the plugin
+    if app.world.contains_resource::<SimUnsupported>() {
+        std::process::exit(1);
+    }
+    app
+}
+
+/// Runs `steps` steps of `plugin` headless, then exits.
+pub(crate) fn run(steps: u32, plugin: SlimeComputePlugin) {
+    let run = HeadlessRun::new(steps);
+
+    let mut app = headless_app(plugin);
+    app.insert_resource(run.clone())
+        .add_system(report_headless_run);
+    app.sub_app_mut(RenderApp)
+        .insert_resource(run)
+        .add_system_to_stage(RenderStage::Cleanup, read_back_agents);
+
+    info!("Running {steps} steps headless");
+    app.run();
+}
+
+/// Copies the active agents into a staging buffer once every step has run and checksums them,
+/// leaving out the room the agent buffers keep for bursts.
+///
+/// The copy is submitted on its own after the frame that ran the last step.
+fn read_back_agents(
+    run: Res<HeadlessRun>,
+    agents: Option<Res<AgentBuffer>>,
+    bursts: Option<Res<AgentBursts>>,
+    config: Res<SimulationConfig>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut staging: Local<Option<StagingBuffers>>,
+) {
+    if run.remaining() > 0 || run.checksum().is_some() {
+        return;
+    }
+    let Some(agents) = agents else {
+        return;
+    };
+
+    let Some(buffers) = &mut *staging else {
+        let active_count = bursts.map_or(config.agent_count, |bursts| bursts.active_count);
+        let size = (active_count as usize * std::mem::size_of::<Agent>()) as u64;
+        let buffers = StagingBuffers::new(&render_device, &[("headless_agents", size)]);
+        let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+            label: Some("headless_readback"),
+        });
+        encoder.copy_buffer_to_buffer(agents.latest(), 0, buffers.buffer(0), 0, size);
+        render_queue.submit([encoder.finish()]);
+        *staging = Some(buffers);
+        return;
+    };
+    match buffers.poll() {
+        ReadbackStatus::Pending => {}
+        ReadbackStatus::Failed => {
+            error!("Failed to read the agents back from the GPU");
+            run.finish(0);
+        }
+        ReadbackStatus::Ready => run.finish(fnv1a(&buffers.read(0))),
+    }
+}
+
+/// 64 bit FNV-1a, stable across platforms and Rust versions unlike `DefaultHasher`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Prints the timing and checksum of the run and exits once it is done.
+fn report_headless_run(
+    run: Res<HeadlessRun>,
+    config: Res<SimulationConfig>,
+    mut timing: Local<(Option<Instant>, Option<Duration>)>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let (started, finished) = &mut *timing;
+    // the pipelines take a few frames to compile, only start timing with the first step
+    if started.is_none() && run.remaining() < run.steps() {
+        *started = Some(Instant::now());
+    }
+    if let (Some(start), None, 0) = (*started, *finished, run.remaining()) {
+        *finished = Some(start.elapsed());
+    }
+
+    let (Some(checksum), Some(elapsed)) = (run.checksum(), *finished) else {
+        return;
+    };
+    println!(
+        "Ran {} steps of {} agents in {:.3} s ({:.1} steps/s), agent checksum {checksum:016x}",
+        run.steps(),
+        config.agent_count,
+        elapsed.as_secs_f64(),
+        run.steps() as f64 / elapsed.as_secs_f64()
+    );
+    exit.send(AppExit);
+}
@@ -0,0 +1,63 @@
+//! Debugging aids: dumping agents to the log and to an agents file with I, to check on the
+//! simulation from the CPU, and drawing the sensors of every agent with D.
+
+use bevy::{prelude::*, render::extract_resource::ExtractResource};
+
+use crate::{
+    agent_file::{self, AGENT_DUMP_FILE},
+    readback::read_agents,
+};
+
+/// Number of agents logged by a dump.
+const DUMPED_AGENTS: usize = 8;
+
+/// Set on the frame I is pressed.
+#[derive(Debug, Clone, Default, Resource, ExtractResource)]
+pub(crate) struct AgentDumpRequest(bool);
+
+pub(crate) fn dump_controls(keys: Res<Input<KeyCode>>, mut request: ResMut<AgentDumpRequest>) {
+    // only touch the resource when something changes, so it isn't re-extracted every frame
+    if request.0 {
+        request.0 = false;
+    }
+    if keys.just_pressed(KeyCode::I) {
+        request.0 = true;
+    }
+}
+
+/// Drawing of the three sensor points of every agent over the trail, toggled with D.
+///
+/// The `update` pass marks the sensed texels in the sensor overlay and `colorize` blends it in
+/// and clears it, so the markers only show on frames the simulation advances.
+#[derive(Debug, Clone, Default, Resource, ExtractResource)]
+pub(crate) struct SensorDebug(pub(crate) bool);
+
+pub(crate) fn sensor_debug_controls(keys: Res<Input<KeyCode>>, mut debug: ResMut<SensorDebug>) {
+    if keys.just_pressed(KeyCode::D) {
+        debug.0 = !debug.0;
+    }
+}
+
+/// Reads the agents back, logs the first few and writes all of them to [`AGENT_DUMP_FILE`], in
+/// the render world after the frame's work has been submitted.
+pub(crate) fn dump_agents(world: &mut World) {
+    if !world.resource::<AgentDumpRequest>().0 {
+        return;
+    }
+    let agents = read_agents(world);
+    info!(
+        "First {} of {} agents:",
+        agents.len().min(DUMPED_AGENTS),
+        agents.len()
+    );
+    for (i, agent) in agents.iter().take(DUMPED_AGENTS).enumerate() {
+        info!(
+            "  #{i}: position {}, angle {:.3}, species {}, speed {:.3}",
+            agent.position, agent.angle, agent.species, agent.speed
+        );
+    }
+    match std::fs::write(AGENT_DUMP_FILE, agent_file::to_csv(&agents)) {
+        Ok(()) => info!("Wrote the agents to {AGENT_DUMP_FILE}"),
+        Err(error) => error!("Failed to write the agents to {AGENT_DUMP_FILE}: {error}"),
+    }
+}
@@ -0,0 +1,842 @@
+//! Simulations added next to the one of [`SlimeComputePlugin`](crate::SlimeComputePlugin) with
+//! [`SlimeSimulations::add_simulation`], each with its own settings, agents and trail maps and,
+//! with the [`TrailDisplayPlugin`](crate::TrailDisplayPlugin), a window of its own.
+//!
+//! They are all simulated by the same app. The main world keeps every one of them by [`SimId`]
+//! in [`SlimeSimulations`], with its own [`SlimeSimulation`], [`SimState`], [`SimClock`] and
+//! frame count, next to the resources of the first one. The render world keeps their buffers,
+//! bind groups and steps by [`SimId`] in [`SimulationsGpu`], stepped by
+//! [`SimulationsNode`](crate::nodes::SimulationsNode). Only the compiled pipelines are the first
+//! simulation's, so the `trail_precision` and workgroup sizes of the first apply to all of them.
+//!
+//! Added simulations are paused, reset and cleared through their [`SlimeSimulation`]. What the
+//! keyboard, mouse and egui panel do, the readbacks and profiling stay with the first one.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use bevy::{
+    core_pipeline::clear_color::ClearColorConfig,
+    prelude::*,
+    render::{
+        camera::RenderTarget,
+        extract_resource::ExtractResource,
+        render_asset::RenderAssets,
+        render_resource::{Buffer, BufferInitDescriptor, BufferUsages, UniformBuffer},
+        renderer::{RenderDevice, RenderQueue},
+        view::RenderLayers,
+    },
+    sprite::MaterialMesh2dBundle,
+    utils::HashMap,
+    window::{CreateWindow, WindowClosed, WindowCreated, WindowId, WindowResized},
+};
+use bytemuck::Zeroable;
+
+use crate::{
+    create_settings_buffer, density, deposit, display::TrailMaterial, food, grid, letterbox,
+    nodes::SlimeStep, noise, palette, spawn_agents, stats, AgentBuffer, AgentInitializer,
+    BindGroupResources, GpuFrame, GpuSimulationSettings, GpuSlime, GpuTrailInjection, SimClock,
+    SimState, SimulationConfig, SimulationSettings, SlimeBindGroups, SlimePipeline,
+    SlimeSimulation, TrailImages, HEIGHT, WIDTH,
+};
+
+/// Names a simulation added with [`SlimeSimulations::add_simulation`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SimId(u32);
+
+impl std::fmt::Display for SimId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The simulations added next to the first one, see the [module docs](self).
+///
+/// Inserted by [`SlimeComputePlugin`](crate::SlimeComputePlugin). An added simulation starts at
+/// the end of the frame it was added in, and closing its window removes it.
+///
+/// ```ignore
+/// fn add_comparison(mut simulations: ResMut<SlimeSimulations>) {
+///     let id = simulations.add_simulation(SimulationSettings {
+///         sensor_angle: 0.6,
+///         ..default()
+///     });
+///     simulations.get_mut(id).unwrap().set_paused(true);
+/// }
+/// ```
+#[derive(Debug, Default, Resource)]
+pub struct SlimeSimulations {
+    /// Ids are never reused, the first one added is 1.
+    last_id: u32,
+    instances: BTreeMap<SimId, Instance>,
+    /// Windows of removed simulations, closed by [`close_simulation_windows`].
+    removed: Vec<Shown>,
+}
+
+impl SlimeSimulations {
+    /// Adds a simulation of `settings` shown in a window of the default size, titled after its
+    /// id.
+    pub fn add_simulation(&mut self, settings: SimulationSettings) -> SimId {
+        let window = WindowDescriptor {
+            title: format!("Slime Simulation {}", self.last_id + 1),
+            width: WIDTH,
+            height: HEIGHT,
+            ..default()
+        };
+        self.add_simulation_in(settings, window)
+    }
+
+    /// Adds a simulation of `settings` shown in a window made from `window`.
+    pub fn add_simulation_in(
+        &mut self,
+        settings: SimulationSettings,
+        window: WindowDescriptor,
+    ) -> SimId {
+        self.last_id += 1;
+        let id = SimId(self.last_id);
+        let settings = settings.validated();
+        self.instances.insert(
+            id,
+            Instance {
+                simulation: SlimeSimulation::default(),
+                revision: 0,
+                window,
+                state: SimState::default(),
+                clock: SimClock::new(settings.steps_per_second, settings.max_dt, false),
+                frame_count: 0,
+                settings,
+                started: None,
+                shown: None,
+            },
+        );
+        id
+    }
+
+    /// Stops simulation `id` and closes its window.
+    pub fn remove_simulation(&mut self, id: SimId) {
+        if let Some(shown) = self
+            .instances
+            .remove(&id)
+            .and_then(|instance| instance.shown)
+        {
+            self.removed.push(shown);
+        }
+    }
+
+    /// The simulations still running, in the order they were added.
+    pub fn ids(&self) -> impl Iterator<Item = SimId> + '_ {
+        self.instances.keys().copied()
+    }
+
+    pub fn get(&self, id: SimId) -> Option<&SlimeSimulation> {
+        self.instances.get(&id).map(|instance| &instance.simulation)
+    }
+
+    /// Pausing, resetting and clearing simulation `id`. Its
+    /// [`settings`](SlimeSimulation::settings) handle stays empty, they are edited with
+    /// [`Self::settings_mut`], and the agents of added simulations can't be read or written.
+    pub fn get_mut(&mut self, id: SimId) -> Option<&mut SlimeSimulation> {
+        self.instances
+            .get_mut(&id)
+            .map(|instance| &mut instance.simulation)
+    }
+
+    /// The settings simulation `id` runs with.
+    pub fn settings(&self, id: SimId) -> Option<&SimulationSettings> {
+        self.instances.get(&id).map(|instance| &instance.settings)
+    }
+
+    /// Edits the settings of simulation `id`, applied at the end of the frame.
+    ///
+    /// Like editing the `.slime` file of the first simulation, the settings of the GPU passes,
+    /// the clock, `palette`, `gamma`, `brightness`, `background` and `display_filter` take
+    /// effect, while the agents and the trail maps keep the size they were added with.
+    pub fn settings_mut(&mut self, id: SimId) -> Option<&mut SimulationSettings> {
+        let instance = self.instances.get_mut(&id)?;
+        instance.revision += 1;
+        Some(&mut instance.settings)
+    }
+}
+
+/// A simulation added to [`SlimeSimulations`].
+#[derive(Debug)]
+struct Instance {
+    simulation: SlimeSimulation,
+    settings: SimulationSettings,
+    /// Bumped by every [`SlimeSimulations::settings_mut`], for the settings to be applied again.
+    revision: u32,
+    window: WindowDescriptor,
+    state: SimState,
+    /// Ticked by [`sync_simulations`] once started.
+    clock: SimClock,
+    /// Frames run since the simulation started, what [`FrameCount`](crate::FrameCount) is to the
+    /// first one.
+    frame_count: u32,
+    /// Set by [`start_simulations`].
+    started: Option<Arc<Started>>,
+    /// Set by [`show_simulations`].
+    shown: Option<Shown>,
+}
+
+/// What an added simulation runs on, created at the end of the frame it was added in.
+#[derive(Debug)]
+struct Started {
+    config: SimulationConfig,
+    images: TrailImages,
+    noise: noise::NoiseTexture,
+}
+
+/// The window an added simulation is shown in.
+#[derive(Debug)]
+struct Shown {
+    window: WindowId,
+    camera: Entity,
+    quad: Entity,
+    /// Render layer of the camera and the quad, the first simulation's being layer 0.
+    layer: u8,
+    material: Handle<TrailMaterial>,
+    /// The [`Instance::revision`] of the settings the material and camera were last set from.
+    revision: u32,
+}
+
+/// Marks the cameras of added simulations, which the camera controls, bloom and preview of the
+/// first simulation leave alone.
+#[derive(Component)]
+pub(crate) struct SimulationCamera;
+
+/// Marks the quads showing added simulations.
+#[derive(Component)]
+struct SimulationQuad;
+
+/// Creates the trail maps of the simulations added this frame.
+///
+/// Runs once the first simulation has been set up, the shader being built for its config.
+pub(crate) fn start_simulations(
+    mut simulations: ResMut<SlimeSimulations>,
+    first: Option<Res<SimulationConfig>>,
+    mut images: ResMut<Assets<Image>>,
+    asset_server: Res<AssetServer>,
+) {
+    let Some(first) = first else {
+        return;
+    };
+    if simulations
+        .instances
+        .values()
+        .all(|instance| instance.started.is_some())
+    {
+        return;
+    }
+    for (id, instance) in &mut simulations.instances {
+        if instance.started.is_some() {
+            continue;
+        }
+        let settings = &instance.settings;
+        let mut config = SimulationConfig::new(
+            settings,
+            first.texture_workgroup_size,
+            first.agent_workgroup_size,
+            first.trail_precision,
+        );
+        // added simulations don't spawn bursts
+        config.burst_headroom = 0;
+        let trail_images = TrailImages::new(&mut images, &asset_server, settings, &config);
+        if let Some(display) = images.get_mut(&trail_images.display.0) {
+            display.sampler_descriptor = settings.display_filter.sampler();
+        }
+        let noise = noise::NoiseTexture(images.add(noise::create_noise_image(settings.seed)));
+        info!(
+            "Started simulation {id}, {} agents on {}x{}",
+            config.agent_count, config.sim_width, config.sim_height
+        );
+        instance.started = Some(Arc::new(Started {
+            config,
+            images: trail_images,
+            noise,
+        }));
+    }
+}
+
+/// Applies what was asked of every added [`SlimeSimulation`] and refreshes what it reports, like
+/// [`sync_simulation`](crate::simulation::sync_simulation) does for the first one, then
+/// advances the clock and frame count of every started simulation.
+pub(crate) fn sync_simulations(time: Res<Time>, mut simulations: ResMut<SlimeSimulations>) {
+    let mut changed = false;
+    // without started simulations nothing changes, so don't mark the resource changed and have
+    // it re-extracted every frame
+    for instance in simulations.bypass_change_detection().instances.values_mut() {
+        // requests made before the simulation started wait for it
+        let Some(started) = &instance.started else {
+            continue;
+        };
+        instance
+            .simulation
+            .sync_added(&mut instance.state, &started.images.display.0);
+        let settings = &instance.settings;
+        instance.clock.steps_per_second = settings.steps_per_second;
+        instance.clock.max_dt = settings.max_dt;
+        instance
+            .clock
+            .tick(time.delta_seconds(), instance.state.running);
+        instance.frame_count = instance.frame_count.wrapping_add(1);
+        changed = true;
+    }
+    if changed {
+        simulations.set_changed();
+    }
+}
+
+/// Opens a window for every started simulation that hasn't got one, with a camera rendering
+/// only its quad, and applies edited settings to the quad and camera.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn show_simulations(
+    mut commands: Commands,
+    mut simulations: ResMut<SlimeSimulations>,
+    mut create_window: EventWriter<CreateWindow>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<TrailMaterial>>,
+    mut images: ResMut<Assets<Image>>,
+    mut cameras: Query<&mut Camera2d, With<SimulationCamera>>,
+    mut warned: Local<Vec<SimId>>,
+) {
+    let mut taken: Vec<u8> = simulations
+        .instances
+        .values()
+        .filter_map(|instance| Some(instance.shown.as_ref()?.layer))
+        .collect();
+    let pending = |instance: &Instance| {
+        instance.started.is_some()
+            && instance
+                .shown
+                .as_ref()
+                .map_or(true, |shown| shown.revision != instance.revision)
+    };
+    if !simulations.instances.values().any(pending) {
+        return;
+    }
+
+    for (id, instance) in &mut simulations.instances {
+        let Some(started) = instance.started.as_ref().filter(|_| pending(instance)) else {
+            continue;
+        };
+        let settings = &instance.settings;
+        let [r, g, b, a] = settings.background;
+        let background = ClearColorConfig::Custom(Color::rgba(r, g, b, a));
+
+        if let Some(shown) = &mut instance.shown {
+            if let Some(material) = materials.get_mut(&shown.material) {
+                material.gamma = settings.gamma;
+                material.brightness = settings.brightness;
+            }
+            if let Some(display) = images.get_mut(&started.images.display.0) {
+                display.sampler_descriptor = settings.display_filter.sampler();
+            }
+            if let Ok(mut camera) = cameras.get_mut(shown.camera) {
+                camera.clear_color = background;
+            }
+            shown.revision = instance.revision;
+            continue;
+        }
+
+        // every window renders one layer, the first simulation's being layer 0
+        let Some(layer) =
+            (1..RenderLayers::TOTAL_LAYERS as u8).find(|layer| !taken.contains(layer))
+        else {
+            if !warned.contains(id) {
+                error!(
+                    "Can't show simulation {id}, at most {} added simulations can be shown at once",
+                    RenderLayers::TOTAL_LAYERS - 1
+                );
+                warned.push(*id);
+            }
+            continue;
+        };
+        taken.push(layer);
+        let window = WindowId::new();
+        create_window.send(CreateWindow {
+            id: window,
+            descriptor: instance.window.clone(),
+        });
+        let camera = commands
+            .spawn((
+                Camera2dBundle {
+                    camera: Camera {
+                        target: RenderTarget::Window(window),
+                        ..default()
+                    },
+                    camera_2d: Camera2d {
+                        clear_color: background,
+                    },
+                    ..default()
+                },
+                RenderLayers::layer(layer),
+                SimulationCamera,
+            ))
+            .id();
+        let material = materials.add(TrailMaterial {
+            gamma: settings.gamma,
+            brightness: settings.brightness,
+            display: started.images.display.0.clone(),
+        });
+        // scaled to the window by `fit_simulation_quads` once it is created
+        let quad = commands
+            .spawn((
+                MaterialMesh2dBundle {
+                    mesh: meshes
+                        .add(Mesh::from(shape::Quad::new(Vec2::new(WIDTH, HEIGHT))))
+                        .into(),
+                    material: material.clone(),
+                    ..default()
+                },
+                RenderLayers::layer(layer),
+                SimulationQuad,
+            ))
+            .id();
+        instance.shown = Some(Shown {
+            window,
+            camera,
+            quad,
+            layer,
+            material,
+            revision: instance.revision,
+        });
+    }
+}
+
+/// Letterboxes the quads of added simulations in their windows, like
+/// [`resize_trail_sprite`](crate::resize_trail_sprite) does for the first one.
+pub(crate) fn fit_simulation_quads(
+    mut created_events: EventReader<WindowCreated>,
+    mut resize_events: EventReader<WindowResized>,
+    mut scale_factor_events: EventReader<bevy::window::WindowScaleFactorChanged>,
+    windows: Res<Windows>,
+    simulations: Res<SlimeSimulations>,
+    mut quads: Query<&mut Transform, With<SimulationQuad>>,
+) {
+    let changed: Vec<WindowId> = created_events
+        .iter()
+        .map(|event| event.id)
+        .chain(resize_events.iter().map(|event| event.id))
+        .chain(scale_factor_events.iter().map(|event| event.id))
+        .collect();
+    for instance in simulations.instances.values() {
+        let (Some(started), Some(shown)) = (&instance.started, &instance.shown) else {
+            continue;
+        };
+        let Some(window) = windows
+            .get(shown.window)
+            .filter(|_| changed.contains(&shown.window))
+        else {
+            continue;
+        };
+        let Ok(mut transform) = quads.get_mut(shown.quad) else {
+            continue;
+        };
+        let physical_size = Vec2::new(
+            window.physical_width() as f32,
+            window.physical_height() as f32,
+        );
+        let shown_size =
+            letterbox(physical_size, started.config.sim_size()) / window.scale_factor() as f32;
+        transform.scale = Vec3::new(shown_size.x / WIDTH, shown_size.y / HEIGHT, 1.);
+    }
+}
+
+/// Removes the simulations whose window was closed, and closes the windows of the ones removed
+/// with [`SlimeSimulations::remove_simulation`].
+pub(crate) fn close_simulation_windows(
+    mut commands: Commands,
+    mut closed_events: EventReader<WindowClosed>,
+    mut simulations: ResMut<SlimeSimulations>,
+    mut windows: ResMut<Windows>,
+) {
+    for event in closed_events.iter() {
+        let closed = simulations.instances.iter().find_map(|(id, instance)| {
+            let shown = instance.shown.as_ref()?;
+            (shown.window == event.id).then_some(*id)
+        });
+        if let Some(id) = closed {
+            info!("Removing simulation {id}, its window was closed");
+            simulations.remove_simulation(id);
+        }
+    }
+    if simulations.removed.is_empty() {
+        return;
+    }
+    for shown in std::mem::take(&mut simulations.removed) {
+        // gone already when it was closed by hand
+        if let Some(window) = windows.get_mut(shown.window) {
+            window.close();
+        }
+        commands.entity(shown.camera).despawn();
+        commands.entity(shown.quad).despawn();
+    }
+}
+
+/// What the render world needs of every started simulation, extracted every frame
+/// [`SlimeSimulations`] changed.
+#[derive(Default, Resource)]
+pub(crate) struct ExtractedSimulations(HashMap<SimId, ExtractedSimulation>);
+
+struct ExtractedSimulation {
+    settings: GpuSimulationSettings,
+    palette: [[u8; 4]; palette::PALETTE_SIZE],
+    revision: u32,
+    started: Arc<Started>,
+    state: SimState,
+    clock: SimClock,
+    frame_count: u32,
+}
+
+impl ExtractedSimulation {
+    /// Whether the settings and palette have to be uploaded again, the ones uploaded being
+    /// those of `uploaded_revision`.
+    fn settings_changed(&self, uploaded_revision: u32) -> bool {
+        self.revision != uploaded_revision
+    }
+}
+
+impl ExtractResource for ExtractedSimulations {
+    type Source = SlimeSimulations;
+
+    fn extract_resource(simulations: &SlimeSimulations) -> Self {
+        let extracted = simulations
+            .instances
+            .iter()
+            .filter_map(|(id, instance)| {
+                let settings = &instance.settings;
+                let simulation = ExtractedSimulation {
+                    settings: GpuSimulationSettings::from(settings),
+                    palette: settings.palette.lut(settings.background),
+                    revision: instance.revision,
+                    started: instance.started.clone()?,
+                    state: instance.state.clone(),
+                    clock: instance.clock.clone(),
+                    frame_count: instance.frame_count,
+                };
+                Some((*id, simulation))
+            })
+            .collect();
+        Self(extracted)
+    }
+}
+
+/// The buffers, bind groups and step of every started simulation, by [`SimId`].
+#[derive(Default, Resource)]
+pub(crate) struct SimulationsGpu(pub(crate) HashMap<SimId, SimulationGpu>);
+
+/// Everything an added simulation binds, like the resources of the first one do.
+pub(crate) struct SimulationGpu {
+    pub(crate) config: SimulationConfig,
+    pub(crate) slime: GpuSlime,
+    /// The [`Instance::revision`] of the settings in `slime` and `palette`.
+    revision: u32,
+    palette: palette::PaletteTexture,
+    agents: AgentBuffer,
+    frame: UniformBuffer<GpuFrame>,
+    /// A placeholder, nothing is painted onto added simulations.
+    injections: Buffer,
+    food: food::FoodBuffer,
+    deposits: deposit::DepositBuffer,
+    density: density::DensityBuffer,
+    grid: grid::AgentGrid,
+    /// Never reduced, the stats are the first simulation's.
+    stats_partials: Buffer,
+    pub(crate) bind_groups: Option<SlimeBindGroups>,
+    pub(crate) step: SlimeStep,
+}
+
+impl SimulationGpu {
+    fn new(
+        render_device: &RenderDevice,
+        render_queue: &RenderQueue,
+        extracted: &ExtractedSimulation,
+    ) -> Self {
+        let config = extracted.started.config.clone();
+        let agents = AgentBuffer::new(render_device, config.agent_count, config.agent_capacity());
+        agents.write(
+            render_queue,
+            &spawn_agents(&config, &AgentInitializer::default()),
+        );
+        let palette = palette::PaletteTexture::new(render_device);
+        palette.write(render_queue, &extracted.palette);
+        Self {
+            slime: GpuSlime::write(
+                create_settings_buffer(render_device),
+                render_queue,
+                &extracted.settings,
+            ),
+            revision: extracted.revision,
+            palette,
+            agents,
+            frame: UniformBuffer::default(),
+            // storage buffers can't be empty
+            injections: render_device.create_buffer_with_data(&BufferInitDescriptor {
+                label: Some("trail_injections"),
+                contents: bytemuck::cast_slice(&[GpuTrailInjection::zeroed()]),
+                usage: BufferUsages::STORAGE,
+            }),
+            food: food::FoodBuffer::new(render_device, &extracted.started.images.food),
+            deposits: deposit::DepositBuffer::new(render_device, &config),
+            density: density::DensityBuffer::new(render_device, &config),
+            grid: grid::AgentGrid::new(render_device, &config),
+            stats_partials: stats::create_partials(render_device, &config).0,
+            bind_groups: None,
+            step: SlimeStep::default(),
+            config,
+        }
+    }
+}
+
+/// Creates the buffers of newly started simulations, drops those of removed ones, and uploads
+/// edited settings, resets and this frame's parameters.
+pub(crate) fn prepare_simulations(
+    extracted: Res<ExtractedSimulations>,
+    mut simulations: ResMut<SimulationsGpu>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    simulations.0.retain(|id, _| extracted.0.contains_key(id));
+    for (id, extracted) in &extracted.0 {
+        let simulation = simulations
+            .0
+            .entry(*id)
+            .or_insert_with(|| SimulationGpu::new(&render_device, &render_queue, extracted));
+        if extracted.settings_changed(simulation.revision) {
+            let buffer = simulation.slime.buffer.clone();
+            simulation.slime = GpuSlime::write(buffer, &render_queue, &extracted.settings);
+            simulation.palette.write(&render_queue, &extracted.palette);
+            simulation.revision = extracted.revision;
+        }
+        // the trail maps are zeroed by the clear pass
+        if extracted.state.reset {
+            let agents = spawn_agents(&simulation.config, &AgentInitializer::default());
+            simulation.agents.write(&render_queue, &agents);
+            simulation
+                .food
+                .reset(&render_queue, &extracted.started.images.food);
+        }
+        let config = &simulation.config;
+        simulation.frame.set(GpuFrame {
+            index: extracted.frame_count,
+            time_step: GpuFrame::time_step_of(&extracted.clock),
+            active_count: config.agent_count,
+            grid_size: grid::grid_size(config),
+            grid_cell_size: config.grid_cell_size,
+            ..default()
+        });
+        simulation.frame.write_buffer(&render_device, &render_queue);
+    }
+}
+
+/// Recreates the bind groups of every started simulation, then decides whether it advances
+/// this frame like [`advance_step`](crate::nodes::advance_step) does for the first one.
+///
+/// They step with the pipelines of the first simulation, so they start once it has.
+pub(crate) fn queue_simulations(
+    mut simulations: ResMut<SimulationsGpu>,
+    extracted: Res<ExtractedSimulations>,
+    first_step: Res<SlimeStep>,
+    pipeline: Res<SlimePipeline>,
+    gpu_images: Res<RenderAssets<Image>>,
+    render_device: Res<RenderDevice>,
+) {
+    for (id, simulation) in &mut simulations.0 {
+        let Some(extracted) = extracted.0.get(id) else {
+            continue;
+        };
+        let started = &extracted.started;
+        let images = &started.images;
+        // the images are prepared asynchronously, keep the previous bind groups until they are
+        // ready
+        let (
+            Some(trail),
+            Some(next_trail),
+            Some(display),
+            Some(sensors),
+            Some(obstacles),
+            Some(noise),
+            Some(frame),
+        ) = (
+            gpu_images.get(&images.trail_map[0]),
+            gpu_images.get(&images.trail_map[1]),
+            gpu_images.get(&images.display.0),
+            gpu_images.get(&images.sensors.0),
+            gpu_images.get(&images.obstacles.0),
+            gpu_images.get(&started.noise.0),
+            simulation.frame.buffer(),
+        )
+        else {
+            continue;
+        };
+        let bind_groups = BindGroupResources {
+            settings: &simulation.slime.buffer,
+            agents: &simulation.agents,
+            trails: [trail, next_trail],
+            display,
+            sensors,
+            obstacles,
+            noise,
+            injections: &simulation.injections,
+            frame,
+            palette: &simulation.palette.view,
+            stats_partials: &simulation.stats_partials,
+            food: &simulation.food.0,
+            deposits: &simulation.deposits.buffer,
+            density: &simulation.density.buffer,
+            previous_density: &simulation.density.previous,
+            grid: &simulation.grid.buffer,
+        }
+        .create(&render_device, &pipeline.texture_bind_group_layout);
+        simulation.bind_groups = Some(bind_groups);
+
+        if !first_step.running() {
+            continue;
+        }
+        if simulation.step.running() {
+            simulation.step.advance(
+                &extracted.state,
+                extracted.clock.steps(),
+                || true,
+                Some(&simulation.slime),
+                &mut simulation.agents,
+            );
+        } else {
+            simulation.step.start();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{obstacles, SensorOverlay, TrailDisplay, TrailMap, TrailPrecision};
+
+    /// Marks simulation `id` started, on images that are never created.
+    fn start(simulations: &mut SlimeSimulations, id: SimId) {
+        let instance = simulations.instances.get_mut(&id).unwrap();
+        let config = SimulationConfig::new(&instance.settings, 8, 64, TrailPrecision::default());
+        instance.started = Some(Arc::new(Started {
+            config,
+            images: TrailImages {
+                trail_map: TrailMap([Handle::default(), Handle::default()]),
+                display: TrailDisplay(Handle::default()),
+                sensors: SensorOverlay(Handle::default()),
+                obstacles: obstacles::ObstacleMask(Handle::default()),
+                food: food::FoodMap(Vec::new()),
+            },
+            noise: noise::NoiseTexture(Handle::default()),
+        }));
+    }
+
+    /// Shows simulation `id` in a new window, with `camera` and `quad`.
+    fn show(
+        simulations: &mut SlimeSimulations,
+        id: SimId,
+        camera: Entity,
+        quad: Entity,
+    ) -> WindowId {
+        let window = WindowId::new();
+        simulations.instances.get_mut(&id).unwrap().shown = Some(Shown {
+            window,
+            camera,
+            quad,
+            layer: 1,
+            material: Handle::default(),
+            revision: 0,
+        });
+        window
+    }
+
+    #[test]
+    fn ids_are_not_reused() {
+        let mut simulations = SlimeSimulations::default();
+        let first = simulations.add_simulation(SimulationSettings::default());
+        let second = simulations.add_simulation(SimulationSettings::default());
+        simulations.remove_simulation(first);
+        let third = simulations.add_simulation(SimulationSettings::default());
+        assert_eq!(simulations.ids().collect::<Vec<_>>(), [second, third]);
+        assert!(simulations.get(first).is_none());
+        assert!(simulations.settings_mut(first).is_none());
+    }
+
+    #[test]
+    fn a_requested_reset_lasts_one_frame() {
+        let mut simulation = SlimeSimulation::default();
+        let mut state = SimState::default();
+        let display = Handle::default();
+        simulation.full_reset();
+        simulation.sync_added(&mut state, &display);
+        assert!(state.reset);
+        simulation.sync_added(&mut state, &display);
+        assert!(!state.reset);
+    }
+
+    #[test]
+    fn only_started_simulations_are_extracted() {
+        let mut simulations = SlimeSimulations::default();
+        let waiting = simulations.add_simulation(default());
+        let started = simulations.add_simulation(default());
+        start(&mut simulations, started);
+        let extracted = ExtractedSimulations::extract_resource(&simulations);
+        assert!(extracted.0.contains_key(&started));
+        assert!(!extracted.0.contains_key(&waiting));
+    }
+
+    #[test]
+    fn edited_settings_are_uploaded_again() {
+        let mut simulations = SlimeSimulations::default();
+        let id = simulations.add_simulation(default());
+        start(&mut simulations, id);
+        let uploaded = ExtractedSimulations::extract_resource(&simulations).0[&id].revision;
+        let unchanged = ExtractedSimulations::extract_resource(&simulations);
+        assert!(!unchanged.0[&id].settings_changed(uploaded));
+
+        simulations.settings_mut(id).unwrap().move_speed = 3.;
+        let edited = ExtractedSimulations::extract_resource(&simulations);
+        assert!(edited.0[&id].settings_changed(uploaded));
+        assert_eq!(edited.0[&id].settings.move_speed, 3.);
+    }
+
+    #[test]
+    fn removing_a_simulation_queues_closing_its_window() {
+        let mut simulations = SlimeSimulations::default();
+        let hidden = simulations.add_simulation(default());
+        let shown = simulations.add_simulation(default());
+        let window = show(
+            &mut simulations,
+            shown,
+            Entity::from_raw(0),
+            Entity::from_raw(1),
+        );
+        // nothing to close without a window
+        simulations.remove_simulation(hidden);
+        assert!(simulations.removed.is_empty());
+        simulations.remove_simulation(shown);
+        assert_eq!(simulations.removed.len(), 1);
+        assert_eq!(simulations.removed[0].window, window);
+    }
+
+    #[test]
+    fn closing_a_window_removes_its_simulation() {
+        let mut world = World::new();
+        world.init_resource::<Events<WindowClosed>>();
+        world.init_resource::<Windows>();
+        let camera = world.spawn_empty().id();
+        let quad = world.spawn_empty().id();
+        let mut simulations = SlimeSimulations::default();
+        let closed = simulations.add_simulation(default());
+        let kept = simulations.add_simulation(default());
+        let window = show(&mut simulations, closed, camera, quad);
+        world.insert_resource(simulations);
+        world
+            .resource_mut::<Events<WindowClosed>>()
+            .send(WindowClosed { id: window });
+
+        SystemStage::single(close_simulation_windows).run(&mut world);
+        let simulations = world.resource::<SlimeSimulations>();
+        assert_eq!(simulations.ids().collect::<Vec<_>>(), [kept]);
+        assert!(simulations.removed.is_empty());
+        assert!(world.get_entity(camera).is_none());
+        assert!(world.get_entity(quad).is_none());
+    }
+}
@@ -0,0 +1,3821 @@
+//! A physarum-style slime mold simulation running in compute shaders.
+//!
+//! Agents wander over a trail map, steering towards the trail left by others, while the trail
+//! diffuses and decays. The colored trail map is shown on a sprite covering the window.
+
+mod agent_access;
+mod agent_file;
+mod animation;
+#[cfg(feature = "benchmark")]
+mod benchmark;
+mod burst;
+mod camera;
+mod cli;
+mod compare;
+#[cfg(feature = "sim_cpu")]
+mod cpu_check;
+mod density;
+mod deposit;
+mod display;
+mod food;
+mod frame_pacing;
+mod grid;
+mod headless;
+mod inspect;
+mod instances;
+mod nodes;
+mod noise;
+mod obstacles;
+mod overlay;
+mod palette;
+mod plugins;
+mod presets;
+mod preview;
+#[cfg(feature = "profiling")]
+mod profiling;
+mod readback;
+mod record;
+mod screenshot;
+mod settings_export;
+mod sim_clock;
+#[cfg(feature = "sim_cpu")]
+mod sim_cpu;
+mod simulation;
+mod snapshot;
+mod stats;
+mod status;
+#[cfg(feature = "ui")]
+mod ui;
+
+use std::{borrow::Cow, f32::consts::PI, path::Path, sync::Arc};
+
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    ecs::system::{
+        lifetimeless::{SRes, SResMut},
+        SystemParamItem,
+    },
+    math::vec3,
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::{PrepareAssetError, RenderAsset, RenderAssetPlugin, RenderAssets},
+        render_graph::RenderGraph,
+        render_resource::{
+            BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+            BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
+            BufferBinding, BufferBindingType, BufferDescriptor, BufferInitDescriptor, BufferUsages,
+            CachedComputePipelineId, CommandEncoderDescriptor, ComputePipelineDescriptor, Extent3d,
+            PipelineCache, ShaderStages, ShaderType, StorageTextureAccess, TextureDimension,
+            TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDimension,
+            UniformBuffer,
+        },
+        renderer::{RenderAdapter, RenderDevice, RenderQueue},
+        settings::WgpuLimits,
+        texture::GpuImage,
+        Extract, RenderApp, RenderStage,
+    },
+    tasks::futures_lite::future::block_on,
+    window::{WindowResized, WindowScaleFactorChanged},
+};
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+
+pub use animation::{AnimatedField, Animation, ParamAnimation, Waveform};
+pub use camera::CameraControllerPlugin;
+#[cfg(feature = "sim_cpu")]
+pub use cpu_check::{check_cpu, CheckError, CheckOutcome};
+pub use display::{DisplayFilter, TrailDisplayPlugin};
+pub use frame_pacing::{FramePacing, FramePacingPlugin};
+pub use instances::{SimId, SlimeSimulations};
+pub use overlay::OverlayPlugin;
+pub use palette::{ColorMode, Palette};
+pub use plugins::{SlimeControlsPlugin, SlimeSimulationPlugins};
+pub use preview::{Corner, PreviewPlugin};
+#[cfg(feature = "profiling")]
+pub use profiling::{PassTimings, ProfilingPlugin};
+pub use sim_clock::SimClock;
+pub use simulation::{SlimeSimulation, SlimeSystem};
+pub use stats::TrailStats;
+#[cfg(feature = "ui")]
+pub use ui::SlimeUiPlugin;
+
+const NO_SLIMES: u32 = 100;
+/// Most agents the simulation allocates, keeping the agent buffers below the 128 MiB storage
+/// buffer binding limit most GPUs have.
+const MAX_AGENT_COUNT: u32 = 1 << 21;
+/// Initial window size, also the default resolution of the trail map.
+const WIDTH: f32 = 1280.;
+const HEIGHT: f32 = 720.;
+/// Default edge length of the square workgroups of the per-texel passes, see
+/// [`SimulationSettings::workgroup_size`].
+const TEXTURE_WORKGROUP_SIZE: u32 = 8;
+/// Default invocations of the workgroups of the per-agent passes, see
+/// [`SimulationSettings::agent_workgroup_size`].
+const AGENT_WORKGROUP_SIZE: u32 = 64;
+/// Each species deposits into its own channel of the trail map.
+const MAX_SPECIES: usize = 4;
+/// Most sensors an agent can have, see [`SimulationSettings::sensor_count`].
+const MAX_SENSORS: u32 = 9;
+/// Recent positions every agent has room for, see [`SimulationSettings::memory_length`].
+const MAX_MEMORY: usize = 4;
+/// Largest neighbourhood the diffuse pass averages over, see [`SimulationSettings::blur_radius`].
+const MAX_BLUR_RADIUS: u32 = 4;
+/// Largest disk agents stamp their deposit onto, see [`DepositShape::Disk`].
+const MAX_DEPOSIT_RADIUS: f32 = 8.;
+/// Longest move a [`DepositShape::Segment`] is drawn along, see there.
+const MAX_SEGMENT_LENGTH: u32 = 64;
+
+/// Runs the simulation configured by the command line arguments, in a window unless they ask
+/// for a headless run or for the settings to be printed.
+pub fn run() {
+    let args = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("{error}\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+    if args.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+    let plugin = match args.plugin() {
+        Ok(plugin) => plugin,
+        Err(error) => {
+            eprintln!("{error}");
+            std::process::exit(1);
+        }
+    };
+    if args.print_settings {
+        match plugin.settings.clone().validated().to_ron() {
+            Ok(ron) => println!("{ron}"),
+            Err(error) => {
+                eprintln!("can't serialize the settings: {error}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    match (args.check_cpu_steps, args.headless_steps) {
+        #[cfg(feature = "sim_cpu")]
+        (Some(steps), _) => cpu_check::run(steps, plugin),
+        (_, Some(steps)) => headless::run(steps, plugin),
+        (_, None) => run_windowed(plugin, &args),
+    }
+}
+
+fn run_windowed(plugin: SlimeComputePlugin, args: &cli::Args) {
+    let pacing = args.fps_cap.map_or(FramePacing::Vsync, FramePacing::Capped);
+    let mut window = WindowDescriptor {
+        title: "Slime Simulation".to_string(),
+        width: WIDTH,
+        height: HEIGHT,
+        present_mode: pacing.present_mode(),
+        ..default()
+    };
+    // the settings compared against are loaded up front, so a broken file fails before the
+    // window opens
+    let compared = args
+        .compare
+        .as_ref()
+        .map(|path| match args.settings(Some(path)) {
+            Ok(settings) => (path, settings),
+            Err(error) => {
+                eprintln!("{error}");
+                std::process::exit(1);
+            }
+        });
+    if compared.is_some() {
+        window.title = compare::title(args.config.as_deref());
+        window.position = compare::Side::Left.position();
+    }
+
+    let mut app = App::new();
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                window,
+                ..default()
+            })
+            .set(AssetPlugin {
+                watch_for_changes: true,
+                ..default()
+            }),
+    );
+    app.add_plugins(SlimeSimulationPlugins.set(plugin).set(FramePacingPlugin {
+        pacing,
+        fps_cap: args.fps_cap.unwrap_or(frame_pacing::DEFAULT_FPS_CAP),
+    }));
+    #[cfg(feature = "benchmark")]
+    app.add_plugin(benchmark::BenchmarkPlugin);
+    if let Some(recording) = &args.record {
+        record::add_recording(&mut app, recording);
+    }
+    if let Some((path, settings)) = compared {
+        compare::add_comparison(&mut app, settings, path, pacing.present_mode());
+    }
+    app.run();
+}
+
+/// Tunable parameters of the simulation, loaded from a `.slime` RON file.
+///
+/// Every field falls back to its default when missing from the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimulationSettings {
+    /// Number of agents to allocate, between 1 and [`MAX_AGENT_COUNT`]. Changing it while
+    /// running keeps the existing agents and spawns or drops the difference.
+    pub agent_count: u32,
+    /// Extra agents the agent buffers have room for, added by shift-clicking, see [`burst`].
+    /// Read at startup and capped so the buffers hold at most [`MAX_AGENT_COUNT`] agents.
+    pub burst_headroom: u32,
+    /// Distance in pixels an agent travels each step.
+    ///
+    /// Like `turn_speed` this is scaled by the frame time, with a step lasting 1/60 s, so the
+    /// simulation runs at the same speed whatever the frame rate.
+    pub move_speed: f32,
+    /// Angle in radians an agent rotates by when steering towards a stronger trail.
+    pub turn_speed: f32,
+    /// Angle in radians between the front sensor and each of the side sensors.
+    pub sensor_angle: f32,
+    /// Distance in pixels from the agent to its sensors.
+    pub sensor_distance: f32,
+    /// Number of sensors fanned out in front of each agent, odd so one looks straight ahead and
+    /// at most [`MAX_SENSORS`]. The classic algorithm uses 3.
+    pub sensor_count: u32,
+    /// Angle in radians between the front sensor and the outermost ones, `sensor_angle` when not
+    /// given. The sensors in between are evenly spaced, and the further out the strongest one is
+    /// the sharper the agent turns towards it.
+    pub sensor_spread: Option<f32>,
+    /// How sharply agents steer towards a stronger reading. An agent turns by `turn_speed` times
+    /// `sense_weight` times the difference between its side sensors, up to `turn_speed`.
+    pub sense_weight: f32,
+    /// Subtracted from a sensor reading for every agent counted on the sensed texel in the
+    /// previous step, so agents spread away from crowds. 0 turns it off, negative values draw
+    /// agents into crowds instead.
+    pub crowd_avoidance: f32,
+    /// How much weaker a sensor reads trail above `exploration_threshold`, taking this times
+    /// the excess off the weighted trail. At 1 readings stop growing at the threshold, above 1
+    /// more trail reads lower, so agents in dense trail turn towards the emptier side and spread
+    /// out over the map instead of clumping. 0 turns it off.
+    pub exploration: f32,
+    /// Weighted trail a sensor reads before `exploration` kicks in.
+    pub exploration_threshold: f32,
+    /// How fast the [`noise`] field turns agents as they move, in radians per 60 fps frame at
+    /// its strongest. Breaks up crystalline patterns into flowing ones, 0 turns it off.
+    pub noise_strength: f32,
+    /// Size in texels of the features of the noise field, above 0.
+    pub noise_scale: f32,
+    /// Number of its last positions an agent remembers and steers away from, at most
+    /// [`MAX_MEMORY`], 0 to turn memory off. Every agent has room for [`MAX_MEMORY`] positions
+    /// whatever this is, 32 of its 64 bytes in each of the two agent buffers.
+    pub memory_length: u32,
+    /// Subtracted from a sensor reading within a couple of texels of a remembered position.
+    pub memory_penalty: f32,
+    /// Trail an agent adds to its species' channel every step, at least 0. Together with
+    /// `decay_rate` this decides how quickly the map fills up.
+    pub deposit_amount: f32,
+    /// Splits each deposit bilinearly between the four texels around the agent instead of
+    /// adding it all to the one it is on, which smooths trails seen up close at the cost of an
+    /// extra pass, see [`deposit`].
+    pub antialiased_deposit: bool,
+    /// Texels every deposit is spread over.
+    pub deposit_shape: DepositShape,
+    /// Factor the diffused trail is multiplied by every frame.
+    ///
+    /// Unlike the speeds this isn't scaled by the frame time yet, so trails fade faster at higher
+    /// frame rates. Both it and `diffuse_rate` would have to be raised to the power of the time
+    /// step to be consistent.
+    pub decay_rate: f32,
+    /// How far each texel moves towards the average of its neighbours every frame, in `[0, 1]`.
+    pub diffuse_rate: f32,
+    /// Whether the trail diffuses, otherwise it stays where it was deposited as if `diffuse_rate`
+    /// were 0, while still decaying.
+    pub enable_diffuse: bool,
+    /// Whether the trail decays, otherwise it persists as if `decay_rate` were 1, while still
+    /// diffusing.
+    pub enable_decay: bool,
+    /// Trail below this after decaying is zeroed, channel by channel, since decaying by a factor
+    /// never reaches 0 by itself and leaves a faint haze over the background. 0 turns it off.
+    pub evaporation_floor: f32,
+    /// Radius in texels of the neighbourhood averaged by the diffuse pass, 1 for 3x3 and 2 for
+    /// 5x5, between 1 and [`MAX_BLUR_RADIUS`]. Larger radii spread trails faster.
+    ///
+    /// The pass reads `(2 * blur_radius + 1)^2` texels for each one, so the cost grows with the
+    /// square of the radius. For wide blurs a small radius with a higher `diffuse_rate`, which
+    /// blurs again every frame, is usually cheaper.
+    pub blur_radius: u32,
+    /// Weights the neighbourhood of the blur with a gaussian falling off towards its edge,
+    /// instead of averaging it evenly, which spreads trails rounder.
+    pub gaussian_blur: bool,
+    /// How strongly the diffused trail is sharpened with an unsharp mask, crisping up filaments
+    /// the blur softens, at least 0. Each texel moves away from the average of its neighbours by
+    /// this times the difference, clamped to `[0, max_trail]`. The extra pass only runs when this
+    /// is above 0.
+    pub sharpen_amount: f32,
+    /// Multiplies the frame time the agents move by, below 1 for slow motion.
+    pub time_scale: f32,
+    /// Number of times agents move each frame, each time by the frame time divided by this, at
+    /// least 1. More substeps keep fast agents from skipping over thin trails, while the trail
+    /// is still diffused and decayed once a frame.
+    ///
+    /// Agents deposit on every substep, so `deposit_amount` has to be divided by this to keep
+    /// trails as bright.
+    pub substeps: u32,
+    /// Fixed steps the simulation runs a second of real time, however many frames that takes,
+    /// see [`SimClock`]. Not given to step once a frame by the frame time.
+    pub steps_per_second: Option<f32>,
+    /// Longest frame time in seconds the simulation advances by, above 0, so the frame after a
+    /// stall doesn't make agents jump. With `steps_per_second` it caps the real time a frame
+    /// adds. Not given to follow any frame time, like headless runs can afford. Runs with
+    /// `--lockstep` step by 1/60 s regardless.
+    pub max_dt: Option<f32>,
+    /// Number of entries of `species` in use, at most [`MAX_SPECIES`]. Only read at startup.
+    pub species_count: u32,
+    /// All [`MAX_SPECIES`] entries have to be listed when given in a `.slime` file.
+    pub species: [SpeciesSettings; MAX_SPECIES],
+    /// Fraction of its deposit each species leaves on each species' trail, a row of
+    /// `species_count` fractions from 0 for every species in use. Empty to deposit only on the
+    /// species' own trail, the default.
+    ///
+    /// ```ron
+    /// // the first species feeds the trail of the second, which keeps its own to itself
+    /// deposit_matrix: [[1., 0.5], [0., 1.]],
+    /// ```
+    pub deposit_matrix: Vec<Vec<f32>>,
+    /// Weight of each species' trail when sensing, a row for every species in use like
+    /// `deposit_matrix`, replacing the `interaction` of the species. Empty to keep those.
+    pub sense_matrix: Vec<Vec<f32>>,
+    /// Seeds both the initial agent placement and the random turns taken in the shader, so runs
+    /// with the same seed are reproducible.
+    pub seed: u64,
+    /// How agents are laid out at startup, and where [`BoundaryMode::Kill`] respawns them.
+    pub spawn_pattern: SpawnPattern,
+    /// Which way agents face at startup and on respawning, overriding the heading
+    /// `spawn_pattern` gives them when set.
+    pub initial_heading: Option<HeadingMode>,
+    /// Width in texels of the trail map, independent of the window size. Only read at startup.
+    pub sim_width: u32,
+    /// Height in texels of the trail map, independent of the window size. Only read at startup.
+    pub sim_height: u32,
+    /// Whether the downsampled preview of the whole trail map is shown at startup, toggled with P
+    /// while running.
+    pub preview: bool,
+    /// Corner of the window the preview is shown in.
+    pub preview_corner: preview::Corner,
+    /// Whether the trail map is rebuilt at the window size once the window stops being resized,
+    /// respawning the agents. Otherwise it keeps its size and is letterboxed to fit the window.
+    pub resize_follows_window: bool,
+    /// What happens to agents reaching the edge of the map.
+    pub boundary_mode: BoundaryMode,
+    /// Whether the trail map tiles without seams, for wallpapers taken with the PNG screenshot.
+    /// The map wraps like with [`BoundaryMode::Wrap`] whatever `boundary_mode` says, the spawn
+    /// pattern is repeated on each of [`SEAMLESS_TILES`] by [`SEAMLESS_TILES`] tiles so it
+    /// continues across the edges like inside the map, and the [`noise`] field repeats across the
+    /// map, its `noise_scale` rounded so a whole number of its features fit over it.
+    pub seamless: bool,
+    /// Whether agents killed by [`BoundaryMode::Kill`] respawn, keeping the population constant.
+    /// Otherwise they stay dead for the rest of the run.
+    pub kill_respawn: bool,
+    /// Steps an agent lives before it respawns as a new one, placed like killed agents respawn,
+    /// 0 for agents that never age out. The turnover keeps the patterns evolving instead of
+    /// settling. Every substep is a step.
+    pub max_age: u32,
+    /// How much less an agent deposits as it ages, its deposit shrinking by this fraction of
+    /// `deposit_amount` over its life to `max_age`. At 1 agents about to respawn leave nothing,
+    /// 0 turns it off, with a `max_age` of 0 it has no effect.
+    pub age_deposit_falloff: f32,
+    /// Radius in texels of the arena of [`BoundaryMode::Circle`], half the smaller sim dimension
+    /// when not given.
+    pub arena_radius: Option<f32>,
+    /// What the color of the trail map shows on screen.
+    ///
+    /// With [`ColorMode::Direction`] the heading takes half the trail channels, so at most 2
+    /// species are simulated. Files from before this setting picked intensity coloring with a
+    /// `palette` other than `Species`, which is why such a palette turns `Species` into
+    /// `Intensity`.
+    pub color_mode: palette::ColorMode,
+    /// Gradient the trail intensity is colored with by [`ColorMode::Intensity`], grayscale for
+    /// `Species`.
+    pub palette: palette::Palette,
+    /// Each agent moves at up to `1 + speed_jitter` times `move_speed`. Only read at startup.
+    pub speed_jitter: f32,
+    /// Upper bound of every channel of the trail map where agents deposit.
+    pub max_trail: f32,
+    /// Gamma the colorized trail map is shown with, above 1 to bring out faint trails.
+    pub gamma: f32,
+    /// Multiplies the colorized trail map on screen.
+    pub brightness: f32,
+    /// How the trail map is filtered when it is zoomed, [`DisplayFilter::Nearest`] for crisp
+    /// pixels and [`DisplayFilter::Linear`] for smooth interpolation.
+    pub display_filter: DisplayFilter,
+    /// Whether the bright trail glows through bevy's bloom at startup, toggled with B while
+    /// running. Bloom needs the camera to render into an HDR target, and it blurs the whole
+    /// window down and back up a chain of smaller textures every frame. That costs a few
+    /// milliseconds a frame at high resolutions on slower GPUs.
+    pub bloom: bool,
+    /// How strongly the bloom is added over the trail.
+    pub bloom_intensity: f32,
+    /// Brightness above which the trail blooms, as shown on screen after `brightness` and
+    /// `gamma`. The colorized trail is at most 1 before `brightness`, so this has to stay below
+    /// 1 for trails to bloom at a `brightness` of 1.
+    pub bloom_threshold: f32,
+    /// Whether `brightness` is scaled up so faint trail shows, following the
+    /// [`TrailStats::p99`] intensity as it is read back, see [`display::Exposure`].
+    pub auto_exposure: bool,
+    /// Fraction of the previous exposure kept each time the trail stats are read back, from 0 to
+    /// jump to the new exposure straight away to 1 to never change it.
+    pub exposure_smoothing: f32,
+    /// RGBA color behind the trail, both where the window isn't covered by the trail map and
+    /// where the palette fades out at zero intensity.
+    pub background: [f32; 4],
+    /// Edge length of the square workgroups of the passes over the texels of the trail map, like
+    /// diffusing and colorizing, clamped to what the GPU supports. The fastest value differs
+    /// between GPUs, 8 or 16 usually do well, compare them with the `benchmark` feature. Only
+    /// read at startup.
+    pub workgroup_size: u32,
+    /// Invocations of the workgroups of the passes over the agents, like the update pass,
+    /// clamped to what the GPU supports. A multiple of 64 keeps every lane of a warp or
+    /// wavefront busy, 64 to 256 usually do well. Only read at startup.
+    pub agent_workgroup_size: u32,
+    /// How precisely the trail maps store the trail. [`TrailPrecision::F16`] halves the memory
+    /// and bandwidth they take at the cost of precision, falling back to `F32` on GPUs that
+    /// can't read and write it from a shader. Only read at startup.
+    pub trail_precision: TrailPrecision,
+    /// Edge length in texels of the cells of the [`grid`] the agents are sorted into every
+    /// frame, for the update pass to look up the agents near one. 0 doesn't build the grid.
+    /// Only read at startup.
+    pub grid_cell_size: u32,
+    /// Image in the asset folder whose bright pixels are walls agents bounce off, resampled to
+    /// the sim size if needed. Only read at startup.
+    pub obstacle_mask: Option<String>,
+    /// Weight of the food when sensing, added to the weighted trail channels.
+    pub food_attraction: f32,
+    /// Food an agent eats each step from the texel it is on, 0 to leave the food untouched.
+    pub food_consumption: f32,
+    /// Image in the asset folder whose brightness is the food placed on each texel, resampled to
+    /// the sim size if needed. Only read at startup.
+    pub food_map: Option<String>,
+    /// Settings driven over time, see [`Animation`]. Animated fields ignore other edits.
+    pub animations: Vec<Animation>,
+    /// List of agents in the asset folder to start with instead of following `spawn_pattern`,
+    /// setting `agent_count` to its length, see [`agent_file`]. Only read at startup, and used
+    /// instead of [`SlimeComputePlugin::with_agent_init`].
+    pub agents_file: Option<String>,
+}
+
+impl Default for SimulationSettings {
+    fn default() -> Self {
+        Self {
+            agent_count: NO_SLIMES,
+            burst_headroom: 50_000,
+            move_speed: 1.,
+            turn_speed: PI / 8.,
+            sensor_angle: PI / 4.,
+            sensor_distance: 9.,
+            sensor_count: 3,
+            sensor_spread: None,
+            sense_weight: 1.,
+            crowd_avoidance: 0.,
+            exploration: 0.,
+            exploration_threshold: 0.5,
+            noise_strength: 0.,
+            noise_scale: 64.,
+            deposit_amount: 1.,
+            antialiased_deposit: false,
+            deposit_shape: DepositShape::default(),
+            memory_length: 0,
+            memory_penalty: 1.,
+            decay_rate: 0.98,
+            diffuse_rate: 1.,
+            enable_diffuse: true,
+            enable_decay: true,
+            evaporation_floor: 0.,
+            blur_radius: 1,
+            gaussian_blur: false,
+            sharpen_amount: 0.,
+            time_scale: 1.,
+            substeps: 1,
+            steps_per_second: None,
+            max_dt: Some(sim_clock::DEFAULT_MAX_DT),
+            species_count: 1,
+            species: [
+                SpeciesSettings::new(0, Vec4::new(0.1, 0.6, 0.8, 1.)),
+                SpeciesSettings::new(1, Vec4::new(0.9, 0.3, 0.1, 1.)),
+                SpeciesSettings::new(2, Vec4::new(0.4, 0.9, 0.2, 1.)),
+                SpeciesSettings::new(3, Vec4::new(0.8, 0.2, 0.9, 1.)),
+            ],
+            deposit_matrix: Vec::new(),
+            sense_matrix: Vec::new(),
+            seed: 0,
+            spawn_pattern: SpawnPattern::default(),
+            initial_heading: None,
+            sim_width: WIDTH as u32,
+            sim_height: HEIGHT as u32,
+            preview: false,
+            preview_corner: preview::Corner::default(),
+            resize_follows_window: false,
+            boundary_mode: BoundaryMode::default(),
+            seamless: false,
+            kill_respawn: true,
+            max_age: 0,
+            age_deposit_falloff: 0.,
+            arena_radius: None,
+            color_mode: palette::ColorMode::default(),
+            palette: palette::Palette::default(),
+            speed_jitter: 0.,
+            max_trail: 1.,
+            gamma: 1.,
+            brightness: 1.,
+            display_filter: DisplayFilter::default(),
+            bloom: false,
+            bloom_intensity: 0.3,
+            bloom_threshold: 0.8,
+            auto_exposure: false,
+            exposure_smoothing: 0.8,
+            background: [0., 0., 0., 1.],
+            workgroup_size: TEXTURE_WORKGROUP_SIZE,
+            agent_workgroup_size: AGENT_WORKGROUP_SIZE,
+            trail_precision: TrailPrecision::default(),
+            grid_cell_size: 0,
+            obstacle_mask: None,
+            food_attraction: 1.,
+            food_consumption: 0.,
+            food_map: None,
+            animations: Vec::new(),
+            agents_file: None,
+        }
+    }
+}
+
+/// A problem with one of the settings, found by [`SimulationSettings::validate`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SettingsError {
+    /// Name of the setting, as written in `.slime` files.
+    pub field: &'static str,
+    /// What is wrong with its value.
+    pub problem: String,
+    /// Whether the simulation can't run with the value at all, otherwise it runs, just not the
+    /// way the value was meant.
+    pub hard: bool,
+}
+
+impl SettingsError {
+    fn hard(field: &'static str, problem: String) -> Self {
+        Self {
+            field,
+            problem,
+            hard: true,
+        }
+    }
+
+    fn soft(field: &'static str, problem: String) -> Self {
+        Self {
+            field,
+            problem,
+            hard: false,
+        }
+    }
+}
+
+impl std::fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.field, self.problem)
+    }
+}
+
+impl std::error::Error for SettingsError {}
+
+impl SimulationSettings {
+    /// Checks the settings against the ranges they make sense in, returning every problem at
+    /// once.
+    ///
+    /// Numbers that aren't finite and counts of 0 are [`hard`](field@SettingsError::hard).
+    /// Angles outside `[0, PI]` and rates outside `[0, 1]` are soft, a `decay_rate` above 1 for
+    /// one just makes the trail grow every frame. The values with an obvious replacement, like a
+    /// `substeps` of 0 or a negative `deposit_amount`, are left to [`Self::validated`], which
+    /// warns about them.
+    pub fn validate(&self) -> Result<(), Vec<SettingsError>> {
+        let mut errors = Vec::new();
+        let numbers = [
+            ("move_speed", Some(self.move_speed)),
+            ("turn_speed", Some(self.turn_speed)),
+            ("sensor_angle", Some(self.sensor_angle)),
+            ("sensor_distance", Some(self.sensor_distance)),
+            ("sensor_spread", self.sensor_spread),
+            ("sense_weight", Some(self.sense_weight)),
+            ("crowd_avoidance", Some(self.crowd_avoidance)),
+            ("exploration", Some(self.exploration)),
+            ("exploration_threshold", Some(self.exploration_threshold)),
+            ("noise_strength", Some(self.noise_strength)),
+            ("memory_penalty", Some(self.memory_penalty)),
+            ("deposit_amount", Some(self.deposit_amount)),
+            ("decay_rate", Some(self.decay_rate)),
+            ("diffuse_rate", Some(self.diffuse_rate)),
+            ("evaporation_floor", Some(self.evaporation_floor)),
+            ("sharpen_amount", Some(self.sharpen_amount)),
+            ("time_scale", Some(self.time_scale)),
+            ("age_deposit_falloff", Some(self.age_deposit_falloff)),
+            ("arena_radius", self.arena_radius),
+            ("max_dt", self.max_dt),
+            ("speed_jitter", Some(self.speed_jitter)),
+            ("max_trail", Some(self.max_trail)),
+            ("gamma", Some(self.gamma)),
+            ("brightness", Some(self.brightness)),
+            ("bloom_intensity", Some(self.bloom_intensity)),
+            ("bloom_threshold", Some(self.bloom_threshold)),
+            ("exposure_smoothing", Some(self.exposure_smoothing)),
+            ("food_attraction", Some(self.food_attraction)),
+            ("food_consumption", Some(self.food_consumption)),
+        ];
+        for (field, value) in numbers {
+            if let Some(value) = value.filter(|value| !value.is_finite()) {
+                errors.push(SettingsError::hard(
+                    field,
+                    format!("{value} isn't a finite number"),
+                ));
+            }
+        }
+        for (field, count) in [
+            ("agent_count", self.agent_count),
+            ("sim_width", self.sim_width),
+            ("sim_height", self.sim_height),
+            ("workgroup_size", self.workgroup_size),
+            ("agent_workgroup_size", self.agent_workgroup_size),
+        ] {
+            if count == 0 {
+                errors.push(SettingsError::hard(
+                    field,
+                    "has to be at least 1".to_string(),
+                ));
+            }
+        }
+        if !(1..=MAX_SPECIES as u32).contains(&self.species_count) {
+            errors.push(SettingsError::hard(
+                "species_count",
+                format!("{} isn't between 1 and {MAX_SPECIES}", self.species_count),
+            ));
+        }
+
+        for (field, angle) in [
+            ("turn_speed", Some(self.turn_speed)),
+            ("sensor_angle", Some(self.sensor_angle)),
+            ("sensor_spread", self.sensor_spread),
+        ] {
+            if let Some(angle) = angle.filter(|angle| angle.is_finite()) {
+                if !(0. ..=PI).contains(&angle) {
+                    errors.push(SettingsError::soft(
+                        field,
+                        format!("{angle} radians isn't between 0 and pi"),
+                    ));
+                }
+            }
+        }
+        for (field, rate) in [
+            ("decay_rate", self.decay_rate),
+            ("diffuse_rate", self.diffuse_rate),
+            ("age_deposit_falloff", self.age_deposit_falloff),
+        ] {
+            if rate.is_finite() && !(0. ..=1.).contains(&rate) {
+                errors.push(SettingsError::soft(
+                    field,
+                    format!("{rate} isn't between 0 and 1"),
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// The `boundary_mode` the agents follow, [`BoundaryMode::Wrap`] when `seamless`.
+    pub(crate) fn boundary(&self) -> BoundaryMode {
+        if self.seamless {
+            BoundaryMode::Wrap
+        } else {
+            self.boundary_mode
+        }
+    }
+
+    /// Replaces the values the simulation can't run with, warning about each. The plugin does
+    /// this with the settings it is given.
+    pub fn validated(mut self) -> Self {
+        if self.substeps == 0 {
+            warn!("A substeps of 0 isn't supported, using 1");
+            self.substeps = 1;
+        }
+        if let Some(rate) = self.steps_per_second {
+            if !rate.is_finite() || rate <= 0. {
+                warn!(
+                    "A steps_per_second of {rate} isn't supported, stepping once a frame instead"
+                );
+                self.steps_per_second = None;
+            }
+        }
+        if let Some(max_dt) = self.max_dt {
+            if !max_dt.is_finite() || max_dt <= 0. {
+                let default = sim_clock::DEFAULT_MAX_DT;
+                warn!("A max_dt of {max_dt} isn't supported, using {default}");
+                self.max_dt = Some(default);
+            }
+        }
+        let blur_radius = self.blur_radius.clamp(1, MAX_BLUR_RADIUS);
+        if blur_radius != self.blur_radius {
+            warn!(
+                "A blur_radius of {} isn't supported, using {blur_radius}",
+                self.blur_radius
+            );
+            self.blur_radius = blur_radius;
+        }
+        if self.deposit_amount < 0. {
+            warn!(
+                "A deposit_amount of {} isn't supported, using 0",
+                self.deposit_amount
+            );
+            self.deposit_amount = 0.;
+        }
+        if self.noise_scale.is_nan() || self.noise_scale <= 0. {
+            warn!(
+                "A noise_scale of {} isn't supported, using 1",
+                self.noise_scale
+            );
+            self.noise_scale = 1.;
+        }
+        let exposure_smoothing = self.exposure_smoothing.clamp(0., 1.);
+        if exposure_smoothing != self.exposure_smoothing {
+            warn!(
+                "An exposure_smoothing of {} isn't supported, using {exposure_smoothing}",
+                self.exposure_smoothing
+            );
+            self.exposure_smoothing = exposure_smoothing;
+        }
+        if let DepositShape::Disk(radius) = self.deposit_shape {
+            if !(0. ..=MAX_DEPOSIT_RADIUS).contains(&radius) {
+                // unlike clamp, max turns NaN into 0
+                let clamped = radius.max(0.).min(MAX_DEPOSIT_RADIUS);
+                warn!("A deposit_shape Disk({radius}) isn't supported, using Disk({clamped})");
+                self.deposit_shape = DepositShape::Disk(clamped);
+            }
+        }
+        if self.sharpen_amount < 0. {
+            warn!(
+                "A sharpen_amount of {} isn't supported, using 0",
+                self.sharpen_amount
+            );
+            self.sharpen_amount = 0.;
+        }
+        let sensor_count = (self.sensor_count | 1).min(MAX_SENSORS);
+        if sensor_count != self.sensor_count {
+            warn!(
+                "A sensor_count of {} isn't supported, it has to be odd and at most \
+                 {MAX_SENSORS}, using {sensor_count}",
+                self.sensor_count
+            );
+            self.sensor_count = sensor_count;
+        }
+        if self.memory_length > MAX_MEMORY as u32 {
+            warn!(
+                "A memory_length of {} isn't supported, using {MAX_MEMORY}",
+                self.memory_length
+            );
+            self.memory_length = MAX_MEMORY as u32;
+        }
+        if self.color_mode == ColorMode::Species && self.palette != Palette::Species {
+            self.color_mode = ColorMode::Intensity;
+        }
+        if self.color_mode == ColorMode::Direction
+            && self.species_count > palette::DIRECTION_SPECIES
+        {
+            warn!(
+                "A species_count of {} isn't supported with Direction coloring, using {}",
+                self.species_count,
+                palette::DIRECTION_SPECIES
+            );
+            self.species_count = palette::DIRECTION_SPECIES;
+        }
+        let species_count = self.species_count.clamp(1, MAX_SPECIES as u32) as usize;
+        for (name, matrix) in [
+            ("deposit_matrix", &mut self.deposit_matrix),
+            ("sense_matrix", &mut self.sense_matrix),
+        ] {
+            let square = matrix.len() == species_count
+                && matrix.iter().all(|row| row.len() == species_count);
+            if !matrix.is_empty() && !square {
+                warn!(
+                    "A {name} that isn't {species_count}x{species_count} like the \
+                     species_count isn't supported, leaving it out"
+                );
+                matrix.clear();
+            }
+        }
+        if self
+            .deposit_matrix
+            .iter()
+            .flatten()
+            .any(|&fraction| fraction < 0.)
+        {
+            warn!("A deposit_matrix with fractions below 0 isn't supported, using 0 for those");
+            for fraction in self.deposit_matrix.iter_mut().flatten() {
+                *fraction = fraction.max(0.);
+            }
+        }
+        for animation in &mut self.animations {
+            if !animation.period.is_finite() || animation.period <= 0. {
+                warn!(
+                    "An animation period of {} isn't supported, using 1",
+                    animation.period
+                );
+                animation.period = 1.;
+            }
+        }
+        self
+    }
+
+    /// The species as the shader senses them, with the rows of `sense_matrix` as their
+    /// `interaction` when it is given, and ignoring the channels holding the heading with
+    /// [`ColorMode::Direction`].
+    fn sensing_species(&self) -> [SpeciesSettings; MAX_SPECIES] {
+        let mut species = self.species;
+        if let Some(rows) = matrix_rows(&self.sense_matrix) {
+            for (species, row) in species.iter_mut().zip(rows) {
+                species.interaction = row;
+            }
+        }
+        if self.color_mode == ColorMode::Direction {
+            for species in &mut species {
+                species.interaction.z = 0.;
+                species.interaction.w = 0.;
+            }
+        }
+        species
+    }
+}
+
+/// The rows of a species matrix as the shader takes them, one [`Vec4`] per species with zeroes
+/// for the species not in use, `None` when the matrix is empty.
+fn matrix_rows(matrix: &[Vec<f32>]) -> Option<[Vec4; MAX_SPECIES]> {
+    if matrix.is_empty() {
+        return None;
+    }
+    let mut rows = [Vec4::ZERO; MAX_SPECIES];
+    for (row, entries) in rows.iter_mut().zip(matrix) {
+        for (channel, &entry) in entries.iter().enumerate().take(MAX_SPECIES) {
+            row[channel] = entry;
+        }
+    }
+    Some(rows)
+}
+
+/// Texels an agent spreads its deposit over every step, the same total amount whichever shape.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum DepositShape {
+    /// The texel the agent ends up on, or the four around it with `antialiased_deposit`.
+    #[default]
+    Point,
+    /// Evenly along the line the agent moved along this step, so fast agents leave a
+    /// continuous line rather than dots. Moves longer than [`MAX_SEGMENT_LENGTH`] texels are
+    /// taken to be wrapping around the map or respawning and deposit a point.
+    Segment,
+    /// Evenly over the texels whose centers are within this radius of the agent, at most
+    /// [`MAX_DEPOSIT_RADIUS`].
+    Disk(f32),
+}
+
+impl DepositShape {
+    /// Passed to the shader as `deposit_shape`, its `DEPOSIT_*` constants are filled in from it
+    /// by [`ShaderConstants`].
+    fn gpu_value(self) -> u32 {
+        match self {
+            Self::Point => 0,
+            Self::Segment => 1,
+            Self::Disk(_) => 2,
+        }
+    }
+}
+
+/// Initial layout of the agents.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SpawnPattern {
+    /// Every agent starts at the center, facing a random direction.
+    #[default]
+    CenterPoint,
+    /// Agents are scattered over the whole map, facing random directions.
+    RandomUniform,
+    /// Agents are scattered inside a circle, facing its center.
+    CircleInward,
+    /// Agents are scattered inside a circle, facing away from its center.
+    CircleOutward,
+    /// Agents are scattered inside a ring, facing random directions.
+    RingRandom,
+}
+
+/// Initial heading of the agents, relative to the center of the map.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum HeadingMode {
+    /// Every agent faces a random direction.
+    Random,
+    /// Every agent faces the center, so they all head for it.
+    FacingCenter,
+    /// Every agent faces straight away from the center.
+    AwayFromCenter,
+    /// Perpendicular to the line from the center, turning counterclockwise around it, which
+    /// starts the simulation off as a vortex.
+    Tangential,
+    /// Every agent faces the same angle in radians, 0 being along the x axis.
+    Fixed(f32),
+}
+
+impl HeadingMode {
+    /// Passed to the shader as `initial_heading`, its `HEADING_*` constants are filled in from
+    /// it by [`ShaderConstants`].
+    fn gpu_value(self) -> u32 {
+        match self {
+            Self::Random => 1,
+            Self::FacingCenter => 2,
+            Self::AwayFromCenter => 3,
+            Self::Tangential => 4,
+            Self::Fixed(_) => 5,
+        }
+    }
+
+    /// Heading in radians of an agent `offset` from the center of the map, `random` being
+    /// uniform in `[0, 1)`.
+    ///
+    /// Agents right on the center have no direction to it, so they face a random one unless the
+    /// heading is fixed.
+    fn angle(self, offset: Vec2, random: f32) -> f32 {
+        let outward = offset.y.atan2(offset.x);
+        match self {
+            Self::Fixed(angle) => angle,
+            Self::Random => random * 2. * PI,
+            _ if offset == Vec2::ZERO => random * 2. * PI,
+            Self::FacingCenter => outward + PI,
+            Self::AwayFromCenter => outward,
+            Self::Tangential => outward + PI / 2.,
+        }
+    }
+}
+
+/// Behaviour of agents at the edge of the map, passed to the shader as a `u32`.
+///
+/// The shader's `BOUNDARY_*` constants are filled in from it by [`ShaderConstants`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BoundaryMode {
+    /// The map is a torus: agents leaving one edge come back on the opposite one, and sensors and
+    /// the diffuse pass see across the edges too.
+    Wrap = 0,
+    /// Agents are reflected off the edges.
+    #[default]
+    Bounce = 1,
+    /// Agents reaching an edge are killed. They respawn where and facing how `spawn_pattern`
+    /// and `initial_heading` place them at startup, unless `kill_respawn` is off.
+    Kill = 2,
+    /// Agents are confined to a circle of `arena_radius` around the center of the map and are
+    /// reflected off its edge, like in a petri dish. They leave no trail outside of it.
+    Circle = 3,
+}
+
+/// Texel format of the trail maps, see [`SimulationSettings::trail_precision`].
+///
+/// The resolved precision is also inserted into the render world, which lays out the pipelines
+/// before the [`SimulationConfig`] is extracted.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize, Resource)]
+pub enum TrailPrecision {
+    /// `Rgba16Float`, about three significant digits, which turns faint trail decaying by a
+    /// `decay_rate` close to 1 into steps.
+    F16,
+    /// `Rgba32Float`.
+    #[default]
+    F32,
+}
+
+impl TrailPrecision {
+    fn texture_format(self) -> TextureFormat {
+        match self {
+            TrailPrecision::F16 => TextureFormat::Rgba16Float,
+            TrailPrecision::F32 => TextureFormat::Rgba32Float,
+        }
+    }
+
+    /// The format as written in WGSL, substituted for `#{TRAIL_FORMAT}` in the shader.
+    fn wgsl_format(self) -> &'static str {
+        match self {
+            TrailPrecision::F16 => "rgba16float",
+            TrailPrecision::F32 => "rgba32float",
+        }
+    }
+
+    /// Size in bytes of a texel.
+    pub(crate) fn texel_size(self) -> u32 {
+        self.texture_format().describe().block_size as u32
+    }
+
+    /// This precision if the shader can read and write it on `adapter`, `F32` otherwise.
+    fn supported(self, adapter: &RenderAdapter) -> Self {
+        if self == TrailPrecision::F32 {
+            return self;
+        }
+        let features = adapter.get_texture_format_features(self.texture_format());
+        if features
+            .allowed_usages
+            .contains(TextureUsages::STORAGE_BINDING)
+            && features
+                .flags
+                .contains(wgpu::TextureFormatFeatureFlags::STORAGE_READ_WRITE)
+        {
+            return self;
+        }
+        warn!(
+            "{} can't read and write {:?} storage textures, using F32 trail precision",
+            adapter.get_info().name,
+            self.texture_format()
+        );
+        TrailPrecision::F32
+    }
+}
+
+/// Fraction of the smaller map dimension used as the radius of the circular spawn patterns.
+/// Must match `SPAWN_RADIUS` in simple.wgsl.
+const SPAWN_RADIUS: f32 = 0.4;
+/// Fraction of the spawn radius where [`SpawnPattern::RingRandom`] starts. Must match
+/// `RING_INNER_RADIUS` in simple.wgsl.
+const RING_INNER_RADIUS: f32 = 0.8;
+/// Columns and rows of tiles the spawn pattern is repeated on with
+/// [`SimulationSettings::seamless`]. Must match `SEAMLESS_TILES` in simple.wgsl.
+const SEAMLESS_TILES: u32 = 2;
+
+/// GPU layout of [`SimulationSettings`], uploaded as a uniform buffer.
+#[derive(Debug, Copy, Clone, ShaderType, Pod, Zeroable)]
+#[repr(C)]
+struct GpuSimulationSettings {
+    pub agent_count: u32,
+    pub move_speed: f32,
+    pub turn_speed: f32,
+    /// [`SimulationSettings::sensor_spread`], or the `sensor_angle` it defaults to.
+    pub sensor_spread: f32,
+    pub sensor_distance: f32,
+    pub decay_rate: f32,
+    pub diffuse_rate: f32,
+    pub species_count: u32,
+    /// [`SimulationSettings::time_scale`] divided by the substeps, so every update pass moves
+    /// the agents by one substep.
+    pub time_scale: f32,
+    /// The 64 bit seed folded down to 32 bits, WGSL has no 64 bit integers.
+    pub seed: u32,
+    pub boundary_mode: u32,
+    pub color_mode: u32,
+    pub max_trail: f32,
+    pub arena_radius: f32,
+    pub food_attraction: f32,
+    pub food_consumption: f32,
+    pub sense_weight: f32,
+    pub deposit_amount: f32,
+    pub memory_penalty: f32,
+    pub memory_length: u32,
+    pub sensor_count: u32,
+    pub antialiased_deposit: u32,
+    pub substeps: u32,
+    pub sharpen_amount: f32,
+    pub kill_respawn: u32,
+    pub spawn_pattern: u32,
+    /// 0 to keep the heading of the spawn pattern, otherwise [`HeadingMode::gpu_value`].
+    pub initial_heading: u32,
+    /// The angle of [`HeadingMode::Fixed`].
+    pub heading_angle: f32,
+    pub blur_radius: u32,
+    pub gaussian_blur: u32,
+    pub crowd_avoidance: f32,
+    pub noise_strength: f32,
+    pub noise_scale: f32,
+    /// [`DepositShape::gpu_value`].
+    pub deposit_shape: u32,
+    /// The radius of [`DepositShape::Disk`].
+    pub deposit_radius: f32,
+    pub exploration: f32,
+    pub exploration_threshold: f32,
+    pub max_age: u32,
+    pub age_deposit_falloff: f32,
+    pub evaporation_floor: f32,
+    pub seamless: u32,
+    pub _padding1: u32,
+    pub _padding2: u32,
+    pub background: Vec4,
+    /// Row `i` is the fraction species `i` deposits on each trail channel, see
+    /// [`SimulationSettings::deposit_matrix`].
+    pub deposit_matrix: [Vec4; MAX_SPECIES],
+    /// With the rows of [`SimulationSettings::sense_matrix`] as their `interaction`.
+    pub species: [SpeciesSettings; MAX_SPECIES],
+}
+
+impl From<&SimulationSettings> for GpuSimulationSettings {
+    fn from(settings: &SimulationSettings) -> Self {
+        Self {
+            agent_count: settings.agent_count,
+            move_speed: settings.move_speed,
+            turn_speed: settings.turn_speed,
+            sensor_spread: settings.sensor_spread.unwrap_or(settings.sensor_angle),
+            sensor_distance: settings.sensor_distance,
+            decay_rate: if settings.enable_decay {
+                settings.decay_rate
+            } else {
+                1.
+            },
+            diffuse_rate: if settings.enable_diffuse {
+                settings.diffuse_rate
+            } else {
+                0.
+            },
+            species_count: settings.species_count,
+            time_scale: settings.time_scale / settings.substeps.max(1) as f32,
+            seed: (settings.seed ^ (settings.seed >> 32)) as u32,
+            boundary_mode: settings.boundary() as u32,
+            color_mode: settings.color_mode as u32,
+            max_trail: settings.max_trail,
+            arena_radius: settings
+                .arena_radius
+                .unwrap_or(settings.sim_width.min(settings.sim_height) as f32 / 2.),
+            food_attraction: settings.food_attraction,
+            food_consumption: settings.food_consumption.max(0.),
+            sense_weight: settings.sense_weight,
+            deposit_amount: settings.deposit_amount,
+            memory_penalty: settings.memory_penalty,
+            memory_length: settings.memory_length.min(MAX_MEMORY as u32),
+            sensor_count: (settings.sensor_count | 1).min(MAX_SENSORS),
+            antialiased_deposit: settings.antialiased_deposit as u32,
+            substeps: settings.substeps.max(1),
+            sharpen_amount: settings.sharpen_amount.max(0.),
+            kill_respawn: settings.kill_respawn as u32,
+            spawn_pattern: settings.spawn_pattern as u32,
+            initial_heading: settings.initial_heading.map_or(0, HeadingMode::gpu_value),
+            heading_angle: match settings.initial_heading {
+                Some(HeadingMode::Fixed(angle)) => angle,
+                _ => 0.,
+            },
+            blur_radius: settings.blur_radius.clamp(1, MAX_BLUR_RADIUS),
+            gaussian_blur: settings.gaussian_blur as u32,
+            crowd_avoidance: settings.crowd_avoidance,
+            noise_strength: settings.noise_strength,
+            noise_scale: settings.noise_scale,
+            deposit_shape: settings.deposit_shape.gpu_value(),
+            deposit_radius: match settings.deposit_shape {
+                DepositShape::Disk(radius) => radius.min(MAX_DEPOSIT_RADIUS),
+                _ => 0.,
+            },
+            exploration: settings.exploration,
+            exploration_threshold: settings.exploration_threshold,
+            max_age: settings.max_age,
+            age_deposit_falloff: settings.age_deposit_falloff,
+            evaporation_floor: settings.evaporation_floor.max(0.),
+            seamless: settings.seamless as u32,
+            _padding1: 0,
+            _padding2: 0,
+            background: Vec4::from(settings.background),
+            deposit_matrix: matrix_rows(&settings.deposit_matrix).unwrap_or([
+                Vec4::X,
+                Vec4::Y,
+                Vec4::Z,
+                Vec4::W,
+            ]),
+            species: settings.sensing_species(),
+        }
+    }
+}
+
+/// Small xorshift generator, enough for scattering agents reproducibly.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // xorshift gets stuck at zero, so scramble the seed first (splitmix64 finalizer)
+        let mut state = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Self((state ^ (state >> 31)).max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Uniformly distributed in `[0, 1)`.
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+#[derive(Debug, Copy, Clone, ShaderType, Pod, Zeroable, Serialize, Deserialize)]
+#[repr(C)]
+#[serde(default)]
+pub struct SpeciesSettings {
+    /// Color the trail of this species is displayed with.
+    pub color: Vec4,
+    /// Weight of each species' trail channel when sensing, positive to attract and negative to
+    /// repel. Replaced by the row of [`SimulationSettings::sense_matrix`] when that is given.
+    pub interaction: Vec4,
+    /// Multiplier of [`SimulationSettings::move_speed`] for this species.
+    pub move_speed: f32,
+    /// Multiplier of [`SimulationSettings::sensor_spread`] for this species.
+    pub sensor_angle: f32,
+    #[serde(skip)]
+    pub _padding0: f32,
+    #[serde(skip)]
+    pub _padding1: f32,
+}
+
+impl SpeciesSettings {
+    /// A species attracted to its own trail at `channel` and repelled by every other species.
+    fn new(channel: usize, color: Vec4) -> Self {
+        let mut interaction = Vec4::splat(-1.);
+        interaction[channel] = 1.;
+        Self {
+            color,
+            interaction,
+            move_speed: 1.,
+            sensor_angle: 1.,
+            _padding0: 0.,
+            _padding1: 0.,
+        }
+    }
+}
+
+impl Default for SpeciesSettings {
+    fn default() -> Self {
+        Self::new(0, Vec4::ONE)
+    }
+}
+
+#[derive(Debug, Clone, Default, Deref, TypeUuid)]
+#[uuid = "1ebefa44-80b6-46bc-939d-5bf39ff15f53"]
+pub struct Slime(SimulationSettings);
+
+#[derive(Default)]
+pub struct SlimeLoader;
+
+impl AssetLoader for SlimeLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let settings = parse_settings(bytes, load_context.path())?;
+            load_context.set_default_asset(LoadedAsset::new(Slime(settings)));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["slime"]
+    }
+}
+
+/// Parses the `.slime` file at `path`, failing on [`hard`](field@SettingsError::hard) errors and
+/// warning about the soft ones before [`SimulationSettings::validated`] patches them up.
+fn parse_settings(bytes: &[u8], path: &Path) -> Result<SimulationSettings, bevy::asset::Error> {
+    let settings = ron::de::from_bytes::<SimulationSettings>(bytes)?;
+    if let Err(errors) = settings.validate() {
+        let path = path.display();
+        for error in errors.iter().filter(|error| !error.hard) {
+            warn!("{path}: {error}");
+        }
+        let hard: Vec<_> = errors
+            .iter()
+            .filter(|error| error.hard)
+            .map(ToString::to_string)
+            .collect();
+        if !hard.is_empty() {
+            return Err(bevy::asset::Error::msg(format!(
+                "Can't run the settings in {path}: {}",
+                hard.join(", ")
+            )));
+        }
+    }
+    Ok(settings.validated())
+}
+
+#[derive(Debug, Copy, Clone, ShaderType, Pod, Zeroable)]
+#[repr(C)]
+pub struct Agent {
+    pub position: Vec2,
+    pub angle: f32,
+    /// Index into [`SimulationSettings::species`].
+    pub species: u32,
+    /// Multiplier of the move speed, picked at spawn from [`SimulationSettings::speed_jitter`].
+    pub speed: f32,
+    /// Index into `recent` the next position is written to.
+    pub memory_cursor: u32,
+    /// The last positions of the agent, see [`SimulationSettings::memory_length`].
+    pub recent: [Vec2; MAX_MEMORY],
+    /// 0 once the agent was killed at the edge with [`SimulationSettings::kill_respawn`] off, it
+    /// is then parked off the map and neither moves nor deposits.
+    pub alive: u32,
+    /// Steps lived since the agent was spawned or last respawned, see
+    /// [`SimulationSettings::max_age`].
+    pub age: u32,
+}
+
+impl Agent {
+    /// An agent of the first species moving at the base speed, remembering only where it starts.
+    pub fn new(position: Vec2, angle: f32) -> Self {
+        Self {
+            position,
+            angle,
+            species: 0,
+            speed: 1.,
+            memory_cursor: 0,
+            recent: [position; MAX_MEMORY],
+            alive: 1,
+            age: 0,
+        }
+    }
+}
+
+/// Places agent `index` of `count`, see [`SlimeComputePlugin::with_agent_init`].
+pub type AgentInit = Arc<dyn Fn(u32, u32) -> Agent + Send + Sync>;
+
+/// The [`AgentInit`] of the plugin, used instead of the spawn pattern when set.
+#[derive(Clone, Default, Resource)]
+struct AgentInitializer(Option<AgentInit>);
+
+/// Storage buffers holding the state of every agent.
+///
+/// The update pass reads one and writes the other, so agents can look at their neighbours
+/// without racing their writes. The roles swap with the trail maps every step.
+#[derive(Resource)]
+struct AgentBuffer {
+    buffers: [Buffer; 2],
+    /// Number of agents spawned into both buffers.
+    count: u32,
+    /// Number of agents both buffers have room for, the ones past `count` are added by bursts.
+    capacity: u32,
+    /// Index of the buffer written by the last step, set by [`nodes::advance_step`].
+    latest: usize,
+}
+
+impl AgentBuffer {
+    fn new(render_device: &RenderDevice, count: u32, capacity: u32) -> Self {
+        let create_buffer = |label| {
+            render_device.create_buffer(&BufferDescriptor {
+                label: Some(label),
+                usage: BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+                size: capacity as u64 * std::mem::size_of::<Agent>() as u64,
+                mapped_at_creation: false,
+            })
+        };
+        Self {
+            buffers: [create_buffer("agents_0"), create_buffer("agents_1")],
+            count,
+            capacity,
+            latest: 0,
+        }
+    }
+
+    /// The agents as of the last step.
+    fn latest(&self) -> &Buffer {
+        &self.buffers[self.latest]
+    }
+
+    /// Replaces every agent, in both buffers so the next step reads them whichever way round
+    /// the buffers are.
+    fn write(&self, render_queue: &RenderQueue, agents: &[Agent]) {
+        self.write_from(render_queue, 0, agents);
+    }
+
+    /// Replaces the agents starting at index `first`.
+    fn write_from(&self, render_queue: &RenderQueue, first: u32, agents: &[Agent]) {
+        let offset = agent_access::agent_offset(first);
+        for buffer in &self.buffers {
+            render_queue.write_buffer(buffer, offset, bytemuck::cast_slice(agents));
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct GpuSlime {
+    pub buffer: Buffer,
+    /// Whether [`nodes::DepositNode`] runs the resolve pass of [`deposit`].
+    pub antialiased_deposit: bool,
+    /// Update passes [`nodes::UpdateNode`] runs each step, see [`SimulationSettings::substeps`].
+    pub substeps: u32,
+    /// Whether [`nodes::DiffuseNode`] runs the sharpen pass after diffusing, see
+    /// [`SimulationSettings::sharpen_amount`].
+    pub sharpen: bool,
+    /// Whether the update pass steers away from crowds, which needs the agents counted into the
+    /// [`density::DensityBuffer`] every step, see [`SimulationSettings::crowd_avoidance`].
+    pub avoids_crowds: bool,
+}
+
+/// The uniform buffer backing every [`GpuSlime`].
+///
+/// It is created once and rewritten in place when the settings change, so bind groups recorded
+/// for a frame that is still in flight keep pointing at a valid buffer.
+#[derive(Resource, Default)]
+struct SlimeSettingsBuffer(Option<Buffer>);
+
+impl RenderAsset for Slime {
+    type ExtractedAsset = (GpuSimulationSettings, [[u8; 4]; palette::PALETTE_SIZE]);
+    type PreparedAsset = GpuSlime;
+    type Param = (
+        SRes<RenderDevice>,
+        SRes<RenderQueue>,
+        SResMut<SlimeSettingsBuffer>,
+        SRes<palette::PaletteTexture>,
+    );
+
+    /// Converts the settings to their GPU layout and bakes the selected palette.
+    fn extract_asset(&self) -> Self::ExtractedAsset {
+        (
+            GpuSimulationSettings::from(&self.0),
+            self.palette.lut(self.background),
+        )
+    }
+
+    /// Uploads the extracted settings into a uniform buffer and the palette into its texture.
+    fn prepare_asset(
+        (settings, palette_lut): Self::ExtractedAsset,
+        (render_device, render_queue, settings_buffer, palette_texture): &mut SystemParamItem<
+            Self::Param,
+        >,
+    ) -> Result<Self::PreparedAsset, PrepareAssetError<Self::ExtractedAsset>> {
+        let buffer = settings_buffer
+            .0
+            .get_or_insert_with(|| create_settings_buffer(render_device))
+            .clone();
+        palette_texture.write(render_queue, &palette_lut);
+        Ok(GpuSlime::write(buffer, render_queue, &settings))
+    }
+}
+
+/// Creates a uniform buffer for the [`GpuSimulationSettings`].
+fn create_settings_buffer(render_device: &RenderDevice) -> Buffer {
+    // the settings are written with bytemuck, so the Rust layout has to cover every byte the
+    // shader, and the `min_binding_size` of the layout, expects
+    let size = std::mem::size_of::<GpuSimulationSettings>() as u64;
+    debug_assert!(
+        size >= GpuSimulationSettings::min_size().get(),
+        "settings buffer is smaller than its min_binding_size"
+    );
+    render_device.create_buffer(&BufferDescriptor {
+        label: Some("simulation_settings"),
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        size,
+        mapped_at_creation: false,
+    })
+}
+
+impl GpuSlime {
+    /// Writes `settings` into `buffer`, which the returned settings are then backed by.
+    fn write(buffer: Buffer, render_queue: &RenderQueue, settings: &GpuSimulationSettings) -> Self {
+        render_queue.write_buffer(&buffer, 0, bytemuck::cast_slice(&[*settings]));
+        Self {
+            buffer,
+            antialiased_deposit: settings.antialiased_deposit != 0,
+            substeps: settings.substeps,
+            sharpen: settings.sharpen_amount > 0.,
+            avoids_crowds: settings.crowd_avoidance != 0.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deref, Resource, ExtractResource)]
+struct SlimeHandle(Handle<Slime>);
+
+/// Parameters fixed at startup that decide how much GPU work the simulation allocates.
+#[derive(Debug, Clone, Resource, ExtractResource)]
+struct SimulationConfig {
+    pub agent_count: u32,
+    /// Room in the agent buffers beyond `agent_count`, see [`Self::agent_capacity`].
+    pub burst_headroom: u32,
+    /// Agents are assigned to species round-robin.
+    pub species_count: u32,
+    pub seed: u64,
+    pub spawn_pattern: SpawnPattern,
+    pub initial_heading: Option<HeadingMode>,
+    /// Whether the spawn pattern is tiled, see [`SimulationSettings::seamless`].
+    pub seamless: bool,
+    pub speed_jitter: f32,
+    /// Size of the trail map in texels, the window shows it scaled to fit.
+    pub sim_width: u32,
+    pub sim_height: u32,
+    /// Substituted for `#{TEXTURE_WORKGROUP_SIZE}` in the shader.
+    pub texture_workgroup_size: u32,
+    /// Substituted for `#{AGENT_WORKGROUP_SIZE}` in the shader.
+    pub agent_workgroup_size: u32,
+    /// [`SimulationSettings::trail_precision`] once checked against the GPU.
+    pub trail_precision: TrailPrecision,
+    /// [`SimulationSettings::grid_cell_size`], 0 without a grid.
+    pub grid_cell_size: u32,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            agent_count: NO_SLIMES,
+            burst_headroom: 0,
+            species_count: 1,
+            seed: 0,
+            spawn_pattern: SpawnPattern::default(),
+            initial_heading: None,
+            seamless: false,
+            speed_jitter: 0.,
+            sim_width: WIDTH as u32,
+            sim_height: HEIGHT as u32,
+            texture_workgroup_size: TEXTURE_WORKGROUP_SIZE,
+            agent_workgroup_size: AGENT_WORKGROUP_SIZE,
+            trail_precision: TrailPrecision::default(),
+            grid_cell_size: 0,
+        }
+    }
+}
+
+/// Number of workgroups of `workgroup_size` invocations needed to cover `invocations`.
+pub(crate) fn workgroups_for(invocations: u32, workgroup_size: u32) -> u32 {
+    (invocations + workgroup_size - 1) / workgroup_size
+}
+
+impl SimulationConfig {
+    /// The configuration `settings` start with, given what the shader was built for.
+    fn new(
+        settings: &SimulationSettings,
+        texture_workgroup_size: u32,
+        agent_workgroup_size: u32,
+        trail_precision: TrailPrecision,
+    ) -> Self {
+        Self {
+            agent_count: clamp_agent_count(settings.agent_count),
+            burst_headroom: settings.burst_headroom,
+            species_count: settings.species_count.clamp(1, MAX_SPECIES as u32),
+            seed: settings.seed,
+            spawn_pattern: settings.spawn_pattern,
+            initial_heading: settings.initial_heading,
+            seamless: settings.seamless,
+            speed_jitter: settings.speed_jitter.max(0.),
+            sim_width: settings.sim_width.max(1),
+            sim_height: settings.sim_height.max(1),
+            texture_workgroup_size,
+            agent_workgroup_size,
+            trail_precision,
+            grid_cell_size: settings.grid_cell_size,
+        }
+    }
+
+    /// Number of agents the agent buffers have room for, at least `agent_count`.
+    fn agent_capacity(&self) -> u32 {
+        self.agent_count
+            .saturating_add(self.burst_headroom)
+            .min(MAX_AGENT_COUNT)
+            .max(self.agent_count)
+    }
+
+    fn trail_extent(&self) -> Extent3d {
+        Extent3d {
+            width: self.sim_width,
+            height: self.sim_height,
+            depth_or_array_layers: 1,
+        }
+    }
+
+    fn sim_size(&self) -> Vec2 {
+        Vec2::new(self.sim_width as f32, self.sim_height as f32)
+    }
+}
+
+/// The textures agents deposit their trail onto and sense from.
+///
+/// The diffuse pass reads one texture and writes the other, with the roles swapping every frame.
+#[derive(Debug, Clone, Deref, Resource, ExtractResource)]
+struct TrailMap([Handle<Image>; 2]);
+
+/// Color mapped version of the trail map, written by the colorize pass and shown on screen.
+///
+/// The trail map itself is a float format, which can't be filtered when sampled by the
+/// [`display::TrailMaterial`] showing this.
+#[derive(Debug, Clone, Deref, Resource, ExtractResource)]
+struct TrailDisplay(Handle<Image>);
+
+/// Texels sensed by the agents this frame, drawn over the trail while [`inspect::SensorDebug`]
+/// is enabled.
+///
+/// Same size and format as the trail map, the alpha channel says how opaque the marker is.
+#[derive(Debug, Clone, Deref, Resource, ExtractResource)]
+struct SensorOverlay(Handle<Image>);
+
+/// Marks the quad showing the [`TrailDisplay`], spawned by [`display::TrailDisplayPlugin`].
+#[derive(Component)]
+struct TrailSprite;
+
+/// Lifecycle of the simulation, driven by the keyboard.
+#[derive(Debug, Clone, Resource, ExtractResource)]
+struct SimState {
+    /// Toggled by Space.
+    pub running: bool,
+    /// Advance a single frame while paused, requested with `.`.
+    pub step: bool,
+    /// Respawn the agents and clear the trail map this frame, requested with R.
+    pub reset: bool,
+    /// Clear the trail map this frame but leave the agents where they are, requested with C.
+    pub clear_trail: bool,
+}
+
+impl Default for SimState {
+    fn default() -> Self {
+        Self {
+            running: true,
+            step: false,
+            reset: false,
+            clear_trail: false,
+        }
+    }
+}
+
+impl SimState {
+    /// Whether the simulation advances this frame.
+    fn advances(&self) -> bool {
+        self.running || self.step
+    }
+}
+
+/// Splats queued by the main world this frame, as trail map pixel coordinates, strength and
+/// what they add to.
+///
+/// Negative strengths erase trail.
+#[derive(Debug, Clone, Default, Deref, DerefMut, Resource, ExtractResource)]
+struct TrailInjections(Vec<(UVec2, f32, InjectionChannel)>);
+
+/// What a [`TrailInjections`] splat adds to, passed to the shader as a `u32`.
+///
+/// The shader's `INJECT_*` constants are filled in from it by [`ShaderConstants`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum InjectionChannel {
+    /// Every species' channel of the trail map.
+    Trail = 0,
+    /// The food map.
+    Food = 1,
+}
+
+#[derive(Debug, Copy, Clone, ShaderType, Pod, Zeroable)]
+#[repr(C)]
+struct GpuTrailInjection {
+    pub position: UVec2,
+    pub strength: f32,
+    pub channel: u32,
+}
+
+/// Storage buffer with this frame's [`TrailInjections`], applied by the `inject` entry point.
+#[derive(Resource)]
+struct InjectionBuffer {
+    buffer: Buffer,
+    count: usize,
+}
+
+fn create_trail_image(size: Extent3d, precision: TrailPrecision) -> Image {
+    let zero = vec![0; precision.texel_size() as usize];
+    let mut trail = Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &zero,
+        precision.texture_format(),
+    );
+    // COPY_DST is needed for the initial upload of the zeroed image data.
+    trail.texture_descriptor.usage =
+        TextureUsages::STORAGE_BINDING | TextureUsages::COPY_SRC | TextureUsages::COPY_DST;
+    trail
+}
+
+fn create_display_image(size: Extent3d) -> Image {
+    let mut display = Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8Unorm,
+    );
+    display.texture_descriptor.usage = TextureUsages::STORAGE_BINDING
+        | TextureUsages::TEXTURE_BINDING
+        | TextureUsages::COPY_SRC
+        | TextureUsages::COPY_DST;
+    display
+}
+
+/// The simulation shader with the [`ShaderConstants`] substituted, added by [`setup`].
+///
+/// Since it is built from the file on disk, editing the file needs a restart.
+const SLIME_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x2f41_9a6c_d4b8_1e73);
+
+/// Clamps `requested` so that the `(size, size, 1)` workgroups of the per-texel passes fit
+/// within `limits`.
+fn clamp_texture_workgroup_size(requested: u32, limits: &WgpuLimits) -> u32 {
+    let max_square = (limits.max_compute_invocations_per_workgroup as f32).sqrt() as u32;
+    let max = limits
+        .max_compute_workgroup_size_x
+        .min(limits.max_compute_workgroup_size_y)
+        .min(max_square);
+    requested.clamp(1, max)
+}
+
+/// Clamps `requested` so that the `(size, 1, 1)` workgroups of the per-agent passes fit within
+/// `limits`.
+fn clamp_agent_workgroup_size(requested: u32, limits: &WgpuLimits) -> u32 {
+    let max = limits
+        .max_compute_workgroup_size_x
+        .min(limits.max_compute_invocations_per_workgroup);
+    requested.clamp(1, max)
+}
+
+/// Values shared by the Rust code and the shader, substituted for `#{NAME}` in the shader source
+/// so the two can't drift apart.
+///
+/// bevy's shader defs can only toggle code, so the values are substituted by hand.
+#[derive(Debug, Clone)]
+struct ShaderConstants {
+    texture_workgroup_size: u32,
+    agent_workgroup_size: u32,
+    trail_precision: TrailPrecision,
+}
+
+impl ShaderConstants {
+    fn new(config: &SimulationConfig) -> Self {
+        Self {
+            texture_workgroup_size: config.texture_workgroup_size,
+            agent_workgroup_size: config.agent_workgroup_size,
+            trail_precision: config.trail_precision,
+        }
+    }
+
+    /// Every `#{NAME}` the shader can use and its value.
+    fn values(&self) -> Vec<(&'static str, u32)> {
+        vec![
+            ("TEXTURE_WORKGROUP_SIZE", self.texture_workgroup_size),
+            // invocations of a square workgroup
+            (
+                "WORKGROUP_TEXELS",
+                self.texture_workgroup_size * self.texture_workgroup_size,
+            ),
+            ("AGENT_WORKGROUP_SIZE", self.agent_workgroup_size),
+            ("MAX_SPECIES", MAX_SPECIES as u32),
+            ("GRID_SCAN_THREADS", grid::SCAN_THREADS),
+            ("MAX_MEMORY", MAX_MEMORY as u32),
+            ("MAX_BLUR_RADIUS", MAX_BLUR_RADIUS),
+            ("BOUNDARY_WRAP", BoundaryMode::Wrap as u32),
+            ("BOUNDARY_BOUNCE", BoundaryMode::Bounce as u32),
+            ("BOUNDARY_KILL", BoundaryMode::Kill as u32),
+            ("BOUNDARY_CIRCLE", BoundaryMode::Circle as u32),
+            ("SPAWN_CENTER_POINT", SpawnPattern::CenterPoint as u32),
+            ("SPAWN_RANDOM_UNIFORM", SpawnPattern::RandomUniform as u32),
+            ("SPAWN_CIRCLE_INWARD", SpawnPattern::CircleInward as u32),
+            ("SPAWN_CIRCLE_OUTWARD", SpawnPattern::CircleOutward as u32),
+            ("SPAWN_RING_RANDOM", SpawnPattern::RingRandom as u32),
+            ("HEADING_RANDOM", HeadingMode::Random.gpu_value()),
+            (
+                "HEADING_FACING_CENTER",
+                HeadingMode::FacingCenter.gpu_value(),
+            ),
+            (
+                "HEADING_AWAY_FROM_CENTER",
+                HeadingMode::AwayFromCenter.gpu_value(),
+            ),
+            ("HEADING_TANGENTIAL", HeadingMode::Tangential.gpu_value()),
+            ("HEADING_FIXED", HeadingMode::Fixed(0.).gpu_value()),
+            ("COLOR_SPECIES", ColorMode::Species as u32),
+            ("COLOR_INTENSITY", ColorMode::Intensity as u32),
+            ("COLOR_DIRECTION", ColorMode::Direction as u32),
+            ("INJECT_TRAIL", InjectionChannel::Trail as u32),
+            ("INJECT_FOOD", InjectionChannel::Food as u32),
+            ("DEPOSIT_SCALE", deposit::DEPOSIT_SCALE),
+            ("DEPOSIT_POINT", DepositShape::Point.gpu_value()),
+            ("DEPOSIT_SEGMENT", DepositShape::Segment.gpu_value()),
+            ("DEPOSIT_DISK", DepositShape::Disk(0.).gpu_value()),
+            ("MAX_DEPOSIT_RADIUS", MAX_DEPOSIT_RADIUS as u32),
+            ("MAX_SEGMENT_LENGTH", MAX_SEGMENT_LENGTH),
+        ]
+    }
+
+    fn apply(&self, source: &str) -> String {
+        // the texel format of the trail maps, the only constant that isn't a number
+        let mut source = source.replace("#{TRAIL_FORMAT}", self.trail_precision.wgsl_format());
+        for (name, value) in self.values() {
+            source = source.replace(&format!("#{{{name}}}"), &value.to_string());
+        }
+        source
+    }
+
+    /// The first constant `source` still uses once [`Self::apply`]'d, ignoring comments.
+    fn unknown_constant(source: &str) -> Option<&str> {
+        source
+            .lines()
+            .map(|line| line.split("//").next().unwrap_or_default())
+            .find_map(|code| {
+                let start = code.find("#{")?;
+                code[start + 2..].split('}').next()
+            })
+    }
+}
+
+/// Reads the simulation shader from the asset folder and substitutes the [`ShaderConstants`].
+fn load_slime_shader(
+    asset_server: &AssetServer,
+    path: &str,
+    constants: &ShaderConstants,
+) -> Option<Shader> {
+    let bytes = match block_on(asset_server.asset_io().load_path(Path::new(path))) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            error!("Failed to read the shader {path}: {error}");
+            return None;
+        }
+    };
+    let Ok(source) = String::from_utf8(bytes) else {
+        error!("The shader {path} isn't valid UTF-8");
+        return None;
+    };
+    let source = constants.apply(&source);
+    if let Some(name) = ShaderConstants::unknown_constant(&source) {
+        error!("The shader {path} uses #{{{name}}}, which isn't one of the ShaderConstants");
+        return None;
+    }
+    Some(Shader::from_wgsl(source))
+}
+
+fn setup(
+    mut commands: Commands,
+    startup: Res<SlimeStartup>,
+    mut slimes: ResMut<Assets<Slime>>,
+    mut images: ResMut<Assets<Image>>,
+    mut shaders: ResMut<Assets<Shader>>,
+    asset_server: Res<AssetServer>,
+    render_device: Res<RenderDevice>,
+) {
+    commands.spawn(Camera2dBundle::default());
+    let settings = startup.settings.clone().validated();
+
+    let limits = render_device.limits();
+    let texture_workgroup_size = clamp_texture_workgroup_size(settings.workgroup_size, &limits);
+    if texture_workgroup_size != settings.workgroup_size {
+        warn!(
+            "workgroup_size {} isn't supported by this GPU, using {texture_workgroup_size}",
+            settings.workgroup_size
+        );
+    }
+    let agent_workgroup_size = clamp_agent_workgroup_size(settings.agent_workgroup_size, &limits);
+    if agent_workgroup_size != settings.agent_workgroup_size {
+        warn!(
+            "agent_workgroup_size {} isn't supported by this GPU, using {agent_workgroup_size}",
+            settings.agent_workgroup_size
+        );
+    }
+    let config = SimulationConfig::new(
+        &settings,
+        texture_workgroup_size,
+        agent_workgroup_size,
+        settings.trail_precision,
+    );
+    let constants = ShaderConstants::new(&config);
+    if let Some(shader) = load_slime_shader(&asset_server, &startup.shader, &constants) {
+        shaders.set_untracked(SLIME_SHADER_HANDLE, shader);
+    }
+    insert_trail_resources(
+        &mut commands,
+        &mut images,
+        &asset_server,
+        &settings,
+        &config,
+    );
+
+    commands.insert_resource(noise::NoiseTexture(
+        images.add(noise::create_noise_image(settings.seed)),
+    ));
+    commands.insert_resource(ParamAnimation(settings.animations.clone()));
+    let slime = slimes.add(Slime(settings));
+    commands.insert_resource(SlimeHandle(slime));
+    commands.insert_resource(config);
+}
+
+/// The trail maps of a simulation and everything sized like them.
+#[derive(Debug, Clone)]
+struct TrailImages {
+    trail_map: TrailMap,
+    display: TrailDisplay,
+    sensors: SensorOverlay,
+    obstacles: obstacles::ObstacleMask,
+    food: food::FoodMap,
+}
+
+impl TrailImages {
+    fn new(
+        images: &mut Assets<Image>,
+        asset_server: &AssetServer,
+        settings: &SimulationSettings,
+        config: &SimulationConfig,
+    ) -> Self {
+        let size = config.trail_extent();
+        let precision = config.trail_precision;
+        let obstacles =
+            obstacles::load_obstacle_mask(asset_server, settings.obstacle_mask.as_deref(), size);
+        Self {
+            trail_map: TrailMap([
+                images.add(create_trail_image(size, precision)),
+                images.add(create_trail_image(size, precision)),
+            ]),
+            display: TrailDisplay(images.add(create_display_image(size))),
+            sensors: SensorOverlay(images.add(create_trail_image(size, precision))),
+            obstacles: obstacles::ObstacleMask(images.add(obstacles)),
+            food: food::load_food_map(asset_server, settings.food_map.as_deref(), size),
+        }
+    }
+}
+
+/// Creates the trail maps and everything sized like them, replacing any previous ones.
+fn insert_trail_resources(
+    commands: &mut Commands,
+    images: &mut Assets<Image>,
+    asset_server: &AssetServer,
+    settings: &SimulationSettings,
+    config: &SimulationConfig,
+) {
+    let trail_images = TrailImages::new(images, asset_server, settings, config);
+    commands.insert_resource(trail_images.obstacles);
+    commands.insert_resource(trail_images.food);
+    commands.insert_resource(trail_images.trail_map);
+    commands.insert_resource(trail_images.display);
+    commands.insert_resource(trail_images.sensors);
+}
+
+/// Reacts to the `.slime` file being edited on disk.
+///
+/// The settings themselves are re-extracted and rewritten into the render world's uniform
+/// buffer by [`RenderAssetPlugin`], taking effect on the next frame: `move_speed`,
+/// `turn_speed`, `sensor_angle`, `sensor_count`, `sensor_spread`, `sensor_distance`,
+/// `sense_weight`, `crowd_avoidance`, `exploration`, `exploration_threshold`, `noise_strength`,
+/// `noise_scale`, `deposit_amount`, `antialiased_deposit`, `deposit_shape`, `decay_rate`,
+/// `evaporation_floor`, `diffuse_rate`, `enable_decay`, `enable_diffuse`, `blur_radius`,
+/// `gaussian_blur`, `sharpen_amount`, `food_attraction`, `food_consumption`, `memory_length`,
+/// `memory_penalty`, `time_scale`, `substeps`, `kill_respawn`, `max_age`,
+/// `age_deposit_falloff`, `deposit_matrix`, `sense_matrix` and `background`, along with where
+/// `spawn_pattern` and `initial_heading` respawn killed agents, while `gamma` and `brightness`
+/// are copied into the [`display::TrailMaterial`] and `display_filter` picks its sampler,
+/// `auto_exposure` and `exposure_smoothing` drive the [`display::Exposure`] and `background`
+/// goes into the [`ClearColor`], `bloom_intensity` and `bloom_threshold` go into the camera's
+/// `BloomSettings`, and `steps_per_second` and `max_dt` go into the [`SimClock`].
+/// A frame already in flight finishes with the old values. `agent_count` resizes the agent
+/// buffers through the [`SimulationConfig`], `sim_width` and `sim_height` size the trail map, so
+/// they need a restart unless `resize_follows_window` is set. New `animations` replace the
+/// [`ParamAnimation`], carrying on from the time animated so far.
+fn reload_settings(
+    mut asset_events: EventReader<AssetEvent<Slime>>,
+    slimes: Res<Assets<Slime>>,
+    slime: Res<SlimeHandle>,
+    mut config: ResMut<SimulationConfig>,
+    mut animation: ResMut<ParamAnimation>,
+    mut clock: ResMut<SimClock>,
+) {
+    for event in asset_events.iter() {
+        if let AssetEvent::Modified { handle } = event {
+            if *handle != slime.0 {
+                continue;
+            }
+            let Some(settings) = slimes.get(handle) else {
+                continue;
+            };
+            debug!("Reloaded simulation settings: {:?}", settings.0);
+            let agent_count = clamp_agent_count(settings.agent_count);
+            // the agents file decided the agent count at startup
+            if agent_count != config.agent_count && settings.agents_file.is_none() {
+                info!(
+                    "Resizing from {} to {agent_count} agents",
+                    config.agent_count
+                );
+                config.agent_count = agent_count;
+            }
+            if (settings.sim_width, settings.sim_height) != (config.sim_width, config.sim_height) {
+                warn!(
+                    "Sim size changed from {}x{} to {}x{}, restart to apply it",
+                    config.sim_width, config.sim_height, settings.sim_width, settings.sim_height
+                );
+            }
+            if settings.steps_per_second != clock.steps_per_second {
+                clock.steps_per_second = settings.steps_per_second;
+            }
+            if settings.max_dt != clock.max_dt {
+                clock.max_dt = settings.max_dt;
+            }
+            // the animation writes the settings every frame, leaving the animations untouched
+            if settings.animations != animation.0 {
+                animation.0 = settings.animations.clone();
+            }
+        }
+    }
+}
+
+/// Paints trail under the cursor while the left mouse button is held and erases it with the
+/// right, Ctrl and the left button paint food. Nothing is painted while Shift is held, which
+/// makes the left button spawn a [`burst`] instead, and the middle button pans the [`camera`].
+fn paint_trail(
+    windows: Res<Windows>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    config: Res<SimulationConfig>,
+    cameras: camera::CameraView,
+    mut injections: ResMut<TrailInjections>,
+) {
+    injections.clear();
+
+    let control = keys.any_pressed([KeyCode::LControl, KeyCode::RControl]);
+    let (strength, channel) = if keys.any_pressed([KeyCode::LShift, KeyCode::RShift]) {
+        return;
+    } else if mouse_buttons.pressed(MouseButton::Left) && control {
+        (1., InjectionChannel::Food)
+    } else if mouse_buttons.pressed(MouseButton::Left) {
+        (1., InjectionChannel::Trail)
+    } else if mouse_buttons.pressed(MouseButton::Right) {
+        (-1., InjectionChannel::Trail)
+    } else {
+        return;
+    };
+    if let Some(position) = windows
+        .get_primary()
+        .and_then(|window| cursor_on_trail(window, &cameras, &config))
+    {
+        injections.push((position.as_uvec2(), strength, channel));
+    }
+}
+
+/// Position on the trail map under the cursor, `None` when the cursor is outside the window or
+/// isn't over the trail map.
+fn cursor_on_trail(
+    window: &Window,
+    cameras: &camera::CameraView,
+    config: &SimulationConfig,
+) -> Option<Vec2> {
+    let world = camera::cursor_world_position(window, cameras)?;
+    window_to_sim_coords(
+        world,
+        Vec2::new(window.width(), window.height()),
+        window.scale_factor() as f32,
+        config.sim_size(),
+    )
+}
+
+/// Position on a trail map of `sim_size` under `cursor`, given in logical pixels from the center of
+/// a window `window_size` logical pixels large with y pointing up, `None` when the trail map isn't
+/// under it.
+///
+/// Bevy measures the cursor and the window in logical pixels, `scale_factor` physical pixels
+/// each, while the trail sprite is [`letterbox`]ed in physical pixels. So both are converted to
+/// physical pixels first, or the mapping is off by the rounding of the letterbox.
+fn window_to_sim_coords(
+    cursor: Vec2,
+    window_size: Vec2,
+    scale_factor: f32,
+    sim_size: Vec2,
+) -> Option<Vec2> {
+    let shown = letterbox(window_size * scale_factor, sim_size);
+    // y points up from the center of the trail sprite, the trail map origin is its top left
+    let cursor = cursor * scale_factor / shown + 0.5;
+    let x = cursor.x * sim_size.x;
+    let y = (1. - cursor.y) * sim_size.y;
+    ((0. ..sim_size.x).contains(&x) && (0. ..sim_size.y).contains(&y)).then_some(Vec2::new(x, y))
+}
+
+/// Clamps a requested agent count to what the simulation allocates, warning when it had to.
+fn clamp_agent_count(requested: u32) -> u32 {
+    let count = requested.clamp(1, MAX_AGENT_COUNT);
+    if count != requested {
+        warn!("An agent_count of {requested} isn't supported, using {count} agents");
+    }
+    count
+}
+
+/// Doubles the number of agents with `+` and halves it with `-`.
+fn agent_count_controls(keys: Res<Input<KeyCode>>, mut config: ResMut<SimulationConfig>) {
+    let agent_count = if keys.any_just_pressed([KeyCode::Equals, KeyCode::NumpadAdd]) {
+        config.agent_count.saturating_mul(2).min(MAX_AGENT_COUNT)
+    } else if keys.any_just_pressed([KeyCode::Minus, KeyCode::NumpadSubtract]) {
+        (config.agent_count / 2).max(1)
+    } else {
+        return;
+    };
+    // only touch the resource when something changes, so it isn't re-extracted every frame
+    if agent_count != config.agent_count {
+        info!(
+            "Resizing from {} to {agent_count} agents",
+            config.agent_count
+        );
+        config.agent_count = agent_count;
+    }
+}
+
+fn sim_controls(keys: Res<Input<KeyCode>>, mut state: ResMut<SimState>) {
+    // only touch the resource when something changes, so it isn't re-extracted every frame
+    if state.step {
+        state.step = false;
+    }
+    if state.reset {
+        state.reset = false;
+    }
+    if state.clear_trail {
+        state.clear_trail = false;
+    }
+
+    if keys.just_pressed(KeyCode::Space) {
+        state.running = !state.running;
+    }
+    if keys.just_pressed(KeyCode::Period) && !state.running {
+        state.step = true;
+    }
+    if keys.just_pressed(KeyCode::R) {
+        state.reset = true;
+    }
+    if keys.just_pressed(KeyCode::C) {
+        state.clear_trail = true;
+    }
+}
+
+/// Size in physical pixels the trail map of `sim_size` is shown at in a window of `window_size`
+/// physical pixels, the largest that fits with the aspect ratio of the map, in whole pixels.
+fn letterbox(window_size: Vec2, sim_size: Vec2) -> Vec2 {
+    let aspect_ratio = sim_size.x / sim_size.y;
+    let shown = if window_size.x / window_size.y > aspect_ratio {
+        Vec2::new(window_size.y * aspect_ratio, window_size.y)
+    } else {
+        Vec2::new(window_size.x, window_size.x / aspect_ratio)
+    };
+    shown.round().max(Vec2::ONE)
+}
+
+/// Scales the trail quad to the [`letterbox`] size, leaving black bars along the sides of the
+/// window the trail map doesn't fill.
+///
+/// The 2D camera measures the world in logical pixels, so the physical size is divided by the
+/// scale factor again, and the quad is rescaled when the window moves to another display.
+fn resize_trail_sprite(
+    mut resize_events: EventReader<WindowResized>,
+    mut scale_factor_events: EventReader<WindowScaleFactorChanged>,
+    windows: Res<Windows>,
+    config: Res<SimulationConfig>,
+    mut sprites: Query<&mut Transform, With<TrailSprite>>,
+) {
+    let resized = resize_events.iter().last().is_some();
+    let rescaled = scale_factor_events.iter().last().is_some();
+    if !resized && !rescaled && !config.is_changed() {
+        return;
+    }
+    let Some(window) = windows.get_primary() else {
+        return;
+    };
+    let physical_size = Vec2::new(
+        window.physical_width() as f32,
+        window.physical_height() as f32,
+    );
+    let shown = letterbox(physical_size, config.sim_size()) / window.scale_factor() as f32;
+    for mut transform in &mut sprites {
+        transform.scale = Vec3::new(shown.x / WIDTH, shown.y / HEIGHT, 1.);
+    }
+}
+
+/// Seconds without a resize event before [`follow_window_size`] rebuilds the trail map.
+const RESIZE_DEBOUNCE: f64 = 0.3;
+
+/// Rebuilds the trail map at the physical window size when `resize_follows_window` is set, once
+/// the window has stopped being resized for [`RESIZE_DEBOUNCE`], and respawns the agents on it.
+#[allow(clippy::too_many_arguments)]
+fn follow_window_size(
+    mut commands: Commands,
+    mut resize_events: EventReader<WindowResized>,
+    mut pending: Local<Option<(UVec2, f64)>>,
+    windows: Res<Windows>,
+    time: Res<Time>,
+    mut slimes: ResMut<Assets<Slime>>,
+    slime: Res<SlimeHandle>,
+    mut config: ResMut<SimulationConfig>,
+    mut state: ResMut<SimState>,
+    mut images: ResMut<Assets<Image>>,
+    asset_server: Res<AssetServer>,
+) {
+    let follows = slimes
+        .get(&slime.0)
+        .map_or(false, |slime| slime.resize_follows_window);
+    if let Some(event) = resize_events.iter().last() {
+        // the event has the logical size, the trail map gets a texel per physical pixel
+        if let Some(window) = windows.get(event.id).filter(|_| follows) {
+            let size = UVec2::new(window.physical_width(), window.physical_height());
+            *pending = Some((size.max(UVec2::ONE), time.elapsed_seconds_f64()));
+        }
+    }
+    let Some((size, last_resized)) = *pending else {
+        return;
+    };
+    if time.elapsed_seconds_f64() - last_resized < RESIZE_DEBOUNCE {
+        return;
+    }
+    *pending = None;
+    if (size.x, size.y) == (config.sim_width, config.sim_height) {
+        return;
+    }
+
+    info!(
+        "Rebuilding the {}x{} sim at {}x{} to follow the window",
+        config.sim_width, config.sim_height, size.x, size.y
+    );
+    config.sim_width = size.x;
+    config.sim_height = size.y;
+    let Some(slime) = slimes.get_mut(&slime.0) else {
+        return;
+    };
+    // keeps the default arena radius in step, and the sim size from being reported as changed
+    slime.0.sim_width = size.x;
+    slime.0.sim_height = size.y;
+    insert_trail_resources(&mut commands, &mut images, &asset_server, &slime.0, &config);
+    // respawns the agents on the new map
+    state.reset = true;
+}
+
+/// Names of the nodes added to the render graph, in the order they run. Passes of your own can
+/// be slotted between them with [`RenderGraph::add_node_edge`].
+pub mod graph {
+    /// Clears the trail maps, injects painted trail, moves the agents and spawns bursts.
+    pub const UPDATE: &str = "slime_update";
+    /// Adds the anti-aliased deposits of the update passes to the trail map.
+    pub const DEPOSIT: &str = "slime_deposit";
+    /// Diffuses and decays the trail map, then sharpens it when `sharpen_amount` is set.
+    pub const DIFFUSE: &str = "slime_diffuse";
+    /// Colorizes the trail map and copies out readbacks, before the camera driver renders it to
+    /// the screen.
+    pub const DISPLAY: &str = "slime_display";
+    /// Runs every pass above for each simulation added to
+    /// [`SlimeSimulations`](crate::SlimeSimulations).
+    pub const SIMULATIONS: &str = "slime_simulations";
+}
+
+/// Shader used when [`SlimeComputePlugin::with_shader`] isn't called, relative to the asset
+/// folder.
+const DEFAULT_SHADER: &str = "shaders/simple.wgsl";
+
+/// Runs the simulation, on its own without a window like the headless runs do. The
+/// [`SlimeSimulationPlugins`] add showing it in a window and the controls.
+///
+/// The defaults match a `.slime` file with no fields set, the `with_*` methods override them:
+///
+/// ```ignore
+/// app.add_plugin(
+///     SlimeComputePlugin::new()
+///         .with_agent_count(5000)
+///         .with_sim_size(2048, 2048)
+///         .with_shader("shaders/custom.wgsl"),
+/// );
+/// ```
+#[derive(Clone)]
+pub struct SlimeComputePlugin {
+    settings: SimulationSettings,
+    shader: String,
+    agent_init: Option<AgentInit>,
+    lockstep: bool,
+}
+
+impl Default for SlimeComputePlugin {
+    fn default() -> Self {
+        Self {
+            settings: SimulationSettings::default(),
+            shader: DEFAULT_SHADER.to_string(),
+            agent_init: None,
+            lockstep: false,
+        }
+    }
+}
+
+impl SlimeComputePlugin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces every setting, like the ones read from a `.slime` file with `--config`.
+    pub(crate) fn with_settings(mut self, settings: SimulationSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    pub fn with_agent_count(mut self, agent_count: u32) -> Self {
+        self.settings.agent_count = agent_count;
+        self
+    }
+
+    /// Sets the resolution of the trail map, independent of the window size.
+    pub fn with_sim_size(mut self, width: u32, height: u32) -> Self {
+        self.settings.sim_width = width;
+        self.settings.sim_height = height;
+        self
+    }
+
+    /// Replaces the simulation shader, a path relative to the asset folder. It has to provide
+    /// the same bindings and entry points as the default one.
+    pub fn with_shader(mut self, path: impl Into<String>) -> Self {
+        self.shader = path.into();
+        self
+    }
+
+    /// Places the agents with `init`, called with the index of each agent and the agent count,
+    /// instead of following the spawn pattern. Resets place them the same way.
+    ///
+    /// ```ignore
+    /// // a sine wave across the default 1280x720 map
+    /// SlimeComputePlugin::new().with_agent_init(|i, count| {
+    ///     let x = i as f32 / count as f32 * 1280.;
+    ///     Agent::new(Vec2::new(x, 360. + 200. * (x / 100.).sin()), 0.)
+    /// })
+    /// ```
+    pub fn with_agent_init(
+        mut self,
+        init: impl Fn(u32, u32) -> Agent + Send + Sync + 'static,
+    ) -> Self {
+        self.agent_init = Some(Arc::new(init));
+        self
+    }
+
+    /// Runs exactly one step of 1/60 s every frame, however long the frame took, see
+    /// [`SimClock::lockstep`].
+    pub fn with_lockstep(mut self) -> Self {
+        self.lockstep = true;
+        self
+    }
+}
+
+/// What [`setup`] starts the simulation with, from the [`SlimeComputePlugin`] configuration.
+#[derive(Debug, Clone, Resource)]
+struct SlimeStartup {
+    settings: SimulationSettings,
+    shader: String,
+}
+
+impl Plugin for SlimeComputePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Slime>()
+            .init_asset_loader::<SlimeLoader>()
+            .add_startup_system(setup)
+            .add_system(reload_settings)
+            .insert_resource(SimClock::new(
+                self.settings.steps_per_second,
+                self.settings.max_dt,
+                self.lockstep,
+            ))
+            // after the controls, which pause and single step
+            .add_system_to_stage(CoreStage::PostUpdate, sim_clock::advance_sim_clock)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                animation::animate_settings.after(sim_clock::advance_sim_clock),
+            );
+        let mut settings = self.settings.clone();
+        let mut agent_init = self.agent_init.clone();
+        if let Some(path) = &self.settings.agents_file {
+            match agent_file::load_agents(app.world.resource::<AssetServer>(), path) {
+                Ok(rows) => {
+                    settings.agent_count = rows.len() as u32;
+                    agent_init = Some(agent_file::agent_init(rows));
+                }
+                Err(error) => {
+                    error!("Can't load the agents from {path}, {error}, using the spawn pattern");
+                }
+            }
+        }
+        let trail_precision = settings
+            .trail_precision
+            .supported(app.sub_app(RenderApp).world.resource::<RenderAdapter>());
+        settings.trail_precision = trail_precision;
+        let pipeline_events = status::PipelineEventChannel::default();
+        let trail_stats = stats::TrailStatsChannel::default();
+        let agent_reads = agent_access::AgentReadsChannel::default();
+        app.insert_resource(SlimeStartup {
+            settings,
+            shader: self.shader.clone(),
+        })
+        .insert_resource(pipeline_events.clone())
+        .init_resource::<stats::TrailStats>()
+        .insert_resource(trail_stats.clone())
+        .insert_resource(agent_reads.clone())
+        .add_system(stats::receive_trail_stats)
+        .init_resource::<status::SlimePipelineStatus>()
+        .add_event::<status::SlimePipelineEvent>()
+        .add_system(status::forward_pipeline_events)
+        .init_resource::<simulation::SlimeSimulation>()
+        // before the clock, so a pause or step requested the same frame decides its steps
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            simulation::sync_simulation
+                .label(SlimeSystem::Sync)
+                .before(sim_clock::advance_sim_clock),
+        )
+        .init_resource::<instances::SlimeSimulations>()
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            instances::start_simulations.before(SlimeSystem::Sync),
+        )
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            instances::sync_simulations
+                .label(SlimeSystem::Sync)
+                .before(sim_clock::advance_sim_clock),
+        )
+        .init_resource::<burst::AgentBursts>()
+        .add_system_to_stage(
+            CoreStage::PostUpdate,
+            burst::apply_bursts.after(SlimeSystem::Sync),
+        )
+        .init_resource::<FrameCount>()
+        .add_system_to_stage(CoreStage::First, advance_frame_count);
+        // Extract the simulation resources from the main world into the render world, for the
+        // compute passes to operate on and the sprite to display. Each one is copied over by its
+        // `ExtractResourcePlugin` in the extract stage of the frames it changed, while the settings
+        // are a `Slime` asset that `RenderAssetPlugin` prepares into a `GpuSlime` whenever it
+        // changes. The simulations added to `SlimeSimulations` are extracted together, settings
+        // included, into `instances::ExtractedSimulations`. Nothing else extracts, what the
+        // render world sends back goes through the `status` and `stats` channels.
+        app.add_plugin(ExtractResourcePlugin::<SlimeHandle>::default())
+            .add_plugin(ExtractResourcePlugin::<SimulationConfig>::default())
+            .add_plugin(ExtractResourcePlugin::<TrailMap>::default())
+            .add_plugin(ExtractResourcePlugin::<TrailDisplay>::default())
+            .add_plugin(ExtractResourcePlugin::<SensorOverlay>::default())
+            .add_plugin(ExtractResourcePlugin::<obstacles::ObstacleMask>::default())
+            .add_plugin(ExtractResourcePlugin::<noise::NoiseTexture>::default())
+            .add_plugin(ExtractResourcePlugin::<food::FoodMap>::default())
+            .add_plugin(ExtractResourcePlugin::<burst::AgentBursts>::default())
+            .add_plugin(ExtractResourcePlugin::<TrailInjections>::default())
+            .add_plugin(ExtractResourcePlugin::<SimState>::default())
+            .add_plugin(ExtractResourcePlugin::<SimClock>::default())
+            .add_plugin(ExtractResourcePlugin::<FrameCount>::default())
+            .add_plugin(ExtractResourcePlugin::<snapshot::SnapshotRequest>::default())
+            .add_plugin(ExtractResourcePlugin::<screenshot::ScreenshotRequest>::default())
+            .add_plugin(ExtractResourcePlugin::<inspect::AgentDumpRequest>::default())
+            .add_plugin(ExtractResourcePlugin::<inspect::SensorDebug>::default())
+            .add_plugin(ExtractResourcePlugin::<density::DensityOverlay>::default())
+            .add_plugin(ExtractResourcePlugin::<agent_access::AgentRequests>::default())
+            .add_plugin(ExtractResourcePlugin::<instances::ExtractedSimulations>::default())
+            .init_resource::<TrailInjections>()
+            .init_resource::<SimState>()
+            .init_resource::<snapshot::SnapshotRequest>()
+            .init_resource::<screenshot::ScreenshotRequest>()
+            .init_resource::<inspect::AgentDumpRequest>()
+            .init_resource::<inspect::SensorDebug>()
+            .init_resource::<density::DensityOverlay>()
+            .init_resource::<agent_access::AgentRequests>();
+        app.add_plugin(RenderAssetPlugin::<Slime>::default());
+        let render_app = app.sub_app_mut(RenderApp);
+        let support = status::check_compute_support(
+            render_app.world.resource::<RenderAdapter>(),
+            render_app.world.resource::<RenderDevice>(),
+        );
+        if let Err(unsupported) = support {
+            error!("Can't run the simulation: {}", unsupported.0);
+            // the render world still extracts, plugins looking at the step see it never advance
+            render_app.init_resource::<nodes::SlimeStep>();
+            app.insert_resource(unsupported);
+            return;
+        }
+        render_app
+            .insert_resource(pipeline_events)
+            .insert_resource(trail_stats)
+            .insert_resource(agent_reads)
+            .init_resource::<agent_access::AgentReads>()
+            .insert_resource(AgentInitializer(agent_init))
+            .insert_resource(trail_precision)
+            .init_resource::<SlimeSettingsBuffer>()
+            .init_resource::<FrameUniform>()
+            .init_resource::<palette::PaletteTexture>()
+            .init_resource::<SlimePipeline>()
+            .init_resource::<nodes::SlimeStep>()
+            .add_system_to_stage(RenderStage::Prepare, prepare_agents)
+            // the reset writes the agents of the resized buffers
+            .add_system_to_stage(RenderStage::Prepare, resize_agents.after(prepare_agents))
+            .add_system_to_stage(RenderStage::Prepare, prepare_injections)
+            .add_system_to_stage(RenderStage::Prepare, food::prepare_food)
+            .add_system_to_stage(RenderStage::Prepare, deposit::prepare_deposits)
+            .add_system_to_stage(RenderStage::Prepare, density::prepare_density)
+            .add_system_to_stage(RenderStage::Prepare, grid::prepare_grid)
+            .add_system_to_stage(RenderStage::Prepare, prepare_frame)
+            .add_system_to_stage(RenderStage::Prepare, reset_simulation.after(resize_agents))
+            // after the reset, which rewrites every agent
+            .add_system_to_stage(
+                RenderStage::Prepare,
+                agent_access::apply_agent_requests
+                    .after(resize_agents)
+                    .after(reset_simulation),
+            )
+            .add_system_to_stage(RenderStage::Prepare, snapshot::apply_snapshot)
+            .add_system_to_stage(RenderStage::Prepare, snapshot::prepare_snapshot_readback)
+            .add_system_to_stage(
+                RenderStage::Prepare,
+                screenshot::prepare_screenshot_readback,
+            )
+            .add_system_to_stage(RenderStage::Cleanup, snapshot::map_snapshot_readback)
+            .add_system_to_stage(RenderStage::Cleanup, screenshot::map_screenshot_readback)
+            .add_system_to_stage(RenderStage::Cleanup, inspect::dump_agents)
+            .add_system_to_stage(RenderStage::Cleanup, agent_access::map_agent_reads)
+            .add_system_to_stage(RenderStage::Prepare, stats::prepare_trail_stats)
+            .add_system_to_stage(RenderStage::Cleanup, stats::map_trail_stats)
+            .add_system_to_stage(RenderStage::Queue, queue_bind_group)
+            .add_system_to_stage(
+                RenderStage::Queue,
+                nodes::advance_step.after(queue_bind_group),
+            )
+            .init_resource::<instances::ExtractedSimulations>()
+            .init_resource::<instances::SimulationsGpu>()
+            .add_system_to_stage(RenderStage::Prepare, instances::prepare_simulations)
+            // once the first simulation has decided whether it starts
+            .add_system_to_stage(
+                RenderStage::Queue,
+                instances::queue_simulations.after(nodes::advance_step),
+            );
+
+        nodes::add_nodes(&mut render_app.world.resource_mut::<RenderGraph>());
+    }
+}
+
+/// One bind group per ping-pong direction, indexed by the trail map the update pass writes to
+/// and then by the agent buffer it writes to.
+///
+/// The trail maps swap once a frame, twice when sharpened, and the agent buffers once a substep,
+/// so with an even number of substeps the same agent buffer is written at the end of every frame.
+#[derive(Resource)]
+struct SlimeBindGroups([[BindGroup; 2]; 2]);
+
+/// Lays out `count` agents on a `width` by `height` map according to `pattern`, facing
+/// `heading` if it is given.
+///
+/// When `seamless` the map is split into [`SEAMLESS_TILES`] by [`SEAMLESS_TILES`] tiles, each
+/// laid out like a whole map and taking every fourth agent, so the layout repeats across the
+/// edges of the torus like it does inside the map. All agents belong to the first species.
+fn build_agents(
+    pattern: SpawnPattern,
+    heading: Option<HeadingMode>,
+    count: u32,
+    seed: u64,
+    width: f32,
+    height: f32,
+    seamless: bool,
+) -> Vec<Agent> {
+    let mut rng = XorShift::new(seed);
+    let tiles = if seamless { SEAMLESS_TILES } else { 1 };
+    let tile_size = Vec2::new(width, height) / tiles as f32;
+    let radius = tile_size.min_element() * SPAWN_RADIUS;
+    (0..count)
+        .map(|i| {
+            let origin = UVec2::new(i % tiles, i / tiles % tiles).as_vec2() * tile_size;
+            let center = origin + tile_size / 2.;
+            let random_angle = rng.next_f32() * 2. * PI;
+            let (position, angle) = match pattern {
+                SpawnPattern::CenterPoint => (center, random_angle),
+                SpawnPattern::RandomUniform => (
+                    origin + Vec2::new(rng.next_f32(), rng.next_f32()) * tile_size,
+                    random_angle,
+                ),
+                SpawnPattern::CircleInward | SpawnPattern::CircleOutward => {
+                    // the square root spreads agents evenly over the area of the circle
+                    let offset = Vec2::from_angle(random_angle) * radius * rng.next_f32().sqrt();
+                    let outward = offset.y.atan2(offset.x);
+                    if pattern == SpawnPattern::CircleInward {
+                        (center + offset, outward + PI)
+                    } else {
+                        (center + offset, outward)
+                    }
+                }
+                SpawnPattern::RingRandom => {
+                    let inner = radius * RING_INNER_RADIUS;
+                    let distance =
+                        (inner * inner + rng.next_f32() * (radius * radius - inner * inner)).sqrt();
+                    (
+                        center + Vec2::from_angle(random_angle) * distance,
+                        rng.next_f32() * 2. * PI,
+                    )
+                }
+            };
+            let angle = heading.map_or(angle, |heading| {
+                heading.angle(position - center, rng.next_f32())
+            });
+            Agent::new(position, angle)
+        })
+        .collect()
+}
+
+/// Builds the initial agents for `config`, assigning species round-robin and jittering speeds.
+///
+/// Agents placed by `init` are used as they are, apart from clamping their species.
+fn spawn_agents(config: &SimulationConfig, init: &AgentInitializer) -> Vec<Agent> {
+    if let Some(init) = &init.0 {
+        return (0..config.agent_count)
+            .map(|i| {
+                let mut agent = init(i, config.agent_count);
+                agent.species = agent.species.min(config.species_count - 1);
+                agent
+            })
+            .collect();
+    }
+
+    let mut agents = build_agents(
+        config.spawn_pattern,
+        config.initial_heading,
+        config.agent_count,
+        config.seed,
+        config.sim_width as f32,
+        config.sim_height as f32,
+        config.seamless,
+    );
+    // a separate generator, so the layout doesn't depend on the jitter
+    let mut rng = XorShift::new(config.seed.wrapping_add(1));
+    for (i, agent) in agents.iter_mut().enumerate() {
+        agent.species = i as u32 % config.species_count;
+        agent.speed = 1. + rng.next_f32() * config.speed_jitter;
+    }
+    agents
+}
+
+fn prepare_agents(
+    mut commands: Commands,
+    agent_buffer: Option<Res<AgentBuffer>>,
+    config: Res<SimulationConfig>,
+    init: Res<AgentInitializer>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    if agent_buffer.is_some() {
+        return;
+    }
+
+    let agent_buffer =
+        AgentBuffer::new(&render_device, config.agent_count, config.agent_capacity());
+    agent_buffer.write(&render_queue, &spawn_agents(&config, &init));
+
+    commands.insert_resource(agent_buffer);
+}
+
+/// Reallocates the agent buffers when the agent count changes.
+///
+/// The agents that fit are copied over from the latest step, any new ones are placed where
+/// the spawn pattern would put agents with their indices, and agents added by bursts are
+/// dropped. The buffers are replaced in place rather than through `Commands`, so the systems
+/// ordered after this one in the prepare stage write to the new ones. The bind groups pick up
+/// the new buffers when they are recreated in the queue stage.
+fn resize_agents(
+    agent_buffer: Option<ResMut<AgentBuffer>>,
+    config: Res<SimulationConfig>,
+    init: Res<AgentInitializer>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let Some(mut agent_buffer) = agent_buffer
+        .filter(|old| old.count != config.agent_count || old.capacity != config.agent_capacity())
+    else {
+        return;
+    };
+
+    let resized = AgentBuffer::new(&render_device, config.agent_count, config.agent_capacity());
+    let kept = agent_buffer.count.min(config.agent_count);
+    let mut encoder = render_device.create_command_encoder(&CommandEncoderDescriptor {
+        label: Some("resize_agents"),
+    });
+    for buffer in &resized.buffers {
+        let size = kept as u64 * std::mem::size_of::<Agent>() as u64;
+        encoder.copy_buffer_to_buffer(agent_buffer.latest(), 0, buffer, 0, size);
+    }
+    render_queue.submit([encoder.finish()]);
+    if config.agent_count > kept {
+        let agents = spawn_agents(&config, &init);
+        resized.write_from(&render_queue, kept, &agents[kept as usize..]);
+    }
+
+    *agent_buffer = resized;
+}
+
+/// Re-uploads the initial agents when a reset was requested, the trail maps are zeroed by the
+/// `clear` pass of [`nodes::UpdateNode`].
+fn reset_simulation(
+    state: Res<SimState>,
+    config: Res<SimulationConfig>,
+    init: Res<AgentInitializer>,
+    agent_buffer: Option<Res<AgentBuffer>>,
+    render_queue: Res<RenderQueue>,
+) {
+    if !state.reset {
+        return;
+    }
+
+    if let Some(agent_buffer) = agent_buffer {
+        agent_buffer.write(&render_queue, &spawn_agents(&config, &init));
+    }
+}
+
+/// Per-frame parameters, kept apart from the [`SimulationSettings`] uniform so that buffer is
+/// only rewritten when the settings change.
+#[derive(Debug, Copy, Clone, Default, ShaderType, Pod, Zeroable)]
+#[repr(C)]
+struct GpuFrame {
+    /// The [`FrameCount`] of the frame.
+    pub index: u32,
+    /// Duration of each step of the frame relative to one at [`REFERENCE_FRAME_RATE`].
+    pub time_step: f32,
+    /// Non-zero while [`inspect::SensorDebug`] is enabled.
+    pub sensor_debug: u32,
+    /// [`burst::AgentBursts::active_count`], the agents the update pass moves.
+    pub active_count: u32,
+    /// Where this frame's burst spawns its agents, after the active ones.
+    pub burst_position: Vec2,
+    /// Agents in this frame's burst, 0 without one.
+    pub burst_count: u32,
+    /// Non-zero while [`density::DensityOverlay`] is enabled.
+    pub density_overlay: u32,
+    /// Columns and rows of cells of the [`grid`], 0 without one.
+    pub grid_size: UVec2,
+    /// [`SimulationSettings::grid_cell_size`].
+    pub grid_cell_size: u32,
+    pub _padding0: u32,
+}
+
+impl GpuFrame {
+    /// The [`Self::time_step`] of the steps `clock` runs this frame.
+    fn time_step_of(clock: &SimClock) -> f32 {
+        clock.step_time() * REFERENCE_FRAME_RATE
+    }
+}
+
+/// Frame rate the speeds in [`SimulationSettings`] are given for.
+const REFERENCE_FRAME_RATE: f32 = 60.;
+
+/// Number of main world frames run so far, wrapping around at `u32::MAX`.
+///
+/// Passed to the shader as `frame.index`, which seeds the random turns along with the agent
+/// index and [`SimulationSettings::seed`], so it only depends on how many frames ran.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Resource, ExtractResource)]
+pub struct FrameCount(pub u32);
+
+fn advance_frame_count(mut frame_count: ResMut<FrameCount>) {
+    frame_count.0 = frame_count.0.wrapping_add(1);
+}
+
+/// Uniform buffer with the index and duration of the frame being rendered.
+///
+/// The buffer is created by the first write and reused after that.
+#[derive(Resource, Default)]
+struct FrameUniform(UniformBuffer<GpuFrame>);
+
+fn prepare_frame(
+    frame_count: Res<FrameCount>,
+    clock: Res<SimClock>,
+    sensor_debug: Res<inspect::SensorDebug>,
+    density_overlay: Res<density::DensityOverlay>,
+    config: Res<SimulationConfig>,
+    bursts: Option<Res<burst::AgentBursts>>,
+    mut frame_uniform: ResMut<FrameUniform>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+) {
+    let burst = bursts.as_ref().and_then(|bursts| bursts.burst);
+    frame_uniform.0.set(GpuFrame {
+        index: frame_count.0,
+        time_step: GpuFrame::time_step_of(&clock),
+        sensor_debug: sensor_debug.0 as u32,
+        active_count: bursts
+            .as_ref()
+            .map_or(config.agent_count, |bursts| bursts.active_count),
+        burst_position: burst.map_or(Vec2::ZERO, |burst| burst.position),
+        burst_count: burst.map_or(0, |burst| burst.count),
+        density_overlay: density_overlay.0 as u32,
+        grid_size: grid::grid_size(&config),
+        grid_cell_size: config.grid_cell_size,
+        _padding0: 0,
+    });
+    frame_uniform.0.write_buffer(&render_device, &render_queue);
+}
+
+fn prepare_injections(
+    mut commands: Commands,
+    injections: Res<TrailInjections>,
+    render_device: Res<RenderDevice>,
+) {
+    let mut contents: Vec<GpuTrailInjection> = injections
+        .iter()
+        .map(|&(position, strength, channel)| GpuTrailInjection {
+            position,
+            strength,
+            channel: channel as u32,
+        })
+        .collect();
+    let count = contents.len();
+    // storage buffers can't be empty, so keep a placeholder around when nothing is queued
+    if contents.is_empty() {
+        contents.push(GpuTrailInjection::zeroed());
+    }
+
+    let buffer = render_device.create_buffer_with_data(&BufferInitDescriptor {
+        label: Some("trail_injections"),
+        contents: bytemuck::cast_slice(&contents),
+        usage: BufferUsages::STORAGE,
+    });
+    commands.insert_resource(InjectionBuffer { buffer, count });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn queue_bind_group(
+    mut commands: Commands,
+    pipeline: Res<SlimePipeline>,
+    render_device: Res<RenderDevice>,
+    slime_store: Res<RenderAssets<Slime>>,
+    slime: Res<SlimeHandle>,
+    agents: Res<AgentBuffer>,
+    gpu_images: Res<RenderAssets<Image>>,
+    trail_map: Res<TrailMap>,
+    trail_display: Res<TrailDisplay>,
+    sensor_overlay: Res<SensorOverlay>,
+    obstacle_mask: Res<obstacles::ObstacleMask>,
+    noise_texture: Res<noise::NoiseTexture>,
+    injections: Res<InjectionBuffer>,
+    frame: Res<FrameUniform>,
+    palette: Res<palette::PaletteTexture>,
+    trail_stats: Res<stats::TrailStatsReduction>,
+    food: Res<food::FoodBuffer>,
+    deposits: Res<deposit::DepositBuffer>,
+    density: Res<density::DensityBuffer>,
+    grid: Res<grid::AgentGrid>,
+    mut logged_ready: Local<bool>,
+) {
+    // the assets are prepared asynchronously, keep the previous bind groups until they are ready
+    let (
+        Some(slime),
+        Some(trail),
+        Some(next_trail),
+        Some(display),
+        Some(sensors),
+        Some(obstacles),
+        Some(noise),
+    ) = (
+        slime_store.get(&slime.0),
+        gpu_images.get(&trail_map[0]),
+        gpu_images.get(&trail_map[1]),
+        gpu_images.get(&trail_display.0),
+        gpu_images.get(&sensor_overlay.0),
+        gpu_images.get(&obstacle_mask.0),
+        gpu_images.get(&noise_texture.0),
+    )
+    else {
+        return;
+    };
+    let Some(frame_buffer) = frame.0.buffer() else {
+        return;
+    };
+    if !*logged_ready {
+        debug!("Simulation assets are ready");
+        *logged_ready = true;
+    }
+
+    let bind_groups = BindGroupResources {
+        settings: &slime.buffer,
+        agents: &agents,
+        trails: [trail, next_trail],
+        display,
+        sensors,
+        obstacles,
+        noise,
+        injections: &injections.buffer,
+        frame: frame_buffer,
+        palette: &palette.view,
+        stats_partials: &trail_stats.partials,
+        food: &food.0,
+        deposits: &deposits.buffer,
+        density: &density.buffer,
+        previous_density: &density.previous,
+        grid: &grid.buffer,
+    }
+    .create(&render_device, &pipeline.texture_bind_group_layout);
+    commands.insert_resource(bind_groups);
+}
+
+/// Binds all of `buffer`.
+fn whole_buffer(buffer: &Buffer) -> BindingResource {
+    BindingResource::Buffer(BufferBinding {
+        buffer,
+        offset: 0,
+        size: None,
+    })
+}
+
+/// Everything a simulation binds for its passes.
+struct BindGroupResources<'a> {
+    settings: &'a Buffer,
+    agents: &'a AgentBuffer,
+    trails: [&'a GpuImage; 2],
+    display: &'a GpuImage,
+    sensors: &'a GpuImage,
+    obstacles: &'a GpuImage,
+    noise: &'a GpuImage,
+    injections: &'a Buffer,
+    frame: &'a Buffer,
+    palette: &'a TextureView,
+    stats_partials: &'a Buffer,
+    food: &'a Buffer,
+    deposits: &'a Buffer,
+    density: &'a Buffer,
+    previous_density: &'a Buffer,
+    grid: &'a Buffer,
+}
+
+impl BindGroupResources<'_> {
+    /// The bind groups for every ping-pong direction.
+    fn create(&self, render_device: &RenderDevice, layout: &BindGroupLayout) -> SlimeBindGroups {
+        let create_bind_group = |current: usize, agents_out: usize| {
+            render_device.create_bind_group(&BindGroupDescriptor {
+                label: None,
+                layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: whole_buffer(self.settings),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: whole_buffer(&self.agents.buffers[1 - agents_out]),
+                    },
+                    BindGroupEntry {
+                        binding: 2,
+                        resource: BindingResource::TextureView(&self.trails[current].texture_view),
+                    },
+                    BindGroupEntry {
+                        binding: 3,
+                        resource: BindingResource::TextureView(
+                            &self.trails[1 - current].texture_view,
+                        ),
+                    },
+                    BindGroupEntry {
+                        binding: 4,
+                        resource: BindingResource::TextureView(&self.display.texture_view),
+                    },
+                    BindGroupEntry {
+                        binding: 5,
+                        resource: whole_buffer(self.injections),
+                    },
+                    BindGroupEntry {
+                        binding: 6,
+                        resource: whole_buffer(self.frame),
+                    },
+                    BindGroupEntry {
+                        binding: 7,
+                        resource: BindingResource::TextureView(self.palette),
+                    },
+                    BindGroupEntry {
+                        binding: 8,
+                        resource: BindingResource::TextureView(&self.sensors.texture_view),
+                    },
+                    BindGroupEntry {
+                        binding: 9,
+                        resource: BindingResource::TextureView(&self.obstacles.texture_view),
+                    },
+                    BindGroupEntry {
+                        binding: 10,
+                        resource: whole_buffer(&self.agents.buffers[agents_out]),
+                    },
+                    BindGroupEntry {
+                        binding: 11,
+                        resource: whole_buffer(self.stats_partials),
+                    },
+                    BindGroupEntry {
+                        binding: 12,
+                        resource: whole_buffer(self.food),
+                    },
+                    BindGroupEntry {
+                        binding: 13,
+                        resource: whole_buffer(self.deposits),
+                    },
+                    BindGroupEntry {
+                        binding: 14,
+                        resource: whole_buffer(self.density),
+                    },
+                    BindGroupEntry {
+                        binding: 15,
+                        resource: whole_buffer(self.previous_density),
+                    },
+                    BindGroupEntry {
+                        binding: 16,
+                        resource: BindingResource::TextureView(&self.noise.texture_view),
+                    },
+                    BindGroupEntry {
+                        binding: 17,
+                        resource: whole_buffer(self.grid),
+                    },
+                ],
+            })
+        };
+        SlimeBindGroups([
+            [create_bind_group(0, 0), create_bind_group(0, 1)],
+            [create_bind_group(1, 0), create_bind_group(1, 1)],
+        ])
+    }
+}
+
+#[derive(Resource)]
+pub struct SlimePipeline {
+    texture_bind_group_layout: BindGroupLayout,
+    clear_pipeline: CachedComputePipelineId,
+    rotate_density_pipeline: CachedComputePipelineId,
+    inject_pipeline: CachedComputePipelineId,
+    update_pipeline: CachedComputePipelineId,
+    spawn_pipeline: CachedComputePipelineId,
+    resolve_pipeline: CachedComputePipelineId,
+    diffuse_pipeline: CachedComputePipelineId,
+    sharpen_pipeline: CachedComputePipelineId,
+    colorize_pipeline: CachedComputePipelineId,
+    reduce_pipeline: CachedComputePipelineId,
+    clear_grid_pipeline: CachedComputePipelineId,
+    count_grid_pipeline: CachedComputePipelineId,
+    scan_grid_pipeline: CachedComputePipelineId,
+    scatter_grid_pipeline: CachedComputePipelineId,
+}
+
+impl SlimePipeline {
+    /// Every pipeline, all of which have to compile before the simulation starts.
+    fn all(&self) -> [CachedComputePipelineId; 14] {
+        [
+            self.clear_pipeline,
+            self.rotate_density_pipeline,
+            self.inject_pipeline,
+            self.update_pipeline,
+            self.spawn_pipeline,
+            self.resolve_pipeline,
+            self.diffuse_pipeline,
+            self.sharpen_pipeline,
+            self.colorize_pipeline,
+            self.reduce_pipeline,
+            self.clear_grid_pipeline,
+            self.count_grid_pipeline,
+            self.scan_grid_pipeline,
+            self.scatter_grid_pipeline,
+        ]
+    }
+}
+
+impl FromWorld for SlimePipeline {
+    fn from_world(world: &mut World) -> Self {
+        let trail_format = world.resource::<TrailPrecision>().texture_format();
+        let texture_bind_group_layout =
+            world
+                .resource::<RenderDevice>()
+                .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: None,
+                    entries: &[
+                        BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: Some(GpuSimulationSettings::min_size()),
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: Some(Agent::min_size()),
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 2,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::StorageTexture {
+                                access: StorageTextureAccess::ReadWrite,
+                                format: trail_format,
+                                view_dimension: TextureViewDimension::D2,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 3,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::StorageTexture {
+                                access: StorageTextureAccess::ReadWrite,
+                                format: trail_format,
+                                view_dimension: TextureViewDimension::D2,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 4,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::StorageTexture {
+                                access: StorageTextureAccess::WriteOnly,
+                                format: TextureFormat::Rgba8Unorm,
+                                view_dimension: TextureViewDimension::D2,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 5,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: true },
+                                has_dynamic_offset: false,
+                                min_binding_size: Some(GpuTrailInjection::min_size()),
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 6,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Uniform,
+                                has_dynamic_offset: false,
+                                min_binding_size: Some(GpuFrame::min_size()),
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 7,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Texture {
+                                sample_type: TextureSampleType::Float { filterable: false },
+                                view_dimension: TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 8,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::StorageTexture {
+                                access: StorageTextureAccess::ReadWrite,
+                                format: trail_format,
+                                view_dimension: TextureViewDimension::D2,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 9,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Texture {
+                                sample_type: TextureSampleType::Float { filterable: false },
+                                view_dimension: TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 10,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: Some(Agent::min_size()),
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 11,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: Some(stats::GpuTrailStats::min_size()),
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 12,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: Some(f32::min_size()),
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 13,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: Some(u32::min_size()),
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 14,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: Some(u32::min_size()),
+                            },
+                            count: None,
+                        },
+                        // the eighth storage buffer, as many as wgpu allows by default
+                        BindGroupLayoutEntry {
+                            binding: 15,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: Some(u32::min_size()),
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 16,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Texture {
+                                sample_type: TextureSampleType::Float { filterable: false },
+                                view_dimension: TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        // the ninth, which the adapter limits of most GPUs allow
+                        BindGroupLayoutEntry {
+                            binding: 17,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Buffer {
+                                ty: BufferBindingType::Storage { read_only: false },
+                                has_dynamic_offset: false,
+                                min_binding_size: Some(u32::min_size()),
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+        let shader = SLIME_SHADER_HANDLE.typed::<Shader>();
+        let mut pipeline_cache = world.resource_mut::<PipelineCache>();
+        let mut queue_pipeline = |entry_point: &'static str| {
+            pipeline_cache.queue_compute_pipeline(ComputePipelineDescriptor {
+                label: None,
+                layout: Some(vec![texture_bind_group_layout.clone()]),
+                shader: shader.clone(),
+                shader_defs: vec![],
+                entry_point: Cow::from(entry_point),
+            })
+        };
+        let clear_pipeline = queue_pipeline("clear");
+        let rotate_density_pipeline = queue_pipeline("rotate_density");
+        let inject_pipeline = queue_pipeline("inject");
+        let update_pipeline = queue_pipeline("update");
+        let spawn_pipeline = queue_pipeline("spawn");
+        let resolve_pipeline = queue_pipeline("resolve");
+        let diffuse_pipeline = queue_pipeline("diffuse");
+        let sharpen_pipeline = queue_pipeline("sharpen");
+        let colorize_pipeline = queue_pipeline("colorize");
+        let reduce_pipeline = queue_pipeline("reduce");
+        let clear_grid_pipeline = queue_pipeline("clear_grid");
+        let count_grid_pipeline = queue_pipeline("count_grid");
+        let scan_grid_pipeline = queue_pipeline("scan_grid");
+        let scatter_grid_pipeline = queue_pipeline("scatter_grid");
+
+        SlimePipeline {
+            texture_bind_group_layout,
+            clear_pipeline,
+            rotate_density_pipeline,
+            inject_pipeline,
+            update_pipeline,
+            spawn_pipeline,
+            resolve_pipeline,
+            diffuse_pipeline,
+            sharpen_pipeline,
+            colorize_pipeline,
+            reduce_pipeline,
+            clear_grid_pipeline,
+            count_grid_pipeline,
+            scan_grid_pipeline,
+            scatter_grid_pipeline,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workgroups_cover_every_invocation() {
+        assert_eq!(workgroups_for(0, 64), 0);
+        assert_eq!(workgroups_for(1, 64), 1);
+        assert_eq!(workgroups_for(64, 64), 1);
+        assert_eq!(workgroups_for(100, 64), 2);
+        assert_eq!(workgroups_for(513, 64), 9);
+        assert_eq!(workgroups_for(1000, 8), 125);
+        assert_eq!(workgroups_for(1000, 1), 1000);
+    }
+
+    #[test]
+    fn agent_workgroups_cover_every_agent_once_over() {
+        for workgroup_size in [1, 32, AGENT_WORKGROUP_SIZE, 256] {
+            for agents in [1, 100, 1000, 513, MAX_AGENT_COUNT] {
+                let groups = workgroups_for(agents, workgroup_size);
+                // enough for every agent, and not a whole workgroup more
+                assert!(groups * workgroup_size >= agents);
+                assert!((groups - 1) * workgroup_size < agents);
+            }
+        }
+    }
+
+    #[test]
+    fn workgroup_sizes_fit_the_limits() {
+        // 256 invocations a workgroup, so squares of at most 16 by 16
+        let limits = WgpuLimits::default();
+        assert_eq!(clamp_texture_workgroup_size(0, &limits), 1);
+        assert_eq!(clamp_texture_workgroup_size(8, &limits), 8);
+        assert_eq!(clamp_texture_workgroup_size(32, &limits), 16);
+        assert_eq!(clamp_agent_workgroup_size(0, &limits), 1);
+        assert_eq!(clamp_agent_workgroup_size(64, &limits), 64);
+        assert_eq!(clamp_agent_workgroup_size(1024, &limits), 256);
+    }
+
+    /// The shaders that get the [`ShaderConstants`] substituted.
+    const CONSTANT_SHADERS: [&str; 2] = [
+        include_str!("../assets/shaders/simple.wgsl"),
+        include_str!("../assets/shaders/preview.wgsl"),
+    ];
+
+    #[test]
+    fn shader_constants_cover_every_shader() {
+        let constants = ShaderConstants::new(&SimulationConfig::default());
+        for shader in CONSTANT_SHADERS {
+            let source = constants.apply(shader);
+            assert_eq!(ShaderConstants::unknown_constant(&source), None);
+            assert!(!source.contains("#{"));
+        }
+
+        // every name the simulation shader expects, not only the ones it happens to use
+        let names: Vec<_> = constants
+            .values()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        for name in [
+            "TEXTURE_WORKGROUP_SIZE",
+            "AGENT_WORKGROUP_SIZE",
+            "MAX_SPECIES",
+            "BOUNDARY_WRAP",
+            "SPAWN_RING_RANDOM",
+            "DEPOSIT_SCALE",
+        ] {
+            assert!(names.contains(&name), "{name} is missing");
+        }
+    }
+
+    /// Size and alignment of a WGSL type by the uniform buffer layout rules, looking up the
+    /// structs it names in `source`.
+    fn wgsl_layout(source: &str, ty: &str) -> (u64, u64) {
+        let round_up = |value: u64, align: u64| (value + align - 1) / align * align;
+        match ty {
+            "f32" | "u32" | "i32" => (4, 4),
+            "vec2<f32>" => (8, 8),
+            "vec4<f32>" => (16, 16),
+            _ => {
+                if let Some(array) = ty
+                    .strip_prefix("array<")
+                    .and_then(|ty| ty.strip_suffix('>'))
+                {
+                    let (element, count) = array.rsplit_once(',').unwrap();
+                    let (size, align) = wgsl_layout(source, element.trim());
+                    let count: u64 = count.trim().parse().unwrap();
+                    return (round_up(size, align) * count, align);
+                }
+                let (_, body) = source.split_once(&format!("struct {ty} {{")).unwrap();
+                let (body, _) = body.split_once("\n}").unwrap();
+                let (mut size, mut align) = (0, 1);
+                for member in body.lines().map(str::trim) {
+                    if member.is_empty() || member.starts_with("//") {
+                        continue;
+                    }
+                    let (_, member_ty) = member.trim_end_matches(',').split_once(':').unwrap();
+                    let (member_size, member_align) = wgsl_layout(source, member_ty.trim());
+                    size = round_up(size, member_align) + member_size;
+                    align = align.max(member_align);
+                }
+                (round_up(size, align), align)
+            }
+        }
+    }
+
+    #[test]
+    fn settings_are_laid_out_like_the_shader_struct() {
+        let source = ShaderConstants::new(&SimulationConfig::default()).apply(CONSTANT_SHADERS[0]);
+        let (shader_size, _) = wgsl_layout(&source, "SimulationSettings");
+        assert_eq!(GpuSimulationSettings::min_size().get(), shader_size);
+        assert_eq!(
+            std::mem::size_of::<GpuSimulationSettings>() as u64,
+            shader_size
+        );
+
+        let (species_size, _) = wgsl_layout(&source, "SpeciesSettings");
+        assert_eq!(SpeciesSettings::min_size().get(), species_size);
+    }
+
+    #[test]
+    fn unknown_shader_constants_are_found_outside_comments() {
+        let source = "// substitutes #{NAME}\nlet size = #{SIZE};\n";
+        assert_eq!(ShaderConstants::unknown_constant(source), Some("SIZE"));
+        assert_eq!(
+            ShaderConstants::unknown_constant("// only #{NAME} in a comment"),
+            None
+        );
+    }
+
+    #[test]
+    fn missing_settings_fall_back_to_their_defaults() {
+        let defaults = format!("{:?}", SimulationSettings::default());
+        let empty: SimulationSettings = ron::from_str("()").unwrap();
+        assert_eq!(format!("{empty:?}"), defaults);
+
+        let partial: SimulationSettings =
+            ron::from_str("(agent_count: 42, move_speed: 2.5, seamless: true)").unwrap();
+        let expected = SimulationSettings {
+            agent_count: 42,
+            move_speed: 2.5,
+            seamless: true,
+            ..default()
+        };
+        assert_eq!(format!("{partial:?}"), format!("{expected:?}"));
+    }
+
+    #[test]
+    fn background_is_read_as_a_tuple() {
+        let settings: SimulationSettings =
+            ron::from_str("(background: (0.1, 0.2, 0.3, 1.0))").unwrap();
+        assert_eq!(settings.background, [0.1, 0.2, 0.3, 1.]);
+        assert_eq!(
+            GpuSimulationSettings::from(&settings).background,
+            Vec4::new(0.1, 0.2, 0.3, 1.)
+        );
+        // all four channels, alpha included
+        assert!(ron::from_str::<SimulationSettings>("(background: (0.1, 0.2, 0.3))").is_err());
+    }
+
+    /// The field of every error `settings` has and whether it is hard, in the order found.
+    fn errors(settings: &SimulationSettings) -> Vec<(&'static str, bool)> {
+        match settings.validate() {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors
+                .iter()
+                .map(|error| (error.field, error.hard))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn default_settings_are_valid() {
+        assert_eq!(SimulationSettings::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn non_finite_numbers_are_hard_errors() {
+        let settings = SimulationSettings {
+            move_speed: f32::NAN,
+            max_dt: Some(f32::INFINITY),
+            // only reported as not finite, not also as out of range
+            turn_speed: f32::NEG_INFINITY,
+            ..default()
+        };
+        assert_eq!(
+            errors(&settings),
+            [("move_speed", true), ("turn_speed", true), ("max_dt", true)]
+        );
+    }
+
+    #[test]
+    fn zero_counts_are_hard_errors() {
+        let settings = SimulationSettings {
+            agent_count: 0,
+            sim_height: 0,
+            agent_workgroup_size: 0,
+            ..default()
+        };
+        assert_eq!(
+            errors(&settings),
+            [
+                ("agent_count", true),
+                ("sim_height", true),
+                ("agent_workgroup_size", true)
+            ]
+        );
+    }
+
+    #[test]
+    fn species_count_outside_the_channels_is_a_hard_error() {
+        for species_count in [0, MAX_SPECIES as u32 + 1] {
+            let settings = SimulationSettings {
+                species_count,
+                ..default()
+            };
+            assert_eq!(errors(&settings), [("species_count", true)]);
+        }
+    }
+
+    #[test]
+    fn angles_and_rates_out_of_range_are_soft_errors() {
+        let settings = SimulationSettings {
+            turn_speed: -0.1,
+            sensor_spread: Some(PI + 0.1),
+            decay_rate: 1.5,
+            age_deposit_falloff: -1.,
+            ..default()
+        };
+        assert_eq!(
+            errors(&settings),
+            [
+                ("turn_speed", false),
+                ("sensor_spread", false),
+                ("decay_rate", false),
+                ("age_deposit_falloff", false)
+            ]
+        );
+    }
+
+    #[test]
+    fn validated_replaces_unsupported_values() {
+        let settings = SimulationSettings {
+            substeps: 0,
+            steps_per_second: Some(0.),
+            max_dt: Some(-1.),
+            blur_radius: MAX_BLUR_RADIUS + 1,
+            deposit_amount: -2.,
+            noise_scale: f32::NAN,
+            exposure_smoothing: 1.5,
+            deposit_shape: DepositShape::Disk(MAX_DEPOSIT_RADIUS * 2.),
+            sharpen_amount: -1.,
+            sensor_count: 4,
+            memory_length: MAX_MEMORY as u32 + 1,
+            ..default()
+        }
+        .validated();
+        assert_eq!(settings.substeps, 1);
+        assert_eq!(settings.steps_per_second, None);
+        assert_eq!(settings.max_dt, Some(sim_clock::DEFAULT_MAX_DT));
+        assert_eq!(settings.blur_radius, MAX_BLUR_RADIUS);
+        assert_eq!(settings.deposit_amount, 0.);
+        assert_eq!(settings.noise_scale, 1.);
+        assert_eq!(settings.exposure_smoothing, 1.);
+        assert_eq!(
+            settings.deposit_shape,
+            DepositShape::Disk(MAX_DEPOSIT_RADIUS)
+        );
+        assert_eq!(settings.sharpen_amount, 0.);
+        assert_eq!(settings.sensor_count, 5);
+        assert_eq!(settings.memory_length, MAX_MEMORY as u32);
+
+        let too_many_sensors = SimulationSettings {
+            sensor_count: MAX_SENSORS + 2,
+            ..default()
+        };
+        assert_eq!(too_many_sensors.validated().sensor_count, MAX_SENSORS);
+    }
+
+    #[test]
+    fn loading_rejects_hard_errors_only() {
+        let path = Path::new("test.slime");
+        assert!(parse_settings(b"(agent_count: 0)", path).is_err());
+        let soft = parse_settings(b"(decay_rate: 1.5, substeps: 0)", path).unwrap();
+        assert_eq!(soft.decay_rate, 1.5);
+        assert_eq!(soft.substeps, 1);
+    }
+
+    #[test]
+    fn same_seed_spawns_identical_agents() {
+        let spawn = |seed| {
+            build_agents(
+                SpawnPattern::RandomUniform,
+                None,
+                1000,
+                seed,
+                64.,
+                32.,
+                false,
+            )
+        };
+        let first = spawn(7);
+        assert_eq!(
+            bytemuck::cast_slice::<_, u8>(&first),
+            bytemuck::cast_slice::<_, u8>(&spawn(7))
+        );
+        assert_ne!(
+            bytemuck::cast_slice::<_, u8>(&first),
+            bytemuck::cast_slice::<_, u8>(&spawn(8))
+        );
+    }
+
+    #[test]
+    fn substeps_split_the_time_of_a_step() {
+        let mut clock = SimClock::new(Some(30.), None, false);
+        clock.tick(1. / 30., true);
+        assert_eq!(clock.steps(), 1);
+        let time_step = GpuFrame::time_step_of(&clock);
+        assert!((time_step - 2.).abs() < 1e-5, "{time_step}");
+
+        for substeps in [0, 1, 2, 4, 8] {
+            let settings = SimulationSettings {
+                time_scale: 1.5,
+                substeps,
+                ..default()
+            };
+            let gpu = GpuSimulationSettings::from(&settings);
+            // each update pass moves the agents by its share of the whole step
+            let pass_time = time_step * gpu.time_scale;
+            assert_eq!(pass_time * gpu.substeps as f32, time_step * 1.5);
+        }
+    }
+
+    #[test]
+    fn speeds_are_jittered_up_from_the_base_speed() {
+        let speeds = |speed_jitter| {
+            let config = SimulationConfig {
+                agent_count: 1000,
+                speed_jitter,
+                ..default()
+            };
+            spawn_agents(&config, &AgentInitializer::default())
+                .into_iter()
+                .map(|agent| agent.speed)
+                .collect::<Vec<_>>()
+        };
+        assert!(speeds(0.).iter().all(|&speed| speed == 1.));
+
+        let jittered = speeds(0.5);
+        assert!(jittered.iter().all(|speed| (1.0..1.5).contains(speed)));
+        // spread over the whole range, not all at one end
+        assert!(jittered.iter().any(|&speed| speed < 1.1));
+        assert!(jittered.iter().any(|&speed| speed > 1.4));
+    }
+
+    #[test]
+    fn spawn_patterns_stay_in_their_bounds() {
+        let size = Vec2::new(200., 100.);
+        let center = size / 2.;
+        let radius = size.min_element() * SPAWN_RADIUS;
+        // a little slack for the rounding of the polar coordinates
+        let epsilon = 1e-3;
+        for pattern in [
+            SpawnPattern::CenterPoint,
+            SpawnPattern::RandomUniform,
+            SpawnPattern::CircleInward,
+            SpawnPattern::CircleOutward,
+            SpawnPattern::RingRandom,
+        ] {
+            for agent in build_agents(pattern, None, 1000, 3, size.x, size.y, false) {
+                let position = agent.position;
+                assert!(
+                    position.cmpge(Vec2::ZERO).all() && position.cmplt(size).all(),
+                    "{pattern:?} spawned an agent off the map at {position}"
+                );
+                let distance = position.distance(center);
+                let (inner, outer) = match pattern {
+                    SpawnPattern::CenterPoint => (0., 0.),
+                    SpawnPattern::RandomUniform => continue,
+                    SpawnPattern::CircleInward | SpawnPattern::CircleOutward => (0., radius),
+                    SpawnPattern::RingRandom => (radius * RING_INNER_RADIUS, radius),
+                };
+                assert!(
+                    distance >= inner - epsilon && distance <= outer + epsilon,
+                    "{pattern:?} spawned an agent {distance} from the center"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn seamless_spawn_repeats_the_pattern_on_every_tile() {
+        let size = Vec2::new(200., 100.);
+        let tile_size = size / SEAMLESS_TILES as f32;
+        let radius = tile_size.min_element() * SPAWN_RADIUS;
+        let epsilon = 1e-3;
+        for pattern in [SpawnPattern::RandomUniform, SpawnPattern::RingRandom] {
+            let mut per_tile = [0; (SEAMLESS_TILES * SEAMLESS_TILES) as usize];
+            for agent in build_agents(pattern, None, 1000, 3, size.x, size.y, true) {
+                let tile = (agent.position / tile_size).floor();
+                assert!(
+                    tile.cmpge(Vec2::ZERO).all()
+                        && tile.cmplt(Vec2::splat(SEAMLESS_TILES as f32)).all(),
+                    "{pattern:?} spawned an agent off the map at {}",
+                    agent.position
+                );
+                per_tile[(tile.y * SEAMLESS_TILES as f32 + tile.x) as usize] += 1;
+                if pattern == SpawnPattern::RingRandom {
+                    let center = tile * tile_size + tile_size / 2.;
+                    let distance = agent.position.distance(center);
+                    assert!(
+                        distance >= radius * RING_INNER_RADIUS - epsilon
+                            && distance <= radius + epsilon,
+                        "{pattern:?} spawned an agent {distance} from the center of its tile"
+                    );
+                }
+            }
+            assert_eq!(per_tile, [250; 4], "{pattern:?}");
+        }
+    }
+
+    #[test]
+    fn tangential_heading_is_perpendicular_to_the_radius() {
+        let size = Vec2::new(200., 100.);
+        for pattern in [SpawnPattern::RandomUniform, SpawnPattern::RingRandom] {
+            let agents = build_agents(
+                pattern,
+                Some(HeadingMode::Tangential),
+                1000,
+                5,
+                size.x,
+                size.y,
+                false,
+            );
+            for agent in agents {
+                let radius = (agent.position - size / 2.).normalize();
+                let heading = Vec2::from_angle(agent.angle);
+                assert!(radius.dot(heading).abs() < 1e-4, "{agent:?}");
+                // counterclockwise around the center
+                assert!(radius.perp_dot(heading) > 0., "{agent:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn headings_relative_to_the_center() {
+        let offset = Vec2::new(3., 4.);
+        let outward = offset.y.atan2(offset.x);
+        assert_eq!(HeadingMode::AwayFromCenter.angle(offset, 0.), outward);
+        assert_eq!(HeadingMode::FacingCenter.angle(offset, 0.), outward + PI);
+        assert_eq!(HeadingMode::Fixed(1.25).angle(offset, 0.5), 1.25);
+        assert_eq!(HeadingMode::Random.angle(offset, 0.25), PI / 2.);
+        // nothing to face on the center itself
+        assert_eq!(HeadingMode::FacingCenter.angle(Vec2::ZERO, 0.5), PI);
+        assert_eq!(HeadingMode::Fixed(1.25).angle(Vec2::ZERO, 0.5), 1.25);
+    }
+
+    #[test]
+    fn letterbox_keeps_the_aspect_ratio() {
+        let sim_size = Vec2::new(1280., 720.);
+        assert_eq!(letterbox(sim_size, sim_size), sim_size);
+        assert_eq!(
+            letterbox(Vec2::new(640., 360.), sim_size),
+            Vec2::new(640., 360.)
+        );
+        // bars left and right
+        assert_eq!(letterbox(Vec2::new(1600., 720.), sim_size), sim_size);
+        // bars above and below
+        assert_eq!(letterbox(Vec2::new(1280., 1000.), sim_size), sim_size);
+        // a portrait map in a square window
+        let portrait = Vec2::new(100., 200.);
+        assert_eq!(
+            letterbox(Vec2::splat(400.), portrait),
+            Vec2::new(200., 400.)
+        );
+        // rounded to whole pixels, never below one
+        assert_eq!(
+            letterbox(Vec2::new(1001., 500.), Vec2::new(2., 1.)),
+            Vec2::new(1000., 500.)
+        );
+        assert_eq!(letterbox(Vec2::new(1., 1.), Vec2::new(100., 1.)), Vec2::ONE);
+    }
+
+    #[test]
+    fn window_coords_map_onto_the_trail_map() {
+        let sim_size = Vec2::new(1280., 720.);
+        let window = Vec2::new(1280., 720.);
+        let to_sim = |cursor| window_to_sim_coords(cursor, window, 1., sim_size);
+        assert_eq!(to_sim(Vec2::ZERO), Some(Vec2::new(640., 360.)));
+        // y points up in the window and down on the map
+        assert_eq!(to_sim(Vec2::new(-640., 360.)), Some(Vec2::ZERO));
+        assert_eq!(to_sim(Vec2::new(320., -180.)), Some(Vec2::new(960., 540.)));
+        assert_eq!(to_sim(Vec2::new(640., 0.)), None);
+        assert_eq!(to_sim(Vec2::new(0., -360.)), None);
+    }
+
+    #[test]
+    fn window_coords_skip_the_letterbox_bars() {
+        let sim_size = Vec2::new(1280., 720.);
+        let window = Vec2::new(1600., 720.);
+        let to_sim = |cursor| window_to_sim_coords(cursor, window, 1., sim_size);
+        assert_eq!(to_sim(Vec2::new(-700., 0.)), None);
+        assert_eq!(to_sim(Vec2::new(700., 0.)), None);
+        assert_eq!(to_sim(Vec2::new(-640., 360.)), Some(Vec2::ZERO));
+
+        // a portrait map scaled up in a square window, with bars left and right
+        let portrait = Vec2::new(100., 200.);
+        let to_sim = |cursor| window_to_sim_coords(cursor, Vec2::splat(400.), 1., portrait);
+        assert_eq!(to_sim(Vec2::new(-100., 200.)), Some(Vec2::ZERO));
+        assert_eq!(to_sim(Vec2::new(50., -100.)), Some(Vec2::new(75., 150.)));
+        assert_eq!(to_sim(Vec2::new(-150., 0.)), None);
+    }
+
+    #[test]
+    fn window_coords_account_for_the_scale_factor() {
+        let sim_size = Vec2::new(1280., 720.);
+        // 640 by 360 logical pixels, 1280 by 720 physical ones
+        let window = Vec2::new(640., 360.);
+        let to_sim = |cursor| window_to_sim_coords(cursor, window, 2., sim_size);
+        assert_eq!(to_sim(Vec2::ZERO), Some(Vec2::new(640., 360.)));
+        assert_eq!(to_sim(Vec2::new(-320., 180.)), Some(Vec2::ZERO));
+        assert_eq!(to_sim(Vec2::new(160., -90.)), Some(Vec2::new(960., 540.)));
+        assert_eq!(to_sim(Vec2::new(320., 0.)), None);
+    }
+}
@@ -0,0 +1,839 @@
+//! The render graph nodes running the compute passes of a step, one node per stage so apps can
+//! add their own passes in between with [`RenderGraph::add_node_edge`].
+//!
+//! They run in this order, every one of them only once its own pipelines have compiled:
+//!
+//! - [`UpdateNode`] at [`graph::UPDATE`] clears the trail maps on resets, injects what was
+//!   painted, rotates the agent density while it is needed, sorts the agents into the grid when
+//!   there is one, moves the agents once per substep and spawns bursts.
+//! - [`DepositNode`] at [`graph::DEPOSIT`] adds the anti-aliased deposits to the trail map.
+//! - [`DiffuseNode`] at [`graph::DIFFUSE`] blurs and decays the trail map into the other one,
+//!   and sharpens it back into the first when `sharpen_amount` is set.
+//! - [`DisplayNode`] at [`graph::DISPLAY`] colorizes the trail map, reduces its stats, draws the
+//!   preview and copies out snapshots, screenshots and recorded frames.
+//! - [`SimulationsNode`] at [`graph::SIMULATIONS`] runs all of the above that applies to them
+//!   for every simulation added with
+//!   [`SlimeSimulations::add_simulation`](crate::SlimeSimulations::add_simulation), before the
+//!   camera driver.
+//!
+//! Which trail map and agent buffer they use is decided once a frame by [`advance_step`], and by
+//! [`instances::queue_simulations`] for the added simulations, since the graph doesn't order the
+//! `update` calls of its nodes.
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_asset::RenderAssets,
+        render_graph::{self, RenderGraph},
+        render_resource::{
+            BindGroup, CachedComputePipelineId, CachedPipelineState, ComputePass,
+            ComputePassDescriptor, PipelineCache, PipelineCacheError,
+        },
+        renderer::RenderContext,
+        texture::GpuImage,
+    },
+};
+
+#[cfg(feature = "benchmark")]
+use crate::benchmark;
+#[cfg(feature = "profiling")]
+use crate::profiling::{PassTimer, ProfiledPass};
+use crate::{
+    agent_access, burst, density, graph, grid, headless, instances, preview, readback, record,
+    screenshot, snapshot, stats, status, workgroups_for, AgentBuffer, GpuSlime, InjectionBuffer,
+    SimClock, SimState, SimulationConfig, Slime, SlimeBindGroups, SlimeHandle, SlimePipeline,
+    TrailDisplay, TrailMap,
+};
+
+/// Adds the nodes to the render graph, each depending on the one before it.
+pub(crate) fn add_nodes(render_graph: &mut RenderGraph) {
+    render_graph.add_node(graph::UPDATE, UpdateNode::default());
+    render_graph.add_node(graph::DEPOSIT, DepositNode::default());
+    render_graph.add_node(graph::DIFFUSE, DiffuseNode::default());
+    render_graph.add_node(graph::DISPLAY, DisplayNode::default());
+    render_graph.add_node(graph::SIMULATIONS, SimulationsNode::default());
+    for (before, after) in [
+        (graph::UPDATE, graph::DEPOSIT),
+        (graph::DEPOSIT, graph::DIFFUSE),
+        (graph::DIFFUSE, graph::DISPLAY),
+        (graph::DISPLAY, graph::SIMULATIONS),
+        (
+            graph::SIMULATIONS,
+            bevy::render::main_graph::node::CAMERA_DRIVER,
+        ),
+    ] {
+        if let Err(error) = render_graph.add_node_edge(before, after) {
+            error!("Can't run {before} before {after}: {error}");
+        }
+    }
+}
+
+enum SlimeState {
+    Loading,
+    Update,
+    /// A pipeline failed to compile, nothing is dispatched.
+    Failed(String),
+}
+
+/// What the nodes do this frame, shared by all of them, and what [`SimulationsNode`] does for
+/// each added simulation.
+#[derive(Resource)]
+pub(crate) struct SlimeStep {
+    state: SlimeState,
+    /// Index of the trail map the update pass writes to this frame.
+    trail_index: usize,
+    /// Whether the last step was sharpened, leaving its output in `trail_index` rather than in
+    /// the map it diffused into.
+    sharpened: bool,
+    /// Index of the agent buffer written by the last update pass of this frame.
+    agent_index: usize,
+    /// Update passes run this frame, the [`SimClock`] steps times
+    /// [`GpuSlime::substeps`](crate::GpuSlime::substeps).
+    substeps: u32,
+    /// Whether the simulation advances this frame, false while paused.
+    advance: bool,
+    /// Whether the trail maps are zeroed before this frame's passes, on the first frame and on
+    /// resets. Storage textures aren't guaranteed to be zeroed on every backend.
+    clear: bool,
+}
+
+impl Default for SlimeStep {
+    fn default() -> Self {
+        Self {
+            state: SlimeState::Loading,
+            trail_index: 0,
+            sharpened: false,
+            agent_index: 0,
+            substeps: 1,
+            advance: false,
+            clear: false,
+        }
+    }
+}
+
+impl SlimeStep {
+    pub(crate) fn running(&self) -> bool {
+        matches!(self.state, SlimeState::Update)
+    }
+
+    /// Starts stepping once the pipelines and bind groups are ready, clearing the trail maps on
+    /// the first frame.
+    pub(crate) fn start(&mut self) {
+        self.state = SlimeState::Update;
+        self.clear = true;
+    }
+
+    /// Decides whether this frame advances, running the `steps` of the clock unless paused or
+    /// `take_step` says otherwise, and swaps the trail maps and `agents` if it does.
+    pub(crate) fn advance(
+        &mut self,
+        state: &SimState,
+        steps: u32,
+        take_step: impl FnOnce() -> bool,
+        slime: Option<&GpuSlime>,
+        agents: &mut AgentBuffer,
+    ) {
+        self.clear = state.reset || state.clear_trail;
+        self.advance = state.advances() && steps > 0 && take_step();
+        if self.advance {
+            self.substeps = steps * slime.map_or(1, |slime| slime.substeps);
+            // the diffuse pass of the previous step wrote into the other trail map, unless it
+            // was sharpened back into this one
+            self.trail_index = self.latest_index();
+            self.sharpened = slime.map_or(false, |slime| slime.sharpen);
+            // and every update pass of this step writes the agents read by the next one
+            self.agent_index = (self.agent_index + self.substeps as usize) % 2;
+            agents.latest = self.agent_index;
+        }
+    }
+
+    /// Whether this frame runs a step.
+    pub(crate) fn advances(&self) -> bool {
+        self.running() && self.advance
+    }
+
+    /// The bind group for the trail map and agent buffer written this frame.
+    fn bind_group<'w>(&self, bind_groups: &'w SlimeBindGroups) -> &'w BindGroup {
+        &bind_groups.0[self.trail_index][self.agent_index]
+    }
+
+    /// Index of the trail map holding the output of the last step.
+    fn latest_index(&self) -> usize {
+        if self.sharpened {
+            self.trail_index
+        } else {
+            1 - self.trail_index
+        }
+    }
+
+    /// The bind group reading the output of the last step as `next_trail_map`.
+    fn latest_bind_group<'w>(&self, bind_groups: &'w SlimeBindGroups) -> &'w BindGroup {
+        &bind_groups.0[1 - self.latest_index()][self.agent_index]
+    }
+
+    /// The trail map holding the output of the last step.
+    pub(crate) fn latest_trail<'w>(&self, world: &'w World) -> Option<&'w GpuImage> {
+        let trail_map = world.resource::<TrailMap>();
+        world
+            .resource::<RenderAssets<Image>>()
+            .get(&trail_map[self.latest_index()])
+    }
+}
+
+/// Waits for the pipelines to compile, then decides whether this frame advances and swaps the
+/// trail maps and agent buffers if it does.
+///
+/// Runs in the queue stage, once the bind groups for the frame exist.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn advance_step(
+    mut step: ResMut<SlimeStep>,
+    pipeline: Res<SlimePipeline>,
+    pipeline_cache: Res<PipelineCache>,
+    channel: Res<status::PipelineEventChannel>,
+    bind_groups: Option<Res<SlimeBindGroups>>,
+    state: Res<SimState>,
+    clock: Res<SimClock>,
+    headless_run: Option<Res<headless::HeadlessRun>>,
+    slime_store: Res<RenderAssets<Slime>>,
+    slime: Res<SlimeHandle>,
+    mut agent_buffer: ResMut<AgentBuffer>,
+) {
+    let step = &mut *step;
+    // if every pipeline has loaded, transition to the next stage
+    match step.state {
+        SlimeState::Loading => {
+            let states = pipeline
+                .all()
+                .map(|id| pipeline_cache.get_compute_pipeline_state(id));
+
+            // the pipeline cache keeps retrying until the shader is loaded, any other error is
+            // final
+            let error = states.iter().find_map(|state| match state {
+                CachedPipelineState::Err(
+                    PipelineCacheError::ShaderNotLoaded(_)
+                    | PipelineCacheError::ShaderImportNotYetAvailable,
+                ) => None,
+                CachedPipelineState::Err(error) => Some(error.to_string()),
+                _ => None,
+            });
+            if let Some(error) = error {
+                error!("The simulation shader failed to compile: {error}");
+                channel.send(status::SlimePipelineEvent::Failed(error.clone()));
+                step.state = SlimeState::Failed(error);
+                return;
+            }
+
+            let loaded = states
+                .iter()
+                .all(|state| matches!(state, CachedPipelineState::Ok(_)));
+            // the clear pass needs the bind groups, so wait for them too
+            if loaded && bind_groups.is_some() {
+                channel.send(status::SlimePipelineEvent::Ready);
+                step.start();
+            }
+        }
+        SlimeState::Update => step.advance(
+            &state,
+            clock.steps(),
+            || headless_run.map_or(true, |run| run.take_step()),
+            slime_store.get(&slime.0),
+            &mut agent_buffer,
+        ),
+        SlimeState::Failed(_) => {}
+    }
+}
+
+/// Whether every one of `pipelines` has compiled.
+fn pipelines_ready(world: &World, pipelines: &[CachedComputePipelineId]) -> bool {
+    let pipeline_cache = world.resource::<PipelineCache>();
+    pipelines
+        .iter()
+        .all(|&id| pipeline_cache.get_compute_pipeline(id).is_some())
+}
+
+/// Dispatches enough square workgroups to cover a `width` by `height` texture.
+///
+/// The size doesn't have to be a multiple of the workgroup size, every texture pass returns
+/// early for the invocations falling outside the texture.
+pub(crate) fn dispatch_2d(pass: &mut ComputePass, width: u32, height: u32, workgroup_size: u32) {
+    let (x, y) = texture_workgroups(width, height, workgroup_size);
+    pass.dispatch_workgroups(x, y, 1);
+}
+
+/// Columns and rows of square workgroups of `workgroup_size` covering a `width` by `height`
+/// texture.
+fn texture_workgroups(width: u32, height: u32, workgroup_size: u32) -> (u32, u32) {
+    (
+        workgroups_for(width, workgroup_size),
+        workgroups_for(height, workgroup_size),
+    )
+}
+
+/// Dispatches a pass over every texel of the trail map.
+fn dispatch_trail(pass: &mut ComputePass, config: &SimulationConfig) {
+    dispatch_2d(
+        pass,
+        config.sim_width,
+        config.sim_height,
+        config.texture_workgroup_size,
+    );
+}
+
+/// The pass timer if this frame's passes are timed.
+#[cfg(feature = "profiling")]
+fn pass_timer(world: &World) -> Option<&PassTimer> {
+    world
+        .get_resource::<PassTimer>()
+        .filter(|timer| timer.timing())
+}
+
+/// The step and bind groups once the simulation is running, `None` while it loads or after it
+/// failed to.
+fn running_step(world: &World) -> Option<(&SlimeStep, &SlimeBindGroups)> {
+    let step = world.resource::<SlimeStep>();
+    let bind_groups = world.get_resource::<SlimeBindGroups>()?;
+    step.running().then_some((step, bind_groups))
+}
+
+/// Clears, injects, counts, sorts and moves the agents and spawns bursts.
+#[derive(Default)]
+pub(crate) struct UpdateNode {
+    ready: bool,
+}
+
+impl render_graph::Node for UpdateNode {
+    fn update(&mut self, world: &mut World) {
+        let pipeline = world.resource::<SlimePipeline>();
+        self.ready = pipelines_ready(
+            world,
+            &[
+                pipeline.clear_pipeline,
+                pipeline.rotate_density_pipeline,
+                pipeline.inject_pipeline,
+                pipeline.update_pipeline,
+                pipeline.spawn_pipeline,
+                pipeline.clear_grid_pipeline,
+                pipeline.count_grid_pipeline,
+                pipeline.scan_grid_pipeline,
+                pipeline.scatter_grid_pipeline,
+            ],
+        );
+    }
+
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        #[cfg(feature = "benchmark")]
+        if let Some(timer) = world
+            .get_resource::<benchmark::DispatchTimer>()
+            .filter(|timer| timer.timing())
+        {
+            timer.begin(&mut render_context.command_encoder);
+        }
+
+        let Some((step, bind_groups)) = running_step(world).filter(|_| self.ready) else {
+            return Ok(());
+        };
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline = world.resource::<SlimePipeline>();
+        let config = world.resource::<SimulationConfig>();
+        let injections = world.resource::<InjectionBuffer>();
+        let bursts = world.get_resource::<burst::AgentBursts>();
+        let active_count = bursts.map_or(config.agent_count, |bursts| bursts.active_count);
+
+        #[cfg(feature = "profiling")]
+        if let Some(timer) = pass_timer(world) {
+            timer.begin(&mut render_context.command_encoder, ProfiledPass::Update);
+        }
+        let mut pass = render_context
+            .command_encoder
+            .begin_compute_pass(&ComputePassDescriptor::default());
+        pass.set_bind_group(0, step.bind_group(bind_groups), &[]);
+
+        if step.clear {
+            let clear_pipeline = pipeline_cache
+                .get_compute_pipeline(pipeline.clear_pipeline)
+                .unwrap();
+            pass.set_pipeline(clear_pipeline);
+            dispatch_trail(&mut pass, config);
+        }
+
+        // paused: keep showing the output of the last step
+        if step.advance {
+            if injections.count > 0 {
+                let inject_pipeline = pipeline_cache
+                    .get_compute_pipeline(pipeline.inject_pipeline)
+                    .unwrap();
+                pass.set_pipeline(inject_pipeline);
+                dispatch_trail(&mut pass, config);
+            }
+
+            let avoids_crowds = world
+                .resource::<RenderAssets<Slime>>()
+                .get(&world.resource::<SlimeHandle>().0)
+                .map_or(false, |slime| slime.avoids_crowds);
+            if world.resource::<density::DensityOverlay>().0 || avoids_crowds {
+                let rotate_density_pipeline = pipeline_cache
+                    .get_compute_pipeline(pipeline.rotate_density_pipeline)
+                    .unwrap();
+                pass.set_pipeline(rotate_density_pipeline);
+                dispatch_trail(&mut pass, config);
+            }
+
+            // the grid sorts the agents the first substep reads
+            let first_agents_out = (step.agent_index + step.substeps as usize + 1) % 2;
+            pass.set_bind_group(0, &bind_groups.0[step.trail_index][first_agents_out], &[]);
+            grid::dispatch_grid(&mut pass, world, config, active_count);
+
+            let update_pipeline = pipeline_cache
+                .get_compute_pipeline(pipeline.update_pipeline)
+                .unwrap();
+            pass.set_pipeline(update_pipeline);
+            for substep in 0..step.substeps as usize {
+                // the last substep writes into `agent_index`, the one before it out of it
+                let agents_out = (step.agent_index + step.substeps as usize + 1 + substep) % 2;
+                pass.set_bind_group(0, &bind_groups.0[step.trail_index][agents_out], &[]);
+                pass.dispatch_workgroups(
+                    workgroups_for(active_count, config.agent_workgroup_size),
+                    1,
+                    1,
+                );
+            }
+        }
+
+        // the spawn pass writes into the latest agents whether or not this frame advanced
+        if let Some(burst) = bursts.and_then(|bursts| bursts.burst) {
+            let spawn_pipeline = pipeline_cache
+                .get_compute_pipeline(pipeline.spawn_pipeline)
+                .unwrap();
+            pass.set_pipeline(spawn_pipeline);
+            pass.dispatch_workgroups(
+                workgroups_for(burst.count, config.agent_workgroup_size),
+                1,
+                1,
+            );
+        }
+
+        #[cfg(feature = "profiling")]
+        if let Some(timer) = pass_timer(world) {
+            drop(pass);
+            timer.end(&mut render_context.command_encoder, ProfiledPass::Update);
+        }
+        Ok(())
+    }
+}
+
+/// Adds the anti-aliased deposits of the step to the trail map.
+#[derive(Default)]
+pub(crate) struct DepositNode {
+    ready: bool,
+}
+
+impl render_graph::Node for DepositNode {
+    fn update(&mut self, world: &mut World) {
+        let pipeline = world.resource::<SlimePipeline>();
+        self.ready = pipelines_ready(world, &[pipeline.resolve_pipeline]);
+    }
+
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        let Some((step, bind_groups)) = running_step(world).filter(|_| self.ready) else {
+            return Ok(());
+        };
+        let antialiased_deposit = world
+            .resource::<RenderAssets<Slime>>()
+            .get(&world.resource::<SlimeHandle>().0)
+            .map_or(false, |slime| slime.antialiased_deposit);
+        if !step.advance || !antialiased_deposit {
+            return Ok(());
+        }
+
+        let resolve_pipeline = world
+            .resource::<PipelineCache>()
+            .get_compute_pipeline(world.resource::<SlimePipeline>().resolve_pipeline)
+            .unwrap();
+        let mut pass = render_context
+            .command_encoder
+            .begin_compute_pass(&ComputePassDescriptor::default());
+        pass.set_bind_group(0, step.bind_group(bind_groups), &[]);
+        pass.set_pipeline(resolve_pipeline);
+        dispatch_trail(&mut pass, world.resource::<SimulationConfig>());
+        Ok(())
+    }
+}
+
+/// Diffuses and decays the trail map into the one the next step reads, then sharpens it back
+/// into the first one if the step is sharpened.
+#[derive(Default)]
+pub(crate) struct DiffuseNode {
+    ready: bool,
+}
+
+impl render_graph::Node for DiffuseNode {
+    fn update(&mut self, world: &mut World) {
+        let pipeline = world.resource::<SlimePipeline>();
+        self.ready = pipelines_ready(
+            world,
+            &[pipeline.diffuse_pipeline, pipeline.sharpen_pipeline],
+        );
+    }
+
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        let Some((step, bind_groups)) = running_step(world).filter(|_| self.ready) else {
+            return Ok(());
+        };
+        if !step.advance {
+            return Ok(());
+        }
+
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline = world.resource::<SlimePipeline>();
+        let config = world.resource::<SimulationConfig>();
+        #[cfg(feature = "profiling")]
+        if let Some(timer) = pass_timer(world) {
+            timer.begin(&mut render_context.command_encoder, ProfiledPass::Diffuse);
+        }
+        let mut pass = render_context
+            .command_encoder
+            .begin_compute_pass(&ComputePassDescriptor::default());
+        pass.set_bind_group(0, step.bind_group(bind_groups), &[]);
+
+        let diffuse_pipeline = pipeline_cache
+            .get_compute_pipeline(pipeline.diffuse_pipeline)
+            .unwrap();
+        pass.set_pipeline(diffuse_pipeline);
+        dispatch_trail(&mut pass, config);
+
+        if step.sharpened {
+            let sharpen_pipeline = pipeline_cache
+                .get_compute_pipeline(pipeline.sharpen_pipeline)
+                .unwrap();
+            pass.set_pipeline(sharpen_pipeline);
+            dispatch_trail(&mut pass, config);
+        }
+
+        #[cfg(feature = "profiling")]
+        if let Some(timer) = pass_timer(world) {
+            drop(pass);
+            timer.end(&mut render_context.command_encoder, ProfiledPass::Diffuse);
+        }
+        Ok(())
+    }
+}
+
+/// Colorizes the trail map for the screen and copies out whatever was asked for.
+///
+/// The copies are made even before the pipelines compile, so a pending readback never waits on
+/// them.
+#[derive(Default)]
+pub(crate) struct DisplayNode {
+    ready: bool,
+}
+
+impl DisplayNode {
+    /// Copies the agents and the latest trail map into the staging buffers of a snapshot.
+    fn copy_to_snapshot(
+        step: &SlimeStep,
+        render_context: &mut RenderContext,
+        world: &World,
+        readback: &snapshot::SnapshotReadback,
+    ) {
+        let agents = world.resource::<AgentBuffer>();
+        let config = world.resource::<SimulationConfig>();
+        let Some(trail) = step.latest_trail(world) else {
+            return;
+        };
+
+        let encoder = &mut render_context.command_encoder;
+        encoder.copy_buffer_to_buffer(
+            agents.latest(),
+            0,
+            readback.staging.buffer(0),
+            0,
+            readback.agents_size,
+        );
+        readback::copy_trail_to_buffer(
+            encoder,
+            &trail.texture,
+            readback.staging.buffer(1),
+            config.trail_extent(),
+            config.trail_precision,
+        );
+    }
+
+    /// Copies the latest trail map into the staging buffer of a screenshot.
+    fn copy_to_screenshot(
+        step: &SlimeStep,
+        render_context: &mut RenderContext,
+        world: &World,
+        readback: &screenshot::ScreenshotReadback,
+    ) {
+        let config = world.resource::<SimulationConfig>();
+        let Some(trail) = step.latest_trail(world) else {
+            return;
+        };
+        readback::copy_trail_to_buffer(
+            &mut render_context.command_encoder,
+            &trail.texture,
+            readback.staging.buffer(0),
+            config.trail_extent(),
+            config.trail_precision,
+        );
+    }
+}
+
+impl render_graph::Node for DisplayNode {
+    fn update(&mut self, world: &mut World) {
+        let pipeline = world.resource::<SlimePipeline>();
+        self.ready = pipelines_ready(
+            world,
+            &[pipeline.colorize_pipeline, pipeline.reduce_pipeline],
+        );
+    }
+
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        let step = world.resource::<SlimeStep>();
+        let trail_stats = world
+            .get_resource::<stats::TrailStatsReduction>()
+            .filter(|reduction| reduction.pending_copy().is_some());
+        if let Some((step, bind_groups)) = running_step(world).filter(|_| self.ready) {
+            let pipeline_cache = world.resource::<PipelineCache>();
+            let pipeline = world.resource::<SlimePipeline>();
+            let config = world.resource::<SimulationConfig>();
+
+            let mut pass = render_context
+                .command_encoder
+                .begin_compute_pass(&ComputePassDescriptor::default());
+            pass.set_bind_group(0, step.latest_bind_group(bind_groups), &[]);
+
+            let colorize_pipeline = pipeline_cache
+                .get_compute_pipeline(pipeline.colorize_pipeline)
+                .unwrap();
+            pass.set_pipeline(colorize_pipeline);
+            dispatch_trail(&mut pass, config);
+
+            if trail_stats.is_some() {
+                let reduce_pipeline = pipeline_cache
+                    .get_compute_pipeline(pipeline.reduce_pipeline)
+                    .unwrap();
+                pass.set_pipeline(reduce_pipeline);
+                dispatch_trail(&mut pass, config);
+            }
+
+            preview::dispatch_preview(&mut pass, world, config.texture_workgroup_size);
+        }
+
+        #[cfg(feature = "benchmark")]
+        if let Some(timer) = world
+            .get_resource::<benchmark::DispatchTimer>()
+            .filter(|timer| timer.timing())
+        {
+            timer.end(&mut render_context.command_encoder);
+        }
+        #[cfg(feature = "profiling")]
+        if let Some(timer) = pass_timer(world) {
+            timer.resolve(&mut render_context.command_encoder);
+        }
+
+        if let Some(readback) = world
+            .get_resource::<snapshot::SnapshotReadback>()
+            .filter(|readback| readback.staging.copy_pending())
+        {
+            Self::copy_to_snapshot(step, render_context, world, readback);
+        }
+        if let Some(readback) = world
+            .get_resource::<screenshot::ScreenshotReadback>()
+            .filter(|readback| readback.staging.copy_pending())
+        {
+            Self::copy_to_screenshot(step, render_context, world, readback);
+        }
+        if let (Some(readback), Some(agents)) = (
+            world
+                .get_resource::<agent_access::AgentReads>()
+                .and_then(|reads| reads.readback.as_ref())
+                .filter(|readback| readback.staging.copy_pending()),
+            world.get_resource::<AgentBuffer>(),
+        ) {
+            readback.copy(&mut render_context.command_encoder, agents);
+        }
+        if let (Some(recorder), Some(display)) = (
+            world.get_resource::<record::FrameRecorder>(),
+            world
+                .resource::<RenderAssets<Image>>()
+                .get(&world.resource::<TrailDisplay>().0),
+        ) {
+            recorder.copy_frame(&mut render_context.command_encoder, &display.texture);
+        }
+        if let Some(reduction) = trail_stats {
+            reduction.copy_partials(&mut render_context.command_encoder);
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs a whole step of every simulation added with
+/// [`SlimeSimulations::add_simulation`](crate::SlimeSimulations::add_simulation) and colorizes it.
+///
+/// They don't paint, spawn bursts or show the density, so only the clear, update, deposit,
+/// diffuse, sharpen and colorize passes run, each simulation in a compute pass of its own.
+#[derive(Default)]
+pub(crate) struct SimulationsNode {
+    ready: bool,
+}
+
+impl render_graph::Node for SimulationsNode {
+    fn update(&mut self, world: &mut World) {
+        let pipeline = world.resource::<SlimePipeline>();
+        self.ready = pipelines_ready(world, &pipeline.all());
+    }
+
+    fn run(
+        &self,
+        _graph: &mut render_graph::RenderGraphContext,
+        render_context: &mut RenderContext,
+        world: &World,
+    ) -> Result<(), render_graph::NodeRunError> {
+        let Some(simulations) = world
+            .get_resource::<instances::SimulationsGpu>()
+            .filter(|_| self.ready)
+        else {
+            return Ok(());
+        };
+        let pipeline_cache = world.resource::<PipelineCache>();
+        let pipeline = world.resource::<SlimePipeline>();
+        let compute_pipeline = |id| pipeline_cache.get_compute_pipeline(id).unwrap();
+
+        for simulation in simulations.0.values() {
+            let (step, config) = (&simulation.step, &simulation.config);
+            let Some(bind_groups) = simulation.bind_groups.as_ref().filter(|_| step.running())
+            else {
+                continue;
+            };
+            let mut pass = render_context
+                .command_encoder
+                .begin_compute_pass(&ComputePassDescriptor::default());
+            pass.set_bind_group(0, step.bind_group(bind_groups), &[]);
+            if step.clear {
+                pass.set_pipeline(compute_pipeline(pipeline.clear_pipeline));
+                dispatch_trail(&mut pass, config);
+            }
+
+            if step.advance {
+                if simulation.slime.avoids_crowds {
+                    pass.set_pipeline(compute_pipeline(pipeline.rotate_density_pipeline));
+                    dispatch_trail(&mut pass, config);
+                }
+
+                let first_agents_out = (step.agent_index + step.substeps as usize + 1) % 2;
+                pass.set_bind_group(0, &bind_groups.0[step.trail_index][first_agents_out], &[]);
+                grid::dispatch_grid(&mut pass, world, config, config.agent_count);
+
+                pass.set_pipeline(compute_pipeline(pipeline.update_pipeline));
+                for substep in 0..step.substeps as usize {
+                    let agents_out = (step.agent_index + step.substeps as usize + 1 + substep) % 2;
+                    pass.set_bind_group(0, &bind_groups.0[step.trail_index][agents_out], &[]);
+                    pass.dispatch_workgroups(
+                        workgroups_for(config.agent_count, config.agent_workgroup_size),
+                        1,
+                        1,
+                    );
+                }
+
+                pass.set_bind_group(0, step.bind_group(bind_groups), &[]);
+                if simulation.slime.antialiased_deposit {
+                    pass.set_pipeline(compute_pipeline(pipeline.resolve_pipeline));
+                    dispatch_trail(&mut pass, config);
+                }
+                pass.set_pipeline(compute_pipeline(pipeline.diffuse_pipeline));
+                dispatch_trail(&mut pass, config);
+                if step.sharpened {
+                    pass.set_pipeline(compute_pipeline(pipeline.sharpen_pipeline));
+                    dispatch_trail(&mut pass, config);
+                }
+            }
+
+            pass.set_bind_group(0, step.latest_bind_group(bind_groups), &[]);
+            pass.set_pipeline(compute_pipeline(pipeline.colorize_pipeline));
+            dispatch_trail(&mut pass, config);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stands in for bevy's camera driver, which the nodes run before.
+    struct CameraDriver;
+
+    impl render_graph::Node for CameraDriver {
+        fn run(
+            &self,
+            _graph: &mut render_graph::RenderGraphContext,
+            _render_context: &mut RenderContext,
+            _world: &World,
+        ) -> Result<(), render_graph::NodeRunError> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn nodes_run_in_order_before_the_camera_driver() {
+        let camera_driver = bevy::render::main_graph::node::CAMERA_DRIVER;
+        let mut render_graph = RenderGraph::default();
+        render_graph.add_node(camera_driver, CameraDriver);
+        add_nodes(&mut render_graph);
+
+        let order = [
+            graph::UPDATE,
+            graph::DEPOSIT,
+            graph::DIFFUSE,
+            graph::DISPLAY,
+            graph::SIMULATIONS,
+            camera_driver,
+        ];
+        for pair in order.windows(2) {
+            let outputs: Vec<_> = render_graph
+                .iter_node_outputs(pair[0])
+                .unwrap()
+                .filter_map(|(_, node)| node.name.clone())
+                .collect();
+            assert_eq!(outputs, [pair[1]], "after {}", pair[0]);
+        }
+    }
+
+    #[test]
+    fn texture_workgroups_cover_every_texel_once_over() {
+        for workgroup_size in [1, 4, 8, 16] {
+            for (width, height) in [(1, 1), (64, 64), (1280, 720), (1281, 719), (7, 3000)] {
+                let (x, y) = texture_workgroups(width, height, workgroup_size);
+                for (groups, texels) in [(x, width), (y, height)] {
+                    // enough for every texel, and not a whole workgroup more
+                    assert!(groups * workgroup_size >= texels);
+                    assert!((groups - 1) * workgroup_size < texels);
+                }
+            }
+        }
+        assert_eq!(texture_workgroups(1280, 720, 8), (160, 90));
+        assert_eq!(texture_workgroups(1281, 721, 8), (161, 91));
+    }
+}
@@ -0,0 +1,44 @@
+//! A seeded noise field turning the agents as they move, see
+//! [`SimulationSettings::noise_strength`](crate::SimulationSettings::noise_strength).
+//!
+//! The field is value noise: a [`NOISE_SIZE`] square lattice of random values from -1 to 1,
+//! generated from the seed at startup, which the `update` pass interpolates smoothly at the
+//! agent's position scaled down by `noise_scale`. Lookups wrap around the lattice, or with
+//! [`SimulationSettings::seamless`](crate::SimulationSettings::seamless) around the trail map,
+//! so the field repeats across it.
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::ExtractResource,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+};
+
+use crate::XorShift;
+
+/// Edge length of the noise lattice in texels.
+pub(crate) const NOISE_SIZE: u32 = 256;
+
+/// The noise lattice, one `R32Float` texel per lattice point.
+#[derive(Debug, Clone, Deref, Resource, ExtractResource)]
+pub(crate) struct NoiseTexture(pub(crate) Handle<Image>);
+
+/// Generates the lattice for `seed`.
+pub(crate) fn create_noise_image(seed: u64) -> Image {
+    // a stream of its own, so the noise doesn't follow the spawn positions
+    let mut random = XorShift::new(seed ^ 0x6e6f_6973_6566_6c64);
+    let values: Vec<f32> = (0..NOISE_SIZE * NOISE_SIZE)
+        .map(|_| random.next_f32() * 2. - 1.)
+        .collect();
+    Image::new(
+        Extent3d {
+            width: NOISE_SIZE,
+            height: NOISE_SIZE,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        bytemuck::cast_slice(&values).to_vec(),
+        TextureFormat::R32Float,
+    )
+}
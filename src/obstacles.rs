@@ -0,0 +1,96 @@
+//! Impassable walls read from a mask image, see
+//! [`SimulationSettings::obstacle_mask`](crate::SimulationSettings::obstacle_mask).
+//!
+//! Agents bounce off masked texels and never deposit on them, and the diffuse pass keeps them
+//! clear of trail.
+
+use std::path::Path;
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::ExtractResource,
+        render_resource::{Extent3d, TextureDimension, TextureFormat},
+    },
+    tasks::futures_lite::future::block_on,
+};
+
+/// Mask texels with a luminance of at least this are walls.
+const OBSTACLE_THRESHOLD: u8 = 128;
+
+/// Walls of the trail map, one `R8Unorm` texel per trail texel with walls set to 1. Without an
+/// `obstacle_mask` it is all zero.
+#[derive(Debug, Clone, Deref, Resource, ExtractResource)]
+pub(crate) struct ObstacleMask(pub(crate) Handle<Image>);
+
+/// Reads the mask at `path` from the asset folder, resampling it to `size` with nearest
+/// neighbor filtering when the dimensions don't match.
+///
+/// Falls back to no obstacles when the image can't be read.
+pub(crate) fn load_obstacle_mask(
+    asset_server: &AssetServer,
+    path: Option<&str>,
+    size: Extent3d,
+) -> Image {
+    let texels = path
+        .and_then(|path| read_mask(asset_server, path, size))
+        .unwrap_or_else(|| vec![0; (size.width * size.height) as usize]);
+    Image::new(size, TextureDimension::D2, texels, TextureFormat::R8Unorm)
+}
+
+fn read_mask(asset_server: &AssetServer, path: &str, size: Extent3d) -> Option<Vec<u8>> {
+    let luminance = read_luminance(asset_server, path, size, "obstacle mask")?;
+    Some(
+        luminance
+            .into_iter()
+            .map(|texel| {
+                if texel >= OBSTACLE_THRESHOLD {
+                    u8::MAX
+                } else {
+                    0
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Reads the image at `path` from the asset folder as one luminance byte per texel of `size`,
+/// resampled with nearest neighbor filtering. `what` names the image in the logs.
+pub(crate) fn read_luminance(
+    asset_server: &AssetServer,
+    path: &str,
+    size: Extent3d,
+    what: &str,
+) -> Option<Vec<u8>> {
+    let bytes = match block_on(asset_server.asset_io().load_path(Path::new(path))) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            error!("Failed to read the {what} {path}: {error}");
+            return None;
+        }
+    };
+    let image = match image::load_from_memory(&bytes) {
+        Ok(image) => image.to_luma8(),
+        Err(error) => {
+            error!("Failed to decode the {what} {path}: {error}");
+            return None;
+        }
+    };
+
+    let (width, height) = image.dimensions();
+    if (width, height) != (size.width, size.height) {
+        warn!(
+            "The {what} {path} is {width}x{height}, resampling it to the {}x{} sim size",
+            size.width, size.height
+        );
+    }
+    let mut texels = Vec::with_capacity((size.width * size.height) as usize);
+    for y in 0..size.height {
+        for x in 0..size.width {
+            let source_x = (x as u64 * width as u64 / size.width as u64) as u32;
+            let source_y = (y as u64 * height as u64 / size.height as u64) as u32;
+            texels.push(image.get_pixel(source_x, source_y).0[0]);
+        }
+    }
+    Some(texels)
+}
@@ -0,0 +1,163 @@
+//! On-screen performance and trail stats, toggled with F3, and the shader compile error if there
+//! is one.
+
+use bevy::{
+    diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+};
+
+use crate::{
+    presets::Presets,
+    stats::TrailStats,
+    status::{SimUnsupported, SlimePipelineStatus},
+    SimClock, SimState, SimulationConfig,
+};
+
+/// Bevy 0.9 has no built-in font, this one ships in the assets folder.
+const OVERLAY_FONT: &str = "fonts/DejaVuSansMono.ttf";
+
+/// The performance and trail stats toggled with F3, and the shader compile error.
+pub struct OverlayPlugin;
+
+impl Plugin for OverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(FrameTimeDiagnosticsPlugin::default())
+            .add_startup_system(spawn_overlay)
+            .add_system(toggle_overlay)
+            .add_system(update_overlay)
+            .add_system(show_pipeline_error);
+    }
+}
+
+#[derive(Component)]
+struct StatsText;
+
+/// Shown in the middle of the screen once the shader fails to compile or when the GPU can't run
+/// the simulation, whatever F3 is set to.
+#[derive(Component)]
+struct PipelineErrorText;
+
+fn spawn_overlay(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let style = TextStyle {
+        font: asset_server.load(OVERLAY_FONT),
+        font_size: 16.,
+        color: Color::WHITE,
+    };
+    commands.spawn((
+        TextBundle::from_section("", style).with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Px(8.),
+                left: Val::Px(8.),
+                ..default()
+            },
+            ..default()
+        }),
+        // UI is drawn after the 2D pass anyway, this keeps it above any other UI too
+        ZIndex::Global(i32::MAX),
+        StatsText,
+    ));
+
+    let error_style = TextStyle {
+        font: asset_server.load(OVERLAY_FONT),
+        font_size: 20.,
+        color: Color::RED,
+    };
+    commands.spawn((
+        TextBundle::from_section("", error_style).with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                top: Val::Percent(45.),
+                left: Val::Px(32.),
+                right: Val::Px(32.),
+                ..default()
+            },
+            ..default()
+        }),
+        ZIndex::Global(i32::MAX),
+        PipelineErrorText,
+    ));
+}
+
+fn toggle_overlay(keys: Res<Input<KeyCode>>, mut texts: Query<&mut Visibility, With<StatsText>>) {
+    if keys.just_pressed(KeyCode::F3) {
+        for mut visibility in &mut texts {
+            visibility.is_visible = !visibility.is_visible;
+        }
+    }
+}
+
+fn update_overlay(
+    diagnostics: Res<Diagnostics>,
+    config: Res<SimulationConfig>,
+    state: Res<SimState>,
+    clock: Res<SimClock>,
+    trail_stats: Res<TrailStats>,
+    presets: Option<Res<Presets>>,
+    #[cfg(feature = "profiling")] pass_timings: Option<Res<crate::PassTimings>>,
+    mut texts: Query<(&mut Text, &Visibility), With<StatsText>>,
+) {
+    let fps = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.smoothed())
+        .unwrap_or_default();
+    let frame_time = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FRAME_TIME)
+        .and_then(|frame_time| frame_time.smoothed())
+        .unwrap_or_default();
+    // the simulation advances once per frame while running, unless it runs fixed steps
+    let step_rate = match clock.steps_per_second {
+        _ if !state.running => 0.,
+        Some(rate) if !clock.lockstep => rate,
+        _ => fps,
+    };
+
+    let preset = presets
+        .as_ref()
+        .and_then(|presets| presets.current())
+        .map_or(String::new(), |preset| format!("\npreset {}", preset.name));
+    #[cfg(feature = "profiling")]
+    let passes = pass_timings.map_or(String::new(), |timings| {
+        format!(
+            "\nupdate {:.2} ms diffuse {:.2} ms",
+            timings.update_ms, timings.diffuse_ms
+        )
+    });
+    #[cfg(not(feature = "profiling"))]
+    let passes = "";
+
+    for (mut text, visibility) in &mut texts {
+        if !visibility.is_visible {
+            continue;
+        }
+        text.sections[0].value = format!(
+            "{fps:.0} fps\n{:.2} ms/frame\n{step_rate:.0} steps/s\n{} agents\n\
+             trail min {:.3} max {:.3} mean {:.4}{passes}{preset}",
+            frame_time * 1000.,
+            config.agent_count,
+            trail_stats.min,
+            trail_stats.max,
+            trail_stats.mean
+        );
+    }
+}
+
+fn show_pipeline_error(
+    status: Res<SlimePipelineStatus>,
+    unsupported: Option<Res<SimUnsupported>>,
+    mut texts: Query<&mut Text, With<PipelineErrorText>>,
+) {
+    if !status.is_changed() {
+        return;
+    }
+    let message = match (&*status, unsupported) {
+        (_, Some(unsupported)) => format!("This GPU can't run the simulation:\n{}", unsupported.0),
+        (SlimePipelineStatus::Failed(error), None) => {
+            format!("Shader failed to compile:\n{error}")
+        }
+        (SlimePipelineStatus::Loading | SlimePipelineStatus::Ready, None) => String::new(),
+    };
+    for mut text in &mut texts {
+        text.sections[0].value = message.clone();
+    }
+}
@@ -0,0 +1,279 @@
+//! Gradients mapping the total trail intensity to a color, as an alternative to the per-species
+//! colors, and coloring the trail by the heading of the agents that left it.
+//!
+//! The selected gradient is baked into a [`PALETTE_SIZE`] texel lookup texture that the
+//! `colorize` shader indexes by intensity.
+//!
+//! [`ColorMode::Direction`] needs the heading on the trail map, which has no channel to spare
+//! for it, so it takes the last two: agents deposit `(1 + cos) / 2` and `(1 + sin) / 2` of what
+//! they leave on the first two channels there, the average heading times the intensity shifted
+//! to stay positive. It diffuses and decays along with the trail, and is decoded by
+//! [`heading_hue`], so only [`DIRECTION_SPECIES`] species are simulated in that mode.
+
+use std::num::NonZeroU32;
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, Texture, TextureAspect,
+            TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureView,
+            TextureViewDescriptor,
+        },
+        renderer::{RenderDevice, RenderQueue},
+    },
+};
+use serde::{Deserialize, Serialize};
+
+/// Number of entries in the lookup texture.
+pub(crate) const PALETTE_SIZE: usize = 256;
+
+/// Color stops of each gradient, evenly spaced from zero to full intensity.
+const GRAYSCALE: &[[u8; 3]] = &[[0, 0, 0], [255, 255, 255]];
+const VIRIDIS: &[[u8; 3]] = &[
+    [68, 1, 84],
+    [71, 45, 123],
+    [59, 82, 139],
+    [44, 114, 142],
+    [33, 145, 140],
+    [40, 174, 128],
+    [94, 201, 98],
+    [173, 220, 48],
+    [253, 231, 37],
+];
+const INFERNO: &[[u8; 3]] = &[
+    [0, 0, 4],
+    [27, 12, 65],
+    [74, 12, 107],
+    [120, 28, 109],
+    [165, 44, 96],
+    [207, 68, 70],
+    [237, 105, 37],
+    [251, 155, 6],
+    [252, 255, 164],
+];
+const BLUE_GREEN: &[[u8; 3]] = &[
+    [0, 0, 0],
+    [8, 48, 107],
+    [8, 104, 172],
+    [43, 140, 190],
+    [78, 179, 211],
+    [123, 204, 196],
+    [204, 235, 197],
+];
+
+/// What the color of the trail map shows, passed to the shader as a `u32`.
+///
+/// The shader's `COLOR_*` constants are filled in from it by `ShaderConstants` in lib.rs.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ColorMode {
+    /// Every species glows in its own color.
+    #[default]
+    Species = 0,
+    /// The total intensity through the gradient of the [`Palette`].
+    Intensity = 1,
+    /// The hue is the average heading of the agents that left the trail, brightening with the
+    /// intensity.
+    Direction = 2,
+}
+
+impl ColorMode {
+    pub(crate) const ALL: [ColorMode; 3] = [
+        ColorMode::Species,
+        ColorMode::Intensity,
+        ColorMode::Direction,
+    ];
+}
+
+/// Species simulated with [`ColorMode::Direction`], whose heading takes the other channels.
+pub(crate) const DIRECTION_SPECIES: u32 = 2;
+
+/// The hue from 0 to 1 of the average heading of the agents that left a trail texel, from the
+/// sum of the sines and cosines of their headings weighted by what they deposited. Red is an angle
+/// of 0, green a third of a turn and blue two thirds.
+///
+/// Must match `heading_hue` in simple.wgsl.
+pub(crate) fn heading_hue(sum_sin: f32, sum_cos: f32) -> f32 {
+    (sum_sin.atan2(sum_cos) / std::f32::consts::TAU).rem_euclid(1.)
+}
+
+/// The fully saturated color of a hue from 0 to 1. Must match `hue_color` in simple.wgsl.
+pub(crate) fn hue_color(hue: f32) -> Vec3 {
+    let offsets = Vec3::new(0., 2. / 3., 1. / 3.);
+    (((Vec3::splat(hue) + offsets).fract() * 6. - 3.).abs() - 1.).clamp(Vec3::ZERO, Vec3::ONE)
+}
+
+/// Gradient the trail map is colored with by [`ColorMode::Intensity`], baked into the lookup
+/// texture.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Palette {
+    /// Grayscale, kept for files from before
+    /// [`SimulationSettings::color_mode`](crate::SimulationSettings::color_mode), which picked
+    /// the species colors with this.
+    #[default]
+    Species = 0,
+    Grayscale = 1,
+    Viridis = 2,
+    Inferno = 3,
+    BlueGreen = 4,
+}
+
+impl Palette {
+    pub(crate) const ALL: [Palette; 5] = [
+        Palette::Species,
+        Palette::Grayscale,
+        Palette::Viridis,
+        Palette::Inferno,
+        Palette::BlueGreen,
+    ];
+
+    fn stops(self) -> &'static [[u8; 3]] {
+        match self {
+            // the species colors don't use the lookup texture, with `ColorMode::Intensity` it
+            // comes out gray
+            Palette::Species | Palette::Grayscale => GRAYSCALE,
+            Palette::Viridis => VIRIDIS,
+            Palette::Inferno => INFERNO,
+            Palette::BlueGreen => BLUE_GREEN,
+        }
+    }
+
+    /// Linearly interpolates the color stops into [`PALETTE_SIZE`] RGBA8 entries, starting from
+    /// `background` instead of the first stop so faint trails fade into it.
+    pub(crate) fn lut(self, background: [f32; 4]) -> [[u8; 4]; PALETTE_SIZE] {
+        let stops = self.stops();
+        let stop = |index: usize, channel: usize| match index {
+            0 => background[channel].clamp(0., 1.) * 255.,
+            _ => stops[index][channel] as f32,
+        };
+        let mut lut = [[0; 4]; PALETTE_SIZE];
+        for (i, entry) in lut.iter_mut().enumerate() {
+            let position = i as f32 / (PALETTE_SIZE - 1) as f32 * (stops.len() - 1) as f32;
+            let from = (position as usize).min(stops.len() - 2);
+            let t = position - from as f32;
+            for channel in 0..3 {
+                let a = stop(from, channel);
+                let b = stop(from + 1, channel);
+                entry[channel] = (a + (b - a) * t).round() as u8;
+            }
+            entry[3] = u8::MAX;
+        }
+        lut
+    }
+}
+
+/// Lookup texture for the selected [`Palette`], rewritten by the `Slime` render asset whenever
+/// the settings change.
+#[derive(Resource)]
+pub(crate) struct PaletteTexture {
+    pub(crate) texture: Texture,
+    pub(crate) view: TextureView,
+}
+
+impl PaletteTexture {
+    pub(crate) fn new(render_device: &RenderDevice) -> Self {
+        let texture = render_device.create_texture(&TextureDescriptor {
+            label: Some("palette"),
+            size: palette_extent(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8Unorm,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+        });
+        let view = texture.create_view(&TextureViewDescriptor::default());
+        Self { texture, view }
+    }
+
+    /// Uploads a lookup table made by [`Palette::lut`].
+    pub(crate) fn write(&self, render_queue: &RenderQueue, lut: &[[u8; 4]; PALETTE_SIZE]) {
+        render_queue.write_texture(
+            ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            bytemuck::cast_slice(lut),
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(PALETTE_SIZE as u32 * 4),
+                rows_per_image: None,
+            },
+            palette_extent(),
+        );
+    }
+}
+
+impl FromWorld for PaletteTexture {
+    fn from_world(world: &mut World) -> Self {
+        Self::new(world.resource::<RenderDevice>())
+    }
+}
+
+pub(crate) fn palette_extent() -> Extent3d {
+    Extent3d {
+        width: PALETTE_SIZE as u32,
+        height: 1,
+        depth_or_array_layers: 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_near(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{actual} isn't {expected}"
+        );
+    }
+
+    #[test]
+    fn heading_hue_goes_around_once_per_turn() {
+        // heading along x, a quarter turn, half a turn and three quarters
+        assert_near(heading_hue(0., 1.), 0.);
+        assert_near(heading_hue(1., 0.), 0.25);
+        assert_near(heading_hue(0., -1.), 0.5);
+        assert_near(heading_hue(-1., 0.), 0.75);
+        // only the direction of the sums counts, not how much was deposited
+        assert_near(heading_hue(3., 3.), 0.125);
+        assert_near(heading_hue(-0.01, 0.01), 0.875);
+        // never negative, also for the other side of the wrap around
+        assert_near(heading_hue(-0., -1.), 0.5);
+    }
+
+    #[test]
+    fn hue_colors_start_red_then_green_then_blue() {
+        let assert_color = |hue, expected: Vec3| {
+            let color = hue_color(hue);
+            assert!(
+                color.abs_diff_eq(expected, 1e-5),
+                "{color} isn't {expected}"
+            );
+        };
+        assert_color(0., Vec3::X);
+        assert_color(1. / 3., Vec3::Y);
+        assert_color(2. / 3., Vec3::Z);
+        assert_color(1. / 6., Vec3::new(1., 1., 0.));
+    }
+
+    #[test]
+    fn viridis_only_gets_brighter() {
+        let luma =
+            |[r, g, b, _]: [u8; 4]| 0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32;
+        let lut = Palette::Viridis.lut([0., 0., 0., 1.]);
+        for (i, pair) in lut.windows(2).enumerate() {
+            // each channel is rounded on its own, which can cost a step up to one of luma
+            assert!(
+                luma(pair[1]) > luma(pair[0]) - 1.,
+                "entry {} is darker than entry {i}",
+                i + 1
+            );
+        }
+        for pair in lut.iter().step_by(16).collect::<Vec<_>>().windows(2) {
+            assert!(luma(*pair[1]) > luma(*pair[0]));
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Everything the windowed app is made of, bundled as [`SlimeSimulationPlugins`].
+
+use bevy::{app::PluginGroupBuilder, prelude::*};
+
+use crate::{
+    burst, camera, density, display, frame_pacing, inspect, overlay, presets, preview, screenshot,
+    settings_export, snapshot, SlimeComputePlugin,
+};
+
+/// The simulation shown in a window, with the keyboard and mouse controls, on top of Bevy's
+/// `DefaultPlugins`:
+///
+/// - [`SlimeComputePlugin`], the simulation itself, which also runs on its own without a window.
+/// - [`TrailDisplayPlugin`](crate::TrailDisplayPlugin), the quad showing the trail map,
+///   letterboxed to the window and stepping by the real frame time.
+/// - [`OverlayPlugin`](crate::OverlayPlugin), the stats toggled with F3 and shader errors.
+/// - [`PreviewPlugin`](crate::PreviewPlugin), the downsampled preview in a corner.
+/// - [`CameraControllerPlugin`](crate::CameraControllerPlugin), zooming and panning.
+/// - [`FramePacingPlugin`](crate::FramePacingPlugin), vsync and the frame rate cap.
+/// - [`SlimeControlsPlugin`], painting, pausing, resetting, the presets and the other key
+///   bindings.
+/// - `SlimeUiPlugin`, the egui settings panel, only with the `ui` feature.
+/// - `ProfilingPlugin`, the GPU time of the update and diffuse passes in the overlay, only with
+///   the `profiling` feature.
+///
+/// Any of them can be configured with `set` or left out with `disable`:
+///
+/// ```ignore
+/// app.add_plugins(DefaultPlugins).add_plugins(
+///     SlimeSimulationPlugins
+///         .build()
+///         .set(SlimeComputePlugin::new().with_agent_count(5000))
+///         .disable::<PreviewPlugin>(),
+/// );
+/// ```
+pub struct SlimeSimulationPlugins;
+
+impl PluginGroup for SlimeSimulationPlugins {
+    // without the ui and profiling features the group is returned as soon as it is built
+    #[allow(clippy::let_and_return)]
+    fn build(self) -> PluginGroupBuilder {
+        let group = PluginGroupBuilder::start::<Self>()
+            .add(SlimeComputePlugin::new())
+            .add(display::TrailDisplayPlugin)
+            .add(overlay::OverlayPlugin)
+            .add(preview::PreviewPlugin)
+            .add(camera::CameraControllerPlugin)
+            .add(frame_pacing::FramePacingPlugin::default())
+            .add(SlimeControlsPlugin);
+        #[cfg(feature = "ui")]
+        let group = group.add(crate::ui::SlimeUiPlugin);
+        #[cfg(feature = "profiling")]
+        let group = group.add(crate::profiling::ProfilingPlugin);
+        group
+    }
+}
+
+/// The keyboard and mouse controls of the simulation, along with Esc closing the window.
+pub struct SlimeControlsPlugin;
+
+impl Plugin for SlimeControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<presets::Presets>()
+            .add_system(crate::paint_trail)
+            .add_system(burst::burst_controls)
+            .add_system(crate::sim_controls)
+            // after `sim_controls`, which clears the reset this requests
+            .add_system(crate::follow_window_size.after(crate::sim_controls))
+            .add_system(presets::preset_controls.after(crate::sim_controls))
+            .add_system(crate::agent_count_controls)
+            .add_system(snapshot::snapshot_controls)
+            .add_system(screenshot::screenshot_controls)
+            .add_system(settings_export::settings_export_controls)
+            .add_system(inspect::dump_controls)
+            .add_system(inspect::sensor_debug_controls)
+            .add_system(density::density_controls)
+            .add_system(bevy::window::close_on_esc);
+    }
+}
@@ -0,0 +1,147 @@
+//! Named settings compiled into the app, cycled through with Tab and Shift-Tab.
+//!
+//! Each preset is a `.slime` file in `src/presets`, embedded with `include_str!` and only parsed
+//! once it is picked. Picking one replaces the settings and resets the simulation. What is only
+//! read at startup, like the agent count and the sim size, is kept from the running settings,
+//! so a preset only has to list what makes its look.
+
+use bevy::prelude::*;
+
+use crate::{SimState, SimulationConfig, SimulationSettings, Slime, SlimeHandle};
+
+/// A `.slime` file compiled into the app.
+#[derive(Debug)]
+pub(crate) struct Preset {
+    pub(crate) name: &'static str,
+    source: &'static str,
+}
+
+impl Preset {
+    /// Parses the preset, it falls back to the defaults for whatever it doesn't list.
+    pub(crate) fn settings(&self) -> Result<SimulationSettings, String> {
+        ron::from_str(self.source).map_err(|error| error.to_string())
+    }
+}
+
+/// The presets Tab cycles through, in order.
+pub(crate) const PRESETS: &[Preset] = &[
+    Preset {
+        name: "Webs",
+        source: include_str!("presets/webs.slime"),
+    },
+    Preset {
+        name: "Cells",
+        source: include_str!("presets/cells.slime"),
+    },
+    Preset {
+        name: "Vortex",
+        source: include_str!("presets/vortex.slime"),
+    },
+];
+
+/// The presets and the one picked last, none until Tab is first pressed.
+#[derive(Debug, Resource)]
+pub(crate) struct Presets {
+    presets: &'static [Preset],
+    current: Option<usize>,
+}
+
+impl Default for Presets {
+    fn default() -> Self {
+        Self {
+            presets: PRESETS,
+            current: None,
+        }
+    }
+}
+
+impl Presets {
+    pub(crate) fn current(&self) -> Option<&'static Preset> {
+        self.current.map(|index| &self.presets[index])
+    }
+}
+
+/// `preset` with what is only read at startup taken from the `running` settings.
+fn keep_startup_settings(
+    mut preset: SimulationSettings,
+    running: &SimulationSettings,
+) -> SimulationSettings {
+    preset.agent_count = running.agent_count;
+    preset.burst_headroom = running.burst_headroom;
+    preset.species_count = running.species_count;
+    preset.sim_width = running.sim_width;
+    preset.sim_height = running.sim_height;
+    preset.resize_follows_window = running.resize_follows_window;
+    preset.workgroup_size = running.workgroup_size;
+    preset.agent_workgroup_size = running.agent_workgroup_size;
+    preset.trail_precision = running.trail_precision;
+    preset.grid_cell_size = running.grid_cell_size;
+    preset.obstacle_mask = running.obstacle_mask.clone();
+    preset.food_map = running.food_map.clone();
+    preset.agents_file = running.agents_file.clone();
+    preset.preview = running.preview;
+    preset.preview_corner = running.preview_corner;
+    preset
+}
+
+/// Switches to the next preset with Tab and to the previous one with Shift-Tab.
+pub(crate) fn preset_controls(
+    keys: Res<Input<KeyCode>>,
+    mut presets: ResMut<Presets>,
+    mut slimes: ResMut<Assets<Slime>>,
+    slime: Res<SlimeHandle>,
+    mut config: ResMut<SimulationConfig>,
+    mut state: ResMut<SimState>,
+) {
+    let list = presets.presets;
+    if !keys.just_pressed(KeyCode::Tab) || list.is_empty() {
+        return;
+    }
+    let count = list.len();
+    let backwards = keys.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    let index = match (presets.current, backwards) {
+        (None, false) => 0,
+        (None, true) => count - 1,
+        (Some(index), false) => (index + 1) % count,
+        (Some(index), true) => (index + count - 1) % count,
+    };
+    let preset = &list[index];
+    let Some(running) = slimes.get_mut(&slime.0) else {
+        return;
+    };
+    let settings = match preset.settings() {
+        Ok(settings) => keep_startup_settings(settings, &running.0).validated(),
+        Err(error) => {
+            error!("Can't parse the {} preset: {error}", preset.name);
+            return;
+        }
+    };
+
+    // the agents are respawned the way the preset places them
+    config.seed = settings.seed;
+    config.spawn_pattern = settings.spawn_pattern;
+    config.initial_heading = settings.initial_heading;
+    config.seamless = settings.seamless;
+    config.speed_jitter = settings.speed_jitter.max(0.);
+    running.0 = settings;
+    state.reset = true;
+    presets.current = Some(index);
+    info!("Switched to the {} preset", preset.name);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_parse_and_validate() {
+        for preset in PRESETS {
+            let settings = preset.settings().unwrap_or_else(|error| {
+                panic!("the {} preset doesn't parse: {error}", preset.name)
+            });
+            if let Err(errors) = settings.validate() {
+                panic!("the {} preset has errors: {errors:?}", preset.name);
+            }
+        }
+    }
+}
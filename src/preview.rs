@@ -0,0 +1,314 @@
+//! A small picture-in-picture preview of the whole trail map, toggled with P.
+//!
+//! The `downsample` pass of preview.wgsl box-filters the colorized [`TrailDisplay`] into a
+//! [`PREVIEW_SIZE`] square texture, averaging however many display texels each preview texel
+//! covers, which is shown on a sprite in the corner set by
+//! [`SimulationSettings::preview_corner`](crate::SimulationSettings::preview_corner).
+//!
+//! It has its own pipeline layout, since the simulation's one already binds as many storage
+//! textures as a shader stage is guaranteed to support.
+
+use std::borrow::Cow;
+
+use bevy::{
+    prelude::*,
+    reflect::TypeUuid,
+    render::{
+        extract_resource::{ExtractResource, ExtractResourcePlugin},
+        render_asset::RenderAssets,
+        render_resource::{
+            BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+            BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType,
+            CachedComputePipelineId, ComputePass, ComputePipelineDescriptor, Extent3d,
+            PipelineCache, ShaderStages, StorageTextureAccess, TextureDimension, TextureFormat,
+            TextureSampleType, TextureUsages, TextureViewDimension,
+        },
+        renderer::RenderDevice,
+        RenderApp, RenderStage,
+    },
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    load_slime_shader, nodes, status::SimUnsupported, ShaderConstants, SimulationConfig, Slime,
+    SlimeHandle, SlimeStartup, TrailDisplay,
+};
+
+/// Edge length in texels of the preview, whatever the size of the trail map.
+const PREVIEW_SIZE: u32 = 256;
+/// Gap in pixels between the preview and the edges of the window.
+const PREVIEW_MARGIN: f32 = 16.;
+const PREVIEW_SHADER: &str = "shaders/preview.wgsl";
+
+/// preview.wgsl with the [`ShaderConstants`] substituted.
+const PREVIEW_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x6c1d_83e5_a097_4b2f);
+
+/// Corner of the window the preview is shown in.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+/// The downsampled preview of the whole trail map, toggled with P.
+pub struct PreviewPlugin;
+
+impl Plugin for PreviewPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PreviewEnabled>()
+            .add_plugin(ExtractResourcePlugin::<PreviewEnabled>::default())
+            .add_plugin(ExtractResourcePlugin::<PreviewImage>::default())
+            // after `setup` has inserted the config and the settings
+            .add_startup_system_to_stage(StartupStage::PostStartup, setup_preview)
+            .add_system(preview_controls)
+            .add_system(place_preview);
+        // the downsample pass is a compute shader too
+        if app.world.contains_resource::<SimUnsupported>() {
+            return;
+        }
+        app.sub_app_mut(RenderApp)
+            .init_resource::<PreviewPipeline>()
+            .add_system_to_stage(RenderStage::Queue, queue_preview_bind_group);
+    }
+}
+
+/// Whether the preview is shown and the downsample pass runs, toggled with P.
+#[derive(Debug, Clone, Default, Resource, ExtractResource)]
+pub(crate) struct PreviewEnabled(pub(crate) bool);
+
+/// The downsampled trail map shown by the preview sprite.
+#[derive(Debug, Clone, Deref, Resource, ExtractResource)]
+pub(crate) struct PreviewImage(Handle<Image>);
+
+#[derive(Component)]
+struct PreviewSprite;
+
+fn setup_preview(
+    mut commands: Commands,
+    startup: Res<SlimeStartup>,
+    config: Res<SimulationConfig>,
+    asset_server: Res<AssetServer>,
+    mut images: ResMut<Assets<Image>>,
+    mut shaders: ResMut<Assets<Shader>>,
+    mut enabled: ResMut<PreviewEnabled>,
+) {
+    let constants = ShaderConstants::new(&config);
+    if let Some(shader) = load_slime_shader(&asset_server, PREVIEW_SHADER, &constants) {
+        shaders.set_untracked(PREVIEW_SHADER_HANDLE, shader);
+    }
+
+    let size = Extent3d {
+        width: PREVIEW_SIZE,
+        height: PREVIEW_SIZE,
+        depth_or_array_layers: 1,
+    };
+    let mut preview = Image::new_fill(
+        size,
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8Unorm,
+    );
+    preview.texture_descriptor.usage =
+        TextureUsages::STORAGE_BINDING | TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST;
+    let preview = images.add(preview);
+
+    enabled.0 = startup.settings.preview;
+    commands.spawn((
+        SpriteBundle {
+            texture: preview.clone(),
+            sprite: Sprite {
+                custom_size: Some(Vec2::splat(PREVIEW_SIZE as f32)),
+                ..default()
+            },
+            visibility: Visibility {
+                is_visible: enabled.0,
+            },
+            ..default()
+        },
+        PreviewSprite,
+    ));
+    commands.insert_resource(PreviewImage(preview));
+}
+
+fn preview_controls(
+    keys: Res<Input<KeyCode>>,
+    mut enabled: ResMut<PreviewEnabled>,
+    mut sprites: Query<&mut Visibility, With<PreviewSprite>>,
+) {
+    if !keys.just_pressed(KeyCode::P) {
+        return;
+    }
+    enabled.0 = !enabled.0;
+    for mut visibility in &mut sprites {
+        visibility.is_visible = enabled.0;
+    }
+}
+
+/// Keeps the preview in its corner as the window, the settings and the camera change.
+fn place_preview(
+    windows: Res<Windows>,
+    slimes: Res<Assets<Slime>>,
+    slime: Res<SlimeHandle>,
+    cameras: Query<
+        (&Transform, &OrthographicProjection),
+        (
+            With<Camera2d>,
+            Without<PreviewSprite>,
+            Without<crate::instances::SimulationCamera>,
+        ),
+    >,
+    mut sprites: Query<&mut Transform, With<PreviewSprite>>,
+) {
+    let (Some(window), Some(settings)) = (windows.get_primary(), slimes.get(&slime.0)) else {
+        return;
+    };
+    let (camera, scale) = cameras
+        .get_single()
+        .map_or((Vec3::ZERO, 1.), |(transform, projection)| {
+            (transform.translation, projection.scale)
+        });
+    // the camera is centered on the window, and the preview on its translation
+    let offset =
+        Vec2::new(window.width(), window.height()) / 2. - PREVIEW_SIZE as f32 / 2. - PREVIEW_MARGIN;
+    let direction = match settings.preview_corner {
+        Corner::TopLeft => Vec2::new(-1., 1.),
+        Corner::TopRight => Vec2::new(1., 1.),
+        Corner::BottomLeft => Vec2::new(-1., -1.),
+        Corner::BottomRight => Vec2::new(1., -1.),
+    };
+    // above the trail quad, and the same size on screen however far the camera is zoomed
+    let translation = (camera.truncate() + offset * direction * scale).extend(1.);
+    let scale = Vec3::splat(scale);
+    for mut transform in &mut sprites {
+        // only touch the transform when it moves, so it isn't marked as changed every frame
+        if transform.translation != translation || transform.scale != scale {
+            transform.translation = translation;
+            transform.scale = scale;
+        }
+    }
+}
+
+#[derive(Resource)]
+pub(crate) struct PreviewPipeline {
+    bind_group_layout: BindGroupLayout,
+    pipeline: CachedComputePipelineId,
+}
+
+impl FromWorld for PreviewPipeline {
+    fn from_world(world: &mut World) -> Self {
+        let bind_group_layout =
+            world
+                .resource::<RenderDevice>()
+                .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                    label: Some("preview"),
+                    entries: &[
+                        BindGroupLayoutEntry {
+                            binding: 0,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::Texture {
+                                sample_type: TextureSampleType::Float { filterable: false },
+                                view_dimension: TextureViewDimension::D2,
+                                multisampled: false,
+                            },
+                            count: None,
+                        },
+                        BindGroupLayoutEntry {
+                            binding: 1,
+                            visibility: ShaderStages::COMPUTE,
+                            ty: BindingType::StorageTexture {
+                                access: StorageTextureAccess::WriteOnly,
+                                format: TextureFormat::Rgba8Unorm,
+                                view_dimension: TextureViewDimension::D2,
+                            },
+                            count: None,
+                        },
+                    ],
+                });
+        let pipeline = world
+            .resource_mut::<PipelineCache>()
+            .queue_compute_pipeline(ComputePipelineDescriptor {
+                label: Some(Cow::from("preview")),
+                layout: Some(vec![bind_group_layout.clone()]),
+                shader: PREVIEW_SHADER_HANDLE.typed::<Shader>(),
+                shader_defs: vec![],
+                entry_point: Cow::from("downsample"),
+            });
+        Self {
+            bind_group_layout,
+            pipeline,
+        }
+    }
+}
+
+#[derive(Resource)]
+pub(crate) struct PreviewBindGroup(BindGroup);
+
+fn queue_preview_bind_group(
+    mut commands: Commands,
+    pipeline: Res<PreviewPipeline>,
+    render_device: Res<RenderDevice>,
+    gpu_images: Res<RenderAssets<Image>>,
+    display: Option<Res<TrailDisplay>>,
+    preview: Option<Res<PreviewImage>>,
+) {
+    let (Some(display), Some(preview)) = (display, preview) else {
+        return;
+    };
+    let (Some(display), Some(preview)) = (gpu_images.get(&display.0), gpu_images.get(&preview.0))
+    else {
+        return;
+    };
+    commands.insert_resource(PreviewBindGroup(render_device.create_bind_group(
+        &BindGroupDescriptor {
+            label: Some("preview"),
+            layout: &pipeline.bind_group_layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&display.texture_view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::TextureView(&preview.texture_view),
+                },
+            ],
+        },
+    )));
+}
+
+/// Downsamples the display into the preview, called by [`nodes::DisplayNode`] after the
+/// colorize pass.
+///
+/// Nothing is dispatched while the preview is hidden or its pipeline is still compiling. This
+/// replaces the bind group, so it has to come after every other pass.
+pub(crate) fn dispatch_preview<'w>(
+    pass: &mut ComputePass<'w>,
+    world: &'w World,
+    workgroup_size: u32,
+) {
+    if !world
+        .get_resource::<PreviewEnabled>()
+        .map_or(false, |enabled| enabled.0)
+    {
+        return;
+    }
+    let (Some(pipeline), Some(bind_group)) = (
+        world.get_resource::<PreviewPipeline>(),
+        world.get_resource::<PreviewBindGroup>(),
+    ) else {
+        return;
+    };
+    let Some(compute_pipeline) = world
+        .resource::<PipelineCache>()
+        .get_compute_pipeline(pipeline.pipeline)
+    else {
+        return;
+    };
+    pass.set_pipeline(compute_pipeline);
+    pass.set_bind_group(0, &bind_group.0, &[]);
+    nodes::dispatch_2d(pass, PREVIEW_SIZE, PREVIEW_SIZE, workgroup_size);
+}
@@ -0,0 +1,235 @@
+//! How long the update and diffuse passes take on the GPU, shown in the overlay, enabled by the
+//! `profiling` feature.
+//!
+//! Timestamps are written around the passes of [`UpdateNode`](crate::nodes::UpdateNode) and
+//! [`DiffuseNode`](crate::nodes::DiffuseNode) every frame and resolved by
+//! [`DisplayNode`](crate::nodes::DisplayNode) into one of up to [`MAX_IN_FLIGHT`] readbacks,
+//! which are reused once read, so measuring every frame never waits on the GPU. GPUs without
+//! timestamp queries run without it and leave [`PassTimings`] out.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{Buffer, BufferDescriptor, BufferUsages, CommandEncoder},
+        renderer::{RenderDevice, RenderQueue},
+        RenderApp, RenderStage,
+    },
+};
+
+use crate::readback::{ReadbackStatus, StagingBuffers};
+
+/// Most readbacks waiting on the GPU at once, frames beyond those aren't measured.
+const MAX_IN_FLIGHT: usize = 3;
+/// A start and an end timestamp for every [`ProfiledPass`].
+const QUERY_COUNT: u32 = 4;
+/// Size of the resolved `u64` timestamps.
+const TIMESTAMPS_SIZE: u64 = QUERY_COUNT as u64 * std::mem::size_of::<u64>() as u64;
+
+/// Measures the update and diffuse passes and shows them in the overlay.
+pub struct ProfilingPlugin;
+
+impl Plugin for ProfilingPlugin {
+    fn build(&self, app: &mut App) {
+        let render_app = app.sub_app_mut(RenderApp);
+        let features = render_app.world.resource::<RenderDevice>().features();
+        if !features.contains(wgpu::Features::TIMESTAMP_QUERY) {
+            warn!("This GPU doesn't support timestamp queries, pass times won't be measured");
+            return;
+        }
+        let channel = PassTimingsChannel::default();
+        render_app
+            .insert_resource(channel.clone())
+            .init_resource::<PassTimer>()
+            .add_system_to_stage(RenderStage::Prepare, prepare_pass_timer)
+            .add_system_to_stage(RenderStage::Cleanup, map_pass_timer);
+        app.insert_resource(channel)
+            .init_resource::<PassTimings>()
+            .add_system(receive_pass_timings);
+    }
+}
+
+/// GPU time of the passes of the last measured frame, in milliseconds.
+///
+/// Only there with the `profiling` feature on a GPU supporting timestamp queries.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Resource)]
+pub struct PassTimings {
+    /// The agent update pass of every step run this frame, with the clears, injections and
+    /// spawns running along with it.
+    pub update_ms: f32,
+    /// Diffusing and decaying the trail map, and sharpening it if that is on.
+    pub diffuse_ms: f32,
+}
+
+/// The latest timings read back by the render world, inserted into both worlds.
+#[derive(Debug, Clone, Default, Resource)]
+struct PassTimingsChannel(Arc<Mutex<Option<PassTimings>>>);
+
+fn receive_pass_timings(channel: Res<PassTimingsChannel>, mut timings: ResMut<PassTimings>) {
+    if let Some(latest) = channel.0.lock().unwrap().take() {
+        *timings = latest;
+    }
+}
+
+/// A pass timed by [`PassTimer`].
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum ProfiledPass {
+    Update,
+    Diffuse,
+}
+
+impl ProfiledPass {
+    /// The query of the start timestamp, the end one follows it.
+    fn query(self) -> u32 {
+        self as u32 * 2
+    }
+}
+
+/// The timestamp queries and the readbacks they are copied into.
+#[derive(Resource)]
+pub(crate) struct PassTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: Buffer,
+    /// Nanoseconds per timestamp tick.
+    period: f32,
+    in_flight: VecDeque<StagingBuffers>,
+    /// Readbacks already read, waiting to be reused.
+    free: Vec<StagingBuffers>,
+    /// The timings as of the last readback.
+    last: PassTimings,
+}
+
+impl FromWorld for PassTimer {
+    fn from_world(world: &mut World) -> Self {
+        let render_device = world.resource::<RenderDevice>();
+        let query_set = render_device
+            .wgpu_device()
+            .create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("pass_timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: QUERY_COUNT,
+            });
+        let resolve_buffer = render_device.create_buffer(&BufferDescriptor {
+            label: Some("pass_timestamps_resolve"),
+            size: TIMESTAMPS_SIZE,
+            usage: BufferUsages::QUERY_RESOLVE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        Self {
+            query_set,
+            resolve_buffer,
+            period: world.resource::<RenderQueue>().get_timestamp_period(),
+            in_flight: VecDeque::new(),
+            free: Vec::new(),
+            last: PassTimings::default(),
+        }
+    }
+}
+
+impl PassTimer {
+    /// The readback started this frame, if any.
+    fn pending_copy(&self) -> Option<&StagingBuffers> {
+        self.in_flight
+            .back()
+            .filter(|staging| staging.copy_pending())
+    }
+
+    /// Whether the nodes should time their passes this frame.
+    pub(crate) fn timing(&self) -> bool {
+        self.pending_copy().is_some()
+    }
+
+    /// Records the timestamp before `pass`.
+    pub(crate) fn begin(&self, encoder: &mut CommandEncoder, pass: ProfiledPass) {
+        encoder.write_timestamp(&self.query_set, pass.query());
+    }
+
+    /// Records the timestamp after `pass`.
+    pub(crate) fn end(&self, encoder: &mut CommandEncoder, pass: ProfiledPass) {
+        encoder.write_timestamp(&self.query_set, pass.query() + 1);
+    }
+
+    /// Copies the timestamps of the frame into the readback started this frame.
+    pub(crate) fn resolve(&self, encoder: &mut CommandEncoder) {
+        let Some(staging) = self.pending_copy() else {
+            return;
+        };
+        encoder.resolve_query_set(&self.query_set, 0..QUERY_COUNT, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            staging.buffer(0),
+            0,
+            TIMESTAMPS_SIZE,
+        );
+    }
+}
+
+/// Starts a readback for this frame, reusing one that has been read if there is one.
+fn prepare_pass_timer(mut timer: ResMut<PassTimer>, render_device: Res<RenderDevice>) {
+    if timer.in_flight.len() >= MAX_IN_FLIGHT {
+        return;
+    }
+    let staging = match timer.free.pop() {
+        Some(mut staging) => {
+            staging.reuse();
+            staging
+        }
+        None => StagingBuffers::new(
+            &render_device,
+            &[("pass_timestamps_staging", TIMESTAMPS_SIZE)],
+        ),
+    };
+    timer.in_flight.push_back(staging);
+}
+
+/// Sends the timings of every readback that has become readable.
+fn map_pass_timer(mut timer: ResMut<PassTimer>, channel: Res<PassTimingsChannel>) {
+    let PassTimer {
+        period,
+        in_flight,
+        free,
+        last,
+        ..
+    } = &mut *timer;
+    let mut read = false;
+    for mut staging in std::mem::take(in_flight) {
+        match staging.poll() {
+            ReadbackStatus::Pending => in_flight.push_back(staging),
+            ReadbackStatus::Failed => {
+                warn!("Failed to read the pass timestamps back from the GPU");
+            }
+            ReadbackStatus::Ready => {
+                let timestamps: Vec<u64> = staging
+                    .read(0)
+                    .chunks_exact(std::mem::size_of::<u64>())
+                    .map(bytemuck::pod_read_unaligned)
+                    .collect();
+                // a pass that didn't run this frame leaves its timestamps as they were, it
+                // keeps the time it was last measured at
+                let milliseconds = |pass: ProfiledPass, previous: f32| {
+                    let start = timestamps[pass.query() as usize];
+                    let end = timestamps[pass.query() as usize + 1];
+                    if end > start {
+                        ((end - start) as f64 * *period as f64 / 1_000_000.) as f32
+                    } else {
+                        previous
+                    }
+                };
+                *last = PassTimings {
+                    update_ms: milliseconds(ProfiledPass::Update, last.update_ms),
+                    diffuse_ms: milliseconds(ProfiledPass::Diffuse, last.diffuse_ms),
+                };
+                free.push(staging);
+                read = true;
+            }
+        }
+    }
+    if read {
+        *channel.0.lock().unwrap() = Some(*last);
+    }
+}
@@ -0,0 +1,338 @@
+//! Copying GPU buffers and textures back to the CPU without stalling the render thread.
+//!
+//! The node copies into [`StagingBuffers`] while recording a frame; once that frame has been
+//! submitted, [`StagingBuffers::poll`] starts mapping them and reports when they're readable.
+
+use std::{
+    num::NonZeroU32,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            Buffer, BufferDescriptor, BufferUsages, CommandEncoder, CommandEncoderDescriptor,
+            Extent3d, ImageCopyBuffer, ImageCopyTexture, ImageDataLayout, Maintain, MapMode,
+            Origin3d, Texture, TextureAspect,
+        },
+        renderer::{RenderDevice, RenderQueue},
+    },
+};
+
+use crate::{Agent, AgentBuffer, SimulationConfig, TrailPrecision};
+
+/// Rows of a texture copied into a buffer have to start at multiples of this many bytes.
+const COPY_BYTES_PER_ROW_ALIGNMENT: u32 = 256;
+
+/// Size in bytes of a tightly packed row of the trail map.
+pub(crate) fn trail_bytes_per_row(width: u32, precision: TrailPrecision) -> u32 {
+    width * precision.texel_size()
+}
+
+/// Size in bytes of a row of the trail map once copied into a buffer.
+pub(crate) fn padded_bytes_per_row(bytes_per_row: u32) -> u32 {
+    (bytes_per_row + COPY_BYTES_PER_ROW_ALIGNMENT - 1) / COPY_BYTES_PER_ROW_ALIGNMENT
+        * COPY_BYTES_PER_ROW_ALIGNMENT
+}
+
+/// Size of the staging buffer needed to copy a whole trail map of `size`.
+pub(crate) fn trail_buffer_size(size: Extent3d, precision: TrailPrecision) -> u64 {
+    texture_buffer_size(size, trail_bytes_per_row(size.width, precision))
+}
+
+/// Size of the staging buffer needed to copy a texture of `size` with tightly packed rows of
+/// `bytes_per_row`.
+pub(crate) fn texture_buffer_size(size: Extent3d, bytes_per_row: u32) -> u64 {
+    padded_bytes_per_row(bytes_per_row) as u64 * size.height as u64
+}
+
+/// Records a copy of a trail map texture of `size` into a buffer of [`trail_buffer_size`] bytes.
+pub(crate) fn copy_trail_to_buffer(
+    encoder: &mut CommandEncoder,
+    trail: &Texture,
+    buffer: &Buffer,
+    size: Extent3d,
+    precision: TrailPrecision,
+) {
+    copy_texture_to_buffer(
+        encoder,
+        trail,
+        buffer,
+        size,
+        trail_bytes_per_row(size.width, precision),
+    );
+}
+
+/// Records a copy of a texture of `size` into a buffer of [`texture_buffer_size`] bytes.
+pub(crate) fn copy_texture_to_buffer(
+    encoder: &mut CommandEncoder,
+    texture: &Texture,
+    buffer: &Buffer,
+    size: Extent3d,
+    bytes_per_row: u32,
+) {
+    encoder.copy_texture_to_buffer(
+        ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: Origin3d::ZERO,
+            aspect: TextureAspect::All,
+        },
+        ImageCopyBuffer {
+            buffer,
+            layout: ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(padded_bytes_per_row(bytes_per_row)),
+                rows_per_image: None,
+            },
+        },
+        size,
+    );
+}
+
+/// Strips the row padding from a trail map copied with [`copy_trail_to_buffer`] and decodes its
+/// texels.
+pub(crate) fn trail_texels(data: &[u8], width: u32, precision: TrailPrecision) -> Vec<Vec4> {
+    let texels = unpad_rows(data, trail_bytes_per_row(width, precision));
+    match precision {
+        TrailPrecision::F16 => texels
+            .chunks_exact(std::mem::size_of::<[u16; 4]>())
+            .map(|texel| {
+                let texel: [u16; 4] = bytemuck::pod_read_unaligned(texel);
+                Vec4::from(texel.map(f16_to_f32))
+            })
+            .collect(),
+        TrailPrecision::F32 => texels
+            .chunks_exact(std::mem::size_of::<Vec4>())
+            .map(bytemuck::pod_read_unaligned)
+            .collect(),
+    }
+}
+
+/// Encodes `texels` as tightly packed trail map texels of `precision`.
+pub(crate) fn trail_bytes(texels: &[Vec4], precision: TrailPrecision) -> Vec<u8> {
+    match precision {
+        TrailPrecision::F16 => texels
+            .iter()
+            .flat_map(|texel| bytemuck::cast::<_, [u8; 8]>(texel.to_array().map(f32_to_f16)))
+            .collect(),
+        TrailPrecision::F32 => bytemuck::cast_slice(texels).to_vec(),
+    }
+}
+
+/// Converts an IEEE half to a float.
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 == 0 { 1. } else { -1. };
+    let exponent = (half >> 10 & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0. => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1. + mantissa / 1024.) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// Converts a float to the nearest IEEE half, saturating to infinity.
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = (bits >> 16) as u16 & 0x8000;
+    let magnitude = value.abs();
+    if value.is_nan() {
+        return sign | 0x7e00;
+    }
+    if magnitude >= 65520. {
+        return sign | 0x7c00;
+    }
+    if magnitude < 2f32.powi(-14) {
+        // subnormal, in steps of 2^-24
+        return sign | (magnitude * 2f32.powi(24)).round() as u16;
+    }
+    let exponent = (bits >> 23 & 0xff) as i32 - 127 + 15;
+    let mantissa = bits & 0x7f_ffff;
+    // round to nearest even, a carry into the exponent is still the right half
+    let half = (exponent as u32) << 10 | mantissa >> 13;
+    let rest = mantissa & 0x1fff;
+    let round = (rest > 0x1000 || (rest == 0x1000 && half & 1 == 1)) as u32;
+    sign | (half + round) as u16
+}
+
+/// Strips the row padding from a texture copied with [`copy_texture_to_buffer`].
+pub(crate) fn unpad_rows(data: &[u8], bytes_per_row: u32) -> Vec<u8> {
+    data.chunks_exact(padded_bytes_per_row(bytes_per_row) as usize)
+        .flat_map(|row| &row[..bytes_per_row as usize])
+        .copied()
+        .collect()
+}
+
+pub(crate) enum ReadbackStatus {
+    Pending,
+    Ready,
+    Failed,
+}
+
+/// Mappable buffers the GPU state is copied into.
+pub(crate) struct StagingBuffers {
+    buffers: Vec<Buffer>,
+    /// Set until the frame recording the copy into the buffers has been submitted.
+    copy_pending: bool,
+    mapped: Arc<AtomicUsize>,
+    failed: Arc<AtomicBool>,
+}
+
+impl StagingBuffers {
+    pub(crate) fn new(render_device: &RenderDevice, buffers: &[(&'static str, u64)]) -> Self {
+        let buffers = buffers
+            .iter()
+            .map(|&(label, size)| {
+                render_device.create_buffer(&BufferDescriptor {
+                    label: Some(label),
+                    size,
+                    usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect();
+        Self {
+            buffers,
+            copy_pending: true,
+            mapped: Arc::new(AtomicUsize::new(0)),
+            failed: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Whether the node should copy into the buffers this frame.
+    pub(crate) fn copy_pending(&self) -> bool {
+        self.copy_pending
+    }
+
+    pub(crate) fn buffer(&self, index: usize) -> &Buffer {
+        &self.buffers[index]
+    }
+
+    /// Call once per frame after the frame has been submitted.
+    ///
+    /// wgpu fires the map callbacks while processing later submissions, so this only ever checks
+    /// whether they happened instead of waiting for them.
+    pub(crate) fn poll(&mut self) -> ReadbackStatus {
+        if self.copy_pending {
+            self.copy_pending = false;
+            for buffer in &self.buffers {
+                let mapped = self.mapped.clone();
+                let failed = self.failed.clone();
+                buffer.slice(..).map_async(MapMode::Read, move |result| {
+                    if result.is_err() {
+                        failed.store(true, Ordering::Release);
+                    }
+                    mapped.fetch_add(1, Ordering::AcqRel);
+                });
+            }
+            return ReadbackStatus::Pending;
+        }
+
+        if self.mapped.load(Ordering::Acquire) < self.buffers.len() {
+            ReadbackStatus::Pending
+        } else if self.failed.load(Ordering::Acquire) {
+            ReadbackStatus::Failed
+        } else {
+            ReadbackStatus::Ready
+        }
+    }
+
+    /// Readies the buffers for another copy, only once every one of them has been read, so a
+    /// readback made every frame doesn't need new buffers every frame.
+    pub(crate) fn reuse(&mut self) {
+        self.copy_pending = true;
+        self.mapped.store(0, Ordering::Release);
+        self.failed.store(false, Ordering::Release);
+    }
+
+    /// Copies the contents of a buffer out, only valid once [`Self::poll`] is `Ready`.
+    pub(crate) fn read(&self, index: usize) -> Vec<u8> {
+        let buffer = &self.buffers[index];
+        let data = buffer.slice(..).get_mapped_range().to_vec();
+        buffer.unmap();
+        data
+    }
+}
+
+/// Copies the agents out of the render world's agent buffer, empty if it isn't prepared yet.
+///
+/// Unlike [`StagingBuffers`] this blocks until the GPU has finished every submitted frame, so
+/// it's meant for debugging and tests rather than for running every frame.
+pub(crate) fn read_agents(world: &World) -> Vec<Agent> {
+    let (Some(agents), Some(config)) = (
+        world.get_resource::<AgentBuffer>(),
+        world.get_resource::<SimulationConfig>(),
+    ) else {
+        return Vec::new();
+    };
+    let size = (config.agent_count as usize * std::mem::size_of::<Agent>()) as u64;
+    let Some(data) = read_blocking(world, "read_agents", size, |encoder, staging| {
+        encoder.copy_buffer_to_buffer(agents.latest(), 0, staging, 0, size);
+    }) else {
+        error!("Failed to read the agents back from the GPU");
+        return Vec::new();
+    };
+    data.chunks_exact(std::mem::size_of::<Agent>())
+        .map(bytemuck::pod_read_unaligned)
+        .collect()
+}
+
+/// Copies a trail map texture of `size` out texel by texel, blocking like [`read_agents`].
+pub(crate) fn read_trail(
+    world: &World,
+    trail: &Texture,
+    size: Extent3d,
+    precision: TrailPrecision,
+) -> Vec<Vec4> {
+    let Some(data) = read_blocking(
+        world,
+        "read_trail",
+        trail_buffer_size(size, precision),
+        |encoder, staging| copy_trail_to_buffer(encoder, trail, staging, size, precision),
+    ) else {
+        error!("Failed to read the trail map back from the GPU");
+        return Vec::new();
+    };
+    trail_texels(&data, size.width, precision)
+}
+
+/// Submits the copy recorded by `copy` into a fresh staging buffer of `size` bytes and waits
+/// for it to be mapped.
+fn read_blocking(
+    world: &World,
+    label: &'static str,
+    size: u64,
+    copy: impl FnOnce(&mut CommandEncoder, &Buffer),
+) -> Option<Vec<u8>> {
+    let render_device = world.resource::<RenderDevice>();
+    let staging = render_device.create_buffer(&BufferDescriptor {
+        label: Some(label),
+        size,
+        usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder =
+        render_device.create_command_encoder(&CommandEncoderDescriptor { label: Some(label) });
+    copy(&mut encoder, &staging);
+    world.resource::<RenderQueue>().submit([encoder.finish()]);
+
+    let (sender, receiver) = mpsc::channel();
+    staging.slice(..).map_async(MapMode::Read, move |result| {
+        // the receiver only goes away if polling panicked
+        let _ = sender.send(result);
+    });
+    render_device.poll(Maintain::Wait);
+    if !matches!(receiver.recv(), Ok(Ok(()))) {
+        return None;
+    }
+
+    let data = staging.slice(..).get_mapped_range().to_vec();
+    staging.unmap();
+    Some(data)
+}
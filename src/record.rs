@@ -0,0 +1,267 @@
+//! Recording the colorized trail map to numbered PNGs for assembling into a video, started with
+//! `--record <dir> --record-every <n>`.
+//!
+//! Every `n`th step the [`DisplayNode`](crate::nodes::DisplayNode) copies the
+//! [`TrailDisplay`](crate::TrailDisplay) into a staging buffer of its own. The buffers are
+//! mapped once their frame has been submitted, and the pixels are handed over a bounded channel
+//! to a background thread writing `frame_000000.png`, `frame_000001.png` and so on, so neither
+//! the readback latency nor the encoding holds up rendering.
+//!
+//! No frame is dropped to keep up. Once [`MAX_IN_FLIGHT`] readbacks are pending, or
+//! [`QUEUED_FRAMES`] frames wait to be encoded, the render thread waits for the oldest one. On
+//! exit the pending frames are flushed and the number of frames written is printed.
+//!
+//! ```text
+//! ffmpeg -framerate 60 -i recording/frame_%06d.png -pix_fmt yuv420p slime.mp4
+//! ```
+
+use std::{
+    collections::VecDeque,
+    path::PathBuf,
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    render::{
+        render_resource::{CommandEncoder, Extent3d, Maintain, Texture, TextureFormat},
+        renderer::RenderDevice,
+        RenderApp, RenderStage,
+    },
+};
+
+use crate::{
+    nodes,
+    readback::{
+        copy_texture_to_buffer, texture_buffer_size, unpad_rows, ReadbackStatus, StagingBuffers,
+    },
+    SimulationConfig,
+};
+
+/// Readbacks waiting for their staging buffer to be mapped before the render thread waits.
+const MAX_IN_FLIGHT: usize = 4;
+/// Frames read back but not yet encoded before the render thread waits.
+const QUEUED_FRAMES: usize = 8;
+
+/// Where and how often `--record` writes frames.
+#[derive(Debug, Clone)]
+pub(crate) struct Recording {
+    pub dir: PathBuf,
+    /// Record every this many steps, at least 1.
+    pub every: u32,
+}
+
+/// State of a recording, shared between the main world, which flushes it on exit, and the
+/// render world.
+#[derive(Clone, Resource)]
+pub(crate) struct FrameRecorder(Arc<Mutex<RecorderState>>);
+
+struct RecorderState {
+    every: u32,
+    dir: PathBuf,
+    /// Steps run since recording started.
+    steps: u32,
+    /// Frames started so far, the number in the name of the next one.
+    started: u32,
+    /// The frame copied out during this frame, if it is recorded.
+    current: Option<RecordedFrame>,
+    /// Frames copied out in earlier frames, oldest first.
+    in_flight: VecDeque<RecordedFrame>,
+    /// `None` once the recording is finished.
+    sender: Option<SyncSender<EncodedFrame>>,
+    encoder: Option<JoinHandle<u32>>,
+}
+
+struct RecordedFrame {
+    index: u32,
+    staging: StagingBuffers,
+    size: Extent3d,
+}
+
+/// A frame waiting to be written by the encoder thread.
+struct EncodedFrame {
+    index: u32,
+    pixels: Vec<u8>,
+    size: Extent3d,
+}
+
+/// Size in bytes of a tightly packed row of the display texture.
+fn display_bytes_per_row(width: u32) -> u32 {
+    width * TextureFormat::Rgba8Unorm.describe().block_size as u32
+}
+
+/// Starts recording to `recording.dir`, creating it if needed.
+pub(crate) fn add_recording(app: &mut App, recording: &Recording) {
+    if let Err(error) = std::fs::create_dir_all(&recording.dir) {
+        error!("Can't record to {}: {error}", recording.dir.display());
+        return;
+    }
+    let (sender, receiver) = mpsc::sync_channel(QUEUED_FRAMES);
+    let dir = recording.dir.clone();
+    let encoder = std::thread::spawn(move || encode_frames(dir, receiver));
+    let recorder = FrameRecorder(Arc::new(Mutex::new(RecorderState {
+        every: recording.every.max(1),
+        dir: recording.dir.clone(),
+        steps: 0,
+        started: 0,
+        current: None,
+        in_flight: VecDeque::new(),
+        sender: Some(sender),
+        encoder: Some(encoder),
+    })));
+
+    app.insert_resource(recorder.clone())
+        .add_system_to_stage(CoreStage::Last, finish_recording);
+    app.sub_app_mut(RenderApp)
+        .insert_resource(recorder)
+        .add_system_to_stage(
+            RenderStage::Queue,
+            start_recorded_frame.after(nodes::advance_step),
+        )
+        .add_system_to_stage(RenderStage::Cleanup, map_recorded_frames);
+}
+
+/// Writes the frames it receives until the recording is finished, returning how many it wrote.
+fn encode_frames(dir: PathBuf, frames: Receiver<EncodedFrame>) -> u32 {
+    let mut written = 0;
+    for frame in frames {
+        let path = dir.join(format!("frame_{:06}.png", frame.index));
+        let Extent3d { width, height, .. } = frame.size;
+        match image::save_buffer(&path, &frame.pixels, width, height, image::ColorType::Rgba8) {
+            Ok(()) => written += 1,
+            Err(error) => error!("Failed to save frame to {}: {error}", path.display()),
+        }
+    }
+    written
+}
+
+impl FrameRecorder {
+    /// Copies the display texture into the staging buffer of this frame, if it is recorded.
+    pub(crate) fn copy_frame(&self, encoder: &mut CommandEncoder, display: &Texture) {
+        let state = self.0.lock().unwrap();
+        if let Some(frame) = state
+            .current
+            .as_ref()
+            .filter(|frame| frame.staging.copy_pending())
+        {
+            copy_texture_to_buffer(
+                encoder,
+                display,
+                frame.staging.buffer(0),
+                frame.size,
+                display_bytes_per_row(frame.size.width),
+            );
+        }
+    }
+}
+
+impl RecorderState {
+    /// Hands the mapped frames to the encoder in order, waiting for the GPU when `flush` is set
+    /// or too many are in flight.
+    fn send_mapped(&mut self, render_device: &RenderDevice, flush: bool) {
+        loop {
+            let wait = flush || self.in_flight.len() > MAX_IN_FLIGHT;
+            let Some(frame) = self.in_flight.front_mut() else {
+                break;
+            };
+            match frame.staging.poll() {
+                ReadbackStatus::Pending if wait => {
+                    render_device.poll(Maintain::Wait);
+                }
+                ReadbackStatus::Pending => break,
+                ReadbackStatus::Failed => {
+                    error!("Failed to read frame {} back from the GPU", frame.index);
+                    self.in_flight.pop_front();
+                }
+                ReadbackStatus::Ready => {
+                    let pixels = unpad_rows(
+                        &frame.staging.read(0),
+                        display_bytes_per_row(frame.size.width),
+                    );
+                    let encoded = EncodedFrame {
+                        index: frame.index,
+                        pixels,
+                        size: frame.size,
+                    };
+                    self.in_flight.pop_front();
+                    // blocks while the encoder is behind, the receiver only goes away if it
+                    // panicked
+                    if let Some(sender) = &self.sender {
+                        let _ = sender.send(encoded);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Creates a staging buffer for this frame if the step it runs is recorded.
+///
+/// Runs in the queue stage, once [`nodes::advance_step`] has decided whether this frame steps.
+fn start_recorded_frame(
+    recorder: Res<FrameRecorder>,
+    step: Res<nodes::SlimeStep>,
+    config: Res<SimulationConfig>,
+    render_device: Res<RenderDevice>,
+) {
+    let mut state = recorder.0.lock().unwrap();
+    if state.sender.is_none() || !step.advances() {
+        return;
+    }
+    let recorded = state.steps % state.every == 0;
+    state.steps += 1;
+    if !recorded {
+        return;
+    }
+
+    let size = config.trail_extent();
+    let buffer_size = texture_buffer_size(size, display_bytes_per_row(size.width));
+    state.current = Some(RecordedFrame {
+        index: state.started,
+        staging: StagingBuffers::new(&render_device, &[("recorded_frame", buffer_size)]),
+        size,
+    });
+    state.started += 1;
+}
+
+/// Starts mapping this frame's copy once it has been submitted and sends on the frames that are
+/// readable.
+fn map_recorded_frames(recorder: Res<FrameRecorder>, render_device: Res<RenderDevice>) {
+    let mut state = recorder.0.lock().unwrap();
+    if let Some(frame) = state.current.take() {
+        state.in_flight.push_back(frame);
+    }
+    state.send_mapped(&render_device, false);
+}
+
+/// Flushes the pending frames and waits for them to be written once the app exits.
+///
+/// Runs in `Last`, after whichever system sent the [`AppExit`]. The render world doesn't start
+/// new frames after this.
+fn finish_recording(
+    mut exit: EventReader<AppExit>,
+    recorder: Res<FrameRecorder>,
+    render_device: Res<RenderDevice>,
+) {
+    if exit.iter().next().is_none() {
+        return;
+    }
+    let mut state = recorder.0.lock().unwrap();
+    if state.sender.is_none() {
+        return;
+    }
+    state.send_mapped(&render_device, true);
+    // dropping the sender ends the encoder thread once it has written the queued frames
+    state.sender = None;
+    let written = state
+        .encoder
+        .take()
+        .and_then(|encoder| encoder.join().ok())
+        .unwrap_or_default();
+    println!("Recorded {written} frames to {}", state.dir.display());
+}
@@ -0,0 +1,181 @@
+//! Exporting the trail map as a PNG with P.
+//!
+//! The trail map is read back the same way a snapshot is, then colored on the IO task pool with
+//! the same mapping the `colorize` shader uses for the screen.
+
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::ExtractResource, render_resource::Extent3d, renderer::RenderDevice,
+    },
+    tasks::IoTaskPool,
+};
+
+use crate::{
+    palette::{heading_hue, hue_color, ColorMode, PALETTE_SIZE},
+    readback::{trail_buffer_size, trail_texels, ReadbackStatus, StagingBuffers},
+    SimulationConfig, SimulationSettings, Slime, SlimeHandle, TrailPrecision, MAX_SPECIES,
+};
+
+/// Directory screenshots are written to, created on the first screenshot.
+pub(crate) const SCREENSHOT_DIR: &str = "screenshots";
+
+/// A screenshot requested by the main world this frame, along with the settings to color it with.
+#[derive(Debug, Clone, Default, Resource, ExtractResource)]
+pub(crate) struct ScreenshotRequest(Option<SimulationSettings>);
+
+/// Takes a screenshot with P.
+pub(crate) fn screenshot_controls(
+    keys: Res<Input<KeyCode>>,
+    mut request: ResMut<ScreenshotRequest>,
+    slimes: Res<Assets<Slime>>,
+    slime: Res<SlimeHandle>,
+) {
+    // only touch the resource when something changes, so it isn't re-extracted every frame
+    if request.0.is_some() {
+        request.0 = None;
+    }
+
+    if keys.just_pressed(KeyCode::P) {
+        if let Some(settings) = slimes.get(&slime.0) {
+            request.0 = Some(settings.0.clone());
+        }
+    }
+}
+
+/// Staging buffer for a screenshot in progress.
+#[derive(Resource)]
+pub(crate) struct ScreenshotReadback {
+    settings: SimulationSettings,
+    /// Holds the padded trail map.
+    pub(crate) staging: StagingBuffers,
+    trail_size: Extent3d,
+    trail_precision: TrailPrecision,
+}
+
+/// Starts a screenshot requested by the main world, unless one is still in progress.
+pub(crate) fn prepare_screenshot_readback(
+    mut commands: Commands,
+    request: Res<ScreenshotRequest>,
+    readback: Option<Res<ScreenshotReadback>>,
+    config: Res<SimulationConfig>,
+    render_device: Res<RenderDevice>,
+) {
+    let Some(settings) = &request.0 else {
+        return;
+    };
+    if readback.is_some() {
+        warn!("A screenshot is still being taken, ignoring the new request");
+        return;
+    }
+
+    commands.insert_resource(ScreenshotReadback {
+        settings: settings.clone(),
+        staging: StagingBuffers::new(
+            &render_device,
+            &[(
+                "screenshot_trail",
+                trail_buffer_size(config.trail_extent(), config.trail_precision),
+            )],
+        ),
+        trail_size: config.trail_extent(),
+        trail_precision: config.trail_precision,
+    });
+}
+
+/// Maps the staging buffer once the copy has been submitted and writes the PNG when it is
+/// readable.
+pub(crate) fn map_screenshot_readback(
+    mut commands: Commands,
+    readback: Option<ResMut<ScreenshotReadback>>,
+) {
+    let Some(mut readback) = readback else {
+        return;
+    };
+
+    match readback.staging.poll() {
+        ReadbackStatus::Pending => return,
+        ReadbackStatus::Failed => {
+            commands.remove_resource::<ScreenshotReadback>();
+            error!("Failed to read the trail map back from the GPU");
+            return;
+        }
+        ReadbackStatus::Ready => commands.remove_resource::<ScreenshotReadback>(),
+    }
+
+    let Extent3d { width, height, .. } = readback.trail_size;
+    let trail = trail_texels(&readback.staging.read(0), width, readback.trail_precision);
+    let settings = readback.settings.clone();
+    IoTaskPool::get()
+        .spawn(async move {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            let path = PathBuf::from(SCREENSHOT_DIR).join(format!("trail_{timestamp}.png"));
+            let pixels = colorize(&trail, &settings);
+            let result = std::fs::create_dir_all(SCREENSHOT_DIR)
+                .map_err(image::ImageError::from)
+                .and_then(|()| {
+                    image::save_buffer(&path, &pixels, width, height, image::ColorType::Rgba8)
+                });
+            match result {
+                Ok(()) => info!("Saved screenshot to {}", path.display()),
+                Err(error) => error!("Failed to save screenshot to {}: {error}", path.display()),
+            }
+        })
+        .detach();
+}
+
+/// Turns trail map texels into RGBA8 pixels, like the `colorize` shader.
+fn colorize(trail: &[Vec4], settings: &SimulationSettings) -> Vec<u8> {
+    let species_count = (settings.species_count as usize).min(MAX_SPECIES);
+    let lut = settings.palette.lut(settings.background);
+    let background = Vec4::from(settings.background).truncate();
+    trail
+        .iter()
+        .flat_map(|texel| {
+            let texel = texel.to_array();
+            let color = match settings.color_mode {
+                ColorMode::Intensity => {
+                    let intensity = texel.iter().sum::<f32>().clamp(0., 1.);
+                    return lut[(intensity * (PALETTE_SIZE - 1) as f32) as usize];
+                }
+                ColorMode::Species => settings.species[..species_count]
+                    .iter()
+                    .zip(texel)
+                    .map(|(species, intensity)| color_ramp(intensity, species.color.truncate()))
+                    .fold(background, |sum, color| sum + color),
+                ColorMode::Direction => {
+                    let [r, g, b, a] = texel;
+                    let intensity = r + g;
+                    let hue = heading_hue(2. * a - intensity, 2. * b - intensity);
+                    background + color_ramp(intensity, hue_color(hue))
+                }
+            }
+            .min(Vec3::ONE);
+            let [r, g, b] = (color * 255.)
+                .round()
+                .to_array()
+                .map(|channel| channel as u8);
+            [r, g, b, u8::MAX]
+        })
+        .collect()
+}
+
+/// Must match `color_ramp` in simple.wgsl.
+fn color_ramp(intensity: f32, color: Vec3) -> Vec3 {
+    let t = intensity.clamp(0., 1.);
+    let glow = Vec3::ZERO.lerp(color, smoothstep(0., 0.5, t));
+    glow.lerp(Vec3::ONE, smoothstep(0.5, 1., t))
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0., 1.);
+    t * t * (3. - 2. * t)
+}
@@ -0,0 +1,175 @@
+//! Writing the settings the simulation is running with back out as a `.slime` file, with F2
+//! while running or `--print-settings` before starting.
+//!
+//! The settings are the ones of the file with the command line overrides and every edit made
+//! since, from the egui panel, hot reloading or animations, so a tuned configuration can be
+//! saved and loaded again with `--config`.
+
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use bevy::{prelude::*, tasks::IoTaskPool};
+use ron::ser::PrettyConfig;
+
+use crate::{SimulationSettings, Slime, SlimeHandle};
+
+/// Directory F2 writes settings files to, created on the first export.
+pub(crate) const SETTINGS_DIR: &str = "settings";
+
+impl SimulationSettings {
+    /// The settings as the contents of a `.slime` file, which reads back to the same settings.
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        ron::ser::to_string_pretty(self, PrettyConfig::default())
+    }
+}
+
+/// Writes the current settings to a new file in [`SETTINGS_DIR`] with F2.
+pub(crate) fn settings_export_controls(
+    keys: Res<Input<KeyCode>>,
+    slimes: Res<Assets<Slime>>,
+    slime: Res<SlimeHandle>,
+) {
+    if !keys.just_pressed(KeyCode::F2) {
+        return;
+    }
+    let Some(settings) = slimes.get(&slime.0) else {
+        return;
+    };
+    let ron = match settings.to_ron() {
+        Ok(ron) => ron,
+        Err(error) => {
+            error!("Failed to serialize the settings: {error}");
+            return;
+        }
+    };
+    IoTaskPool::get()
+        .spawn(async move {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis();
+            let path = PathBuf::from(SETTINGS_DIR).join(format!("settings_{timestamp}.slime"));
+            let result =
+                std::fs::create_dir_all(SETTINGS_DIR).and_then(|()| std::fs::write(&path, ron));
+            match result {
+                Ok(()) => info!("Saved the settings to {}", path.display()),
+                Err(error) => error!("Failed to save the settings to {}: {error}", path.display()),
+            }
+        })
+        .detach();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+    use crate::{
+        AnimatedField, Animation, BoundaryMode, ColorMode, Corner, DepositShape, DisplayFilter,
+        HeadingMode, Palette, SpawnPattern, SpeciesSettings, TrailPrecision, Waveform,
+    };
+
+    #[test]
+    fn exported_settings_read_back_the_same() {
+        // every field set, and away from its default, so none can go missing on the way
+        let settings = SimulationSettings {
+            agent_count: 1234,
+            burst_headroom: 56,
+            move_speed: 1.5,
+            turn_speed: PI / 3.,
+            sensor_angle: 0.7,
+            sensor_distance: 12.25,
+            sensor_count: 5,
+            sensor_spread: Some(1.1),
+            sense_weight: 2.,
+            crowd_avoidance: 0.3,
+            exploration: 1.2,
+            exploration_threshold: 0.4,
+            noise_strength: 0.05,
+            noise_scale: 32.,
+            memory_length: 3,
+            memory_penalty: 0.6,
+            deposit_amount: 0.9,
+            antialiased_deposit: true,
+            deposit_shape: DepositShape::Disk(2.5),
+            decay_rate: 0.95,
+            diffuse_rate: 0.1,
+            enable_diffuse: false,
+            enable_decay: false,
+            evaporation_floor: 0.01,
+            blur_radius: 2,
+            gaussian_blur: true,
+            sharpen_amount: 0.2,
+            time_scale: 0.5,
+            substeps: 3,
+            steps_per_second: Some(120.),
+            max_dt: Some(0.1),
+            species_count: 2,
+            species: [
+                SpeciesSettings::new(1, Vec4::new(0.2, 0.3, 0.4, 1.)),
+                SpeciesSettings::new(0, Vec4::new(0.5, 0.6, 0.7, 0.8)),
+                SpeciesSettings::new(3, Vec4::ONE),
+                SpeciesSettings::new(2, Vec4::ZERO),
+            ],
+            deposit_matrix: vec![vec![1., 0.25], vec![0.5, 1.]],
+            sense_matrix: vec![vec![1., -1.], vec![-0.5, 1.]],
+            seed: u64::MAX - 7,
+            spawn_pattern: SpawnPattern::RingRandom,
+            initial_heading: Some(HeadingMode::Fixed(-0.75)),
+            sim_width: 320,
+            sim_height: 240,
+            preview: true,
+            preview_corner: Corner::TopLeft,
+            resize_follows_window: true,
+            boundary_mode: BoundaryMode::Circle,
+            seamless: true,
+            kill_respawn: false,
+            max_age: 600,
+            age_deposit_falloff: 0.5,
+            arena_radius: Some(100.),
+            color_mode: ColorMode::Direction,
+            palette: Palette::Viridis,
+            speed_jitter: 0.25,
+            max_trail: 4.,
+            gamma: 2.2,
+            brightness: 1.75,
+            display_filter: DisplayFilter::Nearest,
+            bloom: true,
+            bloom_intensity: 0.6,
+            bloom_threshold: 0.5,
+            auto_exposure: true,
+            exposure_smoothing: 0.9,
+            background: [0.1, 0.2, 0.3, 0.4],
+            workgroup_size: 16,
+            agent_workgroup_size: 128,
+            trail_precision: TrailPrecision::F16,
+            grid_cell_size: 8,
+            obstacle_mask: Some("masks/maze.png".to_string()),
+            food_attraction: 2.5,
+            food_consumption: 0.05,
+            food_map: Some("food \"map\".png".to_string()),
+            animations: vec![
+                Animation {
+                    field: AnimatedField::SensorAngle,
+                    waveform: Waveform::Sine,
+                    min: 0.2,
+                    max: 1.2,
+                    period: 10.,
+                },
+                Animation {
+                    field: AnimatedField::DecayRate,
+                    waveform: Waveform::Linear,
+                    min: 0.9,
+                    max: 0.99,
+                    period: 30.,
+                },
+            ],
+            agents_file: Some("agents.bin".to_string()),
+        };
+        let ron = settings.to_ron().unwrap();
+        let read_back: SimulationSettings = ron::from_str(&ron).unwrap();
+        assert_eq!(format!("{read_back:?}"), format!("{settings:?}"));
+    }
+}
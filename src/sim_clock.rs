@@ -0,0 +1,224 @@
+//! How many steps the simulation runs each frame and how long they are, kept by [`SimClock`].
+//!
+//! By default the simulation steps once a frame by the frame time, so it runs as fast as the
+//! display. With a
+//! [`steps_per_second`](crate::SimulationSettings::steps_per_second) it steps by a fixed time
+//! instead, as often as real time allows: the frame times are accumulated and every whole step in
+//! the accumulator runs this frame, none on a fast frame and several on a slow one, so the
+//! simulation runs the same whatever the frame rate. Headless runs and `--lockstep` run exactly
+//! one step of 1/60 s a frame, however long the frame took.
+//!
+//! The steps of a frame run as extra update passes of [`UpdateNode`](crate::nodes::UpdateNode),
+//! like substeps do, while the trail is diffused and decayed once.
+//!
+//! Bevy's fixed timestep runs main world systems several times a frame, but the render world
+//! only extracts once, so the clock counts the steps itself and the render world runs them all.
+
+use bevy::{prelude::*, render::extract_resource::ExtractResource};
+
+use crate::{SimState, REFERENCE_FRAME_RATE};
+
+/// Longest frame the simulation advances by unless the settings say otherwise, see
+/// [`SimClock::max_dt`].
+pub(crate) const DEFAULT_MAX_DT: f32 = 1. / 20.;
+/// Fixed steps run in one frame at most. Real time beyond those is dropped, so a slow GPU
+/// falls behind real time rather than running ever more steps a frame.
+const MAX_STEPS_PER_FRAME: u32 = 8;
+
+/// The steps the simulation runs this frame, advanced by the main world in
+/// [`CoreStage::PostUpdate`] and extracted for the update passes.
+#[derive(Debug, Clone, Resource, ExtractResource)]
+pub struct SimClock {
+    /// Fixed steps a second of real time, `None` to step once a frame by the frame time.
+    pub steps_per_second: Option<f32>,
+    /// Longest frame time in seconds the clock is advanced by, so the frame after a stall, like
+    /// dragging the window, doesn't make agents jump. `None` to follow any frame time.
+    pub max_dt: Option<f32>,
+    /// Run exactly one step of `1 / 60` s every update, ignoring the real time, for headless
+    /// runs and benchmarks.
+    pub lockstep: bool,
+    /// Real time not yet stepped through with `steps_per_second`.
+    accumulated: f32,
+    steps: u32,
+    step_time: f32,
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        Self {
+            steps_per_second: None,
+            max_dt: Some(DEFAULT_MAX_DT),
+            lockstep: false,
+            accumulated: 0.,
+            steps: 1,
+            step_time: 1. / REFERENCE_FRAME_RATE,
+        }
+    }
+}
+
+impl SimClock {
+    pub(crate) fn new(steps_per_second: Option<f32>, max_dt: Option<f32>, lockstep: bool) -> Self {
+        Self {
+            steps_per_second,
+            max_dt,
+            lockstep,
+            ..default()
+        }
+    }
+
+    /// Steps run this frame, 0 when the simulation doesn't advance.
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+
+    /// Duration in seconds of each of this frame's steps.
+    pub fn step_time(&self) -> f32 {
+        self.step_time
+    }
+
+    /// Simulated time this frame advances by, in seconds.
+    pub fn elapsed(&self) -> f32 {
+        self.steps as f32 * self.step_time
+    }
+
+    /// Duration of a fixed step, `None` when stepping by the frame time.
+    fn fixed_step_time(&self) -> Option<f32> {
+        match self.steps_per_second {
+            _ if self.lockstep => Some(1. / REFERENCE_FRAME_RATE),
+            Some(rate) if rate.is_finite() && rate > 0. => Some(1. / rate),
+            _ => None,
+        }
+    }
+
+    /// Advances the clock by a frame of `delta` seconds, at most `max_dt`, during which the
+    /// simulation runs if `running`. Paused frames don't accumulate, so resuming doesn't catch
+    /// up.
+    pub(crate) fn tick(&mut self, delta: f32, running: bool) {
+        if !running {
+            self.accumulated = 0.;
+            self.steps = 0;
+            return;
+        }
+        let delta = self.max_dt.map_or(delta, |max_dt| delta.min(max_dt));
+        match self.fixed_step_time() {
+            Some(step_time) if self.lockstep => {
+                self.steps = 1;
+                self.step_time = step_time;
+            }
+            Some(step_time) => {
+                self.step_time = step_time;
+                self.accumulated += delta;
+                let due = (self.accumulated / self.step_time) as u32;
+                self.accumulated -= due as f32 * self.step_time;
+                self.steps = due.min(MAX_STEPS_PER_FRAME);
+            }
+            None => {
+                self.steps = 1;
+                self.step_time = delta;
+            }
+        }
+    }
+
+    /// Runs exactly one step this frame, of the fixed step time or 1/60 s, for a single step
+    /// while paused.
+    pub(crate) fn single_step(&mut self) {
+        self.accumulated = 0.;
+        self.steps = 1;
+        self.step_time = self.fixed_step_time().unwrap_or(1. / REFERENCE_FRAME_RATE);
+    }
+}
+
+/// Ticks the [`SimClock`] by the real frame time, after the controls have decided whether
+/// this frame advances.
+pub(crate) fn advance_sim_clock(
+    time: Res<Time>,
+    state: Res<SimState>,
+    mut clock: ResMut<SimClock>,
+) {
+    if !state.running && state.step {
+        clock.single_step();
+    } else {
+        clock.tick(time.delta_seconds(), state.running);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixed_steps_accumulate_the_frame_time() {
+        // powers of two, so the accumulator stays exact
+        let mut clock = SimClock::new(Some(4.), None, false);
+        clock.tick(0.125, true);
+        assert_eq!(clock.steps(), 0);
+        clock.tick(0.125, true);
+        assert_eq!((clock.steps(), clock.step_time()), (1, 0.25));
+        clock.tick(0.625, true);
+        assert_eq!(clock.steps(), 2);
+        // the 0.125 left over from the last frame
+        clock.tick(0.125, true);
+        assert_eq!(clock.steps(), 1);
+    }
+
+    #[test]
+    fn fixed_steps_are_capped_per_frame() {
+        let mut clock = SimClock::new(Some(64.), None, false);
+        clock.tick(1., true);
+        assert_eq!(clock.steps(), MAX_STEPS_PER_FRAME);
+        // the real time beyond the cap is dropped rather than caught up on
+        clock.tick(0., true);
+        assert_eq!(clock.steps(), 0);
+    }
+
+    #[test]
+    fn pausing_drops_the_accumulated_time() {
+        let mut clock = SimClock::new(Some(4.), None, false);
+        clock.tick(0.125, true);
+        clock.tick(0.125, false);
+        assert_eq!(clock.steps(), 0);
+        clock.tick(0.125, true);
+        assert_eq!(clock.steps(), 0);
+    }
+
+    #[test]
+    fn lockstep_runs_one_reference_step_a_frame() {
+        let mut clock = SimClock::new(Some(4.), None, true);
+        for delta in [0., 0.001, 3.] {
+            clock.tick(delta, true);
+            assert_eq!(clock.steps(), 1);
+            assert_eq!(clock.step_time(), 1. / REFERENCE_FRAME_RATE);
+        }
+    }
+
+    #[test]
+    fn frame_time_steps_follow_the_frame() {
+        let mut clock = SimClock::default();
+        clock.tick(0.02, true);
+        assert_eq!((clock.steps(), clock.step_time()), (1, 0.02));
+        clock.single_step();
+        assert_eq!(clock.step_time(), 1. / REFERENCE_FRAME_RATE);
+    }
+
+    #[test]
+    fn frame_time_is_capped_at_max_dt() {
+        let mut clock = SimClock::default();
+        clock.tick(2., true);
+        assert_eq!(clock.step_time(), DEFAULT_MAX_DT);
+
+        let mut clock = SimClock::new(None, Some(0.5), false);
+        clock.tick(2., true);
+        assert_eq!(clock.step_time(), 0.5);
+
+        let mut clock = SimClock::new(None, None, false);
+        clock.tick(2., true);
+        assert_eq!(clock.step_time(), 2.);
+    }
+
+    #[test]
+    fn max_dt_caps_what_fixed_steps_accumulate() {
+        let mut clock = SimClock::new(Some(4.), Some(0.5), false);
+        clock.tick(2., true);
+        assert_eq!(clock.steps(), 2);
+    }
+}
@@ -0,0 +1,876 @@
+//! A CPU copy of how the `update` pass in simple.wgsl turns and moves an agent, enabled by the
+//! `sim_cpu` feature, for checking the steering and edge handling against the GPU with
+//! [`cpu_check`](crate::cpu_check).
+//!
+//! Only the movement and aging are mirrored: obstacles are treated as absent, the noise field
+//! is left to the caller of [`step_agent_in_noise`] and nothing is deposited. The tests also get
+//! the crowd avoidance, the point deposit and the diffuse pass, to check them on small maps.
+//! Changes to the step in the shader have to be made here too.
+
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use crate::{
+    Agent, BoundaryMode, GpuFrame, GpuSimulationSettings, SimulationSettings, SpawnPattern,
+    MAX_MEMORY, RING_INNER_RADIUS, SEAMLESS_TILES, SPAWN_RADIUS,
+};
+#[cfg(test)]
+use crate::{ColorMode, MAX_BLUR_RADIUS};
+
+/// Must match `MEMORY_RADIUS` in simple.wgsl.
+const MEMORY_RADIUS: f32 = 2.;
+
+/// Same integer hash as `hash` in simple.wgsl.
+fn hash(value: u32) -> u32 {
+    let mut state = value;
+    state ^= 2747636419;
+    state = state.wrapping_mul(2654435769);
+    state ^= state >> 16;
+    state = state.wrapping_mul(2654435769);
+    state ^= state >> 16;
+    state.wrapping_mul(2654435769)
+}
+
+fn random_float(value: u32) -> f32 {
+    hash(value) as f32 / u32::MAX as f32
+}
+
+/// Places a killed agent again like `respawn` in simple.wgsl.
+fn respawn(agent: &Agent, random: u32, settings: &SimulationSettings, size: Vec2) -> Agent {
+    let first = hash(random);
+    let second = hash(first);
+    let third = hash(second);
+    let random_angle = random_float(first) * 2. * PI;
+    let (size, origin) = if settings.seamless {
+        let size = size / SEAMLESS_TILES as f32;
+        let tile = hash(hash(third)) % (SEAMLESS_TILES * SEAMLESS_TILES);
+        let tile = UVec2::new(tile % SEAMLESS_TILES, tile / SEAMLESS_TILES);
+        (size, tile.as_vec2() * size)
+    } else {
+        (size, Vec2::ZERO)
+    };
+    let center = origin + size / 2.;
+    let radius = size.x.min(size.y) * SPAWN_RADIUS;
+
+    let (position, mut angle) = match settings.spawn_pattern {
+        SpawnPattern::CenterPoint => (center, random_angle),
+        SpawnPattern::RandomUniform => (
+            origin + Vec2::new(random_float(second), random_float(third)) * size,
+            random_angle,
+        ),
+        SpawnPattern::CircleInward | SpawnPattern::CircleOutward => {
+            let distance = radius * random_float(second).sqrt();
+            let position = center + Vec2::from_angle(random_angle) * distance;
+            if settings.spawn_pattern == SpawnPattern::CircleInward {
+                (position, random_angle + PI)
+            } else {
+                (position, random_angle)
+            }
+        }
+        SpawnPattern::RingRandom => {
+            let inner = radius * RING_INNER_RADIUS;
+            let distance =
+                (inner * inner + random_float(second) * (radius * radius - inner * inner)).sqrt();
+            (
+                center + Vec2::from_angle(random_angle) * distance,
+                random_float(third) * 2. * PI,
+            )
+        }
+    };
+    if let Some(heading) = settings.initial_heading {
+        angle = heading.angle(position - center, random_float(hash(third)));
+    }
+
+    Agent {
+        position,
+        angle,
+        memory_cursor: 0,
+        recent: [position; MAX_MEMORY],
+        age: 0,
+        ..*agent
+    }
+}
+
+/// `trail`, the weighted trail a sensor reads, with what is above the `exploration_threshold`
+/// weakened by the `exploration` like the shader's `sense` does.
+pub(crate) fn explore(trail: f32, settings: &SimulationSettings) -> f32 {
+    trail - settings.exploration * (trail - settings.exploration_threshold).max(0.)
+}
+
+/// `value`, what a sensor read, less the crowd of `density` agents counted on its texel over
+/// the substeps of the previous step, like the shader's `sense` subtracts it.
+#[cfg(test)]
+pub(crate) fn avoid_crowd(value: f32, density: u32, settings: &SimulationSettings) -> f32 {
+    if settings.crowd_avoidance == 0. {
+        return value;
+    }
+    let crowd = density as f32 / settings.substeps.max(1) as f32;
+    value - settings.crowd_avoidance * crowd
+}
+
+/// Moves `agent` number `index` by one substep of `frame`, like each `update` pass does.
+///
+/// `sense` returns the reading at a sensor's texel, already weighted the way the shader's
+/// `sense` weights the trail and food, weakens strong trail with [`explore`] and subtracts the
+/// crowd. Sensors outside the map read 0
+/// without calling it, the memory penalty is applied here. The noise field is left out.
+pub(crate) fn step_agent(
+    agent: &Agent,
+    index: u32,
+    frame: &GpuFrame,
+    settings: &SimulationSettings,
+    sense: impl Fn(IVec2) -> f32,
+) -> Agent {
+    step_agent_in_noise(agent, index, frame, settings, sense, |_| 0.)
+}
+
+/// [`step_agent`] turning the agent by `noise`, the shader's `heading_noise` at a position, once
+/// it has steered. Like the shader, `noise` isn't called at all with a `noise_strength` of 0.
+pub(crate) fn step_agent_in_noise(
+    agent: &Agent,
+    index: u32,
+    frame: &GpuFrame,
+    settings: &SimulationSettings,
+    sense: impl Fn(IVec2) -> f32,
+    noise: impl Fn(Vec2) -> f32,
+) -> Agent {
+    let gpu = GpuSimulationSettings::from(settings);
+    let size = Vec2::new(settings.sim_width as f32, settings.sim_height as f32);
+    let in_bounds = |position: IVec2| {
+        position.cmpge(IVec2::ZERO).all() && position.cmplt(size.as_ivec2()).all()
+    };
+    let outside_arena = |position: Vec2| {
+        settings.boundary() == BoundaryMode::Circle
+            && position.distance(size / 2.) > gpu.arena_radius
+    };
+
+    let mut agent = *agent;
+    if agent.alive == 0 {
+        return agent;
+    }
+    let species = gpu.species[agent.species as usize];
+    let sensor_spread = gpu.sensor_spread * species.sensor_angle;
+    let random = hash(index ^ hash(frame.index ^ hash(gpu.seed)));
+
+    let time_step = frame.time_step * gpu.time_scale;
+    let turn = gpu.turn_speed * time_step;
+
+    let sense_at = |angle_offset: f32| {
+        let angle = agent.angle + angle_offset;
+        let direction = Vec2::new(angle.cos(), angle.sin());
+        let mut position = (agent.position + direction * gpu.sensor_distance)
+            .floor()
+            .as_ivec2();
+        if settings.boundary() == BoundaryMode::Wrap {
+            let size = size.as_ivec2();
+            position = IVec2::new(position.x.rem_euclid(size.x), position.y.rem_euclid(size.y));
+        }
+        if !in_bounds(position) {
+            return 0.;
+        }
+        let remembered = agent.recent[..gpu.memory_length as usize]
+            .iter()
+            .any(|recent| position.as_vec2().distance(*recent) < MEMORY_RADIUS);
+        if remembered {
+            sense(position) - gpu.memory_penalty
+        } else {
+            sense(position)
+        }
+    };
+    let forward = sense_at(0.);
+    let (mut left, mut right) = (-3.4e38, -3.4e38);
+    let (mut left_fraction, mut right_fraction) = (0., 0.);
+    let side_count = gpu.sensor_count / 2;
+    for i in 1..=side_count {
+        let fraction = i as f32 / side_count as f32;
+        let left_reading = sense_at(sensor_spread * fraction);
+        if left_reading > left {
+            left = left_reading;
+            left_fraction = fraction;
+        }
+        let right_reading = sense_at(-sensor_spread * fraction);
+        if right_reading > right {
+            right = right_reading;
+            right_fraction = fraction;
+        }
+    }
+
+    let steer = turn * (gpu.sense_weight * (left - right).abs()).min(1.);
+    if forward >= left && forward >= right {
+        // keep going straight
+    } else if left > right {
+        agent.angle += steer * left_fraction;
+    } else if right > left {
+        agent.angle -= steer * right_fraction;
+    } else if random_float(random) < 0.5 {
+        agent.angle += turn;
+    } else {
+        agent.angle -= turn;
+    }
+
+    if gpu.noise_strength != 0. {
+        agent.angle += gpu.noise_strength * time_step * noise(agent.position);
+    }
+
+    let direction = Vec2::new(agent.angle.cos(), agent.angle.sin());
+    let speed = gpu.move_speed * species.move_speed * agent.speed * time_step;
+    let new_position = agent.position + direction * speed;
+    let target = new_position.floor().as_ivec2();
+    let outward = new_position - size / 2.;
+    if outside_arena(new_position) && direction.dot(outward) > 0. {
+        let normal = outward.normalize();
+        let reflected = direction - 2. * direction.dot(normal) * normal;
+        agent.angle = reflected.y.atan2(reflected.x);
+    } else if in_bounds(target) {
+        agent.position = new_position;
+    } else {
+        match settings.boundary() {
+            BoundaryMode::Wrap => {
+                agent.position = new_position - size * (new_position / size).floor();
+            }
+            BoundaryMode::Bounce | BoundaryMode::Circle => {
+                if new_position.x < 0. || new_position.x >= size.x {
+                    agent.angle = PI - agent.angle;
+                }
+                if new_position.y < 0. || new_position.y >= size.y {
+                    agent.angle = -agent.angle;
+                }
+            }
+            BoundaryMode::Kill if settings.kill_respawn => {
+                agent = respawn(&agent, random, settings, size);
+            }
+            BoundaryMode::Kill => {
+                agent.alive = 0;
+                agent.position = Vec2::splat(-1.);
+            }
+        }
+    }
+    agent.age += 1;
+    if gpu.max_age != 0 && agent.age >= gpu.max_age && agent.alive != 0 {
+        agent = respawn(&agent, hash(random), settings, size);
+    }
+    if gpu.memory_length > 0 {
+        agent.recent[(agent.memory_cursor % gpu.memory_length) as usize] = agent.position;
+        agent.memory_cursor = (agent.memory_cursor + 1) % gpu.memory_length;
+    }
+    agent
+}
+
+/// `position` wrapped onto a map of `size` texels, like `wrap` in simple.wgsl.
+#[cfg(test)]
+fn wrap(position: IVec2, size: IVec2) -> IVec2 {
+    IVec2::new(position.x.rem_euclid(size.x), position.y.rem_euclid(size.y))
+}
+
+/// Adds the deposit of `agent` to `trail`, a map of `size` texels stored row by row, like the
+/// shader's `deposit` does with a [`DepositShape::Point`](crate::DepositShape::Point) and no
+/// `antialiased_deposit`.
+#[cfg(test)]
+pub(crate) fn deposit(
+    trail: &mut [Vec4],
+    size: IVec2,
+    agent: &Agent,
+    settings: &SimulationSettings,
+) {
+    let gpu = GpuSimulationSettings::from(settings);
+    let mut amount = gpu.deposit_amount;
+    if gpu.max_age != 0 {
+        let life = agent.age as f32 / gpu.max_age as f32;
+        amount *= (1. - gpu.age_deposit_falloff * life).max(0.);
+    }
+    let mut fractions = gpu.deposit_matrix[agent.species as usize];
+    if settings.color_mode == ColorMode::Direction {
+        let total = fractions.x + fractions.y;
+        let heading = 0.5 + 0.5 * Vec2::new(agent.angle.cos(), agent.angle.sin());
+        fractions = Vec4::new(
+            fractions.x,
+            fractions.y,
+            total * heading.x,
+            total * heading.y,
+        );
+    }
+    // truncated like the shader's conversion to integers
+    let mut texel = agent.position.as_ivec2();
+    if settings.boundary() == BoundaryMode::Wrap {
+        texel = wrap(texel, size);
+    }
+    if texel.cmplt(IVec2::ZERO).any() || texel.cmpge(size).any() {
+        return;
+    }
+    let index = (texel.y * size.x + texel.x) as usize;
+    trail[index] = (trail[index] + fractions * amount).min(Vec4::splat(gpu.max_trail));
+}
+
+/// `trail`, a map of `size` texels stored row by row, after a `diffuse` pass of the shader.
+#[cfg(test)]
+pub(crate) fn diffuse(trail: &[Vec4], size: IVec2, settings: &SimulationSettings) -> Vec<Vec4> {
+    let gpu = GpuSimulationSettings::from(settings);
+    let index = |texel: IVec2| (texel.y * size.x + texel.x) as usize;
+    let evaporate = |trail: Vec4| {
+        Vec4::select(
+            trail.cmplt(Vec4::splat(gpu.evaporation_floor)),
+            Vec4::ZERO,
+            trail,
+        )
+    };
+    // sampling past the edge wraps on a torus and clamps to the edge otherwise
+    let neighbour = |texel: IVec2| {
+        if settings.boundary() == BoundaryMode::Wrap {
+            wrap(texel, size)
+        } else {
+            texel.clamp(IVec2::ZERO, size - 1)
+        }
+    };
+    let radius = gpu.blur_radius.min(MAX_BLUR_RADIUS) as i32;
+    let sigma = (radius as f32 * 0.5).max(0.5);
+
+    let mut next = Vec::with_capacity(trail.len());
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let position = IVec2::new(x, y);
+            let original = trail[index(position)];
+            if gpu.diffuse_rate == 0. {
+                next.push(evaporate(original * gpu.decay_rate));
+                continue;
+            }
+            let mut sum = Vec4::ZERO;
+            let mut weight_sum = 0.;
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    let weight = if gpu.gaussian_blur != 0 {
+                        (-((dx * dx + dy * dy) as f32) / (2. * sigma * sigma)).exp()
+                    } else {
+                        1.
+                    };
+                    sum += weight * trail[index(neighbour(position + IVec2::new(dx, dy)))];
+                    weight_sum += weight;
+                }
+            }
+            let blurred = original.lerp(sum / weight_sum, gpu.diffuse_rate);
+            next.push(evaporate(blurred * gpu.decay_rate));
+        }
+    }
+    next
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+    use super::*;
+
+    const SIZE: u32 = 64;
+
+    fn settings() -> SimulationSettings {
+        SimulationSettings {
+            sim_width: SIZE,
+            sim_height: SIZE,
+            turn_speed: 0.5,
+            sensor_angle: PI / 4.,
+            sensor_distance: 9.,
+            ..default()
+        }
+    }
+
+    fn frame(index: u32) -> GpuFrame {
+        GpuFrame {
+            index,
+            time_step: 1.,
+            active_count: 1,
+            ..default()
+        }
+    }
+
+    /// The texel the sensor `offset` radians from the heading of `agent` reads.
+    fn sensor_texel(agent: &Agent, offset: f32, settings: &SimulationSettings) -> IVec2 {
+        let angle = agent.angle + offset;
+        (agent.position + Vec2::new(angle.cos(), angle.sin()) * settings.sensor_distance)
+            .floor()
+            .as_ivec2()
+    }
+
+    /// Steps an agent in the middle of the map, facing along x, where only the sensor `offset`
+    /// radians from its heading reads any trail.
+    fn step_towards(offset: f32, settings: &SimulationSettings) -> Agent {
+        let agent = Agent::new(Vec2::splat(32.), 0.);
+        let strongest = sensor_texel(&agent, offset, settings);
+        let sense = |texel: IVec2| if texel == strongest { 1. } else { 0. };
+        step_agent(&agent, 0, &frame(0), settings, sense)
+    }
+
+    #[test]
+    fn keeps_straight_when_the_front_is_strongest() {
+        let agent = step_towards(0., &settings());
+        assert_eq!(agent.angle, 0.);
+        assert_eq!(agent.position, Vec2::new(33., 32.));
+        assert_eq!(agent.age, 1);
+    }
+
+    #[test]
+    fn turns_towards_the_stronger_side() {
+        let settings = settings();
+        assert_eq!(step_towards(settings.sensor_angle, &settings).angle, 0.5);
+        assert_eq!(step_towards(-settings.sensor_angle, &settings).angle, -0.5);
+    }
+
+    #[test]
+    fn turns_less_towards_weaker_differences() {
+        let settings = SimulationSettings {
+            sense_weight: 0.5,
+            ..settings()
+        };
+        assert_eq!(step_towards(settings.sensor_angle, &settings).angle, 0.25);
+    }
+
+    #[test]
+    fn turns_randomly_when_both_sides_beat_the_front() {
+        let settings = settings();
+        let turns: Vec<_> = (0..64)
+            .map(|index| {
+                let agent = Agent::new(Vec2::splat(32.), 0.);
+                let front = sensor_texel(&agent, 0., &settings);
+                let sense = |texel: IVec2| if texel == front { 0. } else { 1. };
+                step_agent(&agent, index, &frame(3), &settings, sense).angle
+            })
+            .collect();
+        assert!(turns.iter().all(|&angle| angle == 0.5 || angle == -0.5));
+        assert!(turns.contains(&0.5) && turns.contains(&-0.5));
+
+        // the same seed, frame and index turn the same way again
+        let agent = Agent::new(Vec2::splat(32.), 0.);
+        let front = sensor_texel(&agent, 0., &settings);
+        let sense = |texel: IVec2| if texel == front { 0. } else { 1. };
+        assert_eq!(
+            step_agent(&agent, 5, &frame(3), &settings, sense).angle,
+            turns[5]
+        );
+    }
+
+    #[test]
+    fn five_sensors_turn_sharper_the_further_out_the_strongest_is() {
+        let settings = SimulationSettings {
+            sensor_count: 5,
+            sensor_spread: Some(FRAC_PI_2),
+            ..settings()
+        };
+        assert_eq!(step_towards(FRAC_PI_2 * 0.5, &settings).angle, 0.25);
+        assert_eq!(step_towards(FRAC_PI_2, &settings).angle, 0.5);
+        assert_eq!(step_towards(-FRAC_PI_2 * 0.5, &settings).angle, -0.25);
+        assert_eq!(step_towards(-FRAC_PI_2, &settings).angle, -0.5);
+    }
+
+    #[test]
+    fn exploration_turns_away_from_dense_trail() {
+        let step = |settings: &SimulationSettings| {
+            let agent = Agent::new(Vec2::splat(32.), 0.);
+            let left = sensor_texel(&agent, settings.sensor_angle, settings);
+            let front = sensor_texel(&agent, 0., settings);
+            // dense trail on the left, a little on the right
+            let trail = |texel: IVec2| match texel {
+                texel if texel == left => 5.,
+                texel if texel == front => 3.,
+                _ => 1.,
+            };
+            let sense = |texel: IVec2| explore(trail(texel), settings);
+            step_agent(&agent, 0, &frame(0), settings, sense).angle
+        };
+        assert!(step(&settings()) > 0.);
+        let exploring = SimulationSettings {
+            exploration: 2.,
+            exploration_threshold: 0.5,
+            ..settings()
+        };
+        assert!(step(&exploring) < 0.);
+    }
+
+    #[test]
+    fn crowd_avoidance_turns_away_from_other_agents() {
+        let step = |settings: &SimulationSettings| {
+            let agent = Agent::new(Vec2::splat(32.), 0.);
+            let left = sensor_texel(&agent, settings.sensor_angle, settings);
+            let front = sensor_texel(&agent, 0., settings);
+            // the same trail everywhere, crowded on the left and in front
+            let density = |texel: IVec2| {
+                if texel == left || texel == front {
+                    3
+                } else {
+                    0
+                }
+            };
+            let sense = |texel: IVec2| avoid_crowd(1., density(texel), settings);
+            step_agent(&agent, 0, &frame(0), settings, sense).angle
+        };
+        assert_eq!(step(&settings()), 0.);
+        let avoiding = SimulationSettings {
+            crowd_avoidance: 0.5,
+            ..settings()
+        };
+        assert!(step(&avoiding) < 0.);
+    }
+
+    #[test]
+    fn exploration_weakens_only_the_excess() {
+        let settings = SimulationSettings {
+            exploration: 1.,
+            exploration_threshold: 2.,
+            ..settings()
+        };
+        assert_eq!(explore(1., &settings), 1.);
+        assert_eq!(explore(5., &settings), 2.);
+    }
+
+    #[test]
+    fn no_noise_strength_leaves_the_heading_alone() {
+        let noisy = SimulationSettings {
+            noise_strength: 0.25,
+            ..settings()
+        };
+        let step = |agent: &Agent, settings: &SimulationSettings, noise: fn(Vec2) -> f32| {
+            step_agent_in_noise(agent, 0, &frame(0), settings, |_| 0., noise)
+        };
+        for angle in [0., 1., -2.5] {
+            let agent = Agent::new(Vec2::new(20., 40.), angle);
+            // the same as in a field of no noise, without even sampling it
+            let quiet = step(&agent, &settings(), |_| unreachable!("sampled the noise"));
+            let flat = step(&agent, &noisy, |_| 0.);
+            assert_eq!(quiet.angle, flat.angle);
+            assert_eq!(quiet.position, flat.position);
+        }
+        let agent = step(&Agent::new(Vec2::new(20., 40.), 0.), &noisy, |_| 1.);
+        assert_eq!(agent.angle, 0.25);
+    }
+
+    /// Steps an agent at `position` facing `angle` on a map without any trail.
+    fn step_at(position: Vec2, angle: f32, settings: &SimulationSettings) -> Agent {
+        step_agent(&Agent::new(position, angle), 0, &frame(0), settings, |_| 0.)
+    }
+
+    #[test]
+    fn wraps_around_the_edges() {
+        let settings = SimulationSettings {
+            boundary_mode: BoundaryMode::Wrap,
+            ..settings()
+        };
+        let agent = step_at(Vec2::new(63.5, 32.), 0., &settings);
+        assert_eq!(agent.position, Vec2::new(0.5, 32.));
+        assert_eq!(agent.angle, 0.);
+        // seamless maps always wrap
+        let seamless = SimulationSettings {
+            seamless: true,
+            ..settings
+        };
+        let agent = step_at(Vec2::new(63.5, 32.), 0., &seamless);
+        assert_eq!(agent.position, Vec2::new(0.5, 32.));
+    }
+
+    #[test]
+    fn bounces_off_the_edges() {
+        let settings = SimulationSettings {
+            boundary_mode: BoundaryMode::Bounce,
+            ..settings()
+        };
+        let agent = step_at(Vec2::new(63.5, 32.), 0., &settings);
+        assert_eq!(agent.position, Vec2::new(63.5, 32.));
+        assert_eq!(agent.angle, PI);
+        let agent = step_at(Vec2::new(32., 63.5), FRAC_PI_2, &settings);
+        assert_eq!(agent.position, Vec2::new(32., 63.5));
+        assert_eq!(agent.angle, -FRAC_PI_2);
+    }
+
+    #[test]
+    fn reflects_off_the_rim_of_the_arena() {
+        let settings = SimulationSettings {
+            boundary_mode: BoundaryMode::Circle,
+            arena_radius: Some(20.),
+            ..settings()
+        };
+        let agent = step_at(Vec2::new(51.5, 32.), 0., &settings);
+        assert_eq!(agent.position, Vec2::new(51.5, 32.));
+        assert_eq!(agent.angle, PI);
+        // and goes back in on the next step
+        let agent = step_agent(&agent, 0, &frame(1), &settings, |_| 0.);
+        assert_eq!(agent.position, Vec2::new(50.5, 32.));
+
+        // a glancing hit keeps going along the rim
+        let start = Vec2::new(32., 51.5);
+        let direction = Vec2::from_angle(FRAC_PI_4);
+        let agent = step_at(start, FRAC_PI_4, &settings);
+        assert_eq!(agent.position, start);
+        let normal = (start + direction - Vec2::splat(32.)).normalize();
+        let reflected = Vec2::from_angle(agent.angle);
+        assert!((reflected.dot(normal) + direction.dot(normal)).abs() < 1e-5);
+        assert!((reflected.perp_dot(normal) - direction.perp_dot(normal)).abs() < 1e-5);
+        assert!(reflected.dot(normal) < 0.);
+    }
+
+    #[test]
+    fn dies_at_the_edges_without_respawning() {
+        let settings = SimulationSettings {
+            boundary_mode: BoundaryMode::Kill,
+            kill_respawn: false,
+            ..settings()
+        };
+        let agent = step_at(Vec2::new(0.5, 32.), PI, &settings);
+        assert_eq!(agent.alive, 0);
+        // dead agents stay where they are
+        assert_eq!(
+            step_at(agent.position, PI, &settings).position,
+            agent.position
+        );
+    }
+
+    #[test]
+    fn respawning_at_the_edges_keeps_every_agent_alive() {
+        let settings = SimulationSettings {
+            boundary_mode: BoundaryMode::Kill,
+            kill_respawn: true,
+            move_speed: 4.,
+            ..settings()
+        };
+        // all close to the edges and heading out of the map
+        let mut agents: Vec<_> = (0..64)
+            .map(|i| {
+                let angle = i as f32 / 64. * 2. * PI;
+                Agent::new(Vec2::splat(32.) + Vec2::from_angle(angle) * 30., angle)
+            })
+            .collect();
+        let mut respawned = 0;
+        for step in 0..50 {
+            for (index, agent) in agents.iter_mut().enumerate() {
+                let next = step_agent(agent, index as u32, &frame(step), &settings, |_| 0.);
+                if next.age <= agent.age {
+                    respawned += 1;
+                }
+                *agent = next;
+            }
+            assert_eq!(agents.iter().filter(|agent| agent.alive != 0).count(), 64);
+            assert!(agents
+                .iter()
+                .all(|agent| agent.position.cmpge(Vec2::ZERO).all()
+                    && agent.position.cmplt(Vec2::splat(SIZE as f32)).all()));
+        }
+        assert!(respawned >= 64, "only {respawned} respawns");
+    }
+
+    #[test]
+    fn respawns_once_it_reaches_max_age() {
+        let settings = SimulationSettings {
+            max_age: 3,
+            ..settings()
+        };
+        let mut agent = Agent::new(Vec2::new(10., 20.), 0.);
+        for (step, age) in [1, 2].into_iter().enumerate() {
+            agent = step_agent(&agent, 0, &frame(step as u32), &settings, |_| 0.);
+            assert_eq!(agent.age, age);
+        }
+        agent = step_agent(&agent, 0, &frame(2), &settings, |_| 0.);
+        assert_eq!(agent.age, 0);
+        // the default spawn pattern puts every agent on the center
+        assert_eq!(agent.position, Vec2::splat(SIZE as f32 / 2.));
+        assert_eq!(agent.alive, 1);
+    }
+
+    #[test]
+    fn seamless_respawns_on_the_center_of_a_tile() {
+        let seamless = SimulationSettings {
+            seamless: true,
+            ..settings()
+        };
+        let size = Vec2::splat(SIZE as f32);
+        let agent = Agent::new(Vec2::ZERO, 0.);
+        let tile_centers = [1., 3.].map(|y| [1., 3.].map(|x| Vec2::new(x, y) * size / 4.));
+        let mut centers = Vec::new();
+        for random in 0..64 {
+            assert_eq!(
+                respawn(&agent, random, &settings(), size).position,
+                size / 2.
+            );
+            let position = respawn(&agent, random, &seamless, size).position;
+            assert!(
+                tile_centers
+                    .iter()
+                    .flatten()
+                    .any(|&center| center == position),
+                "respawned at {position}"
+            );
+            if !centers.contains(&position) {
+                centers.push(position);
+            }
+        }
+        assert_eq!(centers.len(), 4);
+    }
+
+    fn trail_index(texel: IVec2) -> usize {
+        (texel.y * SIZE as i32 + texel.x) as usize
+    }
+
+    #[test]
+    fn deposits_on_the_texel_under_the_agent() {
+        let settings = SimulationSettings {
+            deposit_amount: 0.75,
+            max_trail: 1.,
+            ..settings()
+        };
+        let size = IVec2::splat(SIZE as i32);
+        let mut trail = vec![Vec4::ZERO; (SIZE * SIZE) as usize];
+        let agent = Agent::new(Vec2::new(10.7, 20.2), 0.);
+        deposit(&mut trail, size, &agent, &settings);
+        assert_eq!(
+            trail[trail_index(IVec2::new(10, 20))],
+            Vec4::new(0.75, 0., 0., 0.)
+        );
+        // capped at max_trail
+        deposit(&mut trail, size, &agent, &settings);
+        assert_eq!(trail[trail_index(IVec2::new(10, 20))], Vec4::X);
+        assert_eq!(
+            trail.iter().filter(|texel| **texel != Vec4::ZERO).count(),
+            1
+        );
+    }
+
+    #[test]
+    fn deposits_on_other_channels_by_the_deposit_matrix() {
+        // the first species feeds the trail of the second, which keeps its own to itself
+        let settings = SimulationSettings {
+            species_count: 2,
+            deposit_matrix: vec![vec![1., 0.5], vec![0., 1.]],
+            deposit_amount: 1.,
+            max_trail: 10.,
+            ..settings()
+        };
+        let size = IVec2::splat(SIZE as i32);
+        let mut trail = vec![Vec4::ZERO; (SIZE * SIZE) as usize];
+        let first = Agent::new(Vec2::splat(5.), 0.);
+        let second = Agent {
+            species: 1,
+            ..Agent::new(Vec2::splat(9.), 0.)
+        };
+        deposit(&mut trail, size, &first, &settings);
+        deposit(&mut trail, size, &second, &settings);
+        assert_eq!(
+            trail[trail_index(IVec2::splat(5))],
+            Vec4::new(1., 0.5, 0., 0.)
+        );
+        assert_eq!(
+            trail[trail_index(IVec2::splat(9))],
+            Vec4::new(0., 1., 0., 0.)
+        );
+    }
+
+    #[test]
+    fn deposits_the_heading_when_coloring_by_direction() {
+        let settings = SimulationSettings {
+            color_mode: ColorMode::Direction,
+            ..settings()
+        };
+        let size = IVec2::splat(SIZE as i32);
+        let mut trail = vec![Vec4::ZERO; (SIZE * SIZE) as usize];
+        deposit(
+            &mut trail,
+            size,
+            &Agent::new(Vec2::splat(5.), 0.),
+            &settings,
+        );
+        assert_eq!(
+            trail[trail_index(IVec2::splat(5))],
+            Vec4::new(1., 0., 1., 0.5)
+        );
+    }
+
+    /// A map of 8 by 8 texels with 9 trail on `spike` and none elsewhere, diffused once.
+    fn diffuse_spike(spike: IVec2, settings: &SimulationSettings) -> Vec<Vec4> {
+        let size = IVec2::splat(8);
+        let mut trail = vec![Vec4::ZERO; 64];
+        trail[(spike.y * 8 + spike.x) as usize] = Vec4::splat(9.);
+        diffuse(&trail, size, settings)
+    }
+
+    #[test]
+    fn diffuses_into_the_neighbourhood() {
+        let settings = SimulationSettings {
+            sim_width: 8,
+            sim_height: 8,
+            decay_rate: 1.,
+            diffuse_rate: 1.,
+            ..default()
+        };
+        let trail = diffuse_spike(IVec2::splat(4), &settings);
+        for y in 0..8 {
+            for x in 0..8 {
+                let near = (x - 4i32).abs() <= 1 && (y - 4i32).abs() <= 1;
+                let expected = if near { Vec4::ONE } else { Vec4::ZERO };
+                assert_eq!(trail[(y * 8 + x) as usize], expected, "at {x}, {y}");
+            }
+        }
+    }
+
+    #[test]
+    fn diffuses_across_the_edges_only_when_wrapping() {
+        let wrapping = SimulationSettings {
+            sim_width: 8,
+            sim_height: 8,
+            decay_rate: 1.,
+            diffuse_rate: 1.,
+            boundary_mode: BoundaryMode::Wrap,
+            ..default()
+        };
+        let trail = diffuse_spike(IVec2::ZERO, &wrapping);
+        assert_eq!(trail[0], Vec4::ONE);
+        assert_eq!(trail[63], Vec4::ONE);
+        assert_eq!(trail.iter().sum::<Vec4>(), Vec4::splat(9.));
+
+        // clamped, the corner samples itself for every neighbour off the map
+        let clamped = SimulationSettings {
+            boundary_mode: BoundaryMode::Bounce,
+            ..wrapping
+        };
+        let trail = diffuse_spike(IVec2::ZERO, &clamped);
+        assert_eq!(trail[0], Vec4::splat(4.));
+        assert_eq!(trail[1], Vec4::splat(2.));
+        assert_eq!(trail[63], Vec4::ZERO);
+    }
+
+    #[test]
+    fn seamless_maps_diffuse_across_the_edges() {
+        let bouncing = SimulationSettings {
+            sim_width: 8,
+            sim_height: 8,
+            decay_rate: 1.,
+            diffuse_rate: 1.,
+            boundary_mode: BoundaryMode::Bounce,
+            ..default()
+        };
+        let seamless = SimulationSettings {
+            seamless: true,
+            ..bouncing.clone()
+        };
+        // on the left edge, bleeding into the last column on the other side
+        let trail = diffuse_spike(IVec2::new(0, 4), &seamless);
+        for y in 3..=5 {
+            assert_eq!(trail[y * 8 + 7], Vec4::ONE, "at 7, {y}");
+        }
+        assert_eq!(trail.iter().sum::<Vec4>(), Vec4::splat(9.));
+
+        let trail = diffuse_spike(IVec2::new(0, 4), &bouncing);
+        assert_eq!(trail[4 * 8 + 7], Vec4::ZERO);
+    }
+
+    #[test]
+    fn decays_without_diffusing_and_evaporates_faint_trail() {
+        let settings = SimulationSettings {
+            sim_width: 8,
+            sim_height: 8,
+            decay_rate: 0.5,
+            diffuse_rate: 0.,
+            evaporation_floor: 0.1,
+            ..default()
+        };
+        let size = IVec2::splat(8);
+        let mut trail = vec![Vec4::ZERO; 64];
+        trail[0] = Vec4::new(4., 0.1, 0., 1.);
+        let trail = diffuse(&trail, size, &settings);
+        assert_eq!(trail[0], Vec4::new(2., 0., 0., 0.5));
+        assert_eq!(trail[1], Vec4::ZERO);
+    }
+}
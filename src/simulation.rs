@@ -0,0 +1,184 @@
+//! [`SlimeSimulation`], the one resource an app embedding the simulation needs to look at.
+
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    agent_access::{AgentReadsChannel, AgentRequests},
+    stats::TrailStats,
+    Agent, SimState, Slime, SlimeHandle, TrailDisplay,
+};
+
+/// Handles to the simulation's assets, its latest stats, pausing and resetting it, and reading
+/// and writing single agents.
+///
+/// Inserted by [`SlimeComputePlugin`](crate::SlimeComputePlugin). The handles are filled in once
+/// the simulation has started, and requests made through it apply at the end of the frame, see
+/// [`SlimeSystem::Sync`].
+#[derive(Debug, Default, Resource)]
+pub struct SlimeSimulation {
+    trail_texture: Handle<Image>,
+    settings: Handle<Slime>,
+    stats: TrailStats,
+    paused: bool,
+    requested_paused: Option<bool>,
+    requested_reset: bool,
+    requested_clear_trail: bool,
+    /// The agents read back so far, by index.
+    agents: HashMap<u32, Agent>,
+    requested_writes: Vec<(u32, Agent)>,
+    requested_reads: Vec<u32>,
+}
+
+impl SlimeSimulation {
+    /// The colorized trail map, an `Rgba8Unorm` image that can be shown on any sprite or
+    /// material. It is replaced when the trail map is rebuilt at another size.
+    pub fn trail_texture(&self) -> &Handle<Image> {
+        &self.trail_texture
+    }
+
+    /// The settings the simulation runs with, edits to the asset apply on the next frame.
+    pub fn settings(&self) -> &Handle<Slime> {
+        &self.settings
+    }
+
+    /// Intensity of the trail map as of the last readback.
+    pub fn stats(&self) -> TrailStats {
+        self.stats
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.requested_paused.unwrap_or(self.paused)
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.requested_paused = Some(paused);
+    }
+
+    /// Clears the trail map and leaves the agents where they are, like pressing C.
+    pub fn clear_trail(&mut self) {
+        self.requested_clear_trail = true;
+    }
+
+    /// Respawns the agents and clears the trail map, like pressing R.
+    pub fn full_reset(&mut self) {
+        self.requested_reset = true;
+    }
+
+    /// Replaces agent `index` before the next step, writing only that agent to the GPU. Indices
+    /// past the active agents are ignored with a warning.
+    pub fn set_agent(&mut self, index: u32, agent: Agent) {
+        self.requested_writes.push((index, agent));
+    }
+
+    /// Agent `index` as of its last readback, `None` until one has arrived and for indices past
+    /// the active agents.
+    ///
+    /// Every call also asks for the agent to be read back again, which takes a couple of frames,
+    /// so calling this every frame follows the agent a little behind, and an agent just
+    /// [`set`](Self::set_agent) reads back as set a few frames later.
+    pub fn get_agent(&mut self, index: u32) -> Option<Agent> {
+        if !self.requested_reads.contains(&index) {
+            self.requested_reads.push(index);
+        }
+        self.agents.get(&index).copied()
+    }
+}
+
+impl SlimeSimulation {
+    /// What [`sync_simulation`] does for a simulation added to
+    /// [`SlimeSimulations`](crate::SlimeSimulations), whose agents can't be read or written.
+    pub(crate) fn sync_added(&mut self, state: &mut SimState, display: &Handle<Image>) {
+        // a reset lasts one frame
+        state.reset = std::mem::take(&mut self.requested_reset);
+        state.clear_trail = std::mem::take(&mut self.requested_clear_trail);
+        if !self.requested_writes.is_empty() || !self.requested_reads.is_empty() {
+            warn!("Ignoring agent requests, the agents of added simulations can't be accessed");
+            self.requested_writes.clear();
+            self.requested_reads.clear();
+        }
+        if let Some(paused) = self.requested_paused.take() {
+            state.running = !paused;
+        }
+        self.paused = !state.running;
+        if self.trail_texture != *display {
+            self.trail_texture = display.clone();
+        }
+    }
+}
+
+/// Labels of the systems an embedding app may need to order its own systems against.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemLabel)]
+pub enum SlimeSystem {
+    /// Hands what was asked of [`SlimeSimulation`] to the simulation, in
+    /// [`CoreStage::PostUpdate`]. Requests made by systems before it, like every system in
+    /// [`CoreStage::Update`], apply this frame, agent writes and reads included, and the agents
+    /// read back so far are there after it.
+    Sync,
+}
+
+/// Applies the requests made through [`SlimeSimulation`] and refreshes what it reports.
+///
+/// Runs in `PostUpdate`, after the keyboard controls have cleared the previous frame's reset and
+/// before [`advance_sim_clock`](crate::sim_clock::advance_sim_clock), so pausing applies to the
+/// steps of the same frame.
+pub(crate) fn sync_simulation(
+    mut simulation: ResMut<SlimeSimulation>,
+    mut state: ResMut<SimState>,
+    display: Option<Res<TrailDisplay>>,
+    slime: Option<Res<SlimeHandle>>,
+    stats: Res<TrailStats>,
+    mut agent_requests: ResMut<AgentRequests>,
+    agent_reads: Res<AgentReadsChannel>,
+    mut reset_sent: Local<bool>,
+    mut clear_sent: Local<bool>,
+) {
+    // a reset lasts one frame, clear it here too for apps without the keyboard controls
+    if *reset_sent && state.reset {
+        state.reset = false;
+    }
+    if *clear_sent && state.clear_trail {
+        state.clear_trail = false;
+    }
+    *reset_sent = false;
+    *clear_sent = false;
+    if simulation.requested_reset {
+        simulation.requested_reset = false;
+        state.reset = true;
+        *reset_sent = true;
+    }
+    if simulation.requested_clear_trail {
+        simulation.requested_clear_trail = false;
+        state.clear_trail = true;
+        *clear_sent = true;
+    }
+    // the agent requests last one frame too
+    if !agent_requests.is_empty() {
+        *agent_requests = AgentRequests::default();
+    }
+    if !simulation.requested_writes.is_empty() || !simulation.requested_reads.is_empty() {
+        agent_requests.writes = std::mem::take(&mut simulation.requested_writes);
+        agent_requests.reads = std::mem::take(&mut simulation.requested_reads);
+    }
+    for (index, agent) in agent_reads.0.lock().unwrap().drain(..) {
+        simulation.agents.insert(index, agent);
+    }
+    if let Some(paused) = simulation.requested_paused.take() {
+        // only touch the resource when something changes, so it isn't re-extracted every frame
+        if state.running == paused {
+            state.running = !paused;
+        }
+    }
+
+    if simulation.paused != !state.running {
+        simulation.paused = !state.running;
+    }
+    if stats.is_changed() {
+        simulation.stats = *stats;
+    }
+    if let Some(display) = display.filter(|display| display.is_changed()) {
+        simulation.trail_texture = display.0.clone();
+    }
+    if let Some(slime) = slime.filter(|slime| slime.is_changed()) {
+        simulation.settings = slime.0.clone();
+    }
+}
@@ -0,0 +1,325 @@
+//! Saving the agents and trail map to a binary file and restoring them.
+//!
+//! A save copies the GPU state into staging buffers from within [`DisplayNode`](crate::nodes::DisplayNode),
+//! maps them once that frame has been submitted and writes the file on the IO task pool, so the
+//! render thread never waits on the GPU.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    num::NonZeroU32,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        extract_resource::ExtractResource,
+        render_asset::RenderAssets,
+        render_resource::{Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, TextureAspect},
+        renderer::{RenderDevice, RenderQueue},
+    },
+    tasks::IoTaskPool,
+};
+
+use crate::{
+    readback::{
+        trail_buffer_size, trail_bytes, trail_bytes_per_row, trail_texels, ReadbackStatus,
+        StagingBuffers,
+    },
+    Agent, AgentBuffer, SimulationConfig, SimulationSettings, Slime, SlimeHandle, TrailMap,
+    TrailPrecision,
+};
+
+/// File written by F5 and read by F9.
+pub(crate) const SNAPSHOT_PATH: &str = "snapshot.slimestate";
+
+const SNAPSHOT_MAGIC: &[u8; 4] = b"SLMS";
+/// Bumped whenever the layout of [`Agent`] or the file changes.
+const SNAPSHOT_VERSION: u32 = 4;
+
+/// The full state of a running simulation.
+#[derive(Debug, Clone)]
+pub(crate) struct Snapshot {
+    pub(crate) settings: SimulationSettings,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) agents: Vec<Agent>,
+    /// The trail map texel by texel, written as floats whatever the
+    /// [`TrailPrecision`] it was read from.
+    pub(crate) trail: Vec<Vec4>,
+}
+
+impl Snapshot {
+    /// Writes the snapshot as a versioned binary blob.
+    ///
+    /// The header holds the settings as RON so they stay readable across layout changes.
+    pub(crate) fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        let settings = ron::to_string(&self.settings)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        writer.write_all(SNAPSHOT_MAGIC)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        writer.write_all(&(settings.len() as u32).to_le_bytes())?;
+        writer.write_all(settings.as_bytes())?;
+        writer.write_all(&self.width.to_le_bytes())?;
+        writer.write_all(&self.height.to_le_bytes())?;
+        writer.write_all(&(self.agents.len() as u32).to_le_bytes())?;
+        writer.write_all(bytemuck::cast_slice(&self.agents))?;
+        writer.write_all(bytemuck::cast_slice(&self.trail))
+    }
+
+    pub(crate) fn read(reader: &mut impl Read) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != SNAPSHOT_MAGIC {
+            return Err(invalid("not a slime snapshot"));
+        }
+        let version = read_u32(reader)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(invalid(&format!("unsupported snapshot version {version}")));
+        }
+
+        let mut settings = vec![0; read_u32(reader)? as usize];
+        reader.read_exact(&mut settings)?;
+        let settings = ron::de::from_bytes(&settings)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+
+        let width = read_u32(reader)?;
+        let height = read_u32(reader)?;
+        let agent_count = read_u32(reader)? as usize;
+        let mut agents = vec![0; agent_count * std::mem::size_of::<Agent>()];
+        reader.read_exact(&mut agents)?;
+        let agents = agents
+            .chunks_exact(std::mem::size_of::<Agent>())
+            .map(bytemuck::pod_read_unaligned)
+            .collect();
+
+        let mut trail = vec![0; width as usize * height as usize * std::mem::size_of::<Vec4>()];
+        reader.read_exact(&mut trail)?;
+        let trail = trail
+            .chunks_exact(std::mem::size_of::<Vec4>())
+            .map(bytemuck::pod_read_unaligned)
+            .collect();
+
+        Ok(Self {
+            settings,
+            width,
+            height,
+            agents,
+            trail,
+        })
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Snapshot operations requested by the main world this frame.
+#[derive(Debug, Clone, Default, Resource, ExtractResource)]
+pub(crate) struct SnapshotRequest {
+    /// Where to save the current state to, along with the settings to store in its header.
+    pub(crate) save: Option<(PathBuf, SimulationSettings)>,
+    /// A snapshot read from disk, uploaded to the GPU by the render world.
+    pub(crate) load: Option<Arc<Snapshot>>,
+}
+
+/// Queues saving the current state to `path`.
+pub(crate) fn save_state(
+    requests: &mut SnapshotRequest,
+    path: impl Into<PathBuf>,
+    settings: &SimulationSettings,
+) {
+    requests.save = Some((path.into(), settings.clone()));
+}
+
+/// Reads a snapshot written by [`save_state`].
+pub(crate) fn load_state(path: impl AsRef<Path>) -> io::Result<Snapshot> {
+    Snapshot::read(&mut io::BufReader::new(File::open(path)?))
+}
+
+/// Saves with F5 and loads with F9.
+pub(crate) fn snapshot_controls(
+    keys: Res<Input<KeyCode>>,
+    mut requests: ResMut<SnapshotRequest>,
+    mut slimes: ResMut<Assets<Slime>>,
+    slime: Res<SlimeHandle>,
+) {
+    // only touch the resource when something changes, so it isn't re-extracted every frame
+    if requests.save.is_some() || requests.load.is_some() {
+        *requests = SnapshotRequest::default();
+    }
+
+    if keys.just_pressed(KeyCode::F5) {
+        if let Some(settings) = slimes.get(&slime.0) {
+            save_state(&mut requests, SNAPSHOT_PATH, settings);
+        }
+    }
+    if keys.just_pressed(KeyCode::F9) {
+        match load_state(SNAPSHOT_PATH) {
+            Ok(snapshot) => {
+                if let Some(settings) = slimes.get_mut(&slime.0) {
+                    settings.0 = snapshot.settings.clone();
+                }
+                info!("Loaded snapshot from {SNAPSHOT_PATH}");
+                requests.load = Some(Arc::new(snapshot));
+            }
+            Err(error) => error!("Failed to load snapshot from {SNAPSHOT_PATH}: {error}"),
+        }
+    }
+}
+
+/// Uploads a snapshot loaded by the main world into the agent buffer and both trail maps.
+pub(crate) fn apply_snapshot(
+    requests: Res<SnapshotRequest>,
+    config: Res<SimulationConfig>,
+    agent_buffer: Option<Res<AgentBuffer>>,
+    gpu_images: Res<RenderAssets<Image>>,
+    trail_map: Res<TrailMap>,
+    render_queue: Res<RenderQueue>,
+) {
+    let (Some(snapshot), Some(agent_buffer)) = (&requests.load, agent_buffer) else {
+        return;
+    };
+    if snapshot.agents.len() != config.agent_count as usize
+        || snapshot.width != config.sim_width
+        || snapshot.height != config.sim_height
+    {
+        warn!(
+            "Snapshot with {} agents on a {}x{} map doesn't fit this simulation, ignoring it",
+            snapshot.agents.len(),
+            snapshot.width,
+            snapshot.height
+        );
+        return;
+    }
+
+    agent_buffer.write(&render_queue, &snapshot.agents);
+    let precision = config.trail_precision;
+    let texels = trail_bytes(&snapshot.trail, precision);
+    for trail in trail_map.iter().filter_map(|handle| gpu_images.get(handle)) {
+        render_queue.write_texture(
+            ImageCopyTexture {
+                texture: &trail.texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            &texels,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(trail_bytes_per_row(snapshot.width, precision)),
+                rows_per_image: None,
+            },
+            config.trail_extent(),
+        );
+    }
+}
+
+/// Staging buffers for a save in progress.
+#[derive(Resource)]
+pub(crate) struct SnapshotReadback {
+    path: PathBuf,
+    settings: SimulationSettings,
+    /// Holds the agents at index 0 and the padded trail map at index 1.
+    pub(crate) staging: StagingBuffers,
+    pub(crate) agents_size: u64,
+    trail_size: Extent3d,
+    trail_precision: TrailPrecision,
+}
+
+/// Starts a save requested by the main world, unless one is still in progress.
+pub(crate) fn prepare_snapshot_readback(
+    mut commands: Commands,
+    requests: Res<SnapshotRequest>,
+    readback: Option<Res<SnapshotReadback>>,
+    config: Res<SimulationConfig>,
+    render_device: Res<RenderDevice>,
+) {
+    let Some((path, settings)) = &requests.save else {
+        return;
+    };
+    if readback.is_some() {
+        warn!("A snapshot is still being saved, ignoring the new request");
+        return;
+    }
+
+    let agents_size = (config.agent_count as usize * std::mem::size_of::<Agent>()) as u64;
+    commands.insert_resource(SnapshotReadback {
+        path: path.clone(),
+        settings: settings.clone(),
+        staging: StagingBuffers::new(
+            &render_device,
+            &[
+                ("snapshot_agents", agents_size),
+                (
+                    "snapshot_trail",
+                    trail_buffer_size(config.trail_extent(), config.trail_precision),
+                ),
+            ],
+        ),
+        agents_size,
+        trail_size: config.trail_extent(),
+        trail_precision: config.trail_precision,
+    });
+}
+
+/// Maps the staging buffers once the copy has been submitted and writes the file when they are
+/// readable.
+pub(crate) fn map_snapshot_readback(
+    mut commands: Commands,
+    readback: Option<ResMut<SnapshotReadback>>,
+) {
+    let Some(mut readback) = readback else {
+        return;
+    };
+
+    match readback.staging.poll() {
+        ReadbackStatus::Pending => return,
+        ReadbackStatus::Failed => {
+            commands.remove_resource::<SnapshotReadback>();
+            error!("Failed to read the simulation state back from the GPU");
+            return;
+        }
+        ReadbackStatus::Ready => commands.remove_resource::<SnapshotReadback>(),
+    }
+
+    let agents = readback
+        .staging
+        .read(0)
+        .chunks_exact(std::mem::size_of::<Agent>())
+        .map(bytemuck::pod_read_unaligned)
+        .collect();
+    let trail = trail_texels(
+        &readback.staging.read(1),
+        readback.trail_size.width,
+        readback.trail_precision,
+    );
+
+    let snapshot = Snapshot {
+        settings: readback.settings.clone(),
+        width: readback.trail_size.width,
+        height: readback.trail_size.height,
+        agents,
+        trail,
+    };
+    let path = readback.path.clone();
+    IoTaskPool::get()
+        .spawn(async move {
+            let result = File::create(&path).and_then(|file| {
+                let mut writer = BufWriter::new(file);
+                snapshot.write(&mut writer)?;
+                writer.flush()
+            });
+            match result {
+                Ok(()) => info!("Saved snapshot to {}", path.display()),
+                Err(error) => error!("Failed to save snapshot to {}: {error}", path.display()),
+            }
+        })
+        .detach();
+}
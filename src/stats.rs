@@ -0,0 +1,206 @@
+//! Min, max and mean trail intensity, for tuning `decay_rate` and for auto exposure.
+//!
+//! Every [`STATS_INTERVAL`] frames the `reduce` pass boils the trail map down to one partial
+//! result per workgroup, which is read back without blocking and combined on the CPU. Up to
+//! [`MAX_IN_FLIGHT`] readbacks can be waiting on the GPU at once.
+
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{Buffer, BufferDescriptor, BufferUsages, CommandEncoder, ShaderType},
+        renderer::RenderDevice,
+    },
+};
+use bytemuck::{Pod, Zeroable};
+
+use crate::{
+    readback::{ReadbackStatus, StagingBuffers},
+    workgroups_for, SimulationConfig,
+};
+
+/// Frames between two reductions.
+const STATS_INTERVAL: u32 = 30;
+/// Most readbacks waiting on the GPU at once, no new one starts while this many are.
+const MAX_IN_FLIGHT: usize = 3;
+
+/// Result of one workgroup of the `reduce` pass.
+///
+/// Must match `TrailStats` in simple.wgsl.
+#[derive(Debug, Copy, Clone, ShaderType, Pod, Zeroable)]
+#[repr(C)]
+pub(crate) struct GpuTrailStats {
+    pub min: f32,
+    pub max: f32,
+    pub sum: f32,
+    pub _padding0: f32,
+}
+
+/// Intensity of the trail map, the sum of every species' channel, as of the last readback.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Resource)]
+pub struct TrailStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    /// The 99th percentile of the brightest texel of every workgroup, which like the 99th
+    /// percentile of the texels follows how bright the trail is without a few hot texels
+    /// deciding it, but only needs the partial results.
+    pub p99: f32,
+}
+
+/// The latest stats read back by the render world, inserted into both worlds.
+#[derive(Debug, Clone, Default, Resource)]
+pub(crate) struct TrailStatsChannel(Arc<Mutex<Option<TrailStats>>>);
+
+pub(crate) fn receive_trail_stats(channel: Res<TrailStatsChannel>, mut stats: ResMut<TrailStats>) {
+    if let Some(latest) = channel.0.lock().unwrap().take() {
+        *stats = latest;
+    }
+}
+
+/// The buffer the `reduce` pass writes to and the readbacks copying it.
+#[derive(Resource)]
+pub(crate) struct TrailStatsReduction {
+    pub(crate) partials: Buffer,
+    partials_size: u64,
+    texel_count: u32,
+    in_flight: VecDeque<StagingBuffers>,
+    frames_until_next: u32,
+}
+
+impl TrailStatsReduction {
+    /// The readback the node should reduce and copy into this frame, if any.
+    pub(crate) fn pending_copy(&self) -> Option<&StagingBuffers> {
+        self.in_flight
+            .back()
+            .filter(|staging| staging.copy_pending())
+    }
+
+    /// Copies the partial results into the readback started this frame.
+    pub(crate) fn copy_partials(&self, encoder: &mut CommandEncoder) {
+        if let Some(staging) = self.pending_copy() {
+            encoder.copy_buffer_to_buffer(
+                &self.partials,
+                0,
+                staging.buffer(0),
+                0,
+                self.partials_size,
+            );
+        }
+    }
+}
+
+/// The buffer the `reduce` pass writes a partial result into for every workgroup of a trail map
+/// configured by `config`, and its size.
+pub(crate) fn create_partials(
+    render_device: &RenderDevice,
+    config: &SimulationConfig,
+) -> (Buffer, u64) {
+    let partial_count = workgroups_for(config.sim_width, config.texture_workgroup_size)
+        * workgroups_for(config.sim_height, config.texture_workgroup_size);
+    let size = partial_count as u64 * std::mem::size_of::<GpuTrailStats>() as u64;
+    let buffer = render_device.create_buffer(&BufferDescriptor {
+        label: Some("trail_stats"),
+        size,
+        usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    (buffer, size)
+}
+
+/// Creates the partials buffer, again when the trail map is resized, and starts a readback every
+/// [`STATS_INTERVAL`] frames.
+pub(crate) fn prepare_trail_stats(
+    mut commands: Commands,
+    reduction: Option<ResMut<TrailStatsReduction>>,
+    config: Res<SimulationConfig>,
+    render_device: Res<RenderDevice>,
+) {
+    let partial_count = workgroups_for(config.sim_width, config.texture_workgroup_size)
+        * workgroups_for(config.sim_height, config.texture_workgroup_size);
+    let partials_size = partial_count as u64 * std::mem::size_of::<GpuTrailStats>() as u64;
+    let texel_count = config.sim_width * config.sim_height;
+    let Some(mut reduction) = reduction.filter(|reduction| {
+        reduction.partials_size == partials_size && reduction.texel_count == texel_count
+    }) else {
+        let (partials, partials_size) = create_partials(&render_device, &config);
+        // readbacks still in flight are dropped along with the old resource
+        commands.insert_resource(TrailStatsReduction {
+            partials,
+            partials_size,
+            texel_count,
+            in_flight: VecDeque::new(),
+            frames_until_next: 0,
+        });
+        return;
+    };
+
+    if reduction.frames_until_next > 0 {
+        reduction.frames_until_next -= 1;
+        return;
+    }
+    if reduction.in_flight.len() < MAX_IN_FLIGHT {
+        let staging = StagingBuffers::new(
+            &render_device,
+            &[("trail_stats_staging", reduction.partials_size)],
+        );
+        reduction.in_flight.push_back(staging);
+    }
+    reduction.frames_until_next = STATS_INTERVAL - 1;
+}
+
+/// Combines the partial results of every readback that has become readable.
+pub(crate) fn map_trail_stats(
+    reduction: Option<ResMut<TrailStatsReduction>>,
+    channel: Res<TrailStatsChannel>,
+) {
+    let Some(mut reduction) = reduction else {
+        return;
+    };
+    let texel_count = reduction.texel_count;
+
+    reduction
+        .in_flight
+        .retain_mut(|staging| match staging.poll() {
+            ReadbackStatus::Pending => true,
+            ReadbackStatus::Failed => {
+                warn!("Failed to read the trail stats back from the GPU");
+                false
+            }
+            ReadbackStatus::Ready => {
+                let data = staging.read(0);
+                let partials: Vec<GpuTrailStats> = data
+                    .chunks_exact(std::mem::size_of::<GpuTrailStats>())
+                    .map(bytemuck::pod_read_unaligned::<GpuTrailStats>)
+                    .collect();
+                let (min, max, sum) =
+                    partials
+                        .iter()
+                        .fold((f32::MAX, f32::MIN, 0.), |(min, max, sum), partial| {
+                            (
+                                min.min(partial.min),
+                                max.max(partial.max),
+                                sum + partial.sum,
+                            )
+                        });
+                let mut maxima: Vec<f32> = partials.iter().map(|partial| partial.max).collect();
+                let p99_index = (maxima.len() * 99 / 100).min(maxima.len().saturating_sub(1));
+                let p99 = if maxima.is_empty() {
+                    0.
+                } else {
+                    *maxima.select_nth_unstable_by(p99_index, f32::total_cmp).1
+                };
+                *channel.0.lock().unwrap() = Some(TrailStats {
+                    min,
+                    max,
+                    mean: sum / texel_count as f32,
+                    p99,
+                });
+                false
+            }
+        });
+}
@@ -0,0 +1,106 @@
+//! Telling the main world when the compute pipelines finish compiling, or fail to, or that the
+//! GPU can't run them at all.
+//!
+//! The pipelines are checked by [`advance_step`](crate::nodes::advance_step) in the render
+//! world, so it queues its events in a channel shared by both worlds and
+//! [`forward_pipeline_events`] turns them into [`SlimePipelineEvent`]s every frame, also keeping
+//! [`SlimePipelineStatus`] up to date.
+//!
+//! Whether the GPU supports compute shaders is known as soon as the plugin is built. Without
+//! them [`SimUnsupported`] is inserted in the main world instead and nothing is set up in the
+//! render world, so the pipelines never leave [`SlimePipelineStatus::Loading`].
+
+use std::sync::{Arc, Mutex};
+
+use bevy::{
+    prelude::*,
+    render::renderer::{RenderAdapter, RenderDevice},
+};
+
+/// Storage buffers the update pass binds at once, along with [`STORAGE_TEXTURES`]. One more than
+/// wgpu's default limit, but bevy asks for the limits of the adapter.
+const STORAGE_BUFFERS: u32 = 9;
+/// Storage textures the update pass binds at once.
+const STORAGE_TEXTURES: u32 = 4;
+
+/// Sent once the simulation pipelines are compiled, or once if any of them can't be.
+///
+/// Compilation happens in the background over the first few frames. A shader that doesn't
+/// compile leaves the simulation stopped, with the error carried by [`Self::Failed`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlimePipelineEvent {
+    Ready,
+    Failed(String),
+}
+
+/// Latest state of the simulation pipelines, for showing why nothing happens.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Resource)]
+pub enum SlimePipelineStatus {
+    #[default]
+    Loading,
+    Ready,
+    /// The compile error, also logged when it happens.
+    Failed(String),
+}
+
+/// Inserted in the main world when the GPU or backend can't run the simulation, with the reason,
+/// also logged when the plugin is built.
+#[derive(Debug, Clone, PartialEq, Eq, Resource)]
+pub struct SimUnsupported(pub String);
+
+/// Why the simulation can't run on the adapter, if it can't.
+///
+/// WebGL2 and some older GPUs have no compute shaders, while others have them with fewer
+/// storage bindings than the update pass needs.
+pub(crate) fn check_compute_support(
+    adapter: &RenderAdapter,
+    render_device: &RenderDevice,
+) -> Result<(), SimUnsupported> {
+    let downlevel = adapter.get_downlevel_capabilities();
+    if !downlevel
+        .flags
+        .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+    {
+        return Err(SimUnsupported(format!(
+            "{} has no compute shader support",
+            adapter.get_info().name
+        )));
+    }
+    let limits = render_device.limits();
+    if limits.max_storage_buffers_per_shader_stage < STORAGE_BUFFERS
+        || limits.max_storage_textures_per_shader_stage < STORAGE_TEXTURES
+    {
+        return Err(SimUnsupported(format!(
+            "{} allows {} storage buffers and {} storage textures per shader, the simulation \
+             needs {STORAGE_BUFFERS} and {STORAGE_TEXTURES}",
+            adapter.get_info().name,
+            limits.max_storage_buffers_per_shader_stage,
+            limits.max_storage_textures_per_shader_stage
+        )));
+    }
+    Ok(())
+}
+
+/// Events queued by the render world, inserted into both worlds.
+#[derive(Debug, Clone, Default, Resource)]
+pub(crate) struct PipelineEventChannel(Arc<Mutex<Vec<SlimePipelineEvent>>>);
+
+impl PipelineEventChannel {
+    pub(crate) fn send(&self, event: SlimePipelineEvent) {
+        self.0.lock().unwrap().push(event);
+    }
+}
+
+pub(crate) fn forward_pipeline_events(
+    channel: Res<PipelineEventChannel>,
+    mut status: ResMut<SlimePipelineStatus>,
+    mut events: EventWriter<SlimePipelineEvent>,
+) {
+    for event in channel.0.lock().unwrap().drain(..) {
+        *status = match &event {
+            SlimePipelineEvent::Ready => SlimePipelineStatus::Ready,
+            SlimePipelineEvent::Failed(error) => SlimePipelineStatus::Failed(error.clone()),
+        };
+        events.send(event);
+    }
+}
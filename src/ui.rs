@@ -0,0 +1,180 @@
+//! Live tuning of the simulation settings through an egui panel, enabled by the `ui` feature.
+
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext, EguiPlugin};
+
+use crate::{
+    palette::{ColorMode, Palette},
+    DisplayFilter, SimulationSettings, Slime, SlimeHandle,
+};
+
+/// The egui settings panel.
+pub struct SlimeUiPlugin;
+
+impl Plugin for SlimeUiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(EguiPlugin).add_system(settings_panel);
+    }
+}
+
+/// Shows sliders for the settings that can change while running and writes them into the
+/// [`Slime`] asset, which rewrites the uniform buffer on the next frame.
+///
+/// The reset button goes back to the settings the asset held when the panel first saw it.
+fn settings_panel(
+    mut egui_context: ResMut<EguiContext>,
+    mut slimes: ResMut<Assets<Slime>>,
+    slime: Res<SlimeHandle>,
+    mut file_defaults: Local<Option<SimulationSettings>>,
+) {
+    let Some(current) = slimes.get(&slime.0) else {
+        return;
+    };
+    let file_defaults = file_defaults.get_or_insert_with(|| current.0.clone());
+    let mut settings = current.0.clone();
+
+    let mut changed = false;
+    egui::Window::new("Simulation").show(egui_context.ctx_mut(), |ui| {
+        let mut slider = |value: &mut f32, range, text| {
+            changed |= ui.add(egui::Slider::new(value, range).text(text)).changed();
+        };
+        slider(&mut settings.move_speed, 0.0..=5.0, "move_speed");
+        slider(&mut settings.turn_speed, 0.0..=PI, "turn_speed");
+        slider(&mut settings.sensor_angle, 0.0..=PI, "sensor_angle");
+        slider(&mut settings.sensor_distance, 0.0..=50.0, "sensor_distance");
+        slider(&mut settings.sense_weight, 0.0..=10.0, "sense_weight");
+        slider(&mut settings.crowd_avoidance, -1.0..=1.0, "crowd_avoidance");
+        slider(&mut settings.exploration, 0.0..=4.0, "exploration");
+        slider(
+            &mut settings.exploration_threshold,
+            0.0..=2.0,
+            "exploration_threshold",
+        );
+        slider(&mut settings.noise_strength, 0.0..=0.5, "noise_strength");
+        slider(&mut settings.noise_scale, 4.0..=256.0, "noise_scale");
+        slider(&mut settings.deposit_amount, 0.0..=2.0, "deposit_amount");
+        slider(
+            &mut settings.age_deposit_falloff,
+            0.0..=1.0,
+            "age_deposit_falloff",
+        );
+        slider(&mut settings.memory_penalty, 0.0..=4.0, "memory_penalty");
+        slider(&mut settings.decay_rate, 0.8..=1.0, "decay_rate");
+        slider(
+            &mut settings.evaporation_floor,
+            0.0..=0.05,
+            "evaporation_floor",
+        );
+        slider(&mut settings.diffuse_rate, 0.0..=1.0, "diffuse_rate");
+        slider(&mut settings.sharpen_amount, 0.0..=2.0, "sharpen_amount");
+        slider(&mut settings.time_scale, 0.0..=4.0, "time_scale");
+        slider(&mut settings.food_attraction, -4.0..=4.0, "food_attraction");
+        slider(
+            &mut settings.food_consumption,
+            0.0..=0.1,
+            "food_consumption",
+        );
+        slider(&mut settings.gamma, 0.2..=4.0, "gamma");
+        slider(&mut settings.brightness, 0.0..=4.0, "brightness");
+        slider(&mut settings.bloom_intensity, 0.0..=1.0, "bloom_intensity");
+        slider(&mut settings.bloom_threshold, 0.0..=2.0, "bloom_threshold");
+        slider(
+            &mut settings.exposure_smoothing,
+            0.0..=1.0,
+            "exposure_smoothing",
+        );
+
+        ui.horizontal(|ui| {
+            changed |= ui
+                .color_edit_button_rgba_unmultiplied(&mut settings.background)
+                .changed();
+            ui.label("background");
+        });
+        changed |= ui
+            .checkbox(&mut settings.auto_exposure, "auto_exposure")
+            .changed();
+        changed |= ui
+            .checkbox(&mut settings.enable_diffuse, "enable_diffuse")
+            .changed();
+        changed |= ui
+            .checkbox(&mut settings.enable_decay, "enable_decay")
+            .changed();
+
+        egui::ComboBox::from_label("color_mode")
+            .selected_text(format!("{:?}", settings.color_mode))
+            .show_ui(ui, |ui| {
+                for mode in ColorMode::ALL {
+                    changed |= ui
+                        .selectable_value(&mut settings.color_mode, mode, format!("{mode:?}"))
+                        .changed();
+                }
+            });
+        egui::ComboBox::from_label("palette")
+            .selected_text(format!("{:?}", settings.palette))
+            .show_ui(ui, |ui| {
+                for palette in Palette::ALL {
+                    changed |= ui
+                        .selectable_value(&mut settings.palette, palette, format!("{palette:?}"))
+                        .changed();
+                }
+            });
+        egui::ComboBox::from_label("display_filter")
+            .selected_text(format!("{:?}", settings.display_filter))
+            .show_ui(ui, |ui| {
+                for filter in DisplayFilter::ALL {
+                    changed |= ui
+                        .selectable_value(
+                            &mut settings.display_filter,
+                            filter,
+                            format!("{filter:?}"),
+                        )
+                        .changed();
+                }
+            });
+
+        if ui.button("Reset to file defaults").clicked() {
+            settings.move_speed = file_defaults.move_speed;
+            settings.turn_speed = file_defaults.turn_speed;
+            settings.sensor_angle = file_defaults.sensor_angle;
+            settings.sensor_distance = file_defaults.sensor_distance;
+            settings.sense_weight = file_defaults.sense_weight;
+            settings.crowd_avoidance = file_defaults.crowd_avoidance;
+            settings.exploration = file_defaults.exploration;
+            settings.exploration_threshold = file_defaults.exploration_threshold;
+            settings.noise_strength = file_defaults.noise_strength;
+            settings.noise_scale = file_defaults.noise_scale;
+            settings.deposit_amount = file_defaults.deposit_amount;
+            settings.age_deposit_falloff = file_defaults.age_deposit_falloff;
+            settings.memory_penalty = file_defaults.memory_penalty;
+            settings.decay_rate = file_defaults.decay_rate;
+            settings.evaporation_floor = file_defaults.evaporation_floor;
+            settings.diffuse_rate = file_defaults.diffuse_rate;
+            settings.enable_diffuse = file_defaults.enable_diffuse;
+            settings.enable_decay = file_defaults.enable_decay;
+            settings.sharpen_amount = file_defaults.sharpen_amount;
+            settings.time_scale = file_defaults.time_scale;
+            settings.food_attraction = file_defaults.food_attraction;
+            settings.food_consumption = file_defaults.food_consumption;
+            settings.gamma = file_defaults.gamma;
+            settings.brightness = file_defaults.brightness;
+            settings.bloom_intensity = file_defaults.bloom_intensity;
+            settings.bloom_threshold = file_defaults.bloom_threshold;
+            settings.auto_exposure = file_defaults.auto_exposure;
+            settings.exposure_smoothing = file_defaults.exposure_smoothing;
+            settings.color_mode = file_defaults.color_mode;
+            settings.palette = file_defaults.palette;
+            settings.display_filter = file_defaults.display_filter;
+            settings.background = file_defaults.background;
+            changed = true;
+        }
+    });
+
+    // only touch the asset when a value changes, so it isn't re-extracted every frame
+    if changed {
+        if let Some(slime) = slimes.get_mut(&slime.0) {
+            slime.0 = settings;
+        }
+    }
+}
@@ -0,0 +1,23 @@
+//! Steps agents on the GPU and compares them with the CPU copy of the update pass.
+
+#![cfg(feature = "sim_cpu")]
+
+use slime_simulation_bevy::{check_cpu, CheckOutcome, SimulationSettings, SpawnPattern};
+
+#[test]
+#[ignore = "needs a GPU adapter, run with `cargo test --features sim_cpu -- --ignored`"]
+fn gpu_steps_like_the_cpu() {
+    // scattered over the map, so agents sense trail and some reach the edges
+    let settings = SimulationSettings {
+        spawn_pattern: SpawnPattern::RandomUniform,
+        ..Default::default()
+    };
+    let outcome = check_cpu(32, settings).unwrap_or_else(|error| panic!("{error}"));
+    assert_eq!(
+        outcome,
+        CheckOutcome {
+            compared: 31,
+            mismatched: 0
+        }
+    );
+}